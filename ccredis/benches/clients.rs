@@ -0,0 +1,80 @@
+// Throughput of SET and GET with growing number of concurrent clients.
+// Run with `cargo bench`, each client is a thread with its own connection.
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use std::{
+    io::{BufReader, Write},
+    net::TcpStream,
+    sync::{atomic::{AtomicBool, AtomicU64, Ordering}, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{read_reply, ServerProcess};
+
+const CLIENTS: [usize; 5] = [1, 2, 4, 8, 16];
+const KEYS: usize = 10_000;
+const DURATION: Duration = Duration::from_secs(3);
+// requests sent at once before reading replies
+const PIPELINE: usize = 16;
+
+fn request(args: &[&str]) -> Vec<u8> {
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    request.into_bytes()
+}
+
+fn run_client(stream: TcpStream, client: usize, stop: Arc<AtomicBool>, requests: Arc<AtomicU64>) {
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+    let mut counter = client * 7919;
+    while !stop.load(Ordering::Relaxed) {
+        let mut batch: Vec<u8> = Vec::new();
+        for _ in 0..PIPELINE {
+            counter += 1;
+            let key = format!("key:{}", counter % KEYS);
+            if counter.is_multiple_of(2) {
+                batch.extend(request(&["SET", &key, "value"]));
+            } else {
+                batch.extend(request(&["GET", &key]));
+            }
+        }
+        writer.write_all(&batch).unwrap();
+        for _ in 0..PIPELINE {
+            read_reply(&mut reader);
+        }
+        requests.fetch_add(PIPELINE as u64, Ordering::Relaxed);
+    }
+}
+
+fn main() {
+    let server = ServerProcess::start(&["--io-threads", "8"]);
+    println!("{:>8} {:>14} {:>10}", "clients", "requests/sec", "speedup");
+    let mut single_client_rate = 0.0;
+    for clients in CLIENTS {
+        let stop = Arc::new(AtomicBool::new(false));
+        let requests = Arc::new(AtomicU64::new(0));
+        let handles: Vec<_> = (0..clients)
+            .map(|client| {
+                let stream = server.connect();
+                let (stop, requests) = (stop.clone(), requests.clone());
+                thread::spawn(move || run_client(stream, client, stop, requests))
+            })
+            .collect();
+
+        let started_at = Instant::now();
+        thread::sleep(DURATION);
+        stop.store(true, Ordering::Relaxed);
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        let rate = requests.load(Ordering::Relaxed) as f64 / started_at.elapsed().as_secs_f64();
+        if clients == 1 {
+            single_client_rate = rate;
+        }
+        println!("{:>8} {:>14.0} {:>9.2}x", clients, rate, rate / single_client_rate);
+    }
+}
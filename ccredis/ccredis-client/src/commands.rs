@@ -0,0 +1,101 @@
+use crate::{Connection, Error, Message, Result};
+
+// Typed helpers over `Connection::query`, also available on pooled connections
+pub trait Commands {
+    fn connection(&mut self) -> &mut Connection;
+
+    fn ping(&mut self) -> Result<String> {
+        match self.connection().query(&["PING"])? {
+            Message::SimpleString(pong) => Ok(pong),
+            reply => Err(Error::UnexpectedReply(reply)),
+        }
+    }
+
+    fn auth(&mut self, username: Option<&str>, password: &str) -> Result<()> {
+        let reply = match username {
+            Some(username) => self.connection().query(&["AUTH", username, password])?,
+            None => self.connection().query(&["AUTH", password])?,
+        };
+        ok(reply)
+    }
+
+    fn get(&mut self, key: impl AsRef<[u8]>) -> Result<Option<Vec<u8>>> {
+        match self.connection().query(&[b"GET".as_slice(), key.as_ref()])? {
+            Message::BulkString(value) => Ok(value),
+            reply => Err(Error::UnexpectedReply(reply)),
+        }
+    }
+
+    fn set(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>) -> Result<()> {
+        ok(self.connection().query(&[b"SET".as_slice(), key.as_ref(), value.as_ref()])?)
+    }
+
+    fn set_ex(&mut self, key: impl AsRef<[u8]>, value: impl AsRef<[u8]>, seconds: u64) -> Result<()> {
+        let seconds = seconds.to_string();
+        ok(self.connection().query(&[b"SET".as_slice(), key.as_ref(), value.as_ref(), b"EX", seconds.as_bytes()])?)
+    }
+
+    fn del<K: AsRef<[u8]>>(&mut self, keys: &[K]) -> Result<i64> {
+        integer(self.connection().query(&with_command(b"DEL", keys))?)
+    }
+
+    fn exists<K: AsRef<[u8]>>(&mut self, keys: &[K]) -> Result<i64> {
+        integer(self.connection().query(&with_command(b"EXISTS", keys))?)
+    }
+
+    fn incr(&mut self, key: impl AsRef<[u8]>) -> Result<i64> {
+        integer(self.connection().query(&[b"INCR".as_slice(), key.as_ref()])?)
+    }
+
+    fn decr(&mut self, key: impl AsRef<[u8]>) -> Result<i64> {
+        integer(self.connection().query(&[b"DECR".as_slice(), key.as_ref()])?)
+    }
+
+    fn lpush<V: AsRef<[u8]>>(&mut self, key: impl AsRef<[u8]>, values: &[V]) -> Result<i64> {
+        let mut args = vec![b"LPUSH".as_slice(), key.as_ref()];
+        args.extend(values.iter().map(|value| value.as_ref()));
+        integer(self.connection().query(&args)?)
+    }
+
+    fn rpush<V: AsRef<[u8]>>(&mut self, key: impl AsRef<[u8]>, values: &[V]) -> Result<i64> {
+        let mut args = vec![b"RPUSH".as_slice(), key.as_ref()];
+        args.extend(values.iter().map(|value| value.as_ref()));
+        integer(self.connection().query(&args)?)
+    }
+
+    // seconds to live, -1 without expiration, -2 when key doesn't exist
+    fn ttl(&mut self, key: impl AsRef<[u8]>) -> Result<i64> {
+        integer(self.connection().query(&[b"TTL".as_slice(), key.as_ref()])?)
+    }
+
+    // number of subscribers that received the message
+    fn publish(&mut self, channel: impl AsRef<[u8]>, message: impl AsRef<[u8]>) -> Result<i64> {
+        integer(self.connection().query(&[b"PUBLISH".as_slice(), channel.as_ref(), message.as_ref()])?)
+    }
+}
+
+impl Commands for Connection {
+    fn connection(&mut self) -> &mut Connection {
+        self
+    }
+}
+
+fn with_command<'a, K: AsRef<[u8]>>(command: &'a [u8], keys: &'a [K]) -> Vec<&'a [u8]> {
+    let mut args = vec![command];
+    args.extend(keys.iter().map(|key| key.as_ref()));
+    args
+}
+
+fn ok(reply: Message) -> Result<()> {
+    match reply {
+        Message::SimpleString(_) => Ok(()),
+        reply => Err(Error::UnexpectedReply(reply)),
+    }
+}
+
+fn integer(reply: Message) -> Result<i64> {
+    match reply {
+        Message::Integer(integer) => Ok(integer),
+        reply => Err(Error::UnexpectedReply(reply)),
+    }
+}
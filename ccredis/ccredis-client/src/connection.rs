@@ -0,0 +1,115 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    time::Duration,
+};
+
+use ccredis::resp::{message::Message, message_parser::MessageParser};
+
+use crate::{check, Error, Result};
+
+// One TCP connection, commands are sent one by one or in pipelines
+pub struct Connection {
+    writer: TcpStream,
+    reader: BufReader<TcpStream>,
+    parser: MessageParser,
+    // set after I/O or protocol error, the connection can't be used anymore
+    broken: bool,
+}
+
+impl Connection {
+    pub fn connect(addr: impl ToSocketAddrs) -> Result<Connection> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        Ok(Connection { writer: stream, reader, parser: MessageParser::new(), broken: false })
+    }
+
+    pub fn peer_addr(&self) -> Result<SocketAddr> {
+        Ok(self.writer.peer_addr()?)
+    }
+
+    // None waits for reply forever, connection which timed out waiting for reply is broken
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        Ok(self.writer.set_read_timeout(timeout)?)
+    }
+
+    pub fn is_broken(&self) -> bool {
+        self.broken
+    }
+
+    // Sends command and returns its reply, error reply becomes `Error::Server`
+    pub fn query<A: AsRef<[u8]>>(&mut self, args: &[A]) -> Result<Message> {
+        self.write(&[request(args)])?;
+        check(self.read_reply()?)
+    }
+
+    pub(crate) fn write(&mut self, messages: &[Message]) -> Result<()> {
+        let mut buffer = Vec::new();
+        for message in messages {
+            message.write_to(&mut buffer)?;
+        }
+        let written = self.writer.write_all(&buffer);
+        self.fail_on_error(written)
+    }
+
+    // Reply still owed after timeout would be read by the next command, so the connection is broken then
+    pub(crate) fn read_reply(&mut self) -> Result<Message> {
+        let reply = self.read_message();
+        if reply.is_err() {
+            self.broken = true;
+        }
+        reply
+    }
+
+    // Message pushed by server, e.g. published to subscribed channel. Nothing is owed when waiting
+    // for it times out, so reading can be retried.
+    pub(crate) fn read_push(&mut self) -> Result<Message> {
+        self.read_message()
+    }
+
+    fn read_message(&mut self) -> Result<Message> {
+        loop {
+            let buffer = match self.reader.fill_buf() {
+                Ok([]) => return self.fail_on_error(Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Server closed the connection"))),
+                Ok(buffer) => buffer,
+                // partially read message is kept by the parser
+                Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => return Err(Error::Io(err)),
+                Err(err) => return self.fail_on_error(Err(err)),
+            };
+            let mut consumed = 0;
+            let mut parsed = None;
+            for &byte in buffer {
+                consumed += 1;
+                match self.parser.add_byte(byte) {
+                    Ok(Some(reply)) => {
+                        parsed = Some(Ok(reply));
+                        break;
+                    },
+                    Ok(None) => {},
+                    Err(err) => {
+                        parsed = Some(Err(io::Error::new(io::ErrorKind::InvalidData, format!("Protocol error: {:?}", err))));
+                        break;
+                    },
+                }
+            }
+            self.reader.consume(consumed);
+            match parsed {
+                Some(Ok(reply)) => return Ok(reply),
+                Some(Err(err)) => return self.fail_on_error(Err(err)),
+                None => {},
+            }
+        }
+    }
+
+    fn fail_on_error<T>(&mut self, result: io::Result<T>) -> Result<T> {
+        if result.is_err() {
+            self.broken = true;
+        }
+        Ok(result?)
+    }
+}
+
+pub(crate) fn request<A: AsRef<[u8]>>(args: &[A]) -> Message {
+    Message::array(args.iter().map(|arg| Message::BulkString(Some(arg.as_ref().to_vec()))).collect())
+}
@@ -0,0 +1,59 @@
+// Blocking client for ccredis, e.g.
+// `let mut connection = Connection::connect("127.0.0.1:6379")?; connection.set("key", "value")?;`
+use std::{fmt, io};
+
+mod commands;
+mod connection;
+mod pipeline;
+mod pool;
+mod pubsub;
+
+pub use ccredis::resp::message::Message;
+pub use commands::Commands;
+pub use connection::Connection;
+pub use pipeline::Pipeline;
+pub use pool::{Pool, PoolBuilder, PooledConnection};
+pub use pubsub::{PubSubMessage, Subscriber};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    // error reply, e.g. `WRONGTYPE Operation against a key holding the wrong kind of value`
+    Server(String),
+    // reply of unexpected type for typed command helper
+    UnexpectedReply(Message),
+    // EXEC replied nil, because a watched key was changed
+    TransactionAborted,
+    // no connection was returned to the pool in time
+    PoolTimeout,
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Server(err) => write!(f, "{}", err),
+            Error::UnexpectedReply(reply) => write!(f, "Unexpected reply {:?}", reply),
+            Error::TransactionAborted => write!(f, "Transaction aborted"),
+            Error::PoolTimeout => write!(f, "Timed out waiting for connection from pool"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+// Error replies become `Error::Server`
+pub(crate) fn check(reply: Message) -> Result<Message> {
+    match reply {
+        Message::Error(err) => Err(Error::Server(err)),
+        reply => Ok(reply),
+    }
+}
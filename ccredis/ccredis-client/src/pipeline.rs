@@ -0,0 +1,60 @@
+use crate::{check, connection::request, Connection, Error, Message, Result};
+
+// Commands sent at once, replies are read after all of them are written.
+// Atomic pipeline is wrapped into MULTI/EXEC and replies with results of EXEC.
+#[derive(Default)]
+pub struct Pipeline {
+    commands: Vec<Message>,
+    atomic: bool,
+}
+
+impl Pipeline {
+    pub fn new() -> Pipeline {
+        Pipeline::default()
+    }
+
+    pub fn atomic(&mut self) -> &mut Self {
+        self.atomic = true;
+        self
+    }
+
+    pub fn cmd<A: AsRef<[u8]>>(&mut self, args: &[A]) -> &mut Self {
+        self.commands.push(request(args));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.commands.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    // One reply per command, error replies are kept in place as `Message::Error`
+    pub fn query(&self, connection: &mut Connection) -> Result<Vec<Message>> {
+        if !self.atomic {
+            connection.write(&self.commands)?;
+            return (0..self.commands.len()).map(|_| connection.read_reply()).collect();
+        }
+
+        let mut commands = Vec::with_capacity(self.commands.len() + 2);
+        commands.push(request(&["MULTI"]));
+        commands.extend(self.commands.iter().cloned());
+        commands.push(request(&["EXEC"]));
+        connection.write(&commands)?;
+
+        // all replies are read before returning an error, so the next command gets its own reply
+        let multi = connection.read_reply()?;
+        for _ in 0..self.commands.len() {
+            connection.read_reply()?;
+        }
+        let exec = connection.read_reply()?;
+        check(multi)?;
+        match check(exec)? {
+            Message::Array(Some(replies)) => Ok(replies),
+            Message::Array(None) => Err(Error::TransactionAborted),
+            reply => Err(Error::UnexpectedReply(reply)),
+        }
+    }
+}
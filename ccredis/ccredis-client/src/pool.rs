@@ -0,0 +1,190 @@
+use std::{
+    ops::{Deref, DerefMut},
+    sync::{Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{Commands, Connection, Error, Result};
+
+// Thread-safe pool, connections are opened on demand up to `max_size`.
+// Broken connections are dropped when returned and replaced by new ones later.
+#[derive(Clone)]
+pub struct Pool {
+    inner: Arc<Inner>,
+}
+
+pub struct PoolBuilder {
+    addr: String,
+    username: Option<String>,
+    password: Option<String>,
+    max_size: usize,
+    timeout: Duration,
+    health_check_interval: Duration,
+}
+
+struct Inner {
+    options: PoolBuilder,
+    state: Mutex<State>,
+    returned: Condvar,
+}
+
+struct State {
+    idle: Vec<IdleConnection>,
+    // idle and checked out connections
+    size: usize,
+}
+
+struct IdleConnection {
+    connection: Connection,
+    since: Instant,
+}
+
+impl PoolBuilder {
+    pub fn max_size(mut self, max_size: usize) -> Self {
+        self.max_size = max_size.max(1);
+        self
+    }
+
+    pub fn user(mut self, username: &str) -> Self {
+        self.username = Some(username.to_string());
+        self
+    }
+
+    pub fn password(mut self, password: &str) -> Self {
+        self.password = Some(password.to_string());
+        self
+    }
+
+    // how long `get` waits for connection when all of them are checked out
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    // connections idle for longer are checked with PING before they are handed out
+    pub fn health_check_interval(mut self, interval: Duration) -> Self {
+        self.health_check_interval = interval;
+        self
+    }
+
+    pub fn build(self) -> Pool {
+        let state = State { idle: Vec::new(), size: 0 };
+        Pool { inner: Arc::new(Inner { options: self, state: Mutex::new(state), returned: Condvar::new() }) }
+    }
+}
+
+impl Pool {
+    pub fn builder(addr: &str) -> PoolBuilder {
+        PoolBuilder {
+            addr: addr.to_string(),
+            username: None,
+            password: None,
+            max_size: 8,
+            timeout: Duration::from_secs(5),
+            health_check_interval: Duration::from_secs(1),
+        }
+    }
+
+    pub fn get(&self) -> Result<PooledConnection> {
+        let deadline = Instant::now() + self.inner.options.timeout;
+        let mut state = self.state();
+        loop {
+            if let Some(idle) = state.idle.pop() {
+                drop(state);
+                let mut connection = idle.connection;
+                if idle.since.elapsed() < self.inner.options.health_check_interval || connection.ping().is_ok() {
+                    return Ok(self.pooled(connection));
+                }
+                // e.g. server restarted or client was killed, a new connection takes its place
+                state = self.state();
+                state.size -= 1;
+                continue;
+            }
+
+            if state.size < self.inner.options.max_size {
+                state.size += 1;
+                drop(state);
+                return match self.open() {
+                    Ok(connection) => Ok(self.pooled(connection)),
+                    Err(err) => {
+                        self.state().size -= 1;
+                        self.inner.returned.notify_one();
+                        Err(err)
+                    },
+                };
+            }
+
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            if timeout.is_zero() {
+                return Err(Error::PoolTimeout);
+            }
+            state = self.inner.returned.wait_timeout(state, timeout).expect("Pool lock poisoned").0;
+        }
+    }
+
+    // idle and checked out connections
+    pub fn size(&self) -> usize {
+        self.state().size
+    }
+
+    pub fn idle(&self) -> usize {
+        self.state().idle.len()
+    }
+
+    fn open(&self) -> Result<Connection> {
+        let options = &self.inner.options;
+        let mut connection = Connection::connect(options.addr.as_str())?;
+        if let Some(password) = &options.password {
+            connection.auth(options.username.as_deref(), password)?;
+        }
+        Ok(connection)
+    }
+
+    fn pooled(&self, connection: Connection) -> PooledConnection {
+        PooledConnection { connection: Some(connection), pool: self.clone() }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.inner.state.lock().expect("Pool lock poisoned")
+    }
+}
+
+// Connection checked out of the pool, returned to it on drop
+pub struct PooledConnection {
+    connection: Option<Connection>,
+    pool: Pool,
+}
+
+impl Deref for PooledConnection {
+    type Target = Connection;
+
+    fn deref(&self) -> &Connection {
+        self.connection.as_ref().expect("connection is taken only on drop")
+    }
+}
+
+impl DerefMut for PooledConnection {
+    fn deref_mut(&mut self) -> &mut Connection {
+        self.connection.as_mut().expect("connection is taken only on drop")
+    }
+}
+
+impl Commands for PooledConnection {
+    fn connection(&mut self) -> &mut Connection {
+        self
+    }
+}
+
+impl Drop for PooledConnection {
+    fn drop(&mut self) {
+        let connection = self.connection.take().expect("connection is taken only on drop");
+        let mut state = self.pool.state();
+        if connection.is_broken() {
+            state.size -= 1;
+        } else {
+            state.idle.push(IdleConnection { connection, since: Instant::now() });
+        }
+        drop(state);
+        self.pool.inner.returned.notify_one();
+    }
+}
@@ -0,0 +1,129 @@
+use std::{collections::{HashSet, VecDeque}, time::Duration};
+
+use crate::{check, connection::request, Connection, Error, Message, Result};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PubSubMessage {
+    pub channel: Vec<u8>,
+    // pattern the channel matched, for PSUBSCRIBE
+    pub pattern: Option<Vec<u8>>,
+    pub payload: Vec<u8>,
+}
+
+// Connection in subscribed state, it receives published messages and can't run other commands
+pub struct Subscriber {
+    connection: Connection,
+    // messages received while waiting for subscription confirmations
+    pending: VecDeque<PubSubMessage>,
+    channels: HashSet<Vec<u8>>,
+    patterns: HashSet<Vec<u8>>,
+}
+
+impl Subscriber {
+    pub fn new(connection: Connection) -> Subscriber {
+        Subscriber { connection, pending: VecDeque::new(), channels: HashSet::new(), patterns: HashSet::new() }
+    }
+
+    pub fn subscribe<A: AsRef<[u8]>>(&mut self, channels: &[A]) -> Result<()> {
+        self.send("SUBSCRIBE", channels)
+    }
+
+    pub fn psubscribe<A: AsRef<[u8]>>(&mut self, patterns: &[A]) -> Result<()> {
+        self.send("PSUBSCRIBE", patterns)
+    }
+
+    // empty list unsubscribes from all channels
+    pub fn unsubscribe<A: AsRef<[u8]>>(&mut self, channels: &[A]) -> Result<()> {
+        self.send("UNSUBSCRIBE", channels)
+    }
+
+    pub fn punsubscribe<A: AsRef<[u8]>>(&mut self, patterns: &[A]) -> Result<()> {
+        self.send("PUNSUBSCRIBE", patterns)
+    }
+
+    // None waits for message forever, otherwise `next_message` fails with `WouldBlock` or `TimedOut`
+    // and can be called again. Timeout waiting for (un)subscribe confirmations breaks the connection.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        self.connection.set_read_timeout(timeout)
+    }
+
+    pub fn next_message(&mut self) -> Result<PubSubMessage> {
+        if let Some(message) = self.pending.pop_front() {
+            return Ok(message);
+        }
+        loop {
+            let reply = self.connection.read_push()?;
+            if let Event::Message(message) = self.read_event(reply)? {
+                return Ok(message);
+            }
+        }
+    }
+
+    pub fn into_connection(self) -> Connection {
+        self.connection
+    }
+
+    // Server confirms every channel separately, unsubscribe from all confirms every subscribed channel
+    // or replies once when there are none
+    fn send<A: AsRef<[u8]>>(&mut self, command: &str, channels: &[A]) -> Result<()> {
+        let mut args = vec![command.as_bytes()];
+        args.extend(channels.iter().map(|channel| channel.as_ref()));
+        self.connection.write(&[request(&args)])?;
+
+        let expected = match (channels.len(), command) {
+            (0, "UNSUBSCRIBE") => self.channels.len().max(1),
+            (0, "PUNSUBSCRIBE") => self.patterns.len().max(1),
+            (count, _) => count,
+        };
+        let mut confirmations = 0;
+        while confirmations < expected {
+            let reply = self.connection.read_reply()?;
+            match self.read_event(reply)? {
+                Event::Message(message) => self.pending.push_back(message),
+                Event::Confirmation => confirmations += 1,
+            }
+        }
+        Ok(())
+    }
+
+    fn read_event(&mut self, reply: Message) -> Result<Event> {
+        let reply = check(reply)?;
+        let Message::Array(Some(items)) = &reply else {
+            return Err(Error::UnexpectedReply(reply));
+        };
+        let kind = match items.first() {
+            Some(Message::BulkString(Some(kind))) => kind.to_ascii_lowercase(),
+            _ => return Err(Error::UnexpectedReply(reply)),
+        };
+        match (kind.as_slice(), items.as_slice()) {
+            (b"message", [_, Message::BulkString(Some(channel)), Message::BulkString(Some(payload))]) => {
+                Ok(Event::Message(PubSubMessage { channel: channel.clone(), pattern: None, payload: payload.clone() }))
+            },
+            (b"pmessage", [_, Message::BulkString(Some(pattern)), Message::BulkString(Some(channel)), Message::BulkString(Some(payload))]) => {
+                Ok(Event::Message(PubSubMessage { channel: channel.clone(), pattern: Some(pattern.clone()), payload: payload.clone() }))
+            },
+            (b"subscribe" | b"psubscribe" | b"unsubscribe" | b"punsubscribe", [_, channel, Message::Integer(_)]) => {
+                let Message::BulkString(channel) = channel else {
+                    return Err(Error::UnexpectedReply(reply));
+                };
+                // channel is nil when unsubscribing without subscriptions
+                if let Some(channel) = channel.clone() {
+                    match kind.as_slice() {
+                        b"subscribe" => self.channels.insert(channel),
+                        b"psubscribe" => self.patterns.insert(channel),
+                        b"unsubscribe" => self.channels.remove(&channel),
+                        _ => self.patterns.remove(&channel),
+                    };
+                }
+                Ok(Event::Confirmation)
+            },
+            _ => Err(Error::UnexpectedReply(reply)),
+        }
+    }
+}
+
+enum Event {
+    Message(PubSubMessage),
+    // reply to (un)subscribe for one channel or pattern
+    Confirmation,
+}
@@ -0,0 +1,214 @@
+use std::{thread, time::Duration};
+
+use ccredis::server::{Server, ServerHandle};
+use ccredis_client::{Commands, Connection, Error, Message, Pipeline, Pool, PubSubMessage, Subscriber};
+
+fn start_server(name: &str) -> ServerHandle {
+    let db_file_path = std::env::temp_dir().join(format!("ccredis_client_{}_{}.txt", std::process::id(), name));
+    Server::builder().bind("127.0.0.1:0").persistence(db_file_path.to_str().unwrap()).start().unwrap()
+}
+
+// killed clients are closed by their connection tasks, after CLIENT KILL replies
+fn wait_for_clients(connection: &mut Connection, count: usize) {
+    for _ in 0..100 {
+        let Message::BulkString(Some(list)) = connection.query(&["CLIENT", "LIST"]).unwrap() else {
+            panic!("CLIENT LIST replies with bulk string");
+        };
+        if list.split(|byte| *byte == b'\n').filter(|line| !line.is_empty()).count() == count {
+            return;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("Clients were not closed");
+}
+
+#[test]
+fn typed_commands() {
+    let server = start_server("typed");
+    let mut connection = Connection::connect(server.local_addr().unwrap()).unwrap();
+
+    assert_eq!(connection.ping().unwrap(), "PONG");
+    assert_eq!(connection.get("foo").unwrap(), None);
+    connection.set("foo", "bar").unwrap();
+    assert_eq!(connection.get("foo").unwrap(), Some(b"bar".to_vec()));
+    connection.set_ex("temp", b"value", 100).unwrap();
+    assert!((99..=100).contains(&connection.ttl("temp").unwrap()));
+    assert_eq!(connection.incr("counter").unwrap(), 1);
+    assert_eq!(connection.decr("counter").unwrap(), 0);
+    assert_eq!(connection.rpush("list", &["b", "c"]).unwrap(), 2);
+    assert_eq!(connection.lpush("list", &["a"]).unwrap(), 3);
+    assert_eq!(connection.exists(&["foo", "list", "missing"]).unwrap(), 2);
+    assert_eq!(connection.del(&["foo", "temp"]).unwrap(), 2);
+
+    match connection.incr("list") {
+        Err(Error::Server(err)) => assert!(err.starts_with("WRONGTYPE"), "{}", err),
+        result => panic!("Unexpected result {:?}", result),
+    }
+    assert!(matches!(connection.query(&["UNKNOWN"]), Err(Error::Server(_))));
+    // connection is usable after error replies
+    assert_eq!(connection.query(&["ECHO", "still here"]).unwrap(), Message::bulk_string("still here"));
+}
+
+#[test]
+fn pipeline() {
+    let server = start_server("pipeline");
+    let mut connection = Connection::connect(server.local_addr().unwrap()).unwrap();
+
+    let mut pipeline = Pipeline::new();
+    for index in 0..100 {
+        pipeline.cmd(&["SET", &format!("key:{}", index), "value"]);
+    }
+    pipeline.cmd(&["INCR", "key:0"]).cmd(&["GET", "key:99"]);
+    let replies = pipeline.query(&mut connection).unwrap();
+    assert_eq!(replies.len(), 102);
+    assert!(replies[..100].iter().all(|reply| *reply == Message::simple_string("OK")));
+    assert!(matches!(&replies[100], Message::Error(err) if err.contains("not an integer")));
+    assert_eq!(replies[101], Message::bulk_string("value"));
+    assert_eq!(connection.exists(&["key:0", "key:50"]).unwrap(), 2);
+}
+
+#[test]
+fn transaction() {
+    let server = start_server("transaction");
+    let mut connection = Connection::connect(server.local_addr().unwrap()).unwrap();
+    let mut other = Connection::connect(server.local_addr().unwrap()).unwrap();
+
+    let replies = Pipeline::new().atomic().cmd(&["SET", "a", "1"]).cmd(&["INCR", "b"]).query(&mut connection).unwrap();
+    assert_eq!(replies, [Message::simple_string("OK"), Message::Integer(1)]);
+
+    // EXEC replies nil when watched key was changed
+    assert_eq!(connection.query(&["WATCH", "a"]).unwrap(), Message::simple_string("OK"));
+    other.set("a", "changed").unwrap();
+    let aborted = Pipeline::new().atomic().cmd(&["SET", "a", "2"]).query(&mut connection);
+    assert!(matches!(aborted, Err(Error::TransactionAborted)));
+    assert_eq!(connection.get("a").unwrap(), Some(b"changed".to_vec()));
+
+    // command refused while queued discards the transaction, every reply is still read
+    let discarded = Pipeline::new().atomic().cmd(&["SET", "a", "3"]).cmd(&["UNKNOWN"]).query(&mut connection);
+    assert!(matches!(discarded, Err(Error::Server(err)) if err.starts_with("EXECABORT")));
+    assert_eq!(connection.ping().unwrap(), "PONG");
+    assert_eq!(connection.get("a").unwrap(), Some(b"changed".to_vec()));
+}
+
+#[test]
+fn subscriber() {
+    let server = start_server("subscriber");
+    let mut subscriber = Subscriber::new(Connection::connect(server.local_addr().unwrap()).unwrap());
+    let mut publisher = Connection::connect(server.local_addr().unwrap()).unwrap();
+
+    subscriber.subscribe(&["news", "sports"]).unwrap();
+    // message published before PSUBSCRIBE is confirmed is kept for `next_message`
+    assert_eq!(publisher.publish("news", "early").unwrap(), 1);
+    subscriber.psubscribe(&["n*"]).unwrap();
+    assert_eq!(publisher.publish("news", "late").unwrap(), 2);
+    subscriber.unsubscribe::<&str>(&[]).unwrap();
+    assert_eq!(publisher.publish("news", "pattern only").unwrap(), 1);
+    assert_eq!(publisher.publish("sports", "unsubscribed").unwrap(), 0);
+
+    let message = |channel: &str, pattern: Option<&str>, payload: &str| PubSubMessage {
+        channel: channel.as_bytes().to_vec(),
+        pattern: pattern.map(|pattern| pattern.as_bytes().to_vec()),
+        payload: payload.as_bytes().to_vec(),
+    };
+    assert_eq!(subscriber.next_message().unwrap(), message("news", None, "early"));
+    assert_eq!(subscriber.next_message().unwrap(), message("news", None, "late"));
+    assert_eq!(subscriber.next_message().unwrap(), message("news", Some("n*"), "late"));
+    assert_eq!(subscriber.next_message().unwrap(), message("news", Some("n*"), "pattern only"));
+
+    subscriber.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    assert!(matches!(subscriber.next_message(), Err(Error::Io(_))));
+    // connection leaves subscribed state after the last subscription is gone
+    subscriber.punsubscribe::<&str>(&[]).unwrap();
+    let mut connection = subscriber.into_connection();
+    assert!(!connection.is_broken());
+    assert_eq!(connection.ping().unwrap(), "PONG");
+}
+
+#[test]
+fn pool_shared_between_threads() {
+    let server = start_server("pool");
+    let pool = Pool::builder(&server.local_addr().unwrap().to_string()).max_size(4).build();
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let pool = pool.clone();
+            thread::spawn(move || {
+                for _ in 0..50 {
+                    pool.get().unwrap().incr("counter").unwrap();
+                }
+            })
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap();
+    }
+    assert_eq!(pool.get().unwrap().get("counter").unwrap(), Some(b"400".to_vec()));
+    assert!(pool.size() <= 4);
+    assert_eq!(pool.idle(), pool.size());
+
+    // all connections are checked out
+    let pool = Pool::builder(&server.local_addr().unwrap().to_string()).max_size(1).timeout(Duration::from_millis(50)).build();
+    let connection = pool.get().unwrap();
+    assert!(matches!(pool.get(), Err(Error::PoolTimeout)));
+    drop(connection);
+    assert!(pool.get().is_ok());
+}
+
+#[test]
+fn pool_replaces_broken_connections() {
+    let server = start_server("pool_reconnect");
+    let pool = Pool::builder(&server.local_addr().unwrap().to_string()).max_size(2).health_check_interval(Duration::ZERO).build();
+    {
+        let (mut first, mut second) = (pool.get().unwrap(), pool.get().unwrap());
+        first.set("key", "value").unwrap();
+        second.ping().unwrap();
+    }
+    assert_eq!(pool.idle(), 2);
+
+    let mut killer = Connection::connect(server.local_addr().unwrap()).unwrap();
+    assert_eq!(killer.query(&["CLIENT", "KILL", "USER", "default"]).unwrap(), Message::Integer(2));
+    wait_for_clients(&mut killer, 1);
+
+    // idle connections fail health check and new ones are opened
+    let mut connection = pool.get().unwrap();
+    assert_eq!(connection.get("key").unwrap(), Some(b"value".to_vec()));
+    drop(connection);
+    assert_eq!(pool.size(), 1);
+
+    // connection broken while checked out is not returned to the pool
+    let mut connection = pool.get().unwrap();
+    killer.query(&["CLIENT", "KILL", "USER", "default"]).unwrap();
+    wait_for_clients(&mut killer, 1);
+    assert!(matches!(connection.ping(), Err(Error::Io(_))));
+    assert!(connection.is_broken());
+    drop(connection);
+    assert_eq!(pool.size(), 0);
+    assert_eq!(pool.get().unwrap().ping().unwrap(), "PONG");
+}
+
+#[test]
+fn pool_drops_timed_out_connections() {
+    let server = start_server("pool_timeout");
+    let pool = Pool::builder(&server.local_addr().unwrap().to_string()).max_size(1).build();
+
+    // WAIT without replicas replies after its timeout, the reply is still in flight when the read times out
+    let mut connection = pool.get().unwrap();
+    connection.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    assert!(matches!(connection.query(&["WAIT", "1", "300"]), Err(Error::Io(_))));
+    assert!(connection.is_broken());
+    drop(connection);
+    assert_eq!(pool.size(), 0);
+
+    // next borrower gets its own reply instead of the one to WAIT
+    let mut connection = pool.get().unwrap();
+    assert_eq!(connection.query(&["ECHO", "own reply"]).unwrap(), Message::bulk_string("own reply"));
+    drop(connection);
+
+    let mut connection = pool.get().unwrap();
+    connection.set_read_timeout(Some(Duration::from_millis(50))).unwrap();
+    let timed_out = Pipeline::new().cmd(&["PING"]).cmd(&["WAIT", "1", "300"]).query(&mut connection);
+    assert!(matches!(timed_out, Err(Error::Io(_))));
+    assert!(connection.is_broken());
+    drop(connection);
+    assert_eq!(pool.get().unwrap().ping().unwrap(), "PONG");
+}
@@ -0,0 +1,555 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    fs,
+    sync::{Mutex, RwLock},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{glob::glob_match, message_processor::{commands, now}, resp::message::Message};
+
+const ACL_LOG_MAX_LEN: usize = 128;
+
+pub const CATEGORIES: [&str; 14] = [
+    "keyspace", "read", "write", "string", "list", "fast", "slow", "admin", "dangerous", "connection", "pubsub", "scripting", "transaction", "all",
+];
+
+pub fn command_has_category(command: &str, subcommand: Option<&str>, category: &str) -> bool {
+    commands::find(command, subcommand).is_some_and(|spec| spec.has_category(category))
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |difference, (a, b)| difference | (a ^ b)) == 0
+}
+
+pub fn hash_password(password: &str) -> String {
+    Sha256::digest(password.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    enabled: bool,
+    nopass: bool,
+    // sha256 hex digests
+    passwords: BTreeSet<String>,
+    // `+@category`, `-@category`, `+command`, `-command|subcommand` applied in order on top of `-@all`
+    command_rules: Vec<String>,
+    all_keys: bool,
+    key_patterns: Vec<String>,
+    all_channels: bool,
+    channel_patterns: Vec<String>,
+}
+
+impl User {
+    fn new(name: &str) -> User {
+        User {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            command_rules: Vec::new(),
+            all_keys: false,
+            key_patterns: Vec::new(),
+            all_channels: false,
+            channel_patterns: Vec::new(),
+        }
+    }
+
+    fn default_user() -> User {
+        let mut user = User::new("default");
+        for rule in ["on", "nopass", "allkeys", "allchannels", "allcommands"] {
+            user.apply_rule(rule).expect("Default rules are valid");
+        }
+        user
+    }
+
+    pub fn apply_rule(&mut self, rule: &str) -> Result<(), String> {
+        match rule.to_lowercase().as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => {
+                self.all_keys = true;
+                self.key_patterns.clear();
+            }
+            "resetkeys" => {
+                self.all_keys = false;
+                self.key_patterns.clear();
+            }
+            "allchannels" | "&*" => {
+                self.all_channels = true;
+                self.channel_patterns.clear();
+            }
+            "resetchannels" => {
+                self.all_channels = false;
+                self.channel_patterns.clear();
+            }
+            "allcommands" => self.command_rules = vec!["+@all".to_string()],
+            "nocommands" => self.command_rules.clear(),
+            "reset" => *self = User::new(&self.name),
+            _ => return self.apply_value_rule(rule),
+        }
+        Ok(())
+    }
+
+    fn apply_value_rule(&mut self, rule: &str) -> Result<(), String> {
+        let (prefix, value) = rule.split_at(rule.chars().next().map_or(0, char::len_utf8));
+        match prefix {
+            ">" => {
+                self.nopass = false;
+                self.passwords.insert(hash_password(value));
+            }
+            "<" => {
+                if !self.passwords.remove(&hash_password(value)) {
+                    return Err("no such password".to_string());
+                }
+            }
+            "#" => {
+                if value.len() != 64 || !value.chars().all(|c| c.is_ascii_hexdigit()) {
+                    return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".to_string());
+                }
+                self.nopass = false;
+                self.passwords.insert(value.to_lowercase());
+            }
+            "!" => {
+                if !self.passwords.remove(&value.to_lowercase()) {
+                    return Err("no such password".to_string());
+                }
+            }
+            "~" => {
+                if !self.all_keys {
+                    self.key_patterns.push(value.to_string());
+                }
+            }
+            "&" => {
+                if !self.all_channels {
+                    self.channel_patterns.push(value.to_string());
+                }
+            }
+            "+" | "-" => {
+                let name = value.to_lowercase();
+                let known = match name.strip_prefix('@') {
+                    Some(category) => CATEGORIES.contains(&category),
+                    None => {
+                        let (command, subcommand) = name.split_once('|').map_or((name.as_str(), None), |(c, s)| (c, Some(s)));
+                        commands::find(command, subcommand).is_some()
+                    }
+                };
+                if !known {
+                    return Err(format!("Unknown command or category name in ACL '{}'", rule));
+                }
+                if name == "@all" {
+                    self.command_rules.clear();
+                }
+                self.command_rules.push(format!("{}{}", prefix, name));
+            }
+            _ => return Err(format!("Syntax error in ACL rule '{}'", rule)),
+        }
+        Ok(())
+    }
+
+    pub fn check_password(&self, password: &str) -> bool {
+        let hash = hash_password(password);
+        // every stored hash is compared in full, so timing doesn't tell how much of the hash matched
+        let matched = self.passwords.iter().fold(false, |matched, stored| matched | constant_time_eq(stored.as_bytes(), hash.as_bytes()));
+        self.enabled && (self.nopass || matched)
+    }
+
+    pub fn can_run(&self, command: &str, subcommand: Option<&str>) -> bool {
+        let mut allowed = false;
+        for rule in &self.command_rules {
+            let (sign, name) = rule.split_at(1);
+            let matches = match name.strip_prefix('@') {
+                Some(category) => command_has_category(command, subcommand, category),
+                None => match name.split_once('|') {
+                    Some((rule_command, rule_subcommand)) => {
+                        rule_command == command && subcommand.is_some_and(|sub| sub.eq_ignore_ascii_case(rule_subcommand))
+                    }
+                    None => name == command,
+                },
+            };
+            if matches {
+                allowed = sign == "+";
+            }
+        }
+        allowed
+    }
+
+    pub fn can_access_key(&self, key: &[u8]) -> bool {
+        self.all_keys || self.key_patterns.iter().any(|pattern| glob_match(pattern.as_bytes(), key))
+    }
+
+    // Pattern of PSUBSCRIBE must be equal to one of user patterns, e.g. `&news:*` doesn't allow `news:*:eu`
+    pub fn can_access_channel(&self, channel: &[u8], is_pattern: bool) -> bool {
+        self.all_channels || self.channel_patterns.iter().any(|pattern| match is_pattern {
+            true => pattern.as_bytes() == channel,
+            false => glob_match(pattern.as_bytes(), channel),
+        })
+    }
+
+    fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![if self.enabled { "on" } else { "off" }];
+        if self.nopass {
+            flags.push("nopass");
+        }
+        flags
+    }
+
+    fn commands_description(&self) -> String {
+        match self.command_rules.first() {
+            Some(rule) if rule == "+@all" => self.command_rules.join(" "),
+            _ => std::iter::once("-@all".to_string()).chain(self.command_rules.iter().cloned()).collect::<Vec<_>>().join(" "),
+        }
+    }
+
+    fn keys_description(&self) -> String {
+        if self.all_keys {
+            return "~*".to_string();
+        }
+        self.key_patterns.iter().map(|pattern| format!("~{}", pattern)).collect::<Vec<_>>().join(" ")
+    }
+
+    fn channels_description(&self) -> String {
+        if self.all_channels {
+            return "&*".to_string();
+        }
+        self.channel_patterns.iter().map(|pattern| format!("&{}", pattern)).collect::<Vec<_>>().join(" ")
+    }
+
+    // line of ACL LIST and users file
+    pub fn describe(&self) -> String {
+        let mut parts = vec![format!("user {}", self.name)];
+        parts.extend(self.flags().iter().map(|flag| flag.to_string()));
+        parts.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        parts.push(self.keys_description());
+        parts.push(self.channels_description());
+        parts.push(self.commands_description());
+        parts.retain(|part| !part.is_empty());
+        parts.join(" ")
+    }
+
+    // response of ACL GETUSER
+    pub fn to_message(&self) -> Message {
+        Message::array(vec![
+            Message::bulk_string("flags"),
+            Message::array(self.flags().iter().map(|flag| Message::bulk_string(flag)).collect()),
+            Message::bulk_string("passwords"),
+            Message::array(self.passwords.iter().map(|hash| Message::bulk_string(hash)).collect()),
+            Message::bulk_string("commands"),
+            Message::bulk_string(&self.commands_description()),
+            Message::bulk_string("keys"),
+            Message::bulk_string(&self.keys_description()),
+            Message::bulk_string("channels"),
+            Message::bulk_string(&self.channels_description()),
+        ])
+    }
+}
+
+pub struct AclLogEntry {
+    pub count: u64,
+    pub reason: String,
+    pub object: String,
+    pub username: String,
+    pub client_info: String,
+    pub entry_id: u64,
+    pub created_at: u128,
+    pub updated_at: u128,
+}
+
+impl AclLogEntry {
+    pub fn to_message(&self) -> Message {
+        let age_seconds = now().saturating_sub(self.created_at) as f64 / 1000.0;
+        Message::array(vec![
+            Message::bulk_string("count"),
+            Message::Integer(self.count as i64),
+            Message::bulk_string("reason"),
+            Message::bulk_string(&self.reason),
+            Message::bulk_string("context"),
+            Message::bulk_string("toplevel"),
+            Message::bulk_string("object"),
+            Message::bulk_string(&self.object),
+            Message::bulk_string("username"),
+            Message::bulk_string(&self.username),
+            Message::bulk_string("age-seconds"),
+            Message::bulk_string(&format!("{:.3}", age_seconds)),
+            Message::bulk_string("client-info"),
+            Message::bulk_string(&self.client_info),
+            Message::bulk_string("entry-id"),
+            Message::Integer(self.entry_id as i64),
+            Message::bulk_string("timestamp-created"),
+            Message::Integer(self.created_at as i64),
+            Message::bulk_string("timestamp-last-updated"),
+            Message::Integer(self.updated_at as i64),
+        ])
+    }
+}
+
+// Users and their permissions, checked before every command of connected clients.
+pub struct Acl {
+    users: RwLock<BTreeMap<String, User>>,
+    log: Mutex<VecDeque<AclLogEntry>>,
+    next_log_entry_id: Mutex<u64>,
+    pub aclfile: Option<String>,
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        let mut users = BTreeMap::new();
+        users.insert("default".to_string(), User::default_user());
+        Self {
+            users: RwLock::new(users),
+            log: Mutex::new(VecDeque::new()),
+            next_log_entry_id: Mutex::new(0),
+            aclfile: None,
+        }
+    }
+}
+
+impl Acl {
+    pub fn new(requirepass: Option<&str>, aclfile: Option<&str>) -> Result<Acl, String> {
+        let acl = Acl { aclfile: aclfile.map(String::from), ..Acl::default() };
+        if let Some(password) = requirepass {
+            acl.set_user("default", &["resetpass".to_string(), format!(">{}", password)])?;
+        }
+        if acl.aclfile.is_some() {
+            acl.load()?;
+        }
+        Ok(acl)
+    }
+
+    pub fn get_user(&self, name: &str) -> Option<User> {
+        self.users.read().expect("ACL lock poisoned").get(name).cloned()
+    }
+
+    pub fn users(&self) -> Vec<User> {
+        self.users.read().expect("ACL lock poisoned").values().cloned().collect()
+    }
+
+    // rules are applied atomically, user is not changed if any of rules is invalid
+    pub fn set_user(&self, name: &str, rules: &[String]) -> Result<(), String> {
+        let mut users = self.users.write().expect("ACL lock poisoned");
+        let mut user = users.get(name).cloned().unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.apply_rule(rule).map_err(|err| format!("Error in ACL SETUSER modifier '{}': {}", rule, err))?;
+        }
+        users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    pub fn delete_user(&self, name: &str) -> Result<bool, String> {
+        if name == "default" {
+            return Err("The 'default' user cannot be removed".to_string());
+        }
+        Ok(self.users.write().expect("ACL lock poisoned").remove(name).is_some())
+    }
+
+    pub fn authenticate(&self, username: &str, password: &str) -> bool {
+        self.get_user(username).is_some_and(|user| user.check_password(password))
+    }
+
+    // new connections are authenticated as default user when it doesn't require password
+    pub fn default_user_requires_auth(&self) -> bool {
+        !self.get_user("default").is_some_and(|user| user.enabled && user.nopass)
+    }
+
+    pub fn add_log_entry(&self, reason: &str, object: &str, username: &str, client_info: &str) {
+        let timestamp = now();
+        let mut log = self.log.lock().expect("ACL lock poisoned");
+        // similar entries are grouped like in Redis
+        if let Some(entry) = log.iter_mut().find(|entry| entry.reason == reason && entry.object == object && entry.username == username) {
+            entry.count += 1;
+            entry.updated_at = timestamp;
+            entry.client_info = client_info.to_string();
+            return;
+        }
+        let mut next_id = self.next_log_entry_id.lock().expect("ACL lock poisoned");
+        log.push_front(AclLogEntry {
+            count: 1,
+            reason: reason.to_string(),
+            object: object.to_string(),
+            username: username.to_string(),
+            client_info: client_info.to_string(),
+            entry_id: *next_id,
+            created_at: timestamp,
+            updated_at: timestamp,
+        });
+        *next_id += 1;
+        log.truncate(ACL_LOG_MAX_LEN);
+    }
+
+    pub fn log_messages(&self, count: usize) -> Vec<Message> {
+        self.log.lock().expect("ACL lock poisoned").iter().take(count).map(AclLogEntry::to_message).collect()
+    }
+
+    pub fn reset_log(&self) {
+        self.log.lock().expect("ACL lock poisoned").clear();
+    }
+
+    // users file format: `user <name> <rule> <rule>...` per line
+    pub fn load(&self) -> Result<(), String> {
+        let path = self.aclfile.as_ref().ok_or("This instance is not configured to use an ACL file")?;
+        let content = fs::read_to_string(path).map_err(|err| format!("Cannot read ACL file {}: {}", path, err))?;
+
+        let loaded = Acl::default();
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let mut words = line.split_whitespace();
+            if words.next() != Some("user") {
+                return Err(format!("{}:{}: should start with user keyword", path, line_number + 1));
+            }
+            let name = words.next().ok_or(format!("{}:{}: expected user name", path, line_number + 1))?;
+            let rules: Vec<String> = words.map(String::from).collect();
+            // users from file are defined from scratch
+            let mut user = User::new(name);
+            for rule in &rules {
+                user.apply_rule(rule).map_err(|err| format!("{}:{}: {}", path, line_number + 1, err))?;
+            }
+            loaded.users.write().expect("ACL lock poisoned").insert(name.to_string(), user);
+        }
+
+        let loaded_users = loaded.users.into_inner().expect("ACL lock poisoned");
+        *self.users.write().expect("ACL lock poisoned") = loaded_users;
+        Ok(())
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = self.aclfile.as_ref().ok_or("This instance is not configured to use an ACL file")?;
+        let content: String = self.users().iter().map(|user| format!("{}\n", user.describe())).collect();
+        fs::write(path, content).map_err(|err| format!("Cannot write ACL file {}: {}", path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(rules: &str) -> Vec<String> {
+        rules.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn default_user_allows_everything() {
+        let acl = Acl::default();
+        let user = acl.get_user("default").unwrap();
+        assert!(user.check_password("anything"));
+        assert!(user.can_run("set", None));
+        assert!(user.can_access_key(b"foo"));
+        assert_eq!(user.describe(), "user default on nopass ~* &* +@all");
+        assert!(!acl.default_user_requires_auth());
+    }
+
+    #[test]
+    fn requirepass() {
+        let acl = Acl::new(Some("secret"), None).unwrap();
+        assert!(acl.default_user_requires_auth());
+        assert!(acl.authenticate("default", "secret"));
+        assert!(!acl.authenticate("default", "wrong"));
+    }
+
+    #[test]
+    fn user_permissions() {
+        let acl = Acl::default();
+        acl.set_user("alice", &rules("on >pass ~cached:* +@read +set -exists")).unwrap();
+        let user = acl.get_user("alice").unwrap();
+
+        assert!(acl.authenticate("alice", "pass"));
+        assert!(user.can_run("get", None));
+        assert!(user.can_run("set", None));
+        assert!(!user.can_run("exists", None));
+        assert!(!user.can_run("del", None));
+        assert!(user.can_access_key(b"cached:1"));
+        assert!(!user.can_access_key(b"other"));
+        assert_eq!(
+            user.describe(),
+            format!("user alice on #{} ~cached:* -@all +@read +set -exists", hash_password("pass"))
+        );
+    }
+
+    #[test]
+    fn subcommand_permissions() {
+        let acl = Acl::default();
+        acl.set_user("bob", &rules("on nopass +@all -@dangerous")).unwrap();
+        let user = acl.get_user("bob").unwrap();
+
+        assert!(user.can_run("client", Some("id")));
+        assert!(!user.can_run("client", Some("kill")));
+    }
+
+    #[test]
+    fn invalid_rules_do_not_change_user() {
+        let acl = Acl::default();
+        acl.set_user("alice", &rules("on")).unwrap();
+        assert!(acl.set_user("alice", &rules("+get +unknown_command")).is_err());
+        assert!(!acl.get_user("alice").unwrap().can_run("get", None));
+        assert!(acl.set_user("alice", &rules("+client|bogus")).is_err());
+        acl.set_user("alice", &rules("+client|kill &*")).unwrap();
+        assert!(acl.get_user("alice").unwrap().can_run("client", Some("kill")));
+    }
+
+    #[test]
+    fn channel_permissions() {
+        let acl = Acl::default();
+        acl.set_user("alice", &rules("on nopass +@all resetchannels &news:* &sports")).unwrap();
+        let user = acl.get_user("alice").unwrap();
+
+        assert!(user.can_access_channel(b"news:eu", false));
+        assert!(user.can_access_channel(b"sports", false));
+        assert!(!user.can_access_channel(b"weather", false));
+        assert!(user.can_access_channel(b"news:*", true));
+        assert!(!user.can_access_channel(b"news:eu:*", true));
+        assert_eq!(user.describe(), "user alice on nopass &news:* &sports +@all");
+
+        acl.set_user("alice", &rules("allchannels &ignored")).unwrap();
+        assert!(acl.get_user("alice").unwrap().can_access_channel(b"weather", true));
+        assert_eq!(acl.get_user("alice").unwrap().describe(), "user alice on nopass &* +@all");
+        acl.set_user("alice", &rules("resetchannels")).unwrap();
+        assert!(!acl.get_user("alice").unwrap().can_access_channel(b"news:eu", false));
+    }
+
+    #[test]
+    fn disabled_user_cannot_authenticate() {
+        let acl = Acl::default();
+        acl.set_user("alice", &rules("off >pass")).unwrap();
+        assert!(!acl.authenticate("alice", "pass"));
+    }
+
+    #[test]
+    fn log_groups_similar_entries() {
+        let acl = Acl::default();
+        acl.add_log_entry("command", "set", "alice", "id=1");
+        acl.add_log_entry("command", "set", "alice", "id=2");
+        acl.add_log_entry("key", "foo", "alice", "id=2");
+        let log = acl.log.lock().unwrap();
+        assert_eq!(log.len(), 2);
+        assert_eq!(log[1].count, 2);
+    }
+
+    #[test]
+    fn load_and_save_users_file() {
+        let path = std::env::temp_dir().join(format!("ccredis_acl_test_{}.acl", std::process::id()));
+        fs::write(&path, "# users\nuser default on nopass ~* &* +@all\nuser alice on >pass ~app:* -@all +get\n").unwrap();
+
+        let acl = Acl::new(None, path.to_str()).unwrap();
+        assert!(acl.authenticate("alice", "pass"));
+        assert!(acl.get_user("alice").unwrap().can_run("get", None));
+
+        acl.set_user("bob", &rules("on >secret")).unwrap();
+        acl.save().unwrap();
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(saved.lines().count(), 3);
+        assert!(saved.contains(&format!("user bob on #{} -@all", hash_password("secret"))));
+    }
+}
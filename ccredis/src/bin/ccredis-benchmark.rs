@@ -0,0 +1,340 @@
+// Load generator in the spirit of redis-benchmark: every client is a thread with its own connection,
+// requests are shared between clients and latency of every request is recorded.
+use std::{
+    env,
+    io::{self, BufReader, Read, Write},
+    net::TcpStream,
+    process,
+    sync::{atomic::{AtomicU64, Ordering}, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use ccredis::resp::{message::Message, message_parser::MessageParser};
+use rand::Rng;
+
+const USAGE: &str = "Usage: ccredis-benchmark [OPTIONS]
+  -h <hostname>      Server hostname (default 127.0.0.1)
+  -p <port>          Server port (default 6379)
+  -a <password>      Password for AUTH
+  -c <clients>       Number of parallel connections (default 50)
+  -n <requests>      Total number of requests (default 100000)
+  -d <size>          Data size of SET/GET value in bytes (default 3)
+  -P <numreq>        Pipeline <numreq> requests (default 1, no pipeline)
+  -r <keyspacelen>   Use random keys from 0 to keyspacelen-1 instead of a single key
+  -t <tests>         Only run the comma separated list of tests, e.g. -t set,get
+                     Available: ping, set, get, incr, lpush, lrange, mset
+  -q                 Quiet, just show query/sec values
+  --csv              Output in CSV format
+  --help             Output this help and exit";
+
+const TESTS: [&str; 7] = ["ping", "set", "get", "incr", "lpush", "lrange", "mset"];
+// keys set by one MSET
+const MSET_KEYS: usize = 10;
+
+struct Options {
+    host: String,
+    port: u16,
+    password: Option<String>,
+    clients: usize,
+    requests: u64,
+    data_size: usize,
+    pipeline: usize,
+    keyspace: Option<u64>,
+    tests: Vec<String>,
+    quiet: bool,
+    csv: bool,
+}
+
+// Result of one test, latencies are in microseconds and only of successful requests
+struct Report {
+    name: &'static str,
+    elapsed: Duration,
+    latencies: Vec<u64>,
+    errors: u64,
+}
+
+impl Report {
+    fn requests_per_second(&self) -> f64 {
+        self.latencies.len() as f64 / self.elapsed.as_secs_f64()
+    }
+
+    // latencies must be sorted
+    fn percentile(&self, percent: f64) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        // rounded before ceil, 99.9% of 1000 is 999.0000000000001 in floating point
+        let rank = (((percent / 100.0 * self.latencies.len() as f64) * 1e6).round() / 1e6).ceil() as usize;
+        let rank = rank.clamp(1, self.latencies.len());
+        millis(self.latencies[rank - 1])
+    }
+
+    fn average(&self) -> f64 {
+        if self.latencies.is_empty() {
+            return 0.0;
+        }
+        millis(self.latencies.iter().sum::<u64>()) / self.latencies.len() as f64
+    }
+
+    fn min(&self) -> f64 {
+        millis(self.latencies.first().copied().unwrap_or(0))
+    }
+
+    fn max(&self) -> f64 {
+        millis(self.latencies.last().copied().unwrap_or(0))
+    }
+}
+
+fn millis(micros: u64) -> f64 {
+    micros as f64 / 1000.0
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        process::exit(1);
+    });
+    let options = Arc::new(options);
+
+    if options.csv {
+        println!("\"test\",\"rps\",\"avg_latency_ms\",\"min_latency_ms\",\"p50_latency_ms\",\"p99_latency_ms\",\"p999_latency_ms\",\"max_latency_ms\"");
+    }
+    for name in TESTS {
+        if !options.tests.is_empty() && !options.tests.iter().any(|test| test == name) {
+            continue;
+        }
+        match run_test(name, options.clone()) {
+            Ok(report) => print_report(&report, &options),
+            Err(err) => {
+                eprintln!("Could not connect to ccredis at {}:{}: {}", options.host, options.port, err);
+                process::exit(1);
+            },
+        }
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        host: "127.0.0.1".to_string(),
+        port: 6379,
+        password: None,
+        clients: 50,
+        requests: 100_000,
+        data_size: 3,
+        pipeline: 1,
+        keyspace: None,
+        tests: Vec::new(),
+        quiet: false,
+        csv: false,
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Option {} requires a value", name));
+        let invalid = |name: &str| format!("Invalid value of {}", name);
+        match arg.as_str() {
+            "-h" => options.host = value("-h")?,
+            "-p" => options.port = value("-p")?.parse().map_err(|_| invalid("-p"))?,
+            "-a" => options.password = Some(value("-a")?),
+            "-c" => options.clients = value("-c")?.parse().ok().filter(|clients| *clients > 0).ok_or_else(|| invalid("-c"))?,
+            "-n" => options.requests = value("-n")?.parse().map_err(|_| invalid("-n"))?,
+            "-d" => options.data_size = value("-d")?.parse().map_err(|_| invalid("-d"))?,
+            "-P" => options.pipeline = value("-P")?.parse().ok().filter(|pipeline| *pipeline > 0).ok_or_else(|| invalid("-P"))?,
+            "-r" => options.keyspace = Some(value("-r")?.parse().ok().filter(|keyspace| *keyspace > 0).ok_or_else(|| invalid("-r"))?),
+            "-t" => {
+                options.tests = value("-t")?.split(',').map(|test| test.trim().to_lowercase()).collect();
+                if let Some(test) = options.tests.iter().find(|test| !TESTS.contains(&test.as_str())) {
+                    return Err(format!("Unknown test '{}'", test));
+                }
+            },
+            "-q" => options.quiet = true,
+            "--csv" => options.csv = true,
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            _ => return Err(format!("Unrecognized option '{}'", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn run_test(name: &'static str, options: Arc<Options>) -> io::Result<Report> {
+    // requests are claimed by clients in batches of pipeline size until all are sent
+    let claimed = Arc::new(AtomicU64::new(0));
+    let errors = Arc::new(AtomicU64::new(0));
+    let connections = (0..options.clients).map(|_| connect(&options)).collect::<io::Result<Vec<_>>>()?;
+
+    let started_at = Instant::now();
+    let handles: Vec<_> = connections
+        .into_iter()
+        .map(|stream| {
+            let (options, claimed, errors) = (options.clone(), claimed.clone(), errors.clone());
+            thread::spawn(move || run_client(stream, name, &options, &claimed, &errors))
+        })
+        .collect();
+    let mut latencies = Vec::with_capacity(options.requests as usize);
+    for handle in handles {
+        latencies.extend(handle.join().expect("benchmark client panicked")?);
+    }
+    let elapsed = started_at.elapsed();
+    latencies.sort_unstable();
+    Ok(Report { name: test_title(name), elapsed, latencies, errors: errors.load(Ordering::Relaxed) })
+}
+
+fn connect(options: &Options) -> io::Result<(TcpStream, BufReader<TcpStream>)> {
+    let stream = TcpStream::connect((options.host.as_str(), options.port))?;
+    stream.set_nodelay(true)?;
+    let mut connection = (stream.try_clone()?, BufReader::new(stream));
+    if let Some(password) = &options.password {
+        request(&["AUTH", password]).write_to(&mut connection.0)?;
+        if let Message::Error(err) = read_reply(&mut connection.1, &mut MessageParser::new())? {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, err));
+        }
+    }
+    Ok(connection)
+}
+
+fn run_client((mut writer, mut reader): (TcpStream, BufReader<TcpStream>), name: &str, options: &Options, claimed: &AtomicU64, errors: &AtomicU64) -> io::Result<Vec<u64>> {
+    let mut parser = MessageParser::new();
+    let mut latencies = Vec::new();
+    let value = "x".repeat(options.data_size);
+    loop {
+        let first = claimed.fetch_add(options.pipeline as u64, Ordering::Relaxed);
+        if first >= options.requests {
+            return Ok(latencies);
+        }
+        let count = (options.requests - first).min(options.pipeline as u64);
+
+        let mut batch = Vec::new();
+        for _ in 0..count {
+            workload(name, options, &value).write_to(&mut batch)?;
+        }
+        let sent_at = Instant::now();
+        writer.write_all(&batch)?;
+        for _ in 0..count {
+            match read_reply(&mut reader, &mut parser)? {
+                // errors don't count to throughput and latency, the first one of the test is shown
+                Message::Error(err) => {
+                    if errors.fetch_add(1, Ordering::Relaxed) == 0 {
+                        eprintln!("Error from server: {}", err);
+                    }
+                },
+                _ => latencies.push(sent_at.elapsed().as_micros() as u64),
+            }
+        }
+    }
+}
+
+fn workload(name: &str, options: &Options, value: &str) -> Message {
+    let key = |prefix: &str| match options.keyspace {
+        Some(keyspace) => format!("{}:{:012}", prefix, rand::thread_rng().gen_range(0..keyspace)),
+        None => format!("{}:__rand_int__", prefix),
+    };
+    match name {
+        "ping" => request(&["PING"]),
+        "set" => request(&["SET", &key("key"), value]),
+        "get" => request(&["GET", &key("key")]),
+        "incr" => request(&["INCR", &key("counter")]),
+        "lpush" => request(&["LPUSH", "mylist", value]),
+        "lrange" => request(&["LRANGE", "mylist", "0", "99"]),
+        "mset" => {
+            let keys: Vec<String> = (0..MSET_KEYS).map(|_| key("key")).collect();
+            let mut args = vec!["MSET"];
+            for key in &keys {
+                args.extend([key.as_str(), value]);
+            }
+            request(&args)
+        },
+        _ => unreachable!("tests are validated by parse_options"),
+    }
+}
+
+// names as reported by redis-benchmark
+fn test_title(name: &str) -> &'static str {
+    match name {
+        "ping" => "PING_MBULK",
+        "set" => "SET",
+        "get" => "GET",
+        "incr" => "INCR",
+        "lpush" => "LPUSH",
+        "lrange" => "LRANGE_100 (first 100 elements)",
+        "mset" => "MSET (10 keys)",
+        _ => unreachable!("tests are validated by parse_options"),
+    }
+}
+
+fn request(args: &[&str]) -> Message {
+    Message::array(args.iter().map(|arg| Message::bulk_string(arg)).collect())
+}
+
+fn read_reply(reader: &mut BufReader<TcpStream>, parser: &mut MessageParser) -> io::Result<Message> {
+    for byte in reader.bytes() {
+        match parser.add_byte(byte?) {
+            Ok(Some(reply)) => return Ok(reply),
+            Ok(None) => {},
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Protocol error: {:?}", err))),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Server closed the connection"))
+}
+
+fn print_report(report: &Report, options: &Options) {
+    if options.csv {
+        println!(
+            "\"{}\",\"{:.2}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\",\"{:.3}\"",
+            report.name,
+            report.requests_per_second(),
+            report.average(),
+            report.min(),
+            report.percentile(50.0),
+            report.percentile(99.0),
+            report.percentile(99.9),
+            report.max()
+        );
+    } else if options.quiet {
+        println!("{}: {:.2} requests per second, p50={:.3} msec", report.name, report.requests_per_second(), report.percentile(50.0));
+    } else {
+        println!("====== {} ======", report.name);
+        println!("  {} requests completed in {:.2} seconds", report.latencies.len(), report.elapsed.as_secs_f64());
+        println!("  {} parallel clients", options.clients);
+        println!("  {} bytes payload", options.data_size);
+        if report.errors > 0 {
+            println!("  {} errors", report.errors);
+        }
+        println!();
+        println!("Summary:");
+        println!("  throughput summary: {:.2} requests per second", report.requests_per_second());
+        println!("  latency summary (msec):");
+        println!("  {:>9} {:>9} {:>9} {:>9} {:>9} {:>9}", "avg", "min", "p50", "p99", "p999", "max");
+        println!(
+            "  {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3} {:>9.3}\n",
+            report.average(),
+            report.min(),
+            report.percentile(50.0),
+            report.percentile(99.0),
+            report.percentile(99.9),
+            report.max()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let report = Report { name: "SET", elapsed: Duration::from_secs(2), latencies: (1..=1000).collect(), errors: 0 };
+        assert_eq!(report.requests_per_second(), 500.0);
+        assert_eq!(report.percentile(50.0), 0.5);
+        assert_eq!(report.percentile(99.0), 0.99);
+        assert_eq!(report.percentile(99.9), 0.999);
+        assert_eq!(report.percentile(100.0), 1.0);
+        assert_eq!(report.min(), 0.001);
+        assert_eq!(report.max(), 1.0);
+        assert_eq!(report.average(), 0.5005);
+
+        let empty = Report { name: "GET", elapsed: Duration::from_secs(1), latencies: Vec::new(), errors: 0 };
+        assert_eq!(empty.percentile(99.0), 0.0);
+    }
+}
@@ -0,0 +1,63 @@
+// Validates db file the way server loads it and optionally cuts off the damaged part, like redis-check-aof.
+use std::{env, fs, process, sync::Arc};
+
+use ccredis::{
+    config::Config,
+    db_file::{self, DbFile},
+    keyspace::Keyspace,
+};
+
+const USAGE: &str = "Usage: ccredis-check-db [--fix] <file>
+  --fix              Keep every complete command before the damage and drop the rest
+  --help             Output this help and exit";
+
+fn main() {
+    let mut fix = false;
+    let mut path = None;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--fix" => fix = true,
+            "--help" => {
+                println!("{}", USAGE);
+                return;
+            },
+            _ if path.is_none() && !arg.starts_with("--") => path = Some(arg),
+            _ => {
+                eprintln!("Unrecognized option or bad number of args for: '{}'\n{}", arg, USAGE);
+                process::exit(1);
+            },
+        }
+    }
+    let Some(path) = path else {
+        eprintln!("{}", USAGE);
+        process::exit(1);
+    };
+
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            eprintln!("Cannot open {}: {}", path, err);
+            process::exit(1);
+        },
+    };
+    let size = bytes.len();
+    let mut file = DbFile::parse(bytes);
+    // commands are run too, e.g. RPUSH to string key makes the file unloadable as well
+    db_file::replay(&mut file, Arc::new(Keyspace::default()), Arc::new(Config::default()));
+
+    let Some(corruption) = &file.corruption else {
+        println!("{}: OK, {} commands", path, file.records.len());
+        return;
+    };
+    println!("{}: Bad file format {}", path, corruption);
+    println!("{} commands ({} of {} bytes) before it are valid", file.records.len(), file.valid_len(), size);
+    if !fix {
+        println!("Run with --fix to keep them and drop the rest");
+        process::exit(1);
+    }
+    if let Err(err) = fs::write(&path, file.repaired()) {
+        eprintln!("Cannot write {}: {}", path, err);
+        process::exit(1);
+    }
+    println!("Successfully repaired, {} commands are kept", file.records.len());
+}
@@ -0,0 +1,318 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, IsTerminal, Read, Write},
+    path::PathBuf,
+};
+
+const HISTORY_MAX_LEN: usize = 1000;
+
+pub type Completion = fn(&str) -> Vec<String>;
+
+// Reads lines from terminal with history, tab completion and basic emacs-like key bindings.
+// When stdin is not a terminal, lines are read as they are.
+pub struct Editor {
+    history: Vec<String>,
+    history_file: Option<PathBuf>,
+    complete: Completion,
+}
+
+impl Editor {
+    pub fn new(history_file: Option<PathBuf>, complete: Completion) -> Editor {
+        let mut history: Vec<String> = history_file
+            .as_ref()
+            .and_then(|path| File::open(path).ok())
+            .map(|file| BufReader::new(file).lines().map_while(Result::ok).collect())
+            .unwrap_or_default();
+        history.drain(..history.len().saturating_sub(HISTORY_MAX_LEN));
+        Editor { history, history_file, complete }
+    }
+
+    pub fn add_history(&mut self, line: &str) {
+        if self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        if self.history.len() > HISTORY_MAX_LEN {
+            self.history.remove(0);
+        }
+        if let Some(path) = &self.history_file {
+            if let Ok(mut file) = OpenOptions::new().create(true).append(true).open(path) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+    }
+
+    // None on end of input, Ctrl-C or Ctrl-D on empty line
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        #[cfg(unix)]
+        if io::stdin().is_terminal() {
+            let _raw_mode = raw_mode::RawMode::enable()?;
+            return self.edit(prompt);
+        }
+
+        if io::stdin().is_terminal() {
+            print!("{}", prompt);
+            io::stdout().flush()?;
+        }
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+    }
+
+    fn edit(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let mut line = Line { prompt, chars: Vec::new(), cursor: 0 };
+        // position in history, history.len() is the line being edited
+        let mut index = self.history.len();
+        let mut edited = String::new();
+        line.refresh()?;
+
+        loop {
+            let Some(byte) = read_byte()? else {
+                return Ok(None);
+            };
+            match byte {
+                b'\r' | b'\n' => {
+                    print!("\r\n");
+                    io::stdout().flush()?;
+                    return Ok(Some(line.text()));
+                },
+                // Ctrl-C
+                3 => {
+                    print!("^C\r\n");
+                    return Ok(None);
+                },
+                // Ctrl-D
+                4 if line.chars.is_empty() => {
+                    print!("\r\n");
+                    return Ok(None);
+                },
+                4 => line.delete(),
+                127 | 8 => line.backspace(),
+                b'\t' => self.complete(&mut line)?,
+                // Ctrl-A, Ctrl-E, Ctrl-B, Ctrl-F
+                1 => line.cursor = 0,
+                5 => line.cursor = line.chars.len(),
+                2 => line.cursor = line.cursor.saturating_sub(1),
+                6 => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                // Ctrl-U, Ctrl-K
+                21 => line.set(""),
+                11 => line.chars.truncate(line.cursor),
+                // Ctrl-L
+                12 => print!("\x1b[H\x1b[2J"),
+                // Ctrl-P, Ctrl-N
+                16 => self.previous(&mut line, &mut index, &mut edited),
+                14 => self.next(&mut line, &mut index, &edited),
+                // escape sequences of arrows, Home, End and Delete
+                27 => match (read_byte()?, read_byte()?) {
+                    (Some(b'['), Some(b'A')) => self.previous(&mut line, &mut index, &mut edited),
+                    (Some(b'['), Some(b'B')) => self.next(&mut line, &mut index, &edited),
+                    (Some(b'['), Some(b'C')) => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                    (Some(b'['), Some(b'D')) => line.cursor = line.cursor.saturating_sub(1),
+                    (Some(b'['), Some(b'H')) | (Some(b'O'), Some(b'H')) => line.cursor = 0,
+                    (Some(b'['), Some(b'F')) | (Some(b'O'), Some(b'F')) => line.cursor = line.chars.len(),
+                    (Some(b'['), Some(b'3')) => {
+                        read_byte()?;
+                        line.delete();
+                    },
+                    _ => {},
+                },
+                byte if byte >= 0x20 => {
+                    if let Some(char) = read_char(byte)? {
+                        line.chars.insert(line.cursor, char);
+                        line.cursor += 1;
+                    }
+                },
+                _ => {},
+            }
+            line.refresh()?;
+        }
+    }
+
+    fn previous(&self, line: &mut Line, index: &mut usize, edited: &mut String) {
+        if *index == 0 {
+            return;
+        }
+        if *index == self.history.len() {
+            *edited = line.text();
+        }
+        *index -= 1;
+        line.set(&self.history[*index]);
+    }
+
+    fn next(&self, line: &mut Line, index: &mut usize, edited: &str) {
+        if *index >= self.history.len() {
+            return;
+        }
+        *index += 1;
+        line.set(self.history.get(*index).map_or(edited, |entry| entry.as_str()));
+    }
+
+    // single candidate is completed, several are completed to common prefix or listed
+    fn complete(&self, line: &mut Line) -> io::Result<()> {
+        let candidates = (self.complete)(&line.text());
+        match candidates.as_slice() {
+            [] => print!("\x07"),
+            [candidate] => line.set(&format!("{} ", candidate)),
+            candidates => {
+                let prefix = common_prefix(candidates);
+                if prefix.chars().count() > line.chars.len() {
+                    line.set(&prefix);
+                } else {
+                    print!("\r\n{}\r\n", candidates.join("  "));
+                }
+            },
+        }
+        io::stdout().flush()
+    }
+}
+
+struct Line<'a> {
+    prompt: &'a str,
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl Line<'_> {
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    fn set(&mut self, text: &str) {
+        self.chars = text.chars().collect();
+        self.cursor = self.chars.len();
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    fn delete(&mut self) {
+        if self.cursor < self.chars.len() {
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    // redraws the whole line and moves cursor to its position
+    fn refresh(&self) -> io::Result<()> {
+        let mut output = io::stdout().lock();
+        write!(output, "\r{}{}\x1b[0K\r", self.prompt, self.text())?;
+        let column = self.prompt.chars().count() + self.cursor;
+        if column > 0 {
+            write!(output, "\x1b[{}C", column)?;
+        }
+        output.flush()
+    }
+}
+
+fn common_prefix(candidates: &[String]) -> String {
+    let first = &candidates[0];
+    let mut length = first.len();
+    for candidate in &candidates[1..] {
+        length = first.char_indices().zip(candidate.chars()).take_while(|((_, a), b)| a.eq_ignore_ascii_case(b)).last().map_or(0, |((index, char), _)| index + char.len_utf8()).min(length);
+    }
+    first[..length].to_string()
+}
+
+fn read_byte() -> io::Result<Option<u8>> {
+    let mut byte = [0u8];
+    match io::stdin().lock().read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
+
+// UTF-8 character starting with given byte
+fn read_char(first: u8) -> io::Result<Option<char>> {
+    let length = match first {
+        0xf0.. => 4,
+        0xe0.. => 3,
+        0xc0.. => 2,
+        _ => 1,
+    };
+    let mut bytes = vec![first];
+    for _ in 1..length {
+        match read_byte()? {
+            Some(byte) => bytes.push(byte),
+            None => return Ok(None),
+        }
+    }
+    Ok(std::str::from_utf8(&bytes).ok().and_then(|text| text.chars().next()))
+}
+
+#[cfg(unix)]
+mod raw_mode {
+    use std::{io, mem::MaybeUninit};
+
+    // Terminal without line buffering and echo, restored on drop
+    pub struct RawMode {
+        original: libc::termios,
+    }
+
+    impl RawMode {
+        pub fn enable() -> io::Result<RawMode> {
+            let mut termios = MaybeUninit::<libc::termios>::uninit();
+            // SAFETY: termios is initialized by tcgetattr when it succeeds
+            let original = unsafe {
+                if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                termios.assume_init()
+            };
+            let mut raw = original;
+            raw.c_iflag &= !(libc::BRKINT | libc::ICRNL | libc::INPCK | libc::ISTRIP | libc::IXON);
+            raw.c_oflag &= !libc::OPOST;
+            raw.c_cflag |= libc::CS8;
+            raw.c_lflag &= !(libc::ECHO | libc::ICANON | libc::IEXTEN | libc::ISIG);
+            raw.c_cc[libc::VMIN] = 1;
+            raw.c_cc[libc::VTIME] = 0;
+            // SAFETY: raw is a valid termios copied from the current one
+            if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &raw) } != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(RawMode { original })
+        }
+    }
+
+    impl Drop for RawMode {
+        fn drop(&mut self) {
+            // SAFETY: original is the termios read in enable
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSAFLUSH, &self.original);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_common_prefix() {
+        let candidates = ["CLIENT".to_string(), "CLUSTER".to_string()];
+        assert_eq!(common_prefix(&candidates), "CL");
+        let candidates = ["client KILL".to_string(), "client KILL".to_string()];
+        assert_eq!(common_prefix(&candidates), "client KILL");
+        let candidates = ["GET".to_string(), "SET".to_string()];
+        assert_eq!(common_prefix(&candidates), "");
+    }
+
+    #[test]
+    fn test_history() {
+        let path = std::env::temp_dir().join(format!("ccredis_cli_history_{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut editor = Editor::new(Some(path.clone()), |_| Vec::new());
+        editor.add_history("SET a 1");
+        editor.add_history("SET a 1");
+        editor.add_history("GET a");
+
+        let editor = Editor::new(Some(path.clone()), |_| Vec::new());
+        assert_eq!(editor.history, ["SET a 1", "GET a"]);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
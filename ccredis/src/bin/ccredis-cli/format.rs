@@ -0,0 +1,133 @@
+use ccredis::{message_processor::quote, resp::message::Message};
+
+// Splits line into arguments like redis-cli: "double quoted" strings support \n, \r, \t, \b, \a and \xhh escapes,
+// 'single quoted' strings only \'. None when quotes are not balanced.
+pub fn split_args(line: &str) -> Option<Vec<Vec<u8>>> {
+    let mut args = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.next_if(|char| char.is_whitespace()).is_some() {}
+        let Some(&first) = chars.peek() else {
+            return Some(args);
+        };
+
+        let mut arg: Vec<u8> = Vec::new();
+        if first == '"' || first == '\'' {
+            chars.next();
+            loop {
+                match (chars.next()?, first) {
+                    ('"', '"') | ('\'', '\'') => break,
+                    ('\\', '"') => match chars.next()? {
+                        'n' => arg.push(b'\n'),
+                        'r' => arg.push(b'\r'),
+                        't' => arg.push(b'\t'),
+                        'b' => arg.push(0x08),
+                        'a' => arg.push(0x07),
+                        'x' if chars.clone().take(2).all(|char| char.is_ascii_hexdigit()) => {
+                            let hex: String = chars.by_ref().take(2).collect();
+                            arg.push(u8::from_str_radix(&hex, 16).expect("hex digits are checked"));
+                        },
+                        char => push_char(&mut arg, char),
+                    },
+                    ('\\', '\'') if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        arg.push(b'\'');
+                    },
+                    (char, _) => push_char(&mut arg, char),
+                }
+            }
+            // closing quote must be followed by space
+            if chars.peek().is_some_and(|char| !char.is_whitespace()) {
+                return None;
+            }
+        } else {
+            while let Some(char) = chars.next_if(|char| !char.is_whitespace()) {
+                push_char(&mut arg, char);
+            }
+        }
+        args.push(arg);
+    }
+}
+
+fn push_char(arg: &mut Vec<u8>, char: char) {
+    arg.extend_from_slice(char.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+// Reply as printed by redis-cli to terminal, e.g. `(integer) 1`, `(nil)` or numbered array items
+pub fn human(reply: &Message) -> String {
+    match reply {
+        Message::SimpleString(status) => status.clone(),
+        Message::Error(err) => format!("(error) {}", err),
+        Message::Integer(integer) => format!("(integer) {}", integer),
+        Message::BulkString(Some(content)) => quote(content),
+        Message::BulkString(None) | Message::Array(None) => "(nil)".to_string(),
+        Message::Array(Some(items)) if items.is_empty() => "(empty array)".to_string(),
+        Message::Array(Some(items)) => {
+            let width = items.len().to_string().len();
+            let mut lines: Vec<String> = Vec::new();
+            for (index, item) in items.iter().enumerate() {
+                let prefix = format!("{:>width$}) ", index + 1);
+                // lines of nested arrays are aligned under the first one
+                for (line_index, line) in human(item).lines().enumerate() {
+                    let indent = if line_index == 0 { prefix.clone() } else { " ".repeat(prefix.len()) };
+                    lines.push(format!("{}{}", indent, line));
+                }
+            }
+            lines.join("\n")
+        },
+    }
+}
+
+// Reply as printed by redis-cli when output is not a terminal or with --raw
+pub fn raw(reply: &Message) -> String {
+    match reply {
+        Message::SimpleString(status) | Message::Error(status) => status.clone(),
+        Message::Integer(integer) => integer.to_string(),
+        Message::BulkString(Some(content)) => String::from_utf8_lossy(content).into_owned(),
+        Message::BulkString(None) | Message::Array(None) => String::new(),
+        Message::Array(Some(items)) => items.iter().map(raw).collect::<Vec<_>>().join("\n"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Option<Vec<String>> {
+        split_args(line).map(|args| args.into_iter().map(|arg| String::from_utf8_lossy(&arg).into_owned()).collect())
+    }
+
+    #[test]
+    fn test_split_args() {
+        assert_eq!(args("  SET  key value "), Some(vec!["SET".into(), "key".into(), "value".into()]));
+        assert_eq!(args(r#"SET "a key" "line\n\x41\"""#), Some(vec!["SET".into(), "a key".into(), "line\nA\"".into()]));
+        assert_eq!(args(r"SET 'it\'s' 'a\n'"), Some(vec!["SET".into(), "it's".into(), "a\\n".into()]));
+        assert_eq!(args(r#"SET "" ''"#), Some(vec!["SET".into(), "".into(), "".into()]));
+        assert_eq!(args(""), Some(vec![]));
+        assert_eq!(args(r#"SET "unbalanced"#), None);
+        assert_eq!(args(r#"SET "a"b"#), None);
+        assert_eq!(split_args(r#""\xff""#), Some(vec![vec![0xff]]));
+    }
+
+    #[test]
+    fn test_format_reply() {
+        assert_eq!(human(&Message::simple_string("OK")), "OK");
+        assert_eq!(human(&Message::error("ERR oops")), "(error) ERR oops");
+        assert_eq!(human(&Message::Integer(1)), "(integer) 1");
+        assert_eq!(human(&Message::bulk_string("a\"b\n")), r#""a\"b\n""#);
+        assert_eq!(human(&Message::BulkString(None)), "(nil)");
+        assert_eq!(human(&Message::array(vec![])), "(empty array)");
+
+        let nested = Message::array(vec![
+            Message::bulk_string("a"),
+            Message::array(vec![Message::Integer(1), Message::BulkString(None)]),
+        ]);
+        assert_eq!(human(&nested), "1) \"a\"\n2) 1) (integer) 1\n   2) (nil)");
+        let long = Message::array((0..10).map(Message::Integer).collect());
+        assert!(human(&long).starts_with(" 1) (integer) 0\n"));
+        assert!(human(&long).ends_with("\n10) (integer) 9"));
+
+        assert_eq!(raw(&nested), "a\n1\n");
+        assert_eq!(raw(&Message::bulk_string("a\"b")), "a\"b");
+    }
+}
@@ -0,0 +1,322 @@
+mod editor;
+mod format;
+
+use std::{
+    env,
+    io::{self, BufReader, BufWriter, IsTerminal, Read, Write},
+    net::TcpStream,
+    path::PathBuf,
+    process, thread,
+    time::Duration,
+};
+
+use ccredis::{
+    message_processor::commands,
+    resp::{message::Message, message_parser::MessageParser},
+};
+use rand::{distributions::Alphanumeric, Rng};
+
+use editor::Editor;
+
+const USAGE: &str = "Usage: ccredis-cli [OPTIONS] [cmd [arg [arg ...]]]
+  -h <hostname>      Server hostname (default: 127.0.0.1).
+  -p <port>          Server port (default: 6379).
+  -a <password>      Password to use when connecting to the server.
+  --user <username>  Used to send ACL style 'AUTH username pass'. Needs -a.
+  -r <repeat>        Execute specified command N times, -1 to repeat forever.
+  -i <interval>      When -r is used, waits <interval> seconds per command.
+                     It is possible to specify sub-second times like -i 0.1.
+  --pipe             Transfer raw RESP protocol from stdin to server.
+  --raw              Use raw formatting for replies.
+  --no-raw           Force formatted output even when STDOUT is not a tty.
+  --help             Output this help and exit.
+
+Examples:
+  ccredis-cli -p 7000 SET key value
+  ccredis-cli -r 100 -i 1 INFO
+  cat commands.txt | ccredis-cli --pipe
+  ccredis-cli (enters interactive mode)";
+
+struct Options {
+    host: String,
+    port: u16,
+    user: Option<String>,
+    password: Option<String>,
+    repeat: i64,
+    interval: Duration,
+    pipe: bool,
+    raw: bool,
+    command: Vec<String>,
+}
+
+fn main() {
+    let options = parse_options(env::args().skip(1)).unwrap_or_else(|err| {
+        eprintln!("{}\n\n{}", err, USAGE);
+        process::exit(1);
+    });
+
+    let mut connection = Connection::open(&options).unwrap_or_else(|err| {
+        eprintln!("Could not connect to ccredis at {}:{}: {}", options.host, options.port, err);
+        process::exit(1);
+    });
+
+    let result = if options.pipe {
+        pipe(connection)
+    } else if !options.command.is_empty() {
+        let args: Vec<Vec<u8>> = options.command.iter().map(|arg| arg.clone().into_bytes()).collect();
+        one_shot(&mut connection, &options, &args)
+    } else {
+        repl(connection, &options)
+    };
+    match result {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            process::exit(1);
+        },
+    }
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        host: "127.0.0.1".to_string(),
+        port: 6379,
+        user: None,
+        password: None,
+        repeat: 1,
+        interval: Duration::ZERO,
+        pipe: false,
+        raw: !io::stdout().is_terminal(),
+        command: Vec::new(),
+    };
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Option {} requires a value", name));
+        match arg.as_str() {
+            "-h" => options.host = value("-h")?,
+            "-p" => options.port = value("-p")?.parse().map_err(|_| "Invalid port".to_string())?,
+            "-a" | "--pass" => options.password = Some(value("-a")?),
+            "--user" => options.user = Some(value("--user")?),
+            "-r" => options.repeat = value("-r")?.parse().map_err(|_| "Invalid repeat count".to_string())?,
+            "-i" => {
+                let seconds: f64 = value("-i")?.parse().map_err(|_| "Invalid interval".to_string())?;
+                options.interval = Duration::try_from_secs_f64(seconds).map_err(|_| "Invalid interval".to_string())?;
+            },
+            "--pipe" => options.pipe = true,
+            "--raw" => options.raw = true,
+            "--no-raw" => options.raw = false,
+            "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            },
+            _ if arg.starts_with('-') && options.command.is_empty() => return Err(format!("Unrecognized option or bad number of args for: '{}'", arg)),
+            _ => {
+                options.command.push(arg);
+                // everything after the command name is its arguments
+                options.command.extend(args.by_ref());
+            },
+        }
+    }
+    Ok(options)
+}
+
+struct Connection {
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+    parser: MessageParser,
+}
+
+impl Connection {
+    fn open(options: &Options) -> io::Result<Connection> {
+        let stream = TcpStream::connect((options.host.as_str(), options.port))?;
+        stream.set_nodelay(true)?;
+        let reader = BufReader::new(stream.try_clone()?);
+        let mut connection = Connection { stream, reader, parser: MessageParser::new() };
+
+        if let Some(password) = &options.password {
+            let mut auth = vec![b"AUTH".to_vec()];
+            auth.extend(options.user.iter().map(|user| user.clone().into_bytes()));
+            auth.push(password.clone().into_bytes());
+            if let Message::Error(err) = connection.call(&auth)? {
+                eprintln!("AUTH failed: {}", err);
+            }
+        }
+        Ok(connection)
+    }
+
+    fn call(&mut self, args: &[Vec<u8>]) -> io::Result<Message> {
+        let request = Message::Array(Some(args.iter().map(|arg| Message::BulkString(Some(arg.clone()))).collect()));
+        request.write_to(&mut self.stream)?;
+        read_reply(&mut self.reader, &mut self.parser)
+    }
+}
+
+fn read_reply(reader: &mut BufReader<TcpStream>, parser: &mut MessageParser) -> io::Result<Message> {
+    for byte in reader.bytes() {
+        match parser.add_byte(byte?) {
+            Ok(Some(reply)) => return Ok(reply),
+            Ok(None) => {},
+            Err(err) => return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Protocol error: {:?}", err))),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "Server closed the connection"))
+}
+
+fn print_reply(reply: &Message, raw: bool) {
+    let output = if raw { format::raw(reply) } else { format::human(reply) };
+    println!("{}", output);
+}
+
+// runs command given in arguments, repeated according to -r and -i
+fn one_shot(connection: &mut Connection, options: &Options, args: &[Vec<u8>]) -> io::Result<i32> {
+    let mut code = 0;
+    let mut iteration = 0;
+    while options.repeat < 0 || iteration < options.repeat {
+        if iteration > 0 && !options.interval.is_zero() {
+            thread::sleep(options.interval);
+        }
+        let reply = connection.call(args)?;
+        if matches!(reply, Message::Error(_)) {
+            code = 1;
+        }
+        print_reply(&reply, options.raw);
+        iteration += 1;
+    }
+    Ok(code)
+}
+
+fn repl(mut connection: Connection, options: &Options) -> io::Result<i32> {
+    let history_file = env::var_os("CCREDIS_CLI_HISTFILE")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".ccredis_cli_history")));
+    let mut editor = Editor::new(history_file, complete);
+    let prompt = format!("{}:{}> ", options.host, options.port);
+
+    while let Some(line) = editor.read_line(&prompt)? {
+        let Some(mut args) = format::split_args(&line) else {
+            println!("Invalid argument(s)");
+            continue;
+        };
+        if args.is_empty() {
+            continue;
+        }
+        let name = String::from_utf8_lossy(&args[0]).to_lowercase();
+        // lines with passwords are not saved
+        if io::stdin().is_terminal() && name != "auth" && name != "acl" {
+            editor.add_history(&line);
+        }
+        if name == "quit" || name == "exit" {
+            break;
+        }
+
+        // "3 PING" sends PING three times
+        let mut repeat = 1;
+        if let Some(count) = std::str::from_utf8(&args[0]).ok().and_then(|count| count.parse::<u64>().ok()) {
+            if args.len() == 1 {
+                println!("Invalid argument(s)");
+                continue;
+            }
+            repeat = count;
+            args.remove(0);
+        }
+
+        for _ in 0..repeat {
+            let reply = match connection.call(&args) {
+                Ok(reply) => reply,
+                // connection is lost, e.g. after server restart or CLIENT KILL
+                Err(_) => match Connection::open(options) {
+                    Ok(reconnected) => {
+                        connection = reconnected;
+                        connection.call(&args)?
+                    },
+                    Err(err) => {
+                        println!("Could not connect to ccredis at {}:{}: {}", options.host, options.port, err);
+                        break;
+                    },
+                },
+            };
+            print_reply(&reply, options.raw);
+        }
+    }
+    Ok(0)
+}
+
+// candidates for the whole line: command names for the first word, subcommands for the second
+fn complete(line: &str) -> Vec<String> {
+    let words: Vec<&str> = line.split(' ').collect();
+    let names = commands::names();
+    match words.as_slice() {
+        [prefix] => names
+            .iter()
+            .filter(|(name, _)| name.starts_with(&prefix.to_lowercase()))
+            .map(|(name, _)| same_case(name, prefix))
+            .collect(),
+        [command, prefix] => names
+            .iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(command))
+            .flat_map(|(_, subcommands)| subcommands.iter())
+            .filter(|subcommand| subcommand.starts_with(&prefix.to_lowercase()))
+            .map(|subcommand| format!("{} {}", command, same_case(subcommand, prefix)))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+// names are completed in upper case unless user types in lower case
+fn same_case(name: &str, prefix: &str) -> String {
+    if !prefix.is_empty() && prefix.chars().all(|char| !char.is_ascii_uppercase()) {
+        name.to_string()
+    } else {
+        name.to_uppercase()
+    }
+}
+
+// Sends stdin to server as is, then waits until all replies are read.
+// ECHO with random marker is appended to know which reply is the last one.
+fn pipe(connection: Connection) -> io::Result<i32> {
+    let Connection { stream, mut reader, mut parser } = connection;
+    let marker: String = rand::thread_rng().sample_iter(&Alphanumeric).take(20).map(char::from).collect();
+
+    let counter = {
+        let marker = marker.clone();
+        thread::spawn(move || -> io::Result<(u64, u64)> {
+            let (mut replies, mut errors) = (0, 0);
+            loop {
+                match read_reply(&mut reader, &mut parser)? {
+                    Message::BulkString(Some(content)) if content == marker.as_bytes() => return Ok((replies, errors)),
+                    Message::Error(err) => {
+                        println!("{}", err);
+                        errors += 1;
+                    },
+                    _ => {},
+                }
+                replies += 1;
+            }
+        })
+    };
+
+    let mut writer = BufWriter::new(stream);
+    io::copy(&mut io::stdin().lock(), &mut writer)?;
+    Message::array(vec![Message::bulk_string("ECHO"), Message::bulk_string(&marker)]).write_to(&mut writer)?;
+    writer.flush()?;
+    println!("All data transferred. Waiting for the last reply...");
+
+    let (replies, errors) = counter.join().expect("pipe reader thread panicked")?;
+    println!("Last reply received from server.");
+    println!("errors: {}, replies: {}", errors, replies);
+    Ok(if errors > 0 { 1 } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete() {
+        assert_eq!(complete("clie"), ["client"]);
+        assert_eq!(complete("CLIE"), ["CLIENT"]);
+        assert!(complete("").contains(&"PING".to_string()));
+        assert_eq!(complete("client setn"), ["client setname"]);
+        assert_eq!(complete("CLIENT SETN"), ["CLIENT SETNAME"]);
+        assert_eq!(complete("get key "), Vec::<String>::new());
+    }
+}
@@ -0,0 +1,391 @@
+use std::{
+    collections::BTreeMap,
+    sync::{atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering}, Arc, Mutex, RwLock},
+};
+
+use tokio::sync::Notify;
+
+use crate::{
+    config::OutputBufferLimit,
+    connection::{Outbound, Outgoing},
+    message_processor::now,
+    resp::message::Message,
+    transaction::Transaction,
+};
+
+// There is only one database and no SELECT, CLIENT LIST and MONITOR always report db 0
+pub const DB: usize = 0;
+
+// Per-connection bookkeeping, shared between connection task and CLIENT commands of other connections.
+pub struct Client {
+    pub id: u64,
+    pub addr: String,
+    pub laddr: String,
+    pub created_at: u128,
+    name: Mutex<Option<String>>,
+    user: Mutex<String>,
+    authenticated: AtomicBool,
+    last_interaction: Mutex<u128>,
+    last_command: Mutex<String>,
+    pub query_buffer: AtomicUsize,
+    pub output_memory: AtomicUsize,
+    pub no_evict: AtomicBool,
+    // set by PSYNC, replies to replica are not sent, it receives replication stream instead
+    replica: AtomicBool,
+    // set by MONITOR, client receives every command processed by the server
+    monitor: AtomicBool,
+    // set by ASKING, allows next command to access importing cluster slot
+    asking: AtomicBool,
+    // started by MULTI
+    transaction: Mutex<Option<Transaction>>,
+    killed: AtomicBool,
+    kill_notify: Notify,
+    outbound: Outbound,
+    // normal and replica class limits, unlimited until connection sets them
+    output_limits: Mutex<(OutputBufferLimit, OutputBufferLimit)>,
+    // time when output memory went over soft limit
+    over_soft_limit_since: Mutex<Option<u128>>,
+    // queued replies are dropped, connection is closed without sending them
+    output_overflow: AtomicBool,
+}
+
+impl Client {
+    pub fn name(&self) -> Option<String> {
+        self.name.lock().expect("Client lock poisoned").clone()
+    }
+
+    pub fn set_name(&self, name: Option<String>) {
+        *self.name.lock().expect("Client lock poisoned") = name;
+    }
+
+    pub fn user(&self) -> String {
+        self.user.lock().expect("Client lock poisoned").clone()
+    }
+
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::SeqCst)
+    }
+
+    pub fn login(&self, user: &str) {
+        *self.user.lock().expect("Client lock poisoned") = user.to_string();
+        self.authenticated.store(true, Ordering::SeqCst);
+    }
+
+    pub fn touch(&self, command: &str) {
+        *self.last_interaction.lock().expect("Client lock poisoned") = now();
+        *self.last_command.lock().expect("Client lock poisoned") = command.to_string();
+    }
+
+    // milliseconds since last command
+    pub fn idle(&self) -> u128 {
+        now().saturating_sub(*self.last_interaction.lock().expect("Client lock poisoned"))
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.replica.load(Ordering::SeqCst)
+    }
+
+    pub fn set_replica(&self) {
+        self.replica.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_monitor(&self) -> bool {
+        self.monitor.load(Ordering::SeqCst)
+    }
+
+    pub fn set_asking(&self) {
+        self.asking.store(true, Ordering::SeqCst);
+    }
+
+    // ASKING flag is valid only for one command
+    pub fn take_asking(&self) -> bool {
+        self.asking.swap(false, Ordering::SeqCst)
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        self.transaction.lock().expect("Client lock poisoned").is_some()
+    }
+
+    // false when transaction is already started
+    pub fn begin_transaction(&self) -> bool {
+        let mut transaction = self.transaction.lock().expect("Client lock poisoned");
+        if transaction.is_some() {
+            return false;
+        }
+        *transaction = Some(Transaction::default());
+        true
+    }
+
+    pub fn queue_command(&self, parts: Vec<Message>, is_write: bool) {
+        if let Some(transaction) = self.transaction.lock().expect("Client lock poisoned").as_mut() {
+            transaction.commands.push(parts);
+            transaction.writes |= is_write;
+        }
+    }
+
+    // EXEC of transaction with writes is paused by CLIENT PAUSE WRITE
+    pub fn transaction_writes(&self) -> bool {
+        self.transaction.lock().expect("Client lock poisoned").as_ref().is_some_and(|transaction| transaction.writes)
+    }
+
+    // command refused while queued makes EXEC fail
+    pub fn refuse_transaction(&self) {
+        if let Some(transaction) = self.transaction.lock().expect("Client lock poisoned").as_mut() {
+            transaction.refused = true;
+        }
+    }
+
+    pub fn take_transaction(&self) -> Option<Transaction> {
+        self.transaction.lock().expect("Client lock poisoned").take()
+    }
+
+    // queues message to be sent by connection writer, false when connection is closed
+    pub fn send(&self, message: &Message) -> bool {
+        let mut buf: Vec<u8> = Vec::new();
+        message.write_to(&mut buf).expect("Writing to Vec can't fail");
+        self.send_raw(buf)
+    }
+
+    pub fn send_raw(&self, buf: Vec<u8>) -> bool {
+        if self.is_output_overflow() {
+            return false;
+        }
+        let memory = self.output_memory.fetch_add(buf.len(), Ordering::Relaxed) + buf.len();
+        if self.output_limit_reached(memory) {
+            println!("[Client] Closing client id={} addr={} over output buffer limit, omem={}", self.id, self.addr, memory);
+            self.output_overflow.store(true, Ordering::SeqCst);
+            self.kill();
+            return false;
+        }
+        self.outbound.send(Outgoing::Data(buf)).is_ok()
+    }
+
+    // Full sync payload is not limited, only replication stream buffered behind it counts
+    // against replica output buffer limits
+    pub fn send_snapshot(&self, buf: Vec<u8>) -> bool {
+        if self.is_output_overflow() {
+            return false;
+        }
+        self.outbound.send(Outgoing::Snapshot(buf)).is_ok()
+    }
+
+    pub fn set_output_limits(&self, normal: OutputBufferLimit, replica: OutputBufferLimit) {
+        *self.output_limits.lock().expect("Client lock poisoned") = (normal, replica);
+    }
+
+    pub fn is_output_overflow(&self) -> bool {
+        self.output_overflow.load(Ordering::SeqCst)
+    }
+
+    fn output_limit_reached(&self, memory: usize) -> bool {
+        let (normal, replica) = *self.output_limits.lock().expect("Client lock poisoned");
+        let limit = if self.is_replica() { replica } else { normal };
+        if limit.hard > 0 && memory > limit.hard {
+            return true;
+        }
+        let mut since = self.over_soft_limit_since.lock().expect("Client lock poisoned");
+        if limit.soft == 0 || memory <= limit.soft {
+            *since = None;
+            return false;
+        }
+        let since = *since.get_or_insert(now());
+        now().saturating_sub(since) >= limit.soft_seconds as u128 * 1000
+    }
+
+    // writer stops after sending everything queued before
+    pub fn close(&self) {
+        let _ = self.outbound.send(Outgoing::Close);
+    }
+
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::SeqCst);
+        self.kill_notify.notify_one();
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst)
+    }
+
+    // resolves when client is killed by CLIENT KILL or idle timeout
+    pub async fn killed(&self) {
+        if !self.is_killed() {
+            self.kill_notify.notified().await;
+        }
+    }
+
+    // one line of CLIENT LIST / CLIENT INFO
+    pub fn info_line(&self) -> String {
+        let flags = if self.is_replica() {
+            "S"
+        } else if self.is_monitor() {
+            "O"
+        } else if self.no_evict.load(Ordering::Relaxed) {
+            "e"
+        } else {
+            "N"
+        };
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db={} qbuf={} omem={} cmd={} user={}",
+            self.id,
+            self.addr,
+            self.laddr,
+            self.name().unwrap_or_default(),
+            now().saturating_sub(self.created_at) / 1000,
+            self.idle() / 1000,
+            flags,
+            DB,
+            self.query_buffer.load(Ordering::Relaxed),
+            self.output_memory.load(Ordering::Relaxed),
+            self.last_command.lock().expect("Client lock poisoned"),
+            self.user(),
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseMode {
+    All,
+    Write,
+}
+
+// Registry of connected clients, used by CLIENT command family.
+#[derive(Default)]
+pub struct ClientRegistry {
+    clients: RwLock<BTreeMap<u64, Arc<Client>>>,
+    monitors: RwLock<BTreeMap<u64, Arc<Client>>>,
+    next_id: AtomicU64,
+    pause: Mutex<Option<(u128, PauseMode)>>,
+}
+
+impl ClientRegistry {
+    pub fn register(&self, addr: &str, laddr: &str, outbound: Outbound) -> Arc<Client> {
+        let timestamp = now();
+        let client = Arc::new(Client {
+            id: self.next_id.fetch_add(1, Ordering::SeqCst) + 1,
+            addr: addr.to_string(),
+            laddr: laddr.to_string(),
+            created_at: timestamp,
+            name: Mutex::new(None),
+            user: Mutex::new("default".to_string()),
+            authenticated: AtomicBool::new(false),
+            last_interaction: Mutex::new(timestamp),
+            last_command: Mutex::new("NULL".to_string()),
+            query_buffer: AtomicUsize::new(0),
+            output_memory: AtomicUsize::new(0),
+            no_evict: AtomicBool::new(false),
+            replica: AtomicBool::new(false),
+            monitor: AtomicBool::new(false),
+            asking: AtomicBool::new(false),
+            transaction: Mutex::new(None),
+            killed: AtomicBool::new(false),
+            kill_notify: Notify::new(),
+            outbound,
+            output_limits: Mutex::new(Default::default()),
+            over_soft_limit_since: Mutex::new(None),
+            output_overflow: AtomicBool::new(false),
+        });
+        self.clients.write().expect("Clients lock poisoned").insert(client.id, client.clone());
+        client
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.clients.write().expect("Clients lock poisoned").remove(&id);
+        self.monitors.write().expect("Clients lock poisoned").remove(&id);
+    }
+
+    pub fn all(&self) -> Vec<Arc<Client>> {
+        self.clients.read().expect("Clients lock poisoned").values().cloned().collect()
+    }
+
+    pub fn add_monitor(&self, client: Arc<Client>) {
+        client.monitor.store(true, Ordering::SeqCst);
+        self.monitors.write().expect("Clients lock poisoned").insert(client.id, client);
+    }
+
+    // sends line built by `line` to every monitor, line is not built when there are no monitors
+    pub fn feed_monitors(&self, line: impl FnOnce() -> String) {
+        let monitors = self.monitors.read().expect("Clients lock poisoned");
+        if monitors.is_empty() {
+            return;
+        }
+        let message = Message::SimpleString(line());
+        for monitor in monitors.values() {
+            monitor.send(&message);
+        }
+    }
+
+    pub fn pause(&self, until: u128, mode: PauseMode) {
+        let mut pause = self.pause.lock().expect("Clients lock poisoned");
+        // Redis keeps the longest pause and the most restrictive mode
+        let (current_until, current_mode) = pause.unwrap_or((0, PauseMode::Write));
+        let mode = if current_until > now() && current_mode == PauseMode::All { PauseMode::All } else { mode };
+        *pause = Some((until.max(current_until), mode));
+    }
+
+    pub fn unpause(&self) {
+        *self.pause.lock().expect("Clients lock poisoned") = None;
+    }
+
+    // milliseconds to wait before command can be processed
+    pub fn pause_remaining(&self, is_write_command: bool) -> Option<u128> {
+        let pause = *self.pause.lock().expect("Clients lock poisoned");
+        // clock is read once, it may pass `until` between two reads
+        let now = now();
+        match pause {
+            Some((until, mode)) if until > now && (mode == PauseMode::All || is_write_command) => Some(until - now),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::message_processor::travel_to;
+
+    use super::*;
+
+    fn limit(hard: usize, soft: usize, soft_seconds: u64) -> OutputBufferLimit {
+        OutputBufferLimit { hard, soft, soft_seconds }
+    }
+
+    #[test]
+    fn test_output_buffer_limits() {
+        travel_to(1_000_000);
+        let registry = ClientRegistry::default();
+        let (outbound, _queue) = tokio::sync::mpsc::unbounded_channel();
+        let client = registry.register("127.0.0.1:10001", "127.0.0.1:6379", outbound);
+        client.set_output_limits(limit(100, 50, 10), limit(0, 0, 0));
+
+        assert!(client.send_raw(vec![0; 40]));
+        // over soft limit for less than 10 seconds
+        assert!(client.send_raw(vec![0; 40]));
+        travel_to(1_009_000);
+        assert!(client.send_raw(vec![0; 10]));
+        travel_to(1_010_000);
+        assert!(!client.send_raw(vec![0; 1]));
+        assert!(client.is_output_overflow());
+        assert!(client.is_killed());
+        assert!(!client.send_raw(vec![0; 1]));
+
+        let (outbound, _queue) = tokio::sync::mpsc::unbounded_channel();
+        let client = registry.register("127.0.0.1:10002", "127.0.0.1:6379", outbound);
+        client.set_output_limits(limit(100, 50, 10), limit(0, 0, 0));
+        assert!(!client.send_raw(vec![0; 101]));
+
+        // replica class has no limits
+        let (outbound, _queue) = tokio::sync::mpsc::unbounded_channel();
+        let client = registry.register("127.0.0.1:10003", "127.0.0.1:6379", outbound);
+        client.set_output_limits(limit(100, 50, 10), limit(0, 0, 0));
+        client.set_replica();
+        assert!(client.send_raw(vec![0; 1000]));
+
+        // full sync payload is not counted
+        let (outbound, _queue) = tokio::sync::mpsc::unbounded_channel();
+        let client = registry.register("127.0.0.1:10004", "127.0.0.1:6379", outbound);
+        client.set_output_limits(limit(100, 50, 10), limit(100, 50, 10));
+        assert!(client.send_snapshot(vec![0; 1000]));
+        assert_eq!(client.output_memory.load(Ordering::Relaxed), 0);
+        assert!(client.send_raw(vec![0; 100]));
+        assert!(!client.is_killed());
+    }
+}
@@ -0,0 +1,646 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use rand::Rng;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{
+    config::Config,
+    message_processor::now,
+    resp::{message::Message, message_parser::MessageParser},
+};
+
+pub const SLOTS: usize = 16384;
+
+const CRON_INTERVAL: Duration = Duration::from_millis(100);
+// node which did not answer for that long is pinged on every cron tick
+const PING_INTERVAL: u128 = 1000;
+const BUS_TIMEOUT: Duration = Duration::from_secs(1);
+
+// CRC16-CCITT (XMODEM), the same function Redis uses for key slots
+fn crc16(bytes: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+    for &byte in bytes {
+        crc ^= (byte as u16) << 8;
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 { (crc << 1) ^ 0x1021 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+// Only part between first `{` and following `}` is hashed when it is not empty,
+// so related keys like `{user1}.name` and `{user1}.email` are stored on the same node.
+pub fn key_hash_slot(key: &[u8]) -> usize {
+    let hashed = key
+        .iter()
+        .position(|&byte| byte == b'{')
+        .and_then(|start| {
+            let end = key[start + 1..].iter().position(|&byte| byte == b'}')?;
+            Some(&key[start + 1..start + 1 + end])
+        })
+        .filter(|tag| !tag.is_empty())
+        .unwrap_or(key);
+    crc16(hashed) as usize % SLOTS
+}
+
+struct Node {
+    id: String,
+    ip: String,
+    port: u16,
+    cport: u16,
+    config_epoch: u64,
+    // met by CLUSTER MEET or gossip but did not answer yet
+    handshake: bool,
+    ping_sent: u128,
+    pong_received: u128,
+}
+
+impl Node {
+    fn address(&self) -> String {
+        format!("{}:{}", self.ip, self.port)
+    }
+}
+
+struct State {
+    current_epoch: u64,
+    nodes: BTreeMap<String, Node>,
+    // owner node id of every slot
+    slots: Vec<Option<String>>,
+    // slot -> node id, set by CLUSTER SETSLOT during resharding
+    migrating: HashMap<usize, String>,
+    importing: HashMap<usize, String>,
+}
+
+// Cluster mode: keyspace is split into 16384 slots owned by different nodes.
+// Nodes exchange their view of the cluster over the cluster bus (port + 10000 by default).
+pub struct Cluster {
+    pub myself: String,
+    state: Mutex<State>,
+    node_timeout: u128,
+}
+
+// Message of cluster bus, sent as RESP array:
+// type, id, ip, port, cport, config epoch, current epoch, slot ranges, then (id, ip, port, cport) of every known node
+struct BusMessage {
+    kind: String,
+    id: String,
+    ip: String,
+    port: u16,
+    cport: u16,
+    config_epoch: u64,
+    current_epoch: u64,
+    slots: Vec<(usize, usize)>,
+    gossip: Vec<(String, String, u16, u16)>,
+}
+
+impl Cluster {
+    pub fn new(config: &Config) -> Cluster {
+        let myself = new_node_id();
+        let mut nodes = BTreeMap::new();
+        nodes.insert(myself.clone(), Node {
+            id: myself.clone(),
+            ip: config.bind.clone(),
+            port: config.port,
+            cport: config.cluster_bus_port(),
+            config_epoch: 0,
+            handshake: false,
+            ping_sent: 0,
+            pong_received: 0,
+        });
+        Cluster {
+            myself,
+            state: Mutex::new(State {
+                current_epoch: 0,
+                nodes,
+                slots: vec![None; SLOTS],
+                migrating: HashMap::new(),
+                importing: HashMap::new(),
+            }),
+            node_timeout: config.cluster_node_timeout as u128,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("Cluster lock poisoned")
+    }
+
+    // Checks that all keys of command are served by this node.
+    // `exists` tells whether key is stored locally, missing keys of migrating slot are redirected with ASK.
+    pub fn check_keys(&self, keys: &[&[u8]], asking: bool, exists: impl Fn(&[u8]) -> bool) -> Result<(), String> {
+        let Some(first) = keys.first() else {
+            return Ok(());
+        };
+        let slot = key_hash_slot(first);
+        if keys.iter().any(|key| key_hash_slot(key) != slot) {
+            return Err("CROSSSLOT Keys in request don't hash to the same slot".to_string());
+        }
+
+        let state = self.state();
+        match &state.slots[slot] {
+            None => Err("CLUSTERDOWN Hash slot not served".to_string()),
+            Some(owner) if *owner == self.myself => match state.migrating.get(&slot).and_then(|id| state.nodes.get(id)) {
+                Some(target) if !keys.iter().all(|key| exists(key)) => Err(format!("ASK {} {}", slot, target.address())),
+                _ => Ok(()),
+            },
+            Some(_) if asking && state.importing.contains_key(&slot) => Ok(()),
+            Some(owner) => {
+                let address = state.nodes.get(owner).map_or(String::new(), |node| node.address());
+                Err(format!("MOVED {} {}", slot, address))
+            }
+        }
+    }
+
+    pub fn add_slots(&self, slots: &[usize]) -> Result<(), String> {
+        let mut state = self.state();
+        if let Some(slot) = slots.iter().find(|&&slot| state.slots[slot].is_some()) {
+            return Err(format!("ERR Slot {} is already busy", slot));
+        }
+        for &slot in slots {
+            state.slots[slot] = Some(self.myself.clone());
+        }
+        Ok(())
+    }
+
+    // CLUSTER SETSLOT slot IMPORTING|MIGRATING|NODE node-id, CLUSTER SETSLOT slot STABLE
+    pub fn set_slot(&self, slot: usize, action: &str, node_id: Option<&str>) -> Result<(), String> {
+        let mut state = self.state();
+        let node_id = match node_id {
+            Some(id) if !state.nodes.contains_key(id) => return Err(format!("ERR I don't know about node {}", id)),
+            Some(id) => Some(id.to_string()),
+            None => None,
+        };
+        let is_mine = state.slots[slot].as_deref() == Some(self.myself.as_str());
+        match (action, node_id) {
+            ("migrating", Some(id)) if is_mine => {
+                state.migrating.insert(slot, id);
+            }
+            ("migrating", Some(_)) => return Err(format!("ERR I'm not the owner of hash slot {}", slot)),
+            ("importing", Some(_)) if is_mine => return Err(format!("ERR I'm already the owner of hash slot {}", slot)),
+            ("importing", Some(id)) => {
+                state.importing.insert(slot, id);
+            }
+            ("stable", None) => {
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+            }
+            ("node", Some(id)) => {
+                state.migrating.remove(&slot);
+                state.importing.remove(&slot);
+                if id == self.myself && !is_mine {
+                    // new epoch makes other nodes accept our claim of the slot
+                    state.current_epoch += 1;
+                    let epoch = state.current_epoch;
+                    if let Some(myself) = state.nodes.get_mut(&self.myself) {
+                        myself.config_epoch = epoch;
+                    }
+                }
+                state.slots[slot] = Some(id);
+            }
+            _ => return Err("ERR syntax error".to_string()),
+        }
+        Ok(())
+    }
+
+    // CLUSTER MEET ip port cport, handshake is finished by the cluster cron
+    pub fn meet(&self, ip: &str, port: u16, cport: u16) {
+        let mut state = self.state();
+        if state.nodes.values().any(|node| node.ip == ip && node.port == port) {
+            return;
+        }
+        // real id is learned from the answer
+        let id = new_node_id();
+        state.nodes.insert(id.clone(), Node { id, ip: ip.to_string(), port, cport, config_epoch: 0, handshake: true, ping_sent: 0, pong_received: 0 });
+    }
+
+    pub fn is_known_node(&self, id: &str) -> bool {
+        self.state().nodes.contains_key(id)
+    }
+
+    // CLUSTER NODES
+    pub fn nodes(&self) -> String {
+        let state = self.state();
+        let mut lines = String::new();
+        for node in state.nodes.values() {
+            let mut flags: Vec<&str> = Vec::new();
+            if node.id == self.myself {
+                flags.push("myself");
+            }
+            flags.push("master");
+            if node.handshake {
+                flags.push("handshake");
+            } else if self.is_failing(node) {
+                flags.push("fail?");
+            }
+            let link_state = if node.id == self.myself || now().saturating_sub(node.pong_received) < self.node_timeout { "connected" } else { "disconnected" };
+            lines.push_str(&format!(
+                "{} {}@{} {} - {} {} {} {}",
+                node.id, node.address(), node.cport, flags.join(","), node.ping_sent, node.pong_received, node.config_epoch, link_state
+            ));
+            for (start, end) in slot_ranges(&state.slots, &node.id) {
+                if start == end {
+                    lines.push_str(&format!(" {}", start));
+                } else {
+                    lines.push_str(&format!(" {}-{}", start, end));
+                }
+            }
+            for (slot, id) in state.migrating.iter().filter(|_| node.id == self.myself) {
+                lines.push_str(&format!(" [{}->-{}]", slot, id));
+            }
+            for (slot, id) in state.importing.iter().filter(|_| node.id == self.myself) {
+                lines.push_str(&format!(" [{}-<-{}]", slot, id));
+            }
+            lines.push('\n');
+        }
+        lines
+    }
+
+    // CLUSTER SLOTS
+    pub fn slots(&self) -> Message {
+        let state = self.state();
+        let mut ranges: Vec<(usize, usize, &Node)> = Vec::new();
+        for node in state.nodes.values() {
+            for (start, end) in slot_ranges(&state.slots, &node.id) {
+                ranges.push((start, end, node));
+            }
+        }
+        ranges.sort_by_key(|(start, _, _)| *start);
+        Message::array(
+            ranges
+                .into_iter()
+                .map(|(start, end, node)| {
+                    Message::array(vec![
+                        Message::Integer(start as i64),
+                        Message::Integer(end as i64),
+                        Message::array(vec![Message::bulk_string(&node.ip), Message::Integer(node.port as i64), Message::bulk_string(&node.id)]),
+                    ])
+                })
+                .collect(),
+        )
+    }
+
+    // CLUSTER SHARDS, every node is a shard with a single master
+    pub fn shards(&self) -> Message {
+        let state = self.state();
+        let shards = state
+            .nodes
+            .values()
+            .filter(|node| !node.handshake)
+            .map(|node| {
+                let slots = slot_ranges(&state.slots, &node.id)
+                    .into_iter()
+                    .flat_map(|(start, end)| [Message::Integer(start as i64), Message::Integer(end as i64)])
+                    .collect();
+                let health = if self.is_failing(node) { "fail" } else { "online" };
+                let description = Message::array(vec![
+                    Message::bulk_string("id"),
+                    Message::bulk_string(&node.id),
+                    Message::bulk_string("port"),
+                    Message::Integer(node.port as i64),
+                    Message::bulk_string("ip"),
+                    Message::bulk_string(&node.ip),
+                    Message::bulk_string("endpoint"),
+                    Message::bulk_string(&node.ip),
+                    Message::bulk_string("role"),
+                    Message::bulk_string("master"),
+                    Message::bulk_string("replication-offset"),
+                    Message::Integer(0),
+                    Message::bulk_string("health"),
+                    Message::bulk_string(health),
+                ]);
+                Message::array(vec![
+                    Message::bulk_string("slots"),
+                    Message::array(slots),
+                    Message::bulk_string("nodes"),
+                    Message::array(vec![description]),
+                ])
+            })
+            .collect();
+        Message::array(shards)
+    }
+
+    // CLUSTER INFO
+    pub fn info(&self) -> String {
+        let state = self.state();
+        let assigned = state.slots.iter().filter(|owner| owner.is_some()).count();
+        let failing = state.slots.iter().flatten().filter(|owner| state.nodes.get(*owner).is_some_and(|node| self.is_failing(node))).count();
+        let cluster_state = if assigned == SLOTS && failing == 0 { "ok" } else { "fail" };
+        let known_nodes = state.nodes.values().filter(|node| !node.handshake).count();
+        let size = state.nodes.values().filter(|node| state.slots.iter().flatten().any(|owner| *owner == node.id)).count();
+        let my_epoch = state.nodes.get(&self.myself).map_or(0, |node| node.config_epoch);
+        format!(
+            "cluster_state:{}\r\ncluster_slots_assigned:{}\r\ncluster_slots_ok:{}\r\ncluster_slots_pfail:{}\r\ncluster_slots_fail:0\r\ncluster_known_nodes:{}\r\ncluster_size:{}\r\ncluster_current_epoch:{}\r\ncluster_my_epoch:{}\r\n",
+            cluster_state, assigned, assigned - failing, failing, known_nodes, size, state.current_epoch, my_epoch
+        )
+    }
+
+    // node did not answer pings for longer than cluster-node-timeout
+    fn is_failing(&self, node: &Node) -> bool {
+        node.id != self.myself && node.ping_sent > node.pong_received && now().saturating_sub(node.pong_received) > self.node_timeout
+    }
+
+    fn message(&self, kind: &str) -> BusMessage {
+        let state = self.state();
+        let myself = &state.nodes[&self.myself];
+        BusMessage {
+            kind: kind.to_string(),
+            id: myself.id.clone(),
+            ip: myself.ip.clone(),
+            port: myself.port,
+            cport: myself.cport,
+            config_epoch: myself.config_epoch,
+            current_epoch: state.current_epoch,
+            slots: slot_ranges(&state.slots, &self.myself),
+            gossip: state
+                .nodes
+                .values()
+                .filter(|node| !node.handshake && node.id != self.myself)
+                .map(|node| (node.id.clone(), node.ip.clone(), node.port, node.cport))
+                .collect(),
+        }
+    }
+
+    // Updates view of the cluster with message from another node.
+    // `handshake_id` is temporary id of the node we sent MEET to.
+    fn receive(&self, message: &BusMessage, handshake_id: Option<&str>) {
+        let mut state = self.state();
+        if let Some(handshake_id) = handshake_id.filter(|id| *id != message.id) {
+            state.nodes.remove(handshake_id);
+        }
+        if message.id == self.myself {
+            return;
+        }
+
+        let timestamp = now();
+        let node = state.nodes.entry(message.id.clone()).or_insert_with(|| Node {
+            id: message.id.clone(),
+            ip: message.ip.clone(),
+            port: message.port,
+            cport: message.cport,
+            config_epoch: 0,
+            handshake: false,
+            ping_sent: 0,
+            pong_received: 0,
+        });
+        node.ip = message.ip.clone();
+        node.port = message.port;
+        node.cport = message.cport;
+        node.config_epoch = message.config_epoch;
+        node.handshake = false;
+        node.pong_received = timestamp;
+        state.current_epoch = state.current_epoch.max(message.current_epoch);
+
+        // claim of the sender wins over owner with older config
+        for &(start, end) in &message.slots {
+            for slot in start..=end {
+                let owner_epoch = state.slots[slot].as_ref().and_then(|owner| state.nodes.get(owner)).map(|owner| owner.config_epoch);
+                let is_sender = state.slots[slot].as_deref() == Some(message.id.as_str());
+                if !is_sender && owner_epoch.is_none_or(|epoch| epoch < message.config_epoch) {
+                    state.slots[slot] = Some(message.id.clone());
+                    state.importing.remove(&slot);
+                    state.migrating.remove(&slot);
+                }
+            }
+        }
+
+        // nodes we don't know yet are met through handshake
+        for (id, ip, port, cport) in &message.gossip {
+            if *id != self.myself && !state.nodes.contains_key(id) && !state.nodes.values().any(|node| node.ip == *ip && node.port == *port) {
+                state.nodes.insert(id.clone(), Node { id: id.clone(), ip: ip.clone(), port: *port, cport: *cport, config_epoch: 0, handshake: true, ping_sent: 0, pong_received: 0 });
+            }
+        }
+    }
+
+    // nodes to ping on this cron tick: one random node and every node we did not hear from recently
+    fn ping_targets(&self) -> Vec<(String, String, u16, bool)> {
+        let mut state = self.state();
+        let timestamp = now();
+        let others: Vec<String> = state.nodes.keys().filter(|id| **id != self.myself).cloned().collect();
+        if others.is_empty() {
+            return Vec::new();
+        }
+        let random = others[rand::thread_rng().gen_range(0..others.len())].clone();
+
+        let mut targets = Vec::new();
+        for id in others {
+            let node = state.nodes.get_mut(&id).expect("Node exists");
+            if id == random || node.handshake || timestamp.saturating_sub(node.pong_received) > PING_INTERVAL {
+                if node.ping_sent <= node.pong_received {
+                    node.ping_sent = timestamp;
+                }
+                targets.push((node.id.clone(), node.ip.clone(), node.cport, node.handshake));
+            }
+        }
+        targets
+    }
+}
+
+// Accepts connections of the cluster bus, every connection sends one PING or MEET and receives PONG.
+pub async fn serve_bus(cluster: Arc<Cluster>, listener: TcpListener) {
+    loop {
+        let Ok((mut stream, _)) = listener.accept().await else {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            continue;
+        };
+        let cluster = cluster.clone();
+        tokio::spawn(async move {
+            let Ok(Ok(Some(message))) = tokio::time::timeout(BUS_TIMEOUT, read_bus_message(&mut stream)).await else {
+                return;
+            };
+            // nodes which are not part of the cluster have to MEET first
+            if message.kind != "meet" && !cluster.is_known_node(&message.id) {
+                return;
+            }
+            cluster.receive(&message, None);
+            let _ = write_bus_message(&mut stream, &cluster.message("pong")).await;
+        });
+    }
+}
+
+// Pings other nodes, finishes handshakes and spreads slot configuration
+pub async fn run_cron(cluster: Arc<Cluster>) {
+    loop {
+        tokio::time::sleep(CRON_INTERVAL).await;
+        for (id, ip, cport, handshake) in cluster.ping_targets() {
+            let cluster = cluster.clone();
+            tokio::spawn(async move {
+                let kind = if handshake { "meet" } else { "ping" };
+                let exchange = async {
+                    let mut stream = TcpStream::connect((ip.as_str(), cport)).await.ok()?;
+                    write_bus_message(&mut stream, &cluster.message(kind)).await.ok()?;
+                    read_bus_message(&mut stream).await.ok()?
+                };
+                if let Ok(Some(pong)) = tokio::time::timeout(BUS_TIMEOUT, exchange).await {
+                    cluster.receive(&pong, handshake.then_some(id.as_str()));
+                }
+            });
+        }
+    }
+}
+
+async fn write_bus_message(stream: &mut TcpStream, message: &BusMessage) -> std::io::Result<()> {
+    let slots: Vec<String> = message.slots.iter().map(|(start, end)| format!("{}-{}", start, end)).collect();
+    let mut fields = vec![
+        message.kind.clone(),
+        message.id.clone(),
+        message.ip.clone(),
+        message.port.to_string(),
+        message.cport.to_string(),
+        message.config_epoch.to_string(),
+        message.current_epoch.to_string(),
+        slots.join(","),
+    ];
+    for (id, ip, port, cport) in &message.gossip {
+        fields.extend([id.clone(), ip.clone(), port.to_string(), cport.to_string()]);
+    }
+
+    let mut buf: Vec<u8> = Vec::new();
+    Message::array(fields.iter().map(|field| Message::bulk_string(field)).collect()).write_to(&mut buf)?;
+    stream.write_all(&buf).await
+}
+
+async fn read_bus_message(stream: &mut TcpStream) -> std::io::Result<Option<BusMessage>> {
+    let mut parser = MessageParser::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        for &byte in &buf[..read] {
+            match parser.add_byte(byte) {
+                Ok(Some(message)) => return Ok(parse_bus_message(&message)),
+                Ok(None) => {}
+                Err(_) => return Ok(None),
+            }
+        }
+    }
+}
+
+fn parse_bus_message(message: &Message) -> Option<BusMessage> {
+    let Message::Array(Some(items)) = message else {
+        return None;
+    };
+    let fields: Vec<&str> = items.iter().map(|item| item.as_str().ok()).collect::<Option<_>>()?;
+    let [kind, id, ip, port, cport, config_epoch, current_epoch, slots, gossip @ ..] = fields.as_slice() else {
+        return None;
+    };
+    let slots = slots
+        .split(',')
+        .filter(|range| !range.is_empty())
+        .map(|range| {
+            let (start, end) = range.split_once('-')?;
+            let (start, end): (usize, usize) = (start.parse().ok()?, end.parse().ok()?);
+            (start <= end && end < SLOTS).then_some((start, end))
+        })
+        .collect::<Option<_>>()?;
+    let gossip = gossip
+        .chunks(4)
+        .map(|node| match node {
+            [id, ip, port, cport] => Some((id.to_string(), ip.to_string(), port.parse().ok()?, cport.parse().ok()?)),
+            _ => None,
+        })
+        .collect::<Option<_>>()?;
+
+    Some(BusMessage {
+        kind: kind.to_lowercase(),
+        id: id.to_string(),
+        ip: ip.to_string(),
+        port: port.parse().ok()?,
+        cport: cport.parse().ok()?,
+        config_epoch: config_epoch.parse().ok()?,
+        current_epoch: current_epoch.parse().ok()?,
+        slots,
+        gossip,
+    })
+}
+
+// continuous ranges of slots owned by node
+fn slot_ranges(slots: &[Option<String>], id: &str) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = Vec::new();
+    for (slot, owner) in slots.iter().enumerate() {
+        if owner.as_deref() != Some(id) {
+            continue;
+        }
+        match ranges.last_mut() {
+            Some((_, end)) if *end + 1 == slot => *end = slot,
+            _ => ranges.push((slot, slot)),
+        }
+    }
+    ranges
+}
+
+fn new_node_id() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40).map(|_| format!("{:x}", rng.gen_range(0..16))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_hash_slot() {
+        assert_eq!(key_hash_slot(b"foo"), 12182);
+        assert_eq!(key_hash_slot(b"somekey"), 11058);
+        assert_eq!(key_hash_slot(b"{user1000}.following"), key_hash_slot(b"user1000"));
+        assert_eq!(key_hash_slot(b"foo{bar}{zap}"), key_hash_slot(b"bar"));
+        // empty hash tag, whole key is hashed
+        assert_eq!(key_hash_slot(b"foo{}{bar}"), crc16(b"foo{}{bar}") as usize % SLOTS);
+        assert_eq!(key_hash_slot(b"{bar"), crc16(b"{bar") as usize % SLOTS);
+    }
+
+    #[test]
+    fn test_redirections() {
+        let cluster = Cluster::new(&Config::default());
+        assert_eq!(cluster.check_keys(&[b"foo"], false, |_| true), Err("CLUSTERDOWN Hash slot not served".to_string()));
+
+        cluster.add_slots(&(0..8192).collect::<Vec<_>>()).unwrap();
+        assert!(cluster.add_slots(&[10]).is_err());
+        let other = BusMessage {
+            kind: "meet".to_string(),
+            id: "b".repeat(40),
+            ip: "127.0.0.1".to_string(),
+            port: 7001,
+            cport: 17001,
+            config_epoch: 0,
+            current_epoch: 0,
+            slots: vec![(8192, 16383)],
+            gossip: Vec::new(),
+        };
+        cluster.receive(&other, None);
+
+        assert_eq!(cluster.check_keys(&[b"somekey"], false, |_| true), Err("MOVED 11058 127.0.0.1:7001".to_string()));
+        assert_eq!(cluster.check_keys(&[b"a", b"b"], false, |_| true), Err("CROSSSLOT Keys in request don't hash to the same slot".to_string()));
+        assert_eq!(cluster.check_keys(&[b"{a}1", b"{a}2"], false, |_| true), Err("MOVED 15495 127.0.0.1:7001".to_string()));
+        assert_eq!(cluster.check_keys(&[b"b"], false, |_| true), Ok(()));
+
+        cluster.set_slot(3300, "migrating", Some(&"b".repeat(40))).unwrap();
+        assert_eq!(cluster.check_keys(&[b"b"], false, |_| true), Ok(()));
+        assert_eq!(cluster.check_keys(&[b"b"], false, |_| false), Err("ASK 3300 127.0.0.1:7001".to_string()));
+
+        cluster.set_slot(11058, "importing", Some(&"b".repeat(40))).unwrap();
+        assert_eq!(cluster.check_keys(&[b"somekey"], true, |_| false), Ok(()));
+        assert!(cluster.info().starts_with("cluster_state:ok\r\n"));
+    }
+
+    #[test]
+    fn test_bus_message_roundtrip() {
+        let message = Message::array(
+            ["ping", "abc", "127.0.0.1", "7000", "17000", "1", "2", "0-10,20-20", "def", "127.0.0.1", "7001", "17001"]
+                .iter()
+                .map(|field| Message::bulk_string(field))
+                .collect(),
+        );
+        let parsed = parse_bus_message(&message).unwrap();
+        assert_eq!(parsed.slots, vec![(0, 10), (20, 20)]);
+        assert_eq!(parsed.gossip, vec![("def".to_string(), "127.0.0.1".to_string(), 7001, 17001)]);
+        assert_eq!(slot_ranges(&[Some("a".to_string()), Some("a".to_string()), None, Some("a".to_string())], "a"), vec![(0, 1), (3, 3)]);
+    }
+}
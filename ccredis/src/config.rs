@@ -0,0 +1,409 @@
+use std::fs;
+
+use crate::{
+    keyspace::EvictionPolicy,
+    resp::message_parser::{DEFAULT_MAX_BULK_LEN, DEFAULT_MAX_MULTIBULK_LEN},
+    shutdown::ShutdownOptions,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsAuthClients {
+    No,
+    Yes,
+    Optional,
+}
+
+// `client-output-buffer-limit` of one client class, 0 disables a limit
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct OutputBufferLimit {
+    pub hard: usize,
+    pub soft: usize,
+    // client is disconnected when it stays over soft limit for this many seconds
+    pub soft_seconds: u64,
+}
+
+// Server settings.
+// Read from an optional config file (`name value` per line) followed by
+// `--name value` command line overrides, same as redis-server does.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub bind: String,
+    // 0 disables plain TCP listener
+    pub port: u16,
+    // 0 disables TLS listener
+    pub tls_port: u16,
+    pub tls_cert_file: Option<String>,
+    pub tls_key_file: Option<String>,
+    pub tls_ca_cert_file: Option<String>,
+    pub tls_auth_clients: TlsAuthClients,
+    pub unixsocket: Option<String>,
+    // octal file mode, e.g. 700
+    pub unixsocketperm: Option<u32>,
+    pub proto_max_bulk_len: usize,
+    pub proto_max_multibulk_len: usize,
+    pub maxclients: usize,
+    pub client_output_buffer_limit_normal: OutputBufferLimit,
+    pub client_output_buffer_limit_replica: OutputBufferLimit,
+    pub io_threads: usize,
+    // close connection after client is idle for N seconds (0 to disable)
+    pub timeout: u64,
+    pub requirepass: Option<String>,
+    pub aclfile: Option<String>,
+    // master host and port to replicate from on startup
+    pub replicaof: Option<(String, u16)>,
+    pub masteruser: Option<String>,
+    pub masterauth: Option<String>,
+    pub repl_backlog_size: usize,
+    pub replica_read_only: bool,
+    pub cluster_enabled: bool,
+    // cluster bus port, 0 means port + 10000
+    pub cluster_port: u16,
+    // milliseconds without answer after which node is considered failing
+    pub cluster_node_timeout: u64,
+    // memory limit in bytes, 0 means no limit
+    pub maxmemory: usize,
+    pub maxmemory_policy: EvictionPolicy,
+    // number of keys sampled to pick the one to evict
+    pub maxmemory_samples: usize,
+    pub lfu_log_factor: u32,
+    // minutes after which LFU counter is decremented
+    pub lfu_decay_time: u64,
+    // frequency of background tasks such as active expiration, per second
+    pub hz: u64,
+    // microseconds, negative disables slow log
+    pub slowlog_log_slower_than: i64,
+    pub slowlog_max_len: usize,
+    // milliseconds, 0 disables latency monitor
+    pub latency_monitor_threshold: u64,
+    pub latency_tracking: bool,
+    pub latency_tracking_info_percentiles: Vec<f64>,
+    // milliseconds after which running script makes server reply BUSY
+    pub lua_time_limit: u64,
+    // positive is maximal number of entries of packed list, negative from -1 to -5 is its size from 4 to 64 kb
+    pub list_max_listpack_size: i64,
+    // file with process id, removed on shutdown
+    pub pidfile: Option<String>,
+    // seconds to wait for lagging replicas on shutdown
+    pub shutdown_timeout: u64,
+    pub shutdown_on_sigint: ShutdownOptions,
+    pub shutdown_on_sigterm: ShutdownOptions,
+    // damaged db file is loaded up to the damage instead of refusing to start
+    pub load_truncated: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind: "127.0.0.1".to_string(),
+            port: 6379,
+            tls_port: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: TlsAuthClients::No,
+            unixsocket: None,
+            unixsocketperm: None,
+            proto_max_bulk_len: DEFAULT_MAX_BULK_LEN,
+            proto_max_multibulk_len: DEFAULT_MAX_MULTIBULK_LEN,
+            maxclients: 10000,
+            // unlike Redis normal clients are limited too, replies are queued in memory until they are written
+            client_output_buffer_limit_normal: OutputBufferLimit { hard: 256 * 1024 * 1024, soft: 0, soft_seconds: 0 },
+            client_output_buffer_limit_replica: OutputBufferLimit { hard: 256 * 1024 * 1024, soft: 64 * 1024 * 1024, soft_seconds: 60 },
+            io_threads: 4,
+            timeout: 0,
+            requirepass: None,
+            aclfile: None,
+            replicaof: None,
+            masteruser: None,
+            masterauth: None,
+            repl_backlog_size: 1024 * 1024,
+            replica_read_only: true,
+            cluster_enabled: false,
+            cluster_port: 0,
+            cluster_node_timeout: 15000,
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::NoEviction,
+            maxmemory_samples: 5,
+            lfu_log_factor: 10,
+            lfu_decay_time: 1,
+            hz: 10,
+            slowlog_log_slower_than: 10000,
+            slowlog_max_len: 128,
+            latency_monitor_threshold: 0,
+            latency_tracking: true,
+            latency_tracking_info_percentiles: vec![50.0, 99.0, 99.9],
+            lua_time_limit: 5000,
+            list_max_listpack_size: -2,
+            pidfile: None,
+            shutdown_timeout: 10,
+            shutdown_on_sigint: ShutdownOptions::default(),
+            shutdown_on_sigterm: ShutdownOptions::default(),
+            load_truncated: false,
+        }
+    }
+}
+
+impl Config {
+    // usage: ccredis [/path/to/config.conf] [--name value]...
+    pub fn from_args<I: Iterator<Item = String>>(args: I) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut args = args.peekable();
+
+        if let Some(path) = args.next_if(|arg| !arg.starts_with("--")) {
+            let content = fs::read_to_string(&path).map_err(|err| format!("Cannot read config file {}: {}", path, err))?;
+            config.load_str(&content)?;
+        }
+
+        while let Some(arg) = args.next() {
+            let name = arg.strip_prefix("--").ok_or(format!("Unexpected argument: {}", arg))?;
+            let value = args.next().ok_or(format!("Expected value for --{}", name))?;
+            config.set(name, &value)?;
+        }
+
+        Ok(config)
+    }
+
+    pub fn load_str(&mut self, content: &str) -> Result<(), String> {
+        for (line_number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (name, value) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            self.set(name, value.trim())
+                .map_err(|err| format!("Config error at line {}: {}", line_number + 1, err))?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        match name.to_lowercase().as_str() {
+            "bind" => self.bind = value.to_string(),
+            "port" => self.port = parse_port(value)?,
+            "tls-port" => self.tls_port = parse_port(value)?,
+            "tls-cert-file" => self.tls_cert_file = parse_path(value),
+            "tls-key-file" => self.tls_key_file = parse_path(value),
+            "tls-ca-cert-file" => self.tls_ca_cert_file = parse_path(value),
+            "tls-auth-clients" => self.tls_auth_clients = match value.to_lowercase().as_str() {
+                "no" => TlsAuthClients::No,
+                "yes" => TlsAuthClients::Yes,
+                "optional" => TlsAuthClients::Optional,
+                _ => return Err(format!("Invalid tls-auth-clients value '{}', expected yes, no or optional", value)),
+            },
+            "unixsocket" => self.unixsocket = parse_path(value),
+            "unixsocketperm" => self.unixsocketperm = Some(u32::from_str_radix(value, 8).map_err(|_| format!("Invalid unixsocketperm '{}'", value))?),
+            "proto-max-bulk-len" => self.proto_max_bulk_len = parse_memory(value)?,
+            "proto-max-multibulk-len" => self.proto_max_multibulk_len = parse_number(value)?,
+            "maxclients" => self.maxclients = parse_number(value)?,
+            "client-output-buffer-limit" => self.set_output_buffer_limits(value)?,
+            "io-threads" => self.io_threads = parse_number(value)?.max(1),
+            "timeout" => self.timeout = parse_number(value)? as u64,
+            "requirepass" => self.requirepass = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "aclfile" => self.aclfile = parse_path(value),
+            "replicaof" | "slaveof" => self.replicaof = match value.split_whitespace().collect::<Vec<_>>()[..] {
+                [] => None,
+                [host, port] => Some((host.to_string(), parse_port(port)?)),
+                _ => return Err(format!("Invalid replicaof '{}', expected host and port", value)),
+            },
+            "masteruser" => self.masteruser = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "masterauth" => self.masterauth = Some(value.to_string()).filter(|value| !value.is_empty()),
+            "repl-backlog-size" => self.repl_backlog_size = parse_memory(value)?,
+            "replica-read-only" | "slave-read-only" => self.replica_read_only = parse_bool(value)?,
+            "cluster-enabled" => self.cluster_enabled = parse_bool(value)?,
+            "cluster-port" => self.cluster_port = parse_port(value)?,
+            "cluster-node-timeout" => self.cluster_node_timeout = parse_number(value)? as u64,
+            "maxmemory" => self.maxmemory = parse_memory(value)?,
+            "maxmemory-policy" => self.maxmemory_policy = EvictionPolicy::parse(value).ok_or(format!("Invalid maxmemory-policy '{}'", value))?,
+            "maxmemory-samples" => self.maxmemory_samples = parse_number(value)?,
+            "lfu-log-factor" => self.lfu_log_factor = parse_number(value)? as u32,
+            "lfu-decay-time" => self.lfu_decay_time = parse_number(value)? as u64,
+            "hz" => self.hz = parse_number(value)?.clamp(1, 500) as u64,
+            "slowlog-log-slower-than" => self.slowlog_log_slower_than = value.parse().map_err(|_| format!("Invalid number '{}'", value))?,
+            "slowlog-max-len" => self.slowlog_max_len = parse_number(value)?,
+            "latency-monitor-threshold" => self.latency_monitor_threshold = parse_number(value)? as u64,
+            "latency-tracking" => self.latency_tracking = parse_bool(value)?,
+            "latency-tracking-info-percentiles" => self.latency_tracking_info_percentiles = parse_percentiles(value)?,
+            "lua-time-limit" | "busy-reply-threshold" => self.lua_time_limit = parse_number(value)? as u64,
+            "list-max-listpack-size" | "list-max-ziplist-size" => self.list_max_listpack_size = match value.parse() {
+                Ok(size) if size != 0 && size >= -5 => size,
+                _ => return Err(format!("Invalid list-max-listpack-size '{}', expected positive number or -1 to -5", value)),
+            },
+            "pidfile" => self.pidfile = parse_path(value),
+            "shutdown-timeout" => self.shutdown_timeout = parse_number(value)? as u64,
+            "shutdown-on-sigint" => self.shutdown_on_sigint = parse_shutdown_options(value)?,
+            "shutdown-on-sigterm" => self.shutdown_on_sigterm = parse_shutdown_options(value)?,
+            "load-truncated" => self.load_truncated = parse_bool(value)?,
+            _ => return Err(format!("Unknown option '{}'", name)),
+        }
+        Ok(())
+    }
+
+    pub fn cluster_bus_port(&self) -> u16 {
+        if self.cluster_port != 0 {
+            self.cluster_port
+        } else {
+            self.port.wrapping_add(10000)
+        }
+    }
+}
+
+impl Config {
+    // `<class> <hard> <soft> <seconds>` repeated, e.g. "normal 0 0 0 replica 256mb 64mb 60"
+    fn set_output_buffer_limits(&mut self, value: &str) -> Result<(), String> {
+        let words: Vec<&str> = value.split_whitespace().collect();
+        if words.is_empty() || !words.len().is_multiple_of(4) {
+            return Err(format!("Invalid client-output-buffer-limit '{}', expected <class> <hard> <soft> <seconds>", value));
+        }
+        for group in words.chunks(4) {
+            let limit = OutputBufferLimit { hard: parse_memory(group[1])?, soft: parse_memory(group[2])?, soft_seconds: parse_number(group[3])? as u64 };
+            match group[0].to_lowercase().as_str() {
+                "normal" => self.client_output_buffer_limit_normal = limit,
+                "replica" | "slave" => self.client_output_buffer_limit_replica = limit,
+                class => return Err(format!("Invalid client class '{}', expected normal or replica", class)),
+            }
+        }
+        Ok(())
+    }
+}
+
+fn parse_port(value: &str) -> Result<u16, String> {
+    value.parse().map_err(|_| format!("Invalid port '{}'", value))
+}
+
+fn parse_path(value: &str) -> Option<String> {
+    Some(value.to_string()).filter(|value| !value.is_empty())
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err(format!("Invalid value '{}', expected yes or no", value)),
+    }
+}
+
+fn parse_number(value: &str) -> Result<usize, String> {
+    value.parse().map_err(|_| format!("Invalid number '{}'", value))
+}
+
+// space separated list, e.g. "50 99 99.9"
+fn parse_percentiles(value: &str) -> Result<Vec<f64>, String> {
+    value.split_whitespace()
+        .map(|percentile| percentile.parse().ok().filter(|percentile| (0.0..=100.0).contains(percentile)))
+        .map(|percentile| percentile.ok_or(format!("Invalid percentiles '{}'", value)))
+        .collect()
+}
+
+// space separated flags of SHUTDOWN or "default", e.g. "nosave now"
+fn parse_shutdown_options(value: &str) -> Result<ShutdownOptions, String> {
+    ShutdownOptions::parse(value.split_whitespace(), true).ok_or(format!("Invalid shutdown options '{}'", value))
+}
+
+// accepts plain bytes or units: 1k, 1kb, 1m, 1mb, 1g, 1gb
+fn parse_memory(value: &str) -> Result<usize, String> {
+    let lowercase = value.to_lowercase();
+    let units: [(&str, usize); 6] = [
+        ("kb", 1024), ("k", 1000),
+        ("mb", 1024 * 1024), ("m", 1000 * 1000),
+        ("gb", 1024 * 1024 * 1024), ("g", 1000 * 1000 * 1000),
+    ];
+    for (suffix, multiplier) in units {
+        if let Some(number) = lowercase.strip_suffix(suffix) {
+            return parse_number(number)?
+                .checked_mul(multiplier)
+                .ok_or(format!("Invalid memory value '{}'", value));
+        }
+    }
+    parse_number(&lowercase).map_err(|_| format!("Invalid memory value '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> impl Iterator<Item = String> + '_ {
+        line.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn defaults_without_args() {
+        let config = Config::from_args(args("")).unwrap();
+        assert_eq!(config.proto_max_bulk_len, 512 * 1024 * 1024);
+        assert_eq!(config.proto_max_multibulk_len, 1024 * 1024);
+    }
+
+    #[test]
+    fn command_line_overrides() {
+        let config = Config::from_args(args("--proto-max-bulk-len 1mb --proto-max-multibulk-len 16")).unwrap();
+        assert_eq!(config.proto_max_bulk_len, 1024 * 1024);
+        assert_eq!(config.proto_max_multibulk_len, 16);
+    }
+
+    #[test]
+    fn config_file_content() {
+        let mut config = Config::default();
+        config.load_str("# comment\n\nproto-max-bulk-len 100\n").unwrap();
+        assert_eq!(config.proto_max_bulk_len, 100);
+    }
+
+    #[test]
+    fn replicaof_host_and_port() {
+        let mut config = Config::default();
+        config.load_str("replicaof 127.0.0.1 6380\nreplica-read-only no\n").unwrap();
+        assert_eq!(config.replicaof, Some(("127.0.0.1".to_string(), 6380)));
+        assert!(!config.replica_read_only);
+        assert!(config.set("replicaof", "127.0.0.1").is_err());
+    }
+
+    #[test]
+    fn maxmemory_policy() {
+        let config = Config::from_args(args("--maxmemory 100mb --maxmemory-policy allkeys-lfu")).unwrap();
+        assert_eq!(config.maxmemory, 100 * 1024 * 1024);
+        assert_eq!(config.maxmemory_policy, EvictionPolicy::AllKeysLfu);
+        assert!(Config::from_args(args("--maxmemory-policy lru")).is_err());
+    }
+
+    #[test]
+    fn hz_is_clamped() {
+        assert_eq!(Config::from_args(args("--hz 100")).unwrap().hz, 100);
+        assert_eq!(Config::from_args(args("--hz 0")).unwrap().hz, 1);
+        assert_eq!(Config::from_args(args("--hz 1000")).unwrap().hz, 500);
+    }
+
+    #[test]
+    fn latency_tracking_percentiles() {
+        let mut config = Config::default();
+        config.load_str("latency-tracking-info-percentiles 50 99.99\nslowlog-log-slower-than -1").unwrap();
+        assert_eq!(config.latency_tracking_info_percentiles, vec![50.0, 99.99]);
+        assert_eq!(config.slowlog_log_slower_than, -1);
+        assert!(config.load_str("latency-tracking-info-percentiles 50 101").is_err());
+    }
+
+    #[test]
+    fn shutdown_options() {
+        let mut config = Config::default();
+        config.load_str("pidfile /tmp/ccredis.pid\nshutdown-timeout 3\nshutdown-on-sigterm nosave now\n").unwrap();
+        assert_eq!(config.pidfile.as_deref(), Some("/tmp/ccredis.pid"));
+        assert_eq!(config.shutdown_timeout, 3);
+        assert_eq!(config.shutdown_on_sigterm, ShutdownOptions { save: Some(false), now: true, force: false });
+        assert_eq!(config.shutdown_on_sigint, ShutdownOptions::default());
+        assert!(config.set("shutdown-on-sigint", "save abort").is_err());
+    }
+
+    #[test]
+    fn client_output_buffer_limit() {
+        assert!(Config::from_args(args("--client-output-buffer-limit normal")).is_err());
+        let mut config = Config::default();
+        config.load_str("client-output-buffer-limit normal 1mb 512kb 10 slave 0 0 0").unwrap();
+        assert_eq!(config.client_output_buffer_limit_normal, OutputBufferLimit { hard: 1024 * 1024, soft: 512 * 1024, soft_seconds: 10 });
+        assert_eq!(config.client_output_buffer_limit_replica, OutputBufferLimit { hard: 0, soft: 0, soft_seconds: 0 });
+        assert!(config.load_str("client-output-buffer-limit master 0 0 0").is_err());
+    }
+
+    #[test]
+    fn unknown_option() {
+        assert!(Config::from_args(args("--foo 1")).is_err());
+        assert!(Config::from_args(args("--proto-max-bulk-len")).is_err());
+        assert!(Config::from_args(args("--proto-max-bulk-len abc")).is_err());
+    }
+}
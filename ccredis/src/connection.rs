@@ -0,0 +1,200 @@
+use std::{sync::{atomic::{AtomicUsize, Ordering}, Arc}, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufWriter, ReadHalf, WriteHalf},
+    sync::{mpsc, OwnedRwLockReadGuard},
+};
+
+use crate::{
+    client_registry::Client,
+    config::Config,
+    debug,
+    message_processor::MessageProcessor,
+    processing_error::ProcessingError,
+    resp::{message::Message, message_parser::MessageParser},
+};
+
+const READ_BUFFER_SIZE: usize = 16 * 1024;
+const UNPAUSE_POLL_INTERVAL_MS: u128 = 100;
+
+// Every message sent to the client goes through this queue already serialized:
+// command responses as well as messages pushed by other parts of the server
+// (e.g. blocking pops or pub/sub). Queue is drained by connection writer task.
+pub type Outbound = mpsc::UnboundedSender<Outgoing>;
+
+pub enum Outgoing {
+    Data(Vec<u8>),
+    // full sync payload of a replica, not counted in client output memory
+    Snapshot(Vec<u8>),
+    // sent when connection is closed, other parts of the server may still hold the sender
+    Close,
+}
+
+// Number of currently connected clients, used for `maxclients` check.
+// Slot is taken on accept and released when connection is closed.
+#[derive(Clone, Default)]
+pub struct ConnectedClients(Arc<AtomicUsize>);
+
+pub struct ClientSlot(Arc<AtomicUsize>);
+
+impl ConnectedClients {
+    pub fn try_acquire(&self, max_clients: usize) -> Option<ClientSlot> {
+        self.0
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |count| (count < max_clients).then_some(count + 1))
+            .ok()
+            .map(|_| ClientSlot(self.0.clone()))
+    }
+}
+
+impl Drop for ClientSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub async fn reject_client<S: AsyncWrite + Unpin>(mut stream: S) {
+    let mut buf: Vec<u8> = Vec::new();
+    let _ = Message::error("ERR max number of clients reached").write_to(&mut buf);
+    let _ = stream.write_all(&buf).await;
+    let _ = stream.shutdown().await;
+}
+
+pub async fn handle_client<S>(stream: S, addr: String, laddr: String, message_processor: MessageProcessor, config: Arc<Config>)
+where
+    S: AsyncRead + AsyncWrite + Send + 'static,
+{
+    println!("[TCP] Client connected");
+    let (outbound, outbound_queue) = mpsc::unbounded_channel();
+    let client = message_processor.clients.register(&addr, &laddr, outbound);
+    client.set_output_limits(config.client_output_buffer_limit_normal, config.client_output_buffer_limit_replica);
+    if !message_processor.acl.default_user_requires_auth() {
+        client.login("default");
+    }
+    let message_processor = message_processor.with_client(client.clone());
+
+    let (reader, writer) = tokio::io::split(stream);
+    let writer_task = tokio::spawn(write_outbound(writer, outbound_queue, client.clone()));
+
+    read_requests(reader, &message_processor, &client, &config).await;
+    message_processor.clients.unregister(client.id);
+    message_processor.replication.remove_replica(client.id);
+    message_processor.memory.watches.unwatch(client.id);
+    message_processor.pubsub.remove_client(client.id);
+
+    // writer finishes after all messages queued before close are sent,
+    // client over output buffer limit may not read them at all
+    if client.is_output_overflow() {
+        writer_task.abort();
+    } else {
+        client.close();
+        let _ = writer_task.await;
+    }
+    println!("[TCP] Connection closed");
+}
+
+async fn read_requests<S: AsyncRead>(mut reader: ReadHalf<S>, message_processor: &MessageProcessor, client: &Client, config: &Config) {
+    let mut parser = MessageParser::with_limits(config.proto_max_bulk_len, config.proto_max_multibulk_len);
+    let mut buf = vec![0u8; READ_BUFFER_SIZE];
+    loop {
+        let read_result = tokio::select! {
+            read_result = reader.read(&mut buf) => read_result,
+            _ = client.killed() => return,
+            // monitors and replicas only receive data, Redis doesn't close them either
+            _ = idle_timeout(if client.is_monitor() || client.is_replica() { 0 } else { config.timeout }) => {
+                println!("[TCP] Closing idle client");
+                return
+            }
+        };
+        let read = match read_result {
+            Ok(0) => return,
+            Ok(read) => read,
+            Err(err) => {
+                println!("[TCP] Failed to read: {}", err);
+                return
+            }
+        };
+
+        for &byte in &buf[..read] {
+            match parser.add_byte(byte) {
+                Ok(Some(message)) => {
+                    wait_for_unpause(message_processor, &message).await;
+                    debug(&format!("Received request: {:?}", message));
+                    let response = match enter_script_gate(message_processor, &message).await {
+                        Err(err) => Message::Error(err.to_string()),
+                        Ok(_gate) if MessageProcessor::may_block(&message) => {
+                            // runtime workers keep serving I/O meanwhile and can read SCRIPT KILL
+                            let message_processor = message_processor.clone();
+                            tokio::task::spawn_blocking(move || message_processor.process_resp_message(&message)).await.expect("Blocking command panicked")
+                        },
+                        Ok(_gate) => message_processor.process_resp_message(&message),
+                    };
+                    debug(&format!("Sending response: {:?}", response));
+                    // SHUTDOWN succeeded, connection is closed without reply
+                    if message_processor.shutdown.is_exiting() {
+                        return
+                    }
+                    // replica connection gets only replication stream
+                    if !client.is_replica() && !client.send(&response) {
+                        return
+                    }
+                },
+                Err(err) => {
+                    // stream position is unknown after protocol error, so there is no way to recover
+                    println!("[Parser] Failed to parse byte [{}]", err);
+                    client.send(&Message::Error(format!("ERR Protocol error: {}", err)));
+                    return
+                },
+                Ok(None) => {} // message is not parsed yet
+            }
+        }
+        client.query_buffer.store(parser.buffered_len(), Ordering::Relaxed);
+    }
+}
+
+async fn idle_timeout(timeout_seconds: u64) {
+    if timeout_seconds == 0 {
+        std::future::pending::<()>().await;
+    }
+    tokio::time::sleep(Duration::from_secs(timeout_seconds)).await;
+}
+
+// CLIENT PAUSE holds commands until pause is over, it can be lifted early by CLIENT UNPAUSE
+// or failed shutdown
+async fn wait_for_unpause(message_processor: &MessageProcessor, message: &Message) {
+    let is_write_command = message_processor.is_write_command(message);
+    while let Some(remaining) = message_processor.clients.pause_remaining(is_write_command) {
+        tokio::time::sleep(Duration::from_millis(remaining.min(UNPAUSE_POLL_INTERVAL_MS) as u64)).await;
+    }
+}
+
+// Command waits for running script here rather than on shard locks in a runtime worker,
+// so SCRIPT KILL can still be read. Once script is busy, command gets BUSY.
+// Commands which don't run on runtime workers wait for shards locked by the script instead.
+async fn enter_script_gate(message_processor: &MessageProcessor, message: &Message) -> Result<Option<OwnedRwLockReadGuard<()>>, ProcessingError> {
+    if MessageProcessor::allows_busy(message) || MessageProcessor::may_block(message) {
+        return Ok(None);
+    }
+    message_processor.scripting.enter().await.map(Some)
+}
+
+async fn write_outbound<S: AsyncWrite>(writer: WriteHalf<S>, mut outbound_queue: mpsc::UnboundedReceiver<Outgoing>, client: Arc<Client>) {
+    let mut writer = BufWriter::new(writer);
+    loop {
+        let (buf, accounted) = match outbound_queue.recv().await {
+            Some(Outgoing::Data(buf)) => (buf, true),
+            Some(Outgoing::Snapshot(buf)) => (buf, false),
+            Some(Outgoing::Close) | None => break,
+        };
+        if writer.write_all(&buf).await.is_err() {
+            break;
+        }
+        if accounted {
+            client.output_memory.fetch_sub(buf.len(), Ordering::Relaxed);
+        }
+        // flush only when queue is drained, so pipelined responses are sent together
+        if outbound_queue.is_empty() && writer.flush().await.is_err() {
+            break;
+        }
+    }
+    let _ = writer.shutdown().await;
+}
@@ -0,0 +1,231 @@
+use std::{fmt, sync::Arc};
+
+use crate::{
+    acl::Acl,
+    client_registry::ClientRegistry,
+    config::Config,
+    latency::Latency,
+    message_processor::{MessageProcessor, SharedMemory},
+    pubsub::PubSub,
+    replication::Replication,
+    resp::{message::Message, message_parser::MessageParser},
+    scripting::Scripting,
+    shutdown::Shutdown,
+    slowlog::SlowLog,
+    stats::Stats,
+};
+
+// length of `*<count>\r\n` header of snapshot
+const MAX_HEADER_LENGTH: usize = 32;
+
+// Db file read command by command, so damage is found at exact byte and everything before it
+// can be kept. SAVE writes snapshot as one array of commands, AOF would be a plain sequence of them.
+pub struct DbFile {
+    bytes: Vec<u8>,
+    // true for snapshot arrays, false for sequences of plain commands
+    segments: Vec<bool>,
+    // complete commands before corruption
+    pub records: Vec<Record>,
+    pub corruption: Option<Corruption>,
+}
+
+pub struct Record {
+    segment: usize,
+    pub start: usize,
+    pub end: usize,
+    pub command: Message,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Corruption {
+    pub offset: usize,
+    pub reason: String,
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "at byte {}: {}", self.offset, self.reason)
+    }
+}
+
+impl DbFile {
+    pub fn parse(bytes: Vec<u8>) -> DbFile {
+        let mut file = DbFile { bytes: Vec::new(), segments: Vec::new(), records: Vec::new(), corruption: None };
+        file.corruption = file.scan(&bytes).err();
+        file.bytes = bytes;
+        file
+    }
+
+    // bytes of the last complete command
+    pub fn valid_len(&self) -> usize {
+        self.records.last().map_or(0, |record| record.end)
+    }
+
+    // File without damaged part, snapshot headers are rewritten with the number of kept commands
+    pub fn repaired(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        for (segment, &wrapped) in self.segments.iter().enumerate() {
+            let records: Vec<&Record> = self.records.iter().filter(|record| record.segment == segment).collect();
+            if wrapped {
+                buf.extend_from_slice(format!("*{}\r\n", records.len()).as_bytes());
+            }
+            for record in records {
+                buf.extend_from_slice(&self.bytes[record.start..record.end]);
+            }
+        }
+        buf
+    }
+
+    fn scan(&mut self, bytes: &[u8]) -> Result<(), Corruption> {
+        let mut position = 0;
+        while position < bytes.len() {
+            if let Some((header_len, count)) = snapshot_header(&bytes[position..]) {
+                self.segments.push(true);
+                position += header_len;
+                for read in 0..count {
+                    if position == bytes.len() {
+                        let reason = format!("unexpected end of file, {} of {} commands of snapshot are read", read, count);
+                        return Err(Corruption { offset: position, reason });
+                    }
+                    position = self.read_record(bytes, position)?;
+                }
+            } else {
+                if self.segments.last() != Some(&false) {
+                    self.segments.push(false);
+                }
+                position = self.read_record(bytes, position)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn read_record(&mut self, bytes: &[u8], start: usize) -> Result<usize, Corruption> {
+        let mut parser = MessageParser::new();
+        for (offset, &byte) in bytes.iter().enumerate().skip(start) {
+            match parser.add_byte(byte) {
+                Ok(Some(command)) if is_command(&command) => {
+                    self.records.push(Record { segment: self.segments.len() - 1, start, end: offset + 1, command });
+                    return Ok(offset + 1);
+                },
+                Ok(Some(_)) => return Err(Corruption { offset: start, reason: "expected array of bulk strings".to_string() }),
+                Ok(None) => {} // command is not parsed yet
+                Err(err) => return Err(Corruption { offset, reason: err.to_string() }),
+            }
+        }
+        Err(Corruption { offset: bytes.len(), reason: "unexpected end of file".to_string() })
+    }
+}
+
+// `*<count>\r\n` followed by array is a snapshot, empty snapshot is `*0\r\n`
+fn snapshot_header(bytes: &[u8]) -> Option<(usize, usize)> {
+    let line_end = bytes.iter().take(MAX_HEADER_LENGTH).position(|&byte| byte == b'\n')?;
+    let line = bytes[..line_end + 1].strip_prefix(b"*")?.strip_suffix(b"\r\n")?;
+    let count: usize = std::str::from_utf8(line).ok()?.parse().ok()?;
+    (count == 0 || bytes.get(line_end + 1) == Some(&b'*')).then_some((line_end + 1, count))
+}
+
+fn is_command(message: &Message) -> bool {
+    match message {
+        Message::Array(Some(items)) => !items.is_empty() && items.iter().all(|item| matches!(item, Message::BulkString(Some(_)))),
+        _ => false,
+    }
+}
+
+// Runs commands of the file against `memory` and stops at the first failed one,
+// which is reported as corruption with commands before it kept.
+pub fn replay(file: &mut DbFile, memory: SharedMemory, config: Arc<Config>) {
+    let message_processor = MessageProcessor {
+        memory,
+        db_file_path: None,
+        clients: Arc::new(ClientRegistry::default()),
+        acl: Arc::new(Acl::default()),
+        replication: Arc::new(Replication::new(&Config::default())),
+        cluster: None,
+        slowlog: Arc::new(SlowLog::new(&Config::default())),
+        latency: Arc::new(Latency::new(&Config::default())),
+        scripting: Arc::new(Scripting::new(&Config::default())),
+        config,
+        stats: Arc::new(Stats::default()),
+        shutdown: Arc::new(Shutdown::default()),
+        pubsub: Arc::new(PubSub::default()),
+        client: None,
+    };
+    let failed = file.records.iter().enumerate().find_map(|(index, record)| {
+        match message_processor.process_resp_message(&record.command) {
+            Message::Error(err) => Some((index, Corruption { offset: record.start, reason: format!("command failed: {}", err) })),
+            _ => None,
+        }
+    });
+    if let Some((index, corruption)) = failed {
+        file.records.truncate(index);
+        file.corruption = Some(corruption);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::keyspace::Keyspace;
+
+    use super::*;
+
+    const SNAPSHOT: &[u8] = b"*2\r\n*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*3\r\n$5\r\nRPUSH\r\n$1\r\nl\r\n$1\r\nx\r\n";
+
+    #[test]
+    fn test_valid_files() {
+        let file = DbFile::parse(SNAPSHOT.to_vec());
+        assert_eq!(file.corruption, None);
+        assert_eq!(file.records.len(), 2);
+        assert_eq!(file.valid_len(), SNAPSHOT.len());
+        assert_eq!(file.repaired(), SNAPSHOT);
+
+        // plain sequence of commands, as AOF
+        let file = DbFile::parse(SNAPSHOT[4..].to_vec());
+        assert_eq!(file.corruption, None);
+        assert_eq!(file.records.len(), 2);
+
+        assert_eq!(DbFile::parse(b"*0\r\n".to_vec()).corruption, None);
+        assert_eq!(DbFile::parse(Vec::new()).corruption, None);
+    }
+
+    #[test]
+    fn test_truncated_snapshot() {
+        let mut bytes = SNAPSHOT[..SNAPSHOT.len() - 5].to_vec();
+        let file = DbFile::parse(bytes.clone());
+        assert_eq!(file.corruption, Some(Corruption { offset: bytes.len(), reason: "unexpected end of file".to_string() }));
+        assert_eq!(file.records.len(), 1);
+        assert_eq!(file.repaired(), b"*1\r\n*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n");
+
+        bytes.truncate(file.valid_len());
+        let file = DbFile::parse(bytes);
+        assert_eq!(file.corruption.as_ref().unwrap().reason, "unexpected end of file, 1 of 2 commands of snapshot are read");
+        assert_eq!(DbFile::parse(file.repaired()).corruption, None);
+    }
+
+    #[test]
+    fn test_damaged_byte() {
+        let mut bytes = SNAPSHOT.to_vec();
+        bytes[31] = b'#';
+        let file = DbFile::parse(bytes);
+        assert_eq!(file.corruption, Some(Corruption { offset: 31, reason: "Invalid byte encountered: 0x23".to_string() }));
+        assert_eq!(file.records.len(), 1);
+
+        let file = DbFile::parse(b"*1\r\n:1\r\n".to_vec());
+        assert_eq!(file.corruption, Some(Corruption { offset: 0, reason: "expected array of bulk strings".to_string() }));
+    }
+
+    #[test]
+    fn test_replay() {
+        let memory: SharedMemory = Arc::new(Keyspace::default());
+        let mut bytes = SNAPSHOT.to_vec();
+        bytes.extend_from_slice(b"*2\r\n$4\r\nINCR\r\n$1\r\nl\r\n*2\r\n$3\r\nDEL\r\n$1\r\na\r\n");
+        let mut file = DbFile::parse(bytes);
+        replay(&mut file, memory.clone(), Arc::new(Config::default()));
+
+        let corruption = file.corruption.unwrap();
+        assert_eq!(corruption.offset, SNAPSHOT.len());
+        assert!(corruption.reason.starts_with("command failed: WRONGTYPE"), "{}", corruption.reason);
+        assert_eq!(file.records.len(), 2);
+        // commands after the failed one are not run
+        assert!(memory.read("a").contains_key("a"));
+    }
+}
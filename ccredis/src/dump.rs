@@ -0,0 +1,177 @@
+use crate::{
+    encoding::{ListValue, StringValue},
+    message_processor::Value,
+    processing_error::ProcessingError,
+};
+
+// Payload of DUMP: type byte, value, format version (2 bytes) and CRC64 of all previous bytes (8 bytes),
+// both little endian as in Redis. The format is ccredis own, payloads of Redis are rejected by version.
+const DUMP_VERSION: u16 = 1;
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+
+pub fn serialize(value: &Value) -> Vec<u8> {
+    let mut payload = Vec::new();
+    match value {
+        Value::Single(content) => {
+            payload.push(TYPE_STRING);
+            write_string(&mut payload, &content.to_vec());
+        },
+        Value::List(list) => {
+            payload.push(TYPE_LIST);
+            write_length(&mut payload, list.len());
+            for element in list.iter() {
+                write_string(&mut payload, element);
+            }
+        },
+    }
+    payload.extend_from_slice(&DUMP_VERSION.to_le_bytes());
+    let crc = crc64(&payload);
+    payload.extend_from_slice(&crc.to_le_bytes());
+    payload
+}
+
+// lists are encoded according to `list-max-listpack-size`
+pub fn deserialize(payload: &[u8], list_max_listpack_size: i64) -> Result<Value, ProcessingError> {
+    let invalid = || ProcessingError::from("ERR DUMP payload version or checksum are wrong");
+    let (body, crc) = payload.split_at_checked(payload.len().checked_sub(8).ok_or_else(invalid)?).ok_or_else(invalid)?;
+    if crc64(body).to_le_bytes() != crc {
+        return Err(invalid());
+    }
+    let (body, version) = body.split_at_checked(body.len().checked_sub(2).ok_or_else(invalid)?).ok_or_else(invalid)?;
+    if u16::from_le_bytes([version[0], version[1]]) > DUMP_VERSION {
+        return Err(invalid());
+    }
+
+    let mut reader = Reader { bytes: body, position: 0 };
+    let value = match reader.byte()? {
+        TYPE_STRING => Value::Single(StringValue::new(reader.string()?)),
+        TYPE_LIST => {
+            let length = reader.length()?;
+            let elements = (0..length).map(|_| reader.string()).collect::<Result<Vec<_>, _>>()?;
+            Value::List(ListValue::from_elements(elements, list_max_listpack_size))
+        },
+        _ => return Err("ERR Bad data format".into()),
+    };
+    if reader.position != body.len() {
+        return Err("ERR Bad data format".into());
+    }
+    Ok(value)
+}
+
+// RDB length encoding: 6 bit, 14 bit, 32 bit or 64 bit length
+fn write_length(payload: &mut Vec<u8>, length: usize) {
+    if length < 1 << 6 {
+        payload.push(length as u8);
+    } else if length < 1 << 14 {
+        payload.extend_from_slice(&(length as u16 | 0x4000).to_be_bytes());
+    } else if length <= u32::MAX as usize {
+        payload.push(0x80);
+        payload.extend_from_slice(&(length as u32).to_be_bytes());
+    } else {
+        payload.push(0x81);
+        payload.extend_from_slice(&(length as u64).to_be_bytes());
+    }
+}
+
+fn write_string(payload: &mut Vec<u8>, content: &[u8]) {
+    write_length(payload, content.len());
+    payload.extend_from_slice(content);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], ProcessingError> {
+        let end = self.position.checked_add(count).filter(|end| *end <= self.bytes.len()).ok_or("ERR Bad data format")?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, ProcessingError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn length(&mut self) -> Result<usize, ProcessingError> {
+        let first = self.byte()?;
+        let length = match first >> 6 {
+            0 => first as u64,
+            1 => u16::from_be_bytes([first & 0x3f, self.byte()?]) as u64,
+            _ if first == 0x80 => u32::from_be_bytes(self.take(4)?.try_into().expect("4 bytes are taken")) as u64,
+            _ if first == 0x81 => u64::from_be_bytes(self.take(8)?.try_into().expect("8 bytes are taken")),
+            _ => return Err("ERR Bad data format".into()),
+        };
+        usize::try_from(length).map_err(|_| "ERR Bad data format".into())
+    }
+
+    fn string(&mut self) -> Result<Vec<u8>, ProcessingError> {
+        let length = self.length()?;
+        Ok(self.take(length)?.to_vec())
+    }
+}
+
+// CRC-64/Jones, the same function Redis uses for DUMP payloads
+fn crc64(bytes: &[u8]) -> u64 {
+    let mut crc: u64 = 0;
+    for &byte in bytes {
+        crc ^= byte as u64;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x95ac9329ac4bc9b5 } else { crc >> 1 };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn deserialize(payload: &[u8]) -> Result<Value, ProcessingError> {
+        super::deserialize(payload, -2)
+    }
+
+    #[test]
+    fn test_crc64() {
+        assert_eq!(crc64(b"123456789"), 0xe9c6d914c4b8d9ca);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let long = vec![b'x'; 20000];
+        let values = [
+            Value::from(""),
+            Value::from("value"),
+            Value::Single(StringValue::new(long.clone())),
+            Value::List(ListValue::from_elements([b"a".to_vec(), vec![b'y'; 100], long], -2)),
+            Value::List(ListValue::default()),
+        ];
+        for value in values {
+            assert_eq!(deserialize(&serialize(&value)).unwrap(), value);
+        }
+        assert_eq!(serialize(&Value::from("ab")), [0, 2, b'a', b'b', 1, 0, 0x49, 0xfe, 0x22, 0xa5, 0x91, 0xcc, 0xac, 0x46]);
+    }
+
+    #[test]
+    fn test_corrupted_payload() {
+        let mut payload = serialize(&Value::from("value"));
+        payload[2] = b'V';
+        assert_eq!(deserialize(&payload).unwrap_err().to_string(), "ERR DUMP payload version or checksum are wrong");
+        assert!(deserialize(b"short").is_err());
+
+        // newer version
+        let mut payload = vec![TYPE_STRING, 0, 2, 0];
+        let crc = crc64(&payload);
+        payload.extend_from_slice(&crc.to_le_bytes());
+        assert!(deserialize(&payload).is_err());
+
+        // valid checksum of truncated value
+        let mut payload = vec![TYPE_STRING, 5, b'a', 1, 0];
+        let crc = crc64(&payload);
+        payload.extend_from_slice(&crc.to_le_bytes());
+        assert_eq!(deserialize(&payload).unwrap_err().to_string(), "ERR Bad data format");
+    }
+}
@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+
+// strings up to this length are stored inline, so the value fits into the size of Vec header
+const EMBSTR_SIZE: usize = 22;
+// rough size of list element header in linked encoding
+pub const LIST_ELEMENT_OVERHEAD: usize = 24;
+// packed list limits for negative `list-max-listpack-size`, from -1 to -5
+const LISTPACK_BYTES: [usize; 5] = [4096, 8192, 16384, 32768, 65536];
+// packed list limited by number of entries still can't grow past this size
+const LISTPACK_SAFETY_BYTES: usize = 8192;
+
+// Internal representation of string value, reported by OBJECT ENCODING
+#[derive(Debug, Clone)]
+pub enum StringValue {
+    // integer in canonical form, e.g. counter
+    Int(i64),
+    // short string without heap allocation
+    Embedded(u8, [u8; EMBSTR_SIZE]),
+    Raw(Vec<u8>),
+}
+
+impl StringValue {
+    pub fn new(content: Vec<u8>) -> StringValue {
+        // "007" or "+7" are not integers in canonical form, they would be replied differently
+        if let Some(integer) = std::str::from_utf8(&content).ok().and_then(|text| text.parse::<i64>().ok().filter(|integer| integer.to_string() == text)) {
+            return StringValue::Int(integer);
+        }
+        if content.len() <= EMBSTR_SIZE {
+            let mut bytes = [0u8; EMBSTR_SIZE];
+            bytes[..content.len()].copy_from_slice(&content);
+            return StringValue::Embedded(content.len() as u8, bytes);
+        }
+        StringValue::Raw(content)
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            StringValue::Int(integer) => Some(*integer),
+            _ => std::str::from_utf8(&self.to_vec()).ok()?.parse().ok(),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<u8> {
+        match self {
+            StringValue::Int(integer) => integer.to_string().into_bytes(),
+            StringValue::Embedded(len, bytes) => bytes[..*len as usize].to_vec(),
+            StringValue::Raw(content) => content.clone(),
+        }
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            StringValue::Int(_) => "int",
+            StringValue::Embedded(..) => "embstr",
+            StringValue::Raw(_) => "raw",
+        }
+    }
+
+    // bytes allocated outside of the value itself
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            StringValue::Raw(content) => content.len(),
+            _ => 0,
+        }
+    }
+}
+
+impl PartialEq for StringValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.to_vec() == other.to_vec()
+    }
+}
+
+// Internal representation of list value. Small lists are packed into one buffer
+// and converted to linked elements past `list-max-listpack-size`.
+#[derive(Debug, Clone)]
+pub enum ListValue {
+    // elements one after another, each prefixed by its length
+    Packed { bytes: Vec<u8>, len: usize },
+    Linked(VecDeque<Vec<u8>>),
+}
+
+impl Default for ListValue {
+    fn default() -> Self {
+        ListValue::Packed { bytes: Vec::new(), len: 0 }
+    }
+}
+
+impl ListValue {
+    pub fn from_elements(elements: impl IntoIterator<Item = Vec<u8>>, max_size: i64) -> ListValue {
+        let mut list = ListValue::default();
+        for element in elements {
+            list.push_back(element, max_size);
+        }
+        list
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            ListValue::Packed { len, .. } => *len,
+            ListValue::Linked(list) => list.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn push_front(&mut self, element: Vec<u8>, max_size: i64) {
+        match self {
+            ListValue::Packed { bytes, len } => {
+                let mut packed = Vec::with_capacity(bytes.len() + element.len() + 4);
+                pack(&mut packed, &element);
+                packed.extend_from_slice(bytes);
+                *bytes = packed;
+                *len += 1;
+            },
+            ListValue::Linked(list) => list.push_front(element),
+        }
+        self.convert_if_large(max_size);
+    }
+
+    pub fn push_back(&mut self, element: Vec<u8>, max_size: i64) {
+        match self {
+            ListValue::Packed { bytes, len } => {
+                pack(bytes, &element);
+                *len += 1;
+            },
+            ListValue::Linked(list) => list.push_back(element),
+        }
+        self.convert_if_large(max_size);
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &[u8]> + '_> {
+        match self {
+            ListValue::Packed { bytes, .. } => Box::new(Unpacked { bytes }),
+            ListValue::Linked(list) => Box::new(list.iter().map(|element| element.as_slice())),
+        }
+    }
+
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            ListValue::Packed { .. } => "listpack",
+            ListValue::Linked(_) => "quicklist",
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            ListValue::Packed { bytes, .. } => bytes.len(),
+            ListValue::Linked(list) => list.iter().map(|element| element.len() + LIST_ELEMENT_OVERHEAD).sum(),
+        }
+    }
+
+    // positive max size limits number of entries, negative one limits bytes
+    fn convert_if_large(&mut self, max_size: i64) {
+        let ListValue::Packed { bytes, len } = self else {
+            return;
+        };
+        let too_large = if max_size > 0 {
+            *len > max_size as usize || bytes.len() > LISTPACK_SAFETY_BYTES
+        } else {
+            bytes.len() > LISTPACK_BYTES[(max_size.unsigned_abs().clamp(1, 5) - 1) as usize]
+        };
+        if too_large {
+            *self = ListValue::Linked(self.iter().map(|element| element.to_vec()).collect());
+        }
+    }
+}
+
+impl PartialEq for ListValue {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter().eq(other.iter())
+    }
+}
+
+// length takes one byte up to 127, four bytes with the highest bit set otherwise
+fn pack(bytes: &mut Vec<u8>, element: &[u8]) {
+    if element.len() < 0x80 {
+        bytes.push(element.len() as u8);
+    } else {
+        bytes.extend_from_slice(&(element.len() as u32 | 0x8000_0000).to_be_bytes());
+    }
+    bytes.extend_from_slice(element);
+}
+
+struct Unpacked<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Iterator for Unpacked<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        let first = *self.bytes.first()?;
+        let (header, len) = if first < 0x80 {
+            (1, first as usize)
+        } else {
+            (4, (u32::from_be_bytes(self.bytes[..4].try_into().expect("4 bytes of length")) & 0x7fff_ffff) as usize)
+        };
+        let (element, rest) = self.bytes[header..].split_at(len);
+        self.bytes = rest;
+        Some(element)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_string_encodings() {
+        let encodings = [("12345", "int"), ("-9223372036854775808", "int"), ("007", "embstr"), ("+7", "embstr"), ("", "embstr"), ("short string", "embstr")];
+        for (content, encoding) in encodings {
+            let value = StringValue::new(content.into());
+            assert_eq!(value.encoding(), encoding, "{}", content);
+            assert_eq!(value.to_vec(), content.as_bytes());
+            assert_eq!(value.memory_usage(), 0);
+        }
+        let long = StringValue::new(vec![b'x'; 23]);
+        assert_eq!(long.encoding(), "raw");
+        assert_eq!(long.memory_usage(), 23);
+        assert_eq!(StringValue::new("007".into()).as_int(), Some(7));
+        assert_eq!(long.as_int(), None);
+    }
+
+    #[test]
+    fn test_list_conversion() {
+        let mut list = ListValue::from_elements([b"b".to_vec(), vec![b'y'; 200]], 3);
+        list.push_front(b"a".to_vec(), 3);
+        assert_eq!(list.encoding(), "listpack");
+        assert_eq!(list.memory_usage(), 2 + 2 + 204);
+        assert_eq!(list.iter().collect::<Vec<_>>(), [&b"a"[..], b"b", &[b'y'; 200]]);
+
+        list.push_back(b"c".to_vec(), 3);
+        assert_eq!(list.encoding(), "quicklist");
+        assert_eq!(list.len(), 4);
+        assert_eq!(list, ListValue::from_elements([b"a".to_vec(), b"b".to_vec(), vec![b'y'; 200], b"c".to_vec()], 10));
+
+        let list = ListValue::from_elements((0..50).map(|_| vec![b'z'; 100]), -1);
+        assert_eq!(list.encoding(), "quicklist");
+        let list = ListValue::from_elements((0..50).map(|_| vec![b'z'; 100]), -2);
+        assert_eq!(list.encoding(), "listpack");
+    }
+}
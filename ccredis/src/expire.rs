@@ -0,0 +1,107 @@
+use std::{sync::atomic::Ordering, time::{Duration, Instant}};
+
+use rand::Rng;
+
+use crate::{keyspace::Keyspace, message_processor::now};
+
+// keys with expiration sampled at once
+const KEYS_PER_LOOP: usize = 20;
+// sampling is repeated while more than this percent of sampled keys were expired
+const ACCEPTABLE_STALE_PERCENT: usize = 25;
+// percent of every `hz` period that active expiration may take
+pub const CYCLE_TIME_PERCENT: u32 = 25;
+
+// Removes expired keys the way Redis does: samples random keys with expiration and
+// keeps going while many of them were expired, until `time_limit` is reached.
+// Shards are visited one by one starting from random one and locked only for one loop,
+// so clients are not blocked for the whole cycle.
+// `on_expired` is called while shard of the key is locked. Returns number of removed keys.
+pub fn active_expire_cycle(keyspace: &Keyspace, time_limit: Duration, mut on_expired: impl FnMut(&str)) -> usize {
+    let started_at = Instant::now();
+    let start = rand::thread_rng().gen_range(0..keyspace.shard_count());
+    let mut expired = 0;
+    for index in (start..start + keyspace.shard_count()).map(|index| index % keyspace.shard_count()) {
+        loop {
+            let mut shard = keyspace.write_shard(index);
+            let timestamp = now();
+            let sampled = KEYS_PER_LOOP.min(shard.expires_len());
+            let mut expired_in_loop = 0;
+
+            for _ in 0..sampled {
+                let Some((key, expire_at)) = shard.random_volatile_key() else {
+                    break;
+                };
+                if timestamp > expire_at {
+                    let key = key.clone();
+                    shard.remove(&key);
+                    on_expired(&key);
+                    expired_in_loop += 1;
+                }
+            }
+            keyspace.expired_keys.fetch_add(expired_in_loop as u64, Ordering::Relaxed);
+            expired += expired_in_loop;
+
+            if started_at.elapsed() >= time_limit {
+                keyspace.expired_time_cap_reached_count.fetch_add(1, Ordering::Relaxed);
+                return expired;
+            }
+            if sampled == 0 || expired_in_loop * 100 <= sampled * ACCEPTABLE_STALE_PERCENT {
+                break;
+            }
+        }
+    }
+    expired
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_processor::travel_to;
+
+    fn keyspace(keys: &[(String, u128)]) -> Keyspace {
+        let keyspace = Keyspace::default();
+        for (key, expire_at) in keys {
+            let mut shard = keyspace.write(key);
+            shard.insert(key.clone(), "value".into());
+            shard.set_expire(key, Some(*expire_at));
+        }
+        keyspace
+    }
+
+    #[test]
+    fn test_cycle_repeats_while_many_keys_expired() {
+        let keys: Vec<(String, u128)> = (0..1000).map(|index| (format!("key{}", index), 10)).collect();
+        let keyspace = keyspace(&keys);
+        travel_to(100);
+
+        let mut expired: Vec<String> = Vec::new();
+        assert_eq!(active_expire_cycle(&keyspace, Duration::from_secs(10), |key| expired.push(key.to_string())), 1000);
+        assert_eq!(expired.len(), 1000);
+        assert!(keyspace.is_empty());
+        assert_eq!(keyspace.expired_keys.load(Ordering::Relaxed), 1000);
+    }
+
+    #[test]
+    fn test_cycle_stops_when_few_keys_expired() {
+        let mut keys: Vec<(String, u128)> = (0..1000).map(|index| (format!("live{}", index), 1000)).collect();
+        keys.push(("expired".to_string(), 10));
+        let keyspace = keyspace(&keys);
+        travel_to(100);
+
+        let mut expired: Vec<String> = Vec::new();
+        active_expire_cycle(&keyspace, Duration::from_secs(10), |key| expired.push(key.to_string()));
+        assert!(expired.iter().all(|key| key == "expired"));
+        assert!(keyspace.expires_len() >= 1000);
+    }
+
+    #[test]
+    fn test_cycle_time_limit() {
+        let keys: Vec<(String, u128)> = (0..1000).map(|index| (format!("key{}", index), 10)).collect();
+        let keyspace = keyspace(&keys);
+        travel_to(100);
+
+        assert_eq!(active_expire_cycle(&keyspace, Duration::ZERO, |_| {}), KEYS_PER_LOOP);
+        assert_eq!(keyspace.len(), 1000 - KEYS_PER_LOOP);
+        assert_eq!(keyspace.expired_time_cap_reached_count.load(Ordering::Relaxed), 1);
+    }
+}
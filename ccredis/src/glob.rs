@@ -0,0 +1,121 @@
+// Redis style glob matching (same rules as KEYS and ACL patterns):
+// `*` any sequence, `?` any single byte, `[abc]`, `[^abc]`, `[a-z]` character classes, `\x` escape.
+// Iterative: on mismatch only the last `*` is retried one byte further, so matching takes
+// O(pattern * string) steps even for patterns like `*a*a*a*b`.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    let (mut pattern_position, mut string_position) = (0, 0);
+    // pattern position after the last star and string position it was matched against
+    let mut last_star: Option<(usize, usize)> = None;
+    while string_position < string.len() {
+        if pattern.get(pattern_position) == Some(&b'*') {
+            pattern_position += 1;
+            last_star = Some((pattern_position, string_position));
+            continue;
+        }
+        if let Some(next) = match_single(pattern, pattern_position, string[string_position]) {
+            pattern_position = next;
+            string_position += 1;
+            continue;
+        }
+        // star takes one more byte
+        let Some((star_pattern_position, star_string_position)) = last_star else {
+            return false;
+        };
+        pattern_position = star_pattern_position;
+        string_position = star_string_position + 1;
+        last_star = Some((star_pattern_position, string_position));
+    }
+    pattern[pattern_position..].iter().all(|&byte| byte == b'*')
+}
+
+// matches one byte against pattern element at `position`, returns position of the next element
+fn match_single(pattern: &[u8], position: usize, byte: u8) -> Option<usize> {
+    match &pattern[position..] {
+        [] => None,
+        [b'?', ..] => Some(position + 1),
+        [b'[', class @ ..] => match match_class(class, byte) {
+            Some((true, rest)) => Some(pattern.len() - rest.len()),
+            Some((false, _)) => None,
+            // unterminated class is matched literally
+            None => (byte == b'[').then_some(position + 1),
+        },
+        [b'\\', escaped, ..] => (*escaped == byte).then_some(position + 2),
+        [literal, ..] => (*literal == byte).then_some(position + 1),
+    }
+}
+
+// matches byte against class body (after `[`), returns match result and pattern after `]`
+fn match_class(class: &[u8], byte: u8) -> Option<(bool, &[u8])> {
+    let (negated, mut class) = match class.split_first() {
+        Some((b'^', rest)) => (true, rest),
+        _ => (false, class),
+    };
+    let mut matched = false;
+    loop {
+        match class {
+            [] => return None,
+            [b']', rest @ ..] => return Some((matched != negated, rest)),
+            [b'\\', escaped, rest @ ..] => {
+                matched |= *escaped == byte;
+                class = rest;
+            }
+            [from, b'-', to, rest @ ..] if *to != b']' => {
+                let (from, to) = if from <= to { (*from, *to) } else { (*to, *from) };
+                matched |= (from..=to).contains(&byte);
+                class = rest;
+            }
+            [literal, rest @ ..] => {
+                matched |= *literal == byte;
+                class = rest;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, string: &str) -> bool {
+        glob_match(pattern.as_bytes(), string.as_bytes())
+    }
+
+    #[test]
+    fn match_literals_and_wildcards() {
+        assert!(matches("foo", "foo"));
+        assert!(!matches("foo", "foobar"));
+        assert!(matches("*", ""));
+        assert!(matches("foo*", "foobar"));
+        assert!(matches("*bar", "foobar"));
+        assert!(matches("f*o*r", "foobar"));
+        assert!(!matches("f*z", "foobar"));
+        assert!(matches("h?llo", "hello"));
+        assert!(!matches("h?llo", "hllo"));
+    }
+
+    #[test]
+    fn match_classes() {
+        assert!(matches("h[ae]llo", "hallo"));
+        assert!(!matches("h[ae]llo", "hillo"));
+        assert!(matches("h[^e]llo", "hallo"));
+        assert!(!matches("h[^e]llo", "hello"));
+        assert!(matches("h[a-c]llo", "hbllo"));
+        assert!(!matches("h[a-c]llo", "hdllo"));
+    }
+
+    #[test]
+    fn match_many_stars_in_linear_time() {
+        let string = "a".repeat(100);
+        assert!(!matches("*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*b", &string));
+        assert!(matches("*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a*a", &string));
+        assert!(matches("**a*", "ba"));
+        assert!(matches("[*", "[x"));
+        assert!(!matches("[", ""));
+    }
+
+    #[test]
+    fn match_escaped() {
+        assert!(matches("h\\*llo", "h*llo"));
+        assert!(!matches("h\\*llo", "hello"));
+    }
+}
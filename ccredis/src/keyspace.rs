@@ -0,0 +1,575 @@
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+    },
+};
+
+use indexmap::IndexMap;
+use rand::Rng;
+
+use crate::{
+    config::Config,
+    message_processor::{now, Value},
+    transaction::Watches,
+};
+
+// rough size of map slot, key and value headers, counted for every key
+const ENTRY_OVERHEAD: usize = 64;
+// new keys start with non-zero frequency, so they are not evicted right away
+const LFU_INIT_VAL: u8 = 5;
+// number of lock stripes
+const SHARDS: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvictionPolicy {
+    NoEviction,
+    AllKeysLru,
+    AllKeysLfu,
+    AllKeysRandom,
+    VolatileLru,
+    VolatileLfu,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    pub fn parse(name: &str) -> Option<EvictionPolicy> {
+        let policy = match name.to_lowercase().as_str() {
+            "noeviction" => EvictionPolicy::NoEviction,
+            "allkeys-lru" => EvictionPolicy::AllKeysLru,
+            "allkeys-lfu" => EvictionPolicy::AllKeysLfu,
+            "allkeys-random" => EvictionPolicy::AllKeysRandom,
+            "volatile-lru" => EvictionPolicy::VolatileLru,
+            "volatile-lfu" => EvictionPolicy::VolatileLfu,
+            "volatile-random" => EvictionPolicy::VolatileRandom,
+            "volatile-ttl" => EvictionPolicy::VolatileTtl,
+            _ => return None,
+        };
+        Some(policy)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::AllKeysRandom => "allkeys-random",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+            EvictionPolicy::VolatileLfu => "volatile-lfu",
+            EvictionPolicy::VolatileRandom => "volatile-random",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    pub fn is_lfu(&self) -> bool {
+        matches!(self, EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu)
+    }
+
+    fn is_volatile(&self) -> bool {
+        matches!(self, EvictionPolicy::VolatileLru | EvictionPolicy::VolatileLfu | EvictionPolicy::VolatileRandom | EvictionPolicy::VolatileTtl)
+    }
+}
+
+pub struct Entry {
+    value: Value,
+    // memory accounted for this entry
+    size: usize,
+    // access metadata is updated by reads under shared lock
+    last_access: AtomicU64,
+    // logarithmic access counter, see `lfu-log-factor`
+    frequency: AtomicU8,
+}
+
+// Part of the keyspace behind its own lock: values with expirations and per-key access metadata.
+// Keys are stored in IndexMap, so random key can be picked in O(1).
+pub struct Shard {
+    entries: IndexMap<String, Entry>,
+    // expiration timestamps in milliseconds
+    expires: IndexMap<String, u128>,
+    // memory of the whole keyspace
+    used_memory: Arc<AtomicUsize>,
+    // told about every change of a key
+    watches: Arc<Watches>,
+    lfu_log_factor: u32,
+    lfu_decay_time: u64,
+}
+
+impl Shard {
+    // value of the key, counted as access
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let entry = self.entries.get(key)?;
+        self.touch(entry);
+        Some(&entry.value)
+    }
+
+    // value of the key without counting access, e.g. for OBJECT ENCODING
+    pub fn peek(&self, key: &str) -> Option<&Value> {
+        self.entries.get(key).map(|entry| &entry.value)
+    }
+
+    // milliseconds since the last access
+    pub fn idle_time(&self, key: &str) -> Option<u64> {
+        let entry = self.entries.get(key)?;
+        Some((now() as u64).saturating_sub(entry.last_access.load(Ordering::Relaxed)))
+    }
+
+    // access frequency counter with decay applied
+    pub fn frequency(&self, key: &str) -> Option<u8> {
+        self.entries.get(key).map(|entry| self.decayed_frequency(entry, now() as u64))
+    }
+
+    // memory accounted for the key and its value
+    pub fn memory_usage(&self, key: &str) -> Option<usize> {
+        self.entries.get(key).map(|entry| entry.size)
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    // expiration of existing key is kept
+    pub fn insert(&mut self, key: String, value: Value) {
+        self.watches.touch(&key);
+        let size = entry_size(&key, &value);
+        self.used_memory.fetch_add(size, Ordering::Relaxed);
+        let entry = Entry { value, size, last_access: AtomicU64::new(now() as u64), frequency: AtomicU8::new(LFU_INIT_VAL) };
+        if let Some(previous) = self.entries.insert(key, entry) {
+            self.used_memory.fetch_sub(previous.size, Ordering::Relaxed);
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<Value> {
+        self.expires.swap_remove(key);
+        let entry = self.entries.swap_remove(key)?;
+        self.watches.touch(key);
+        self.used_memory.fetch_sub(entry.size, Ordering::Relaxed);
+        Some(entry.value)
+    }
+
+    // modifies value in place and recalculates its memory usage
+    pub fn update<R>(&mut self, key: &str, modify: impl FnOnce(&mut Value) -> R) -> Option<R> {
+        let (index, key, entry) = self.entries.get_full_mut(key)?;
+        self.watches.touch(key);
+        let result = modify(&mut entry.value);
+        let size = entry_size(key, &entry.value);
+        self.used_memory.fetch_add(size, Ordering::Relaxed);
+        self.used_memory.fetch_sub(entry.size, Ordering::Relaxed);
+        entry.size = size;
+        self.touch(&self.entries[index]);
+        Some(result)
+    }
+
+    // RESTORE IDLETIME, makes key look idle for LRU eviction
+    pub fn set_last_access(&self, key: &str, timestamp: u64) {
+        if let Some(entry) = self.entries.get(key) {
+            entry.last_access.store(timestamp, Ordering::Relaxed);
+        }
+    }
+
+    pub fn expire_at(&self, key: &str) -> Option<u128> {
+        self.expires.get(key).copied()
+    }
+
+    // None makes key persistent
+    pub fn set_expire(&mut self, key: &str, expire_at: Option<u128>) {
+        self.watches.touch(key);
+        match expire_at {
+            Some(expire_at) => {
+                self.expires.insert(key.to_string(), expire_at);
+            },
+            None => {
+                self.expires.swap_remove(key);
+            },
+        }
+    }
+
+    pub fn is_expired(&self, key: &str, timestamp: u128) -> bool {
+        self.expires.get(key).is_some_and(|expire_at| timestamp > *expire_at)
+    }
+
+    // random key with expiration, used by active expiration
+    pub fn random_volatile_key(&self) -> Option<(&String, u128)> {
+        if self.expires.is_empty() {
+            return None;
+        }
+        let index = rand::thread_rng().gen_range(0..self.expires.len());
+        self.expires.get_index(index).map(|(key, expire_at)| (key, *expire_at))
+    }
+
+    // keys with values and expirations
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value, Option<u128>)> {
+        self.entries.iter().map(|(key, entry)| (key, &entry.value, self.expire_at(key)))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn expires_len(&self) -> usize {
+        self.expires.len()
+    }
+
+    fn clear(&mut self) {
+        let size: usize = self.entries.values().map(|entry| entry.size).sum();
+        self.used_memory.fetch_sub(size, Ordering::Relaxed);
+        self.entries.clear();
+        self.expires.clear();
+    }
+
+    // Random key with its eviction score, the lower the score the better candidate the key is.
+    // Volatile policies sample only keys with expiration.
+    fn eviction_sample(&self, policy: EvictionPolicy) -> Option<(u128, String)> {
+        let key = if policy.is_volatile() {
+            self.random_volatile_key()?.0
+        } else if self.entries.is_empty() {
+            return None;
+        } else {
+            self.entries.get_index(rand::thread_rng().gen_range(0..self.entries.len()))?.0
+        };
+        let entry = self.entries.get(key)?;
+        let score = match policy {
+            EvictionPolicy::AllKeysLru | EvictionPolicy::VolatileLru => entry.last_access.load(Ordering::Relaxed) as u128,
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => self.decayed_frequency(entry, now() as u64) as u128,
+            EvictionPolicy::VolatileTtl => self.expire_at(key).unwrap_or(u128::MAX),
+            _ => 0,
+        };
+        Some((score, key.clone()))
+    }
+
+    // frequency counter decremented by one for every `lfu-decay-time` minutes without access
+    fn decayed_frequency(&self, entry: &Entry, timestamp: u64) -> u8 {
+        let frequency = entry.frequency.load(Ordering::Relaxed);
+        if self.lfu_decay_time == 0 {
+            return frequency;
+        }
+        let periods = timestamp.saturating_sub(entry.last_access.load(Ordering::Relaxed)) / (self.lfu_decay_time * 60 * 1000);
+        frequency.saturating_sub(periods.min(u8::MAX as u64) as u8)
+    }
+
+    fn touch(&self, entry: &Entry) {
+        let timestamp = now() as u64;
+        let mut frequency = self.decayed_frequency(entry, timestamp);
+        // the higher the counter the less likely it is incremented, so 255 means about a million accesses
+        if frequency < u8::MAX {
+            let base = frequency.saturating_sub(LFU_INIT_VAL) as f64;
+            if rand::thread_rng().gen::<f64>() < 1.0 / (base * self.lfu_log_factor as f64 + 1.0) {
+                frequency += 1;
+            }
+        }
+        entry.frequency.store(frequency, Ordering::Relaxed);
+        entry.last_access.store(timestamp, Ordering::Relaxed);
+    }
+}
+
+// Keyspace split into lock-striped shards by key hash, so commands on different keys
+// don't wait for each other. Shards are always locked in ascending order.
+pub struct Keyspace {
+    shards: Vec<RwLock<Shard>>,
+    used_memory: Arc<AtomicUsize>,
+    pub watches: Arc<Watches>,
+    pub expired_keys: AtomicU64,
+    // active expiration cycles stopped by time limit
+    pub expired_time_cap_reached_count: AtomicU64,
+    pub evicted_keys: AtomicU64,
+    pub maxmemory: usize,
+    pub policy: EvictionPolicy,
+    samples: usize,
+}
+
+impl Default for Keyspace {
+    fn default() -> Self {
+        Keyspace::from_config(&Config::default())
+    }
+}
+
+impl Keyspace {
+    pub fn from_config(config: &Config) -> Keyspace {
+        let used_memory = Arc::new(AtomicUsize::new(0));
+        let watches = Arc::new(Watches::default());
+        let shards = (0..SHARDS)
+            .map(|_| RwLock::new(Shard {
+                entries: IndexMap::new(),
+                expires: IndexMap::new(),
+                used_memory: used_memory.clone(),
+                watches: watches.clone(),
+                lfu_log_factor: config.lfu_log_factor,
+                lfu_decay_time: config.lfu_decay_time,
+            }))
+            .collect();
+        Keyspace {
+            shards,
+            used_memory,
+            watches,
+            expired_keys: AtomicU64::new(0),
+            expired_time_cap_reached_count: AtomicU64::new(0),
+            evicted_keys: AtomicU64::new(0),
+            maxmemory: config.maxmemory,
+            policy: config.maxmemory_policy,
+            samples: config.maxmemory_samples.max(1),
+        }
+    }
+
+    // shard holding the key
+    pub fn read(&self, key: &str) -> RwLockReadGuard<'_, Shard> {
+        self.read_shard(shard_index(key.as_bytes()))
+    }
+
+    // commands lock their keys with `lock_keys`
+    #[cfg(test)]
+    pub fn write(&self, key: &str) -> RwLockWriteGuard<'_, Shard> {
+        self.write_shard(shard_index(key.as_bytes()))
+    }
+
+    pub fn read_shard(&self, index: usize) -> RwLockReadGuard<'_, Shard> {
+        self.shards[index].read().expect("Memory lock poisoned")
+    }
+
+    pub fn write_shard(&self, index: usize) -> RwLockWriteGuard<'_, Shard> {
+        self.shards[index].write().expect("Memory lock poisoned")
+    }
+
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    // Write locks of shards holding given keys for commands working with several keys
+    pub fn lock_keys(&self, keys: &[&[u8]]) -> LockedShards<'_> {
+        let mut indexes: Vec<usize> = keys.iter().map(|key| shard_index(key)).collect();
+        indexes.sort_unstable();
+        indexes.dedup();
+        LockedShards { keyspace: self, guards: indexes.into_iter().map(|index| (index, self.write_shard(index))).collect(), atomic: false }
+    }
+
+    // Write locks of every shard, e.g. for scripts which keys are not known in advance
+    pub fn lock_all(&self) -> LockedShards<'_> {
+        LockedShards { keyspace: self, guards: (0..self.shards.len()).map(|index| (index, self.write_shard(index))).collect(), atomic: true }
+    }
+
+    // consistent view of the whole keyspace, e.g. for snapshot
+    pub fn read_all(&self) -> Vec<RwLockReadGuard<'_, Shard>> {
+        (0..self.shards.len()).map(|index| self.read_shard(index)).collect()
+    }
+
+    pub fn clear(&self) {
+        for mut shard in (0..self.shards.len()).map(|index| self.write_shard(index)) {
+            shard.clear();
+        }
+        self.watches.touch_all();
+    }
+
+    pub fn len(&self) -> usize {
+        (0..self.shards.len()).map(|index| self.read_shard(index).len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn expires_len(&self) -> usize {
+        (0..self.shards.len()).map(|index| self.read_shard(index).expires_len()).sum()
+    }
+
+    pub fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed)
+    }
+
+    pub fn is_over_maxmemory(&self) -> bool {
+        self.maxmemory != 0 && self.used_memory() > self.maxmemory
+    }
+
+    // Evicts the best key according to policy among `maxmemory-samples` keys sampled from different shards.
+    // `on_evicted` is called while shard is still locked. Returns false when there is nothing to evict.
+    pub fn evict(&self, on_evicted: impl FnOnce(&str)) -> bool {
+        if self.policy == EvictionPolicy::NoEviction {
+            return false;
+        }
+        let sample = |index: usize| self.read_shard(index).eviction_sample(self.policy).map(|(score, key)| (score, index, key));
+        // consecutive shards starting from random one
+        let start = rand::thread_rng().gen_range(0..self.shards.len());
+        let mut candidate = (start..start + self.samples).filter_map(|index| sample(index % self.shards.len())).min();
+        // few keys can be missed by sampling, e.g. when only some keys have expiration
+        if candidate.is_none() {
+            candidate = (0..self.shards.len()).filter_map(sample).min();
+        }
+        let Some((_, index, key)) = candidate else {
+            return false;
+        };
+
+        let mut shard = self.write_shard(index);
+        if shard.remove(&key).is_some() {
+            self.evicted_keys.fetch_add(1, Ordering::Relaxed);
+            on_evicted(&key);
+        }
+        true
+    }
+}
+
+// Write locks of shards holding keys of a command
+pub struct LockedShards<'a> {
+    keyspace: &'a Keyspace,
+    // sorted by shard index
+    guards: Vec<(usize, RwLockWriteGuard<'a, Shard>)>,
+    // whole keyspace stays locked until script ends
+    atomic: bool,
+}
+
+impl LockedShards<'_> {
+    pub fn shard(&mut self, key: &str) -> &mut Shard {
+        let index = shard_index(key.as_bytes());
+        let position = self.guards.binary_search_by_key(&index, |(index, _)| *index).expect("Shard of the key is not locked");
+        &mut self.guards[position].1
+    }
+
+    // Releases shards while `unlocked` runs, e.g. network exchange, and locks them again.
+    // Other clients may change the keys meanwhile.
+    pub fn unlocked<R>(&mut self, unlocked: impl FnOnce() -> R) -> R {
+        if self.atomic {
+            return unlocked();
+        }
+        let indexes: Vec<usize> = self.guards.drain(..).map(|(index, _)| index).collect();
+        let result = unlocked();
+        self.guards = indexes.into_iter().map(|index| (index, self.keyspace.write_shard(index))).collect();
+        result
+    }
+}
+
+fn shard_index(key: &[u8]) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish() as usize % SHARDS
+}
+
+fn entry_size(key: &str, value: &Value) -> usize {
+    ENTRY_OVERHEAD + key.len() + value.memory_usage()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoding::{ListValue, LIST_ELEMENT_OVERHEAD};
+
+    fn keyspace(maxmemory: usize, policy: EvictionPolicy) -> Keyspace {
+        // every shard is sampled
+        Keyspace { maxmemory, policy, samples: SHARDS, ..Keyspace::default() }
+    }
+
+    #[test]
+    fn test_used_memory() {
+        let keyspace = Keyspace::default();
+        keyspace.write("key").insert("key".to_string(), "x".repeat(30).into());
+        // short string is stored inline
+        keyspace.write("other").insert("other".to_string(), "value".into());
+        assert_eq!(keyspace.used_memory(), 2 * ENTRY_OVERHEAD + 3 + 30 + 5);
+
+        keyspace.write("key").insert("key".to_string(), Value::List(ListValue::from_elements([b"a".to_vec(), b"bc".to_vec()], -2)));
+        assert_eq!(keyspace.used_memory(), 2 * ENTRY_OVERHEAD + 3 + 5 + 5);
+
+        keyspace.write("key").update("key", |value| if let Value::List(list) = value { list.push_back(vec![b'y'; 30], 2) });
+        assert_eq!(keyspace.used_memory(), 2 * ENTRY_OVERHEAD + 3 + 1 + 2 + 30 + 3 * LIST_ELEMENT_OVERHEAD + 5);
+
+        keyspace.write("key").remove("key");
+        assert_eq!(keyspace.used_memory(), ENTRY_OVERHEAD + 5);
+
+        keyspace.clear();
+        assert_eq!(keyspace.used_memory(), 0);
+    }
+
+    #[test]
+    fn test_lock_keys_in_shard_order() {
+        let keyspace = Keyspace::default();
+        let keys: Vec<String> = (0..100).map(|index| format!("key{}", index)).collect();
+        let key_bytes: Vec<&[u8]> = keys.iter().map(|key| key.as_bytes()).collect();
+        let mut shards = keyspace.lock_keys(&key_bytes);
+        assert_eq!(shards.guards.len(), SHARDS);
+        assert!(shards.guards.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for key in &keys {
+            shards.shard(key).insert(key.clone(), "value".into());
+        }
+        drop(shards);
+        assert_eq!(keyspace.len(), 100);
+        assert!(keyspace.read("key1").contains_key("key1"));
+    }
+
+    // every key is alone in its shard, so sampling of shard always returns it
+    fn keys_in_different_shards(count: usize) -> Vec<String> {
+        let mut used = Vec::new();
+        (0..)
+            .map(|index| format!("key{}", index))
+            .filter(|key| {
+                let index = shard_index(key.as_bytes());
+                let unused = !used.contains(&index);
+                used.push(index);
+                unused
+            })
+            .take(count)
+            .collect()
+    }
+
+    #[test]
+    fn test_eviction() {
+        let [a, b, c] = <[String; 3]>::try_from(keys_in_different_shards(3)).unwrap();
+        let keyspace = keyspace(1, EvictionPolicy::NoEviction);
+        for key in [&a, &b, &c] {
+            keyspace.write(key).insert(key.to_string(), "value".into());
+        }
+        keyspace.write(&b).set_expire(&b, Some(200));
+        keyspace.write(&c).set_expire(&c, Some(100));
+        assert!(keyspace.is_over_maxmemory());
+        assert!(!keyspace.evict(|_| {}));
+
+        let keyspace = Keyspace { policy: EvictionPolicy::VolatileTtl, ..keyspace };
+        let mut evicted = Vec::new();
+        assert!(keyspace.evict(|key| evicted.push(key.to_string())));
+        assert!(keyspace.evict(|key| evicted.push(key.to_string())));
+        assert_eq!(evicted, [c, b]);
+        // only keys with expiration are evicted by volatile policies
+        assert!(!keyspace.evict(|_| {}));
+        assert_eq!(keyspace.evicted_keys.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_lru_and_lfu_metadata() {
+        let [old, new] = <[String; 2]>::try_from(keys_in_different_shards(2)).unwrap();
+        let keyspace = keyspace(1, EvictionPolicy::AllKeysLru);
+        keyspace.write(&old).insert(old.clone(), "value".into());
+        keyspace.write(&new).insert(new.clone(), "value".into());
+        keyspace.read(&old).entries[&old].last_access.store(0, Ordering::Relaxed);
+        keyspace.read(&new).entries[&new].last_access.store(1000, Ordering::Relaxed);
+        let mut evicted = String::new();
+        keyspace.evict(|key| evicted = key.to_string());
+        assert_eq!(evicted, old);
+
+        let keyspace = Keyspace { policy: EvictionPolicy::AllKeysLfu, ..keyspace };
+        keyspace.write(&old).insert(old.clone(), "value".into());
+        let shard = keyspace.read(&new);
+        for _ in 0..100 {
+            shard.get(&new);
+        }
+        let frequency = shard.entries[&new].frequency.load(Ordering::Relaxed);
+        assert!(frequency > LFU_INIT_VAL);
+        // counter decays without access
+        assert_eq!(shard.decayed_frequency(&shard.entries[&new], 2 * 60 * 1000), frequency - 2);
+        drop(shard);
+        keyspace.evict(|key| evicted = key.to_string());
+        assert_eq!(evicted, old);
+    }
+
+    #[test]
+    fn test_unlocked_shards() {
+        let keyspace = Keyspace::default();
+        let mut shards = keyspace.lock_keys(&[b"key"]);
+        shards.unlocked(|| keyspace.write("key").insert("key".to_string(), "value".into()));
+        assert!(shards.shard("key").contains_key("key"));
+        drop(shards);
+
+        // scripts keep the whole keyspace locked
+        let mut shards = keyspace.lock_all();
+        shards.unlocked(|| assert!(keyspace.shards[0].try_write().is_err()));
+    }
+}
@@ -0,0 +1,237 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{atomic::{AtomicU64, Ordering}, Arc, Mutex, RwLock},
+    time::Duration,
+};
+
+use crate::{config::Config, message_processor::now, resp::message::Message};
+
+// the same as in Redis latency monitor
+const HISTORY_LEN: usize = 160;
+
+// values below SUB_BUCKETS are exact, above them every power of two is split into SUB_BUCKETS buckets
+const SUB_BUCKETS: u64 = 16;
+const SUB_BUCKET_BITS: u32 = SUB_BUCKETS.trailing_zeros();
+const BUCKETS: usize = 1024;
+
+#[derive(Default)]
+struct EventSeries {
+    // unix time in seconds and duration in milliseconds
+    latest: (u64, u64),
+    max: u64,
+    history: VecDeque<(u64, u64)>,
+}
+
+// Latency monitor (LATENCY LATEST/HISTORY/RESET) and per-command latency histograms for INFO latencystats
+pub struct Latency {
+    // milliseconds, 0 disables latency monitor
+    monitor_threshold: u64,
+    tracking: bool,
+    pub percentiles: Vec<f64>,
+    events: Mutex<BTreeMap<String, EventSeries>>,
+    histograms: RwLock<BTreeMap<String, Arc<Histogram>>>,
+}
+
+impl Latency {
+    pub fn new(config: &Config) -> Latency {
+        Latency {
+            monitor_threshold: config.latency_monitor_threshold,
+            tracking: config.latency_tracking,
+            percentiles: config.latency_tracking_info_percentiles.clone(),
+            events: Mutex::new(BTreeMap::new()),
+            histograms: RwLock::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn record_command(&self, command: &str, fast: bool, duration: Duration) {
+        if self.tracking {
+            self.histogram(command).record(duration.as_micros() as u64);
+        }
+        let duration_ms = duration.as_millis() as u64;
+        if self.monitor_threshold > 0 && duration_ms >= self.monitor_threshold {
+            self.add_sample(if fast { "fast-command" } else { "command" }, duration_ms);
+        }
+    }
+
+    fn histogram(&self, command: &str) -> Arc<Histogram> {
+        if let Some(histogram) = self.histograms.read().expect("Latency histograms lock poisoned").get(command) {
+            return histogram.clone();
+        }
+        let mut histograms = self.histograms.write().expect("Latency histograms lock poisoned");
+        histograms.entry(command.to_string()).or_insert_with(|| Arc::new(Histogram::new())).clone()
+    }
+
+    fn events(&self) -> std::sync::MutexGuard<'_, BTreeMap<String, EventSeries>> {
+        self.events.lock().expect("Latency events lock poisoned")
+    }
+
+    fn add_sample(&self, event: &str, duration_ms: u64) {
+        let timestamp = (now() / 1000) as u64;
+        let mut events = self.events();
+        let series = events.entry(event.to_string()).or_default();
+        series.latest = (timestamp, duration_ms);
+        series.max = series.max.max(duration_ms);
+        // samples from the same second are merged keeping the worst one
+        match series.history.back_mut() {
+            Some(last) if last.0 == timestamp => last.1 = last.1.max(duration_ms),
+            _ => {
+                if series.history.len() == HISTORY_LEN {
+                    series.history.pop_front();
+                }
+                series.history.push_back((timestamp, duration_ms));
+            },
+        }
+    }
+
+    // LATENCY LATEST: event name, time of the latest sample, latest and max duration
+    pub fn latest(&self) -> Message {
+        let events = self.events();
+        Message::array(events.iter().map(|(event, series)| Message::array(vec![
+            Message::bulk_string(event),
+            Message::Integer(series.latest.0 as i64),
+            Message::Integer(series.latest.1 as i64),
+            Message::Integer(series.max as i64),
+        ])).collect())
+    }
+
+    pub fn history(&self, event: &str) -> Message {
+        let events = self.events();
+        let samples = events.get(event).map(|series| series.history.iter().map(|(timestamp, duration)| {
+            Message::array(vec![Message::Integer(*timestamp as i64), Message::Integer(*duration as i64)])
+        }).collect()).unwrap_or_default();
+        Message::array(samples)
+    }
+
+    // resets given events or all of them, returns number of reset events
+    pub fn reset(&self, events: &[&str]) -> usize {
+        let mut all_events = self.events();
+        if events.is_empty() {
+            let count = all_events.len();
+            all_events.clear();
+            return count;
+        }
+        events.iter().filter(|event| all_events.remove(**event).is_some()).count()
+    }
+
+    // lines of INFO latencystats section
+    pub fn info(&self) -> String {
+        let histograms = self.histograms.read().expect("Latency histograms lock poisoned");
+        let mut info = String::from("# Latencystats\r\n");
+        for (command, histogram) in histograms.iter() {
+            let percentiles: Vec<String> = self.percentiles.iter()
+                .map(|percentile| format!("p{}={:.3}", percentile, histogram.percentile(*percentile) as f64))
+                .collect();
+            info.push_str(&format!("latency_percentiles_usec_{}:{}\r\n", command, percentiles.join(",")));
+        }
+        info
+    }
+}
+
+// Log-linear histogram of durations in microseconds with about 6% precision
+struct Histogram {
+    counts: Vec<AtomicU64>,
+}
+
+impl Histogram {
+    fn new() -> Histogram {
+        Histogram { counts: (0..BUCKETS).map(|_| AtomicU64::new(0)).collect() }
+    }
+
+    fn bucket(value: u64) -> usize {
+        if value < SUB_BUCKETS {
+            return value as usize;
+        }
+        let power = value.ilog2();
+        let shift = power - SUB_BUCKET_BITS;
+        let sub_bucket = (value >> shift) - SUB_BUCKETS;
+        ((shift as u64 + 1) * SUB_BUCKETS + sub_bucket) as usize
+    }
+
+    // largest value which falls into the bucket
+    fn bucket_max(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < SUB_BUCKETS {
+            return bucket;
+        }
+        let shift = bucket / SUB_BUCKETS - 1;
+        let sub_bucket = bucket % SUB_BUCKETS;
+        (((SUB_BUCKETS + sub_bucket + 1) as u128) << shift).saturating_sub(1).min(u64::MAX as u128) as u64
+    }
+
+    fn record(&self, value: u64) {
+        self.counts[Self::bucket(value)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn percentile(&self, percentile: f64) -> u64 {
+        let counts: Vec<u64> = self.counts.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+        let total: u64 = counts.iter().sum();
+        let rank = ((percentile / 100.0 * total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Self::bucket_max(bucket);
+            }
+        }
+        0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::message_processor::travel_to;
+
+    #[test]
+    fn test_histogram_buckets() {
+        assert_eq!(Histogram::bucket(15), 15);
+        assert_eq!(Histogram::bucket(16), 16);
+        assert_eq!(Histogram::bucket(33), 32);
+        assert_eq!(Histogram::bucket_max(32), 33);
+        assert!(Histogram::bucket(u64::MAX) < BUCKETS);
+        assert_eq!(Histogram::bucket_max(Histogram::bucket(u64::MAX)), u64::MAX);
+        for value in [1, 17, 100, 1000, 123_456, 10_000_000] {
+            let max = Histogram::bucket_max(Histogram::bucket(value));
+            assert!(max >= value && max - value <= value / 16, "{} -> {}", value, max);
+        }
+
+        let histogram = Histogram::new();
+        for value in 1..=100 {
+            histogram.record(value);
+        }
+        assert_eq!(histogram.percentile(50.0), 51);
+        assert_eq!(histogram.percentile(99.0), 99);
+        assert_eq!(histogram.percentile(100.0), 103);
+    }
+
+    #[test]
+    fn test_latency_monitor() {
+        let latency = Latency::new(&Config { latency_monitor_threshold: 10, ..Config::default() });
+        travel_to(5000);
+        latency.record_command("get", true, Duration::from_millis(9));
+        assert_eq!(latency.latest(), Message::array(vec![]));
+
+        latency.record_command("get", true, Duration::from_millis(20));
+        latency.record_command("get", true, Duration::from_millis(15));
+        travel_to(6000);
+        latency.record_command("save", false, Duration::from_millis(30));
+        latency.record_command("get", true, Duration::from_millis(12));
+        assert_eq!(latency.latest(), Message::array(vec![
+            Message::array(vec![Message::bulk_string("command"), Message::Integer(6), Message::Integer(30), Message::Integer(30)]),
+            Message::array(vec![Message::bulk_string("fast-command"), Message::Integer(6), Message::Integer(12), Message::Integer(20)]),
+        ]));
+        assert_eq!(latency.history("fast-command"), Message::array(vec![
+            Message::array(vec![Message::Integer(5), Message::Integer(20)]),
+            Message::array(vec![Message::Integer(6), Message::Integer(12)]),
+        ]));
+        assert_eq!(latency.history("unknown"), Message::array(vec![]));
+
+        assert_eq!(latency.reset(&["command", "unknown"]), 1);
+        assert_eq!(latency.reset(&[]), 1);
+        assert_eq!(latency.latest(), Message::array(vec![]));
+
+        let info = latency.info();
+        assert!(info.contains("latency_percentiles_usec_get:p50=12287.000,p99=20479.000,p99.9=20479.000\r\n"), "{}", info);
+        assert!(info.contains("latency_percentiles_usec_save:"), "{}", info);
+    }
+}
@@ -0,0 +1,36 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+pub mod acl;
+pub mod client_registry;
+pub mod cluster;
+pub mod config;
+pub mod connection;
+pub mod db_file;
+pub mod dump;
+pub mod encoding;
+pub mod expire;
+pub mod glob;
+pub mod keyspace;
+pub mod latency;
+pub mod listener;
+pub mod message_processor;
+pub mod processing_error;
+pub mod pubsub;
+pub mod replication;
+pub mod resp;
+pub mod scripting;
+pub mod server;
+pub mod shutdown;
+pub mod slowlog;
+pub mod stats;
+pub mod tls;
+pub mod transaction;
+
+// set by LOG_LEVEL=debug
+pub static DEBUG: AtomicBool = AtomicBool::new(false);
+
+pub fn debug(log: &str) {
+    if DEBUG.load(Ordering::Relaxed) {
+        println!("[Debug]{}", log);
+    }
+}
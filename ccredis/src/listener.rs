@@ -0,0 +1,113 @@
+use std::{sync::{atomic::Ordering, Arc}, time::Duration};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tokio_rustls::TlsAcceptor;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+use crate::{
+    config::Config,
+    connection::{self, ClientSlot, ConnectedClients},
+    message_processor::MessageProcessor,
+};
+
+// Socket file is removed when it is dropped
+#[cfg(unix)]
+pub struct UnixSocketFile(String);
+
+#[cfg(unix)]
+impl Drop for UnixSocketFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// Binds unix socket, stale socket file from previous run is removed before bind.
+#[cfg(unix)]
+pub fn bind_unix(path: &str, permissions: Option<u32>) -> std::io::Result<(UnixListener, UnixSocketFile)> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            return Err(std::io::Error::new(std::io::ErrorKind::AlreadyExists, format!("{} exists and is not a socket", path)));
+        }
+        std::fs::remove_file(path)?;
+    }
+
+    let listener = UnixListener::bind(path)?;
+    let socket_file = UnixSocketFile(path.to_string());
+    if let Some(mode) = permissions {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    Ok((listener, socket_file))
+}
+
+#[cfg(unix)]
+pub async fn serve_unix(listener: UnixListener, path: String, message_processor: MessageProcessor, config: Arc<Config>, connected_clients: ConnectedClients) {
+    // the same format as Redis uses in CLIENT LIST for unix socket clients
+    let addr = format!("{}:0", path);
+    loop {
+        let stream = match listener.accept().await {
+            Ok((stream, _)) => stream,
+            Err(e) => {
+                eprintln!("[Unix] Error accepting connection: {}", e);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                continue;
+            }
+        };
+
+        let slot = connected_clients.try_acquire(config.maxclients);
+        tokio::spawn(serve_stream(stream, slot, addr.clone(), addr.clone(), message_processor.clone(), config.clone()));
+    }
+}
+
+// Accepts TCP connections, when `tls` is set every connection starts with TLS handshake.
+pub async fn serve_tcp(listener: TcpListener, tls: Option<TlsAcceptor>, message_processor: MessageProcessor, config: Arc<Config>, connected_clients: ConnectedClients) {
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                eprintln!("[TCP] Error accepting connection: {}", e);
+                // e.g. out of file descriptors, give other connections a chance to close
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                continue;
+            }
+        };
+
+        let slot = connected_clients.try_acquire(config.maxclients);
+        let laddr = stream.local_addr().map(|laddr| laddr.to_string()).unwrap_or_default();
+        let message_processor = message_processor.clone();
+        let config = config.clone();
+        let tls = tls.clone();
+        tokio::spawn(async move {
+            match tls {
+                None => serve_stream(stream, slot, addr.to_string(), laddr, message_processor, config).await,
+                Some(acceptor) => match acceptor.accept(stream).await {
+                    Ok(stream) => serve_stream(stream, slot, addr.to_string(), laddr, message_processor, config).await,
+                    Err(err) => println!("[TLS] Handshake failed: {}", err),
+                },
+            }
+        });
+    }
+}
+
+async fn serve_stream<S>(stream: S, slot: Option<ClientSlot>, addr: String, laddr: String, message_processor: MessageProcessor, config: Arc<Config>)
+where
+    S: AsyncRead + AsyncWrite + Send + Unpin + 'static,
+{
+    match slot {
+        Some(slot) => {
+            message_processor.stats.total_connections_received.fetch_add(1, Ordering::Relaxed);
+            connection::handle_client(stream, addr, laddr, message_processor, config).await;
+            drop(slot);
+        }
+        None => {
+            println!("[TCP] Client rejected, max number of clients reached");
+            message_processor.stats.rejected_connections.fetch_add(1, Ordering::Relaxed);
+            connection::reject_client(stream).await;
+        }
+    }
+}
@@ -1,37 +1,131 @@
-use std::{cell::Cell, collections::{HashMap, VecDeque}, fs::File, io::BufWriter, sync::{Arc, RwLock}};
-
-use crate::{processing_error::ProcessingError, resp::message::Message};
+use std::{
+    cell::Cell,
+    fs::{self, File},
+    io::{Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    sync::{atomic::Ordering, Arc, RwLockReadGuard},
+    time::{Duration, Instant},
+};
+
+use crate::{
+    acl::{self, Acl},
+    client_registry::{self, Client, ClientRegistry, PauseMode},
+    cluster::{self, Cluster},
+    config::Config,
+    dump,
+    encoding::{ListValue, StringValue},
+    keyspace::{Keyspace, LockedShards, Shard},
+    latency::Latency,
+    processing_error::ProcessingError,
+    pubsub::{PubSub, Subscription},
+    replication::Replication,
+    resp::{message::Message, message_parser::MessageParser},
+    scripting::Scripting,
+    shutdown::{Shutdown, ShutdownOptions},
+    slowlog::SlowLog,
+    stats::{self, Stats},
+};
+
+pub mod commands;
+use commands::CommandSpec;
+
+// transaction control commands after MULTI run right away instead of being queued
+const NOT_QUEUED: [&str; 4] = ["multi", "exec", "discard", "watch"];
+
+// commands allowed while client is subscribed to a channel or pattern
+const SUBSCRIBED_CONTEXT: [&str; 5] = ["subscribe", "psubscribe", "unsubscribe", "punsubscribe", "ping"];
 
 #[derive(Debug, PartialEq)]
 pub enum Value {
-    Single(Vec<u8>),
-    List(VecDeque<Vec<u8>>)
+    Single(StringValue),
+    List(ListValue)
 }
 
+impl Value {
+    // OBJECT ENCODING reply
+    pub fn encoding(&self) -> &'static str {
+        match self {
+            Value::Single(content) => content.encoding(),
+            Value::List(list) => list.encoding(),
+        }
+    }
+
+    pub fn memory_usage(&self) -> usize {
+        match self {
+            Value::Single(content) => content.memory_usage(),
+            Value::List(list) => list.memory_usage(),
+        }
+    }
+}
 
 impl From<&str> for Value {
     fn from(content: &str) -> Self {
-        Value::Single(content.into())
+        Value::Single(StringValue::new(content.into()))
     }
 }
 
 impl From<String> for Value {
     fn from(content: String) -> Self {
-        Value::Single(content.into())
+        Value::Single(StringValue::new(content.into()))
     }
 }
 
-pub type SharedMemory = Arc<RwLock<HashMap<String, Value>>>;
-pub type KeyExpiration = Arc<RwLock<HashMap<String, u128>>>;
-
+pub type SharedMemory = Arc<Keyspace>;
 
+#[derive(Clone)]
 pub struct MessageProcessor {
     pub memory: SharedMemory, 
-    pub key_expiration: KeyExpiration,
-    pub db_file_path: String,
+    // None when persistence is not configured, SAVE fails then
+    pub db_file_path: Option<String>,
+    pub clients: Arc<ClientRegistry>,
+    pub acl: Arc<Acl>,
+    pub replication: Arc<Replication>,
+    // None when cluster mode is disabled
+    pub cluster: Option<Arc<Cluster>>,
+    pub config: Arc<Config>,
+    pub stats: Arc<Stats>,
+    pub slowlog: Arc<SlowLog>,
+    pub latency: Arc<Latency>,
+    pub scripting: Arc<Scripting>,
+    pub shutdown: Arc<Shutdown>,
+    pub pubsub: Arc<PubSub>,
+    // connection which sends commands, None when commands are not sent by client (e.g. loading from file)
+    pub client: Option<Arc<Client>>,
 }
 
 impl MessageProcessor {
+    pub fn with_client(&self, client: Arc<Client>) -> MessageProcessor {
+        MessageProcessor { client: Some(client), ..self.clone() }
+    }
+
+    // commands blocked by CLIENT PAUSE WRITE, scripts and transactions may write too
+    pub fn is_write_command(&self, message: &Message) -> bool {
+        Self::spec_of(message).is_some_and(|spec| {
+            spec.has_flag("write") || spec.has_flag("may_replicate") || (spec.name() == "exec" && self.client.as_ref().is_some_and(|client| client.transaction_writes()))
+        })
+    }
+
+    // commands which don't wait for running script
+    pub fn allows_busy(message: &Message) -> bool {
+        Self::spec_of(message).is_some_and(|spec| spec.has_flag("allow_busy"))
+    }
+
+    // commands which are run off the runtime workers
+    pub fn may_block(message: &Message) -> bool {
+        Self::spec_of(message).is_some_and(|spec| spec.may_block())
+    }
+
+    fn spec_of(message: &Message) -> Option<CommandSpec> {
+        let Message::Array(Some(items)) = message else {
+            return None;
+        };
+        let mut names = items.iter().map(|item| item.as_str().ok());
+        match (names.next().flatten(), names.next().flatten()) {
+            (Some(command), subcommand) => commands::find(&command.to_lowercase(), subcommand),
+            _ => None,
+        }
+    }
+
     pub fn process_resp_message(&self, message: &Message) -> Message {
         match message {
             Message::Array(Some(items)) => match self.process_resp_command(items) {
@@ -45,348 +139,1640 @@ impl MessageProcessor {
         }
     }
 
-    fn process_resp_command(&self, parts: &Vec<Message>) -> Result<Message, ProcessingError> {
-        let (command, args) = split_to_command_args(&parts)?;
+    fn process_resp_command(&self, parts: &[Message]) -> Result<Message, ProcessingError> {
+        let spec = commands::lookup(parts).inspect_err(|_| {
+            if let Some(client) = &self.client {
+                client.refuse_transaction();
+            }
+        })?;
+        let (command, args) = split_to_command_args(parts)?;
+        let command = command.as_str()?.to_lowercase();
+        let key_args: Vec<&[u8]> = args.iter().map(|arg| arg.extract_bulk_content().map_or(&[][..], |content| content.as_slice())).collect();
+        let keys = spec.keys(&key_args);
+
+        if let Some(client) = &self.client {
+            client.touch(&command);
+            let checked = self.check_client_command(client, spec, &command, args, &keys);
+            // commands after MULTI run on EXEC, a command refused while queued makes EXEC fail
+            if client.in_transaction() && !NOT_QUEUED.contains(&command.as_str()) {
+                let queueable = checked.and_then(|_| self.check_read_only(spec)).and_then(|_| match spec.has_flag("no_multi") {
+                    true => Err("ERR Command not allowed inside a transaction".into()),
+                    false => Ok(()),
+                });
+                if let Err(err) = queueable {
+                    client.refuse_transaction();
+                    return Err(err);
+                }
+                client.queue_command(parts.to_vec(), spec.has_flag("write"));
+                return Ok(Message::simple_string("QUEUED"));
+            }
+            checked?;
+        }
+
+        let is_replica = self.replication.is_replica();
+        self.check_read_only(spec)?;
+        // running script locks shards of the whole keyspace, guards are released after the shards.
+        // Clients enter the gate in their connection task, commands which may block don't run
+        // on runtime workers and can wait for the script.
+        let _script = if spec.locks_keyspace() { Some(self.scripting.start()?) } else { None };
+        let _gate = (self.client.is_none() && !spec.may_block() && !spec.has_flag("allow_busy")).then(|| self.scripting.enter_blocking());
+        // writes from clients may need memory, removal of keys never does
+        if spec.has_flag("denyoom") && !is_replica && self.client.is_some() {
+            self.free_memory()?;
+        }
+
+        // shards of the keys stay locked until write is propagated, so replicas apply writes to a key in the same order
+        let mut shards = if spec.locks_keyspace() { self.memory.lock_all() } else { self.memory.lock_keys(&keys) };
+        let started_at = Instant::now();
+        let result = self.execute(spec, &command, &mut shards, parts);
+        let duration = started_at.elapsed();
+        drop(shards);
+        self.record_latency(spec, &command, parts, duration);
+        self.feed_monitors(spec, &command, parts);
+        result
+    }
+
+    fn check_client_command(&self, client: &Client, spec: CommandSpec, command: &str, args: &[Message], keys: &[&[u8]]) -> Result<(), ProcessingError> {
+        // client would wait for shards locked by the script
+        if !spec.has_flag("allow_busy") {
+            self.scripting.check_busy()?;
+        }
+        self.check_permissions(client, spec, command, args, keys)?;
+        if !SUBSCRIBED_CONTEXT.contains(&command) && self.pubsub.count(client.id) > 0 {
+            return Err(format!("ERR Can't execute '{}': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context", command).into());
+        }
+        let asking = command != "asking" && client.take_asking();
+        if let Some(cluster) = &self.cluster {
+            self.check_cluster_slot(cluster, keys, asking)?;
+        }
+        Ok(())
+    }
+
+    // Command called by script with redis.call, shards of the whole keyspace are locked by the script
+    fn execute_from_script(&self, shards: &mut LockedShards, parts: &[Message]) -> Result<Message, ProcessingError> {
+        let spec = commands::lookup(parts)?;
+        if spec.has_flag("noscript") {
+            return Err("ERR This Redis command is not allowed from script".into());
+        }
+        let (command, args) = split_to_command_args(parts)?;
+        let command = command.as_str()?.to_lowercase();
+        if let Some(client) = &self.client {
+            let key_args: Vec<&[u8]> = args.iter().map(|arg| arg.extract_bulk_content().map_or(&[][..], |content| content.as_slice())).collect();
+            self.check_permissions(client, spec, &command, args, &spec.keys(&key_args))?;
+        }
+        self.check_read_only(spec)?;
+        // keys can't be evicted while the script holds shard locks, writes which need memory fail instead
+        if spec.has_flag("denyoom") && !self.replication.is_replica() && self.client.is_some() && self.memory.is_over_maxmemory() {
+            return Err(ProcessingError::Oom);
+        }
+        self.execute(spec, &command, shards, parts)
+    }
+
+    fn check_read_only(&self, spec: CommandSpec) -> Result<(), ProcessingError> {
+        if spec.has_flag("write") && self.replication.is_replica() && self.replication.read_only && self.client.is_some() {
+            return Err(ProcessingError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    fn execute(&self, spec: CommandSpec, command: &str, shards: &mut LockedShards, parts: &[Message]) -> Result<Message, ProcessingError> {
+        self.stats.total_commands_processed.fetch_add(1, Ordering::Relaxed);
+        let result = spec.call(self, shards, &parts[1..]);
+        // writes of scripts are propagated one by one, replicas don't run scripts
+        if spec.has_flag("write") && result.is_ok() {
+            self.stats.changes_since_last_save.fetch_add(1, Ordering::Relaxed);
+            if let Some(propagated) = self.propagated_command(spec, shards, command, parts) {
+                self.replication.propagate(&propagated);
+            }
+        }
+        result
+    }
+
+    // administrative commands are not shown to monitors, as in Redis
+    fn feed_monitors(&self, spec: CommandSpec, command: &str, parts: &[Message]) {
+        let Some(client) = &self.client else {
+            return;
+        };
+        if spec.has_flag("admin") {
+            return;
+        }
+        self.clients.feed_monitors(|| {
+            let timestamp = now() * 1000;
+            let mut line = format!("{}.{:06} [{} {}]", timestamp / 1_000_000, timestamp % 1_000_000, client_registry::DB, client.addr);
+            for (index, part) in parts.iter().enumerate() {
+                line.push(' ');
+                if command == "auth" && index > 0 {
+                    line.push_str("\"(redacted)\"");
+                } else {
+                    line.push_str(&quote(&part.extract_bulk_content().cloned().unwrap_or_default()));
+                }
+            }
+            line
+        });
+    }
+
+    fn record_latency(&self, spec: CommandSpec, command: &str, parts: &[Message], duration: Duration) {
+        // time spent waiting for replicas is not execution time
+        if command != "wait" {
+            self.latency.record_command(command, spec.has_flag("fast"), duration);
+        }
+        // arguments of these commands contain passwords
+        if !["wait", "auth", "acl"].contains(&command) {
+            self.slowlog.record(duration, parts, self.client.as_deref());
+        }
+    }
+
+    // Evicts keys according to maxmemory-policy until used memory fits into maxmemory
+    fn free_memory(&self) -> Result<(), ProcessingError> {
+        while self.memory.is_over_maxmemory() {
+            let evicted = self.memory.evict(|key| self.replication.propagate(&[Message::bulk_string("DEL"), Message::bulk_string(key)]));
+            if !evicted {
+                return Err(ProcessingError::Oom);
+            }
+        }
+        Ok(())
+    }
 
-        match command.as_str()?.to_lowercase().as_str() {
-            "ping" => Ok(self.command_ping()),
-            "echo" => self.command_echo(args),
-            "set" => self.command_set(args),
-            "get" => self.command_get(args),
-            "exists" => self.command_exists(args),
-            "del" => self.command_del(args),
-            "incr" => self.command_incr(args),
-            "decr" => self.command_decr(args),
-            "lpush" => self.command_lpush(args),
-            "rpush" => self.command_rpush(args),
-            "save" => self.command_save(),
-            _ => Err(ProcessingError::from("Expected command"))
-        }    
+    // Relative expiration is sent as absolute timestamp, so replica expires key at the same time.
+    // Keys moved away by MIGRATE are deleted on replicas, None when nothing has to be propagated.
+    fn propagated_command(&self, spec: CommandSpec, shards: &mut LockedShards, command: &str, parts: &[Message]) -> Option<Vec<Message>> {
+        match command {
+            "set" if parts.len() > 3 => {
+                if let Ok(key) = parts[1].as_str() {
+                    if let Some(expire_at) = shards.shard(key).expire_at(key) {
+                        return Some(vec![
+                            Message::bulk_string("SET"),
+                            parts[1].clone(),
+                            parts[2].clone(),
+                            Message::bulk_string("PXAT"),
+                            Message::bulk_string(&expire_at.to_string()),
+                        ]);
+                    }
+                }
+            },
+            "restore" => {
+                let key = parts[1].as_str().ok()?;
+                let shard = shards.shard(key);
+                // already expired TTL removes the key
+                if !shard.contains_key(key) {
+                    return Some(vec![Message::bulk_string("DEL"), parts[1].clone()]);
+                }
+                let expire_at = shard.expire_at(key).unwrap_or(0);
+                return Some(vec![
+                    Message::bulk_string("RESTORE"),
+                    parts[1].clone(),
+                    Message::bulk_string(&expire_at.to_string()),
+                    parts[3].clone(),
+                    Message::bulk_string("REPLACE"),
+                    Message::bulk_string("ABSTTL"),
+                ]);
+            },
+            "migrate" => {
+                let key_args: Vec<&[u8]> = parts[1..].iter().map(|arg| arg.extract_bulk_content().map_or(&[][..], |content| content.as_slice())).collect();
+                let mut deleted = vec![Message::bulk_string("DEL")];
+                for key in spec.keys(&key_args) {
+                    let key = std::str::from_utf8(key).ok()?;
+                    if !shards.shard(key).contains_key(key) {
+                        deleted.push(Message::bulk_string(key));
+                    }
+                }
+                return (deleted.len() > 1).then_some(deleted);
+            },
+            _ => {},
+        }
+        Some(parts.to_vec())
     }
 
     fn command_ping(&self) -> Message {
-        Message::simple_string("PONG")
+        match self.client.as_ref().is_some_and(|client| self.pubsub.count(client.id) > 0) {
+            true => Message::array(vec![Message::bulk_string("pong"), Message::bulk_string("")]),
+            false => Message::simple_string("PONG"),
+        }
     }
 
     fn command_echo(&self, args: &[Message]) -> Result<Message, ProcessingError> {
         if args.len() != 1 {
-            return Err(ProcessingError::from(format!("[echo] expected 1 argument but got {}", args.len())));
+            return Err(ProcessingError::WrongArity("echo".into()));
         }
         let argument_text = args.first().unwrap().as_str()?;
 
         Ok(Message::bulk_string(argument_text))
     }
 
-    fn command_set(&self, args: &[Message]) -> Result<Message, ProcessingError> {
-        let key = args.get(0).ok_or("[set] expected key")?.as_str()?;
-        let value = args.get(1).ok_or("[set] expected value")?.extract_bulk_content()?;
+    fn command_set(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let key = args.first().ok_or(ProcessingError::WrongArity("set".into()))?.as_str()?;
+        let value = args.get(1).ok_or(ProcessingError::WrongArity("set".into()))?.extract_bulk_content()?;
         let expire_type = args.get(2);
         let expire_value = args.get(3);
 
         let mut expire_timestamp: Option<u128> = None;
 
         if let Some(expire_type) = expire_type {
-            let expire_value_str = expire_value.ok_or("[set] expected value when using expire")?.as_str()?;
-            let expire_value_parsed: u128 = expire_value_str.parse().map_err(|_| ProcessingError::InvalidInteger)?;
+            let expire_value_str = expire_value.ok_or(ProcessingError::Syntax)?.as_str()?;
+            let expire_value_parsed: u128 = expire_value_str.parse().map_err(|_| ProcessingError::NotInteger)?;
 
             match expire_type.as_str()?.to_lowercase().as_str() {
                 "ex" => {
-                    expire_timestamp = Some(now() + (expire_value_parsed as u128) * 1000);
+                    expire_timestamp = Some(now() + expire_value_parsed * 1000);
                 },
                 "px" => {
-                    expire_timestamp = Some(now() + expire_value_parsed as u128);
+                    expire_timestamp = Some(now() + expire_value_parsed);
                 },
                 "exat" => {
-                    expire_timestamp = Some(expire_value_parsed as u128 * 1000);
+                    expire_timestamp = Some(expire_value_parsed * 1000);
                 },
                 "pxat" => {
-                    expire_timestamp = Some(expire_value_parsed as u128);
+                    expire_timestamp = Some(expire_value_parsed);
                 },
-                arg => {
-                    return Err(ProcessingError::from(format!("[set] unsupported arg: {}", arg)));
+                _ => {
+                    return Err(ProcessingError::Syntax);
                 }
             }
         }
 
-        self.insert(key, value, expire_timestamp);
+        self.insert(shards, key, value, expire_timestamp);
 
         Ok(Message::simple_string("OK"))
     }
 
-    fn command_get(&self, args: &[Message]) -> Result<Message, ProcessingError> {
-        let key = args.get(0).ok_or("[set] expected key")?.as_str()?;
+    fn command_get(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let key = args.first().ok_or(ProcessingError::WrongArity("get".into()))?.as_str()?;
 
-        if !self.check_expiration(key) {
+        if !self.check_expiration(shards, key) {
             return Ok(Message::BulkString(None));
         }
 
-        let memory_read_lock = self.memory.read().expect("Memory lock poisoned");
-        let value = memory_read_lock.get(key);
+        let value = shards.shard(key).get(key);
+        let counter = if value.is_some() { &self.stats.keyspace_hits } else { &self.stats.keyspace_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
 
         match value {
-            Some(Value::Single(bulk_string_content)) => Ok(Message::BulkString(Some(bulk_string_content.clone()))),
-            Some(Value::List(_)) => Err("Wrong type. Expected single element, got list.".into()),
+            Some(Value::Single(bulk_string_content)) => Ok(Message::BulkString(Some(bulk_string_content.to_vec()))),
+            Some(Value::List(_)) => Err(ProcessingError::WrongType),
             None => Ok(Message::BulkString(None))
         }
     }
 
-    fn command_exists(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+    fn command_mset(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        if !args.len().is_multiple_of(2) {
+            return Err(ProcessingError::WrongArity("mset".into()));
+        }
+        for pair in args.chunks(2) {
+            let key = pair[0].as_str()?;
+            let value = pair[1].extract_bulk_content()?;
+            self.insert(shards, key, value, None);
+        }
+        Ok(Message::simple_string("OK"))
+    }
+
+    fn command_exists(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
         let mut count = 0;
         for arg in args {
             let key = arg.as_str()?;
 
-            if !self.check_expiration(key) {
+            if !self.check_expiration(shards, key) {
                 continue;
             }
 
-            let memory_read_lock = self.memory.read().expect("Memory lock poisoned");
-            if memory_read_lock.contains_key(key) {
+            if shards.shard(key).contains_key(key) {
+                self.stats.keyspace_hits.fetch_add(1, Ordering::Relaxed);
                 count += 1;
+            } else {
+                self.stats.keyspace_misses.fetch_add(1, Ordering::Relaxed);
             }
         }
         Ok(Message::Integer(count))
     }
 
-    fn command_del(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+    fn command_del(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
         let mut removed = 0;
         for arg in args {
             let key = arg.as_str()?;
 
-            if self.remove(key) {
-                removed += 1;
+            if self.remove(shards, key) {
+                removed += 1;
+            }
+        }
+        Ok(Message::Integer(removed))
+    }
+
+    // TTL key, PTTL key
+    fn command_ttl(&self, shards: &mut LockedShards, args: &[Message], unit: u128) -> Result<Message, ProcessingError> {
+        let key = args[0].as_str()?;
+        if !self.check_expiration(shards, key) || !shards.shard(key).contains_key(key) {
+            return Ok(Message::Integer(-2));
+        }
+        match shards.shard(key).expire_at(key) {
+            Some(expire_at) => Ok(Message::Integer(((expire_at.saturating_sub(now()) + unit / 2) / unit) as i64)),
+            None => Ok(Message::Integer(-1)),
+        }
+    }
+
+    fn command_dump(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let key = args[0].as_str()?;
+        if !self.check_expiration(shards, key) {
+            return Ok(Message::BulkString(None));
+        }
+        Ok(Message::BulkString(shards.shard(key).get(key).map(dump::serialize)))
+    }
+
+    // OBJECT ENCODING | FREQ | IDLETIME | REFCOUNT key
+    fn command_object(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (subcommand, args) = split_to_command_args(args)?;
+        let subcommand = subcommand.as_str()?.to_lowercase();
+        let key = args[0].as_str()?;
+        if !self.check_expiration(shards, key) {
+            return Ok(Message::BulkString(None));
+        }
+        let shard = shards.shard(key);
+        let Some(value) = shard.peek(key) else {
+            return Ok(Message::BulkString(None));
+        };
+
+        match subcommand.as_str() {
+            "encoding" => Ok(Message::bulk_string(value.encoding())),
+            "freq" => {
+                if !self.memory.policy.is_lfu() {
+                    return Err("ERR An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.".into());
+                }
+                Ok(Message::Integer(shard.frequency(key).unwrap_or(0) as i64))
+            },
+            "idletime" => {
+                if self.memory.policy.is_lfu() {
+                    return Err("ERR An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.".into());
+                }
+                Ok(Message::Integer((shard.idle_time(key).unwrap_or(0) / 1000) as i64))
+            },
+            // values are never shared between keys
+            "refcount" => Ok(Message::Integer(1)),
+            subcommand => Err(ProcessingError::UnknownSubcommand { subcommand: subcommand.to_string(), command: "object".to_string() }),
+        }
+    }
+
+    // MEMORY USAGE key [SAMPLES count]
+    fn command_memory(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (subcommand, args) = split_to_command_args(args)?;
+        match subcommand.as_str()?.to_lowercase().as_str() {
+            "usage" => {
+                let key = args[0].as_str()?;
+                // usage is calculated exactly, so samples are only validated
+                match &args[1..] {
+                    [] => {},
+                    [option, samples] if option.as_str()?.eq_ignore_ascii_case("samples") => {
+                        samples.as_str()?.parse::<i64>().map_err(|_| ProcessingError::NotInteger)?;
+                    },
+                    _ => return Err(ProcessingError::Syntax),
+                }
+                if !self.check_expiration(shards, key) {
+                    return Ok(Message::BulkString(None));
+                }
+                Ok(shards.shard(key).memory_usage(key).map_or(Message::BulkString(None), |usage| Message::Integer(usage as i64)))
+            },
+            subcommand => Err(ProcessingError::UnknownSubcommand { subcommand: subcommand.to_string(), command: "memory".to_string() }),
+        }
+    }
+
+    // RESTORE key ttl serialized-value [REPLACE] [ABSTTL] [IDLETIME seconds]
+    fn command_restore(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let [key, ttl, payload, options @ ..] = args else {
+            return Err(ProcessingError::WrongArity("restore".into()));
+        };
+        let key = key.as_str()?;
+        let ttl: i64 = ttl.as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+        if ttl < 0 {
+            return Err("ERR Invalid TTL value, must be >= 0".into());
+        }
+
+        let mut replace = false;
+        let mut absttl = false;
+        let mut idletime: Option<u64> = None;
+        let mut options = options.iter();
+        while let Some(option) = options.next() {
+            match option.as_str()?.to_lowercase().as_str() {
+                "replace" => replace = true,
+                "absttl" => absttl = true,
+                "idletime" => {
+                    let seconds: i64 = options.next().ok_or(ProcessingError::Syntax)?.as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+                    if seconds < 0 {
+                        return Err("ERR Invalid IDLETIME value, must be >= 0".into());
+                    }
+                    idletime = Some(seconds as u64);
+                },
+                _ => return Err(ProcessingError::Syntax),
+            }
+        }
+
+        if !replace && self.check_expiration(shards, key) && shards.shard(key).contains_key(key) {
+            return Err("BUSYKEY Target key name already exists.".into());
+        }
+        let value = dump::deserialize(payload.extract_bulk_content()?, self.config.list_max_listpack_size)?;
+        let expire_at = match ttl as u128 {
+            0 => None,
+            ttl if absttl => Some(ttl),
+            ttl => Some(now() + ttl),
+        };
+
+        let shard = shards.shard(key);
+        if expire_at.is_some_and(|expire_at| expire_at <= now()) {
+            shard.remove(key);
+            return Ok(Message::simple_string("OK"));
+        }
+        shard.insert(key.to_string(), value);
+        shard.set_expire(key, expire_at);
+        if let Some(seconds) = idletime {
+            shard.set_last_access(key, (now() as u64).saturating_sub(seconds * 1000));
+        }
+        Ok(Message::simple_string("OK"))
+    }
+
+    // MIGRATE host port key|"" destination-db timeout [COPY] [REPLACE] [AUTH password | AUTH2 username password] [KEYS key [key ...]]
+    // Keys are serialized while locked, the locks are released during network exchange and
+    // only keys which didn't change meanwhile are deleted after target confirms them.
+    fn command_migrate(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let [host, port, key, db, timeout, options @ ..] = args else {
+            return Err(ProcessingError::WrongArity("migrate".into()));
+        };
+        let port: u16 = port.as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+        let db: i64 = db.as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+        if db != 0 {
+            return Err("ERR DB index is out of range".into());
+        }
+        let timeout: i64 = timeout.as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+        let timeout = Duration::from_millis(if timeout <= 0 { 1000 } else { timeout as u64 });
+
+        let mut copy = false;
+        let mut replace = false;
+        let mut auth: Vec<Message> = Vec::new();
+        let mut keys: Vec<&str> = Vec::new();
+        let mut index = 0;
+        while index < options.len() {
+            match options[index].as_str()?.to_lowercase().as_str() {
+                "copy" => copy = true,
+                "replace" => replace = true,
+                "auth" => {
+                    let password = options.get(index + 1).ok_or(ProcessingError::Syntax)?;
+                    auth = vec![Message::bulk_string("AUTH"), password.clone()];
+                    index += 1;
+                },
+                "auth2" => {
+                    let credentials = options.get(index + 1..index + 3).ok_or(ProcessingError::Syntax)?;
+                    auth = vec![Message::bulk_string("AUTH"), credentials[0].clone(), credentials[1].clone()];
+                    index += 2;
+                },
+                "keys" => {
+                    if !key.extract_bulk_content()?.is_empty() {
+                        return Err("ERR When using MIGRATE KEYS option, the key argument must be set to the empty string".into());
+                    }
+                    keys = options[index + 1..].iter().map(|key| key.as_str()).collect::<Result<_, _>>()?;
+                    break;
+                },
+                _ => return Err(ProcessingError::Syntax),
+            }
+            index += 1;
+        }
+        if keys.is_empty() {
+            keys.push(key.as_str()?);
+        }
+
+        let mut commands: Vec<Message> = Vec::new();
+        if !auth.is_empty() {
+            commands.push(Message::array(auth));
+        }
+        let mut migrated: Vec<(&str, Vec<u8>, Option<u128>)> = Vec::new();
+        for key in keys {
+            if !self.check_expiration(shards, key) {
+                continue;
+            }
+            let shard = shards.shard(key);
+            let Some(value) = shard.get(key) else {
+                continue;
+            };
+            let payload = dump::serialize(value);
+            let expire_at = shard.expire_at(key);
+            // relative TTL, so clocks of servers don't have to match
+            let ttl = expire_at.map_or(0, |expire_at| expire_at.saturating_sub(now()).max(1));
+            let mut restore = vec![Message::bulk_string("RESTORE"), Message::bulk_string(key), Message::bulk_string(&ttl.to_string()), Message::BulkString(Some(payload.clone()))];
+            if replace {
+                restore.push(Message::bulk_string("REPLACE"));
+            }
+            commands.push(Message::array(restore));
+            migrated.push((key, payload, expire_at));
+        }
+        if migrated.is_empty() {
+            return Ok(Message::simple_string("NOKEY"));
+        }
+
+        let own_ports = [self.config.port, self.config.tls_port];
+        let replies = shards.unlocked(|| send_to_target(host.as_str()?, port, timeout, &own_ports, &commands))?;
+        if let Some(Message::Error(err)) = replies.iter().find(|reply| matches!(reply, Message::Error(_))) {
+            return Err(format!("ERR Target instance replied with error: {}", err).into());
+        }
+        if !copy {
+            for (key, payload, expire_at) in migrated {
+                let shard = shards.shard(key);
+                let unchanged = shard.peek(key).is_some_and(|value| dump::serialize(value) == payload) && shard.expire_at(key) == expire_at;
+                if unchanged {
+                    self.remove(shards, key);
+                }
+            }
+        }
+        Ok(Message::simple_string("OK"))
+    }
+
+    fn command_incr(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let key = args.first().ok_or(ProcessingError::WrongArity("incr".into()))?.as_str()?;
+
+        if !self.check_expiration(shards, key) {
+            return Ok(Message::BulkString(None));
+        }
+
+        let shard = shards.shard(key);
+        if !shard.contains_key(key) {
+            shard.insert(key.to_string(), "0".into());
+        }
+        shard.update(key, |counter| {
+            if let Value::Single(counter) = counter {
+                let integer = counter.as_int().ok_or(ProcessingError::NotInteger)?.checked_add(1).ok_or(ProcessingError::Overflow)?;
+                *counter = StringValue::Int(integer);
+
+                Ok(Message::Integer(integer))
+            } else {
+                Err(ProcessingError::WrongType)
+            }
+        }).expect("Key is inserted above")
+    }
+    
+    fn command_decr(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let key = args.first().ok_or(ProcessingError::WrongArity("decr".into()))?.as_str()?;
+
+        if !self.check_expiration(shards, key) {
+            return Ok(Message::BulkString(None));
+        }
+
+        let shard = shards.shard(key);
+        if !shard.contains_key(key) {
+            shard.insert(key.to_string(), "0".into());
+        }
+        shard.update(key, |counter| {
+            if let Value::Single(counter) = counter {
+                let integer = counter.as_int().ok_or(ProcessingError::NotInteger)?.checked_sub(1).ok_or(ProcessingError::Overflow)?;
+                *counter = StringValue::Int(integer);
+
+                Ok(Message::Integer(integer))
+            } else {
+                Err(ProcessingError::WrongType)
+            }
+        }).expect("Key is inserted above")
+    }
+
+    fn command_lpush(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        if args.len() <= 1 {
+            return Err(ProcessingError::WrongArity("lpush".into()));
+        }
+
+
+        let (key_message, element_messages) = split_to_command_args(args)?;
+        let key = key_message.as_str()?;
+
+        self.check_expiration(shards, key);
+
+        let mut elements: Vec<Vec<u8>> = Vec::new();
+        for element in element_messages {
+            elements.push(element.extract_bulk_content()?.clone());
+        }
+
+        let shard = shards.shard(key);
+        let pushed = shard.update(key, |value| match value {
+            Value::Single(_) => Err(ProcessingError::WrongType),
+            Value::List(list) => {
+                for element in elements.drain(..) {
+                    list.push_front(element, self.config.list_max_listpack_size);
+                }
+                Ok(Message::Integer(list.len() as i64))
+            },
+        });
+
+        match pushed {
+            Some(result) => result,
+            None => {
+                let mut list = ListValue::default();
+                for element in elements {
+                    list.push_front(element, self.config.list_max_listpack_size);
+                }
+                let length = list.len();
+                shard.insert(key.to_string(), Value::List(list));
+                Ok(Message::Integer(length as i64))
+            }
+        }
+    }
+
+
+    fn command_rpush(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        if args.len() <= 1 {
+            return Err(ProcessingError::WrongArity("rpush".into()));
+        }
+
+
+        let (key_message, element_messages) = split_to_command_args(args)?;
+        let key = key_message.as_str()?;
+
+        self.check_expiration(shards, key);
+
+        let mut elements: Vec<Vec<u8>> = Vec::new();
+        for element in element_messages {
+            elements.push(element.extract_bulk_content()?.clone());
+        }
+
+        let shard = shards.shard(key);
+        let pushed = shard.update(key, |value| match value {
+            Value::Single(_) => Err(ProcessingError::WrongType),
+            Value::List(list) => {
+                for element in elements.drain(..) {
+                    list.push_back(element, self.config.list_max_listpack_size);
+                }
+                Ok(Message::Integer(list.len() as i64))
+            },
+        });
+
+        match pushed {
+            Some(result) => result,
+            None => {
+                let mut list = ListValue::default();
+                for element in elements {
+                    list.push_back(element, self.config.list_max_listpack_size);
+                }
+                let length = list.len();
+                shard.insert(key.to_string(), Value::List(list));
+                Ok(Message::Integer(length as i64))
+            }
+        }
+    }
+
+    // LRANGE key start stop, negative indexes count from the end of the list
+    fn command_lrange(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let key = args[0].as_str()?;
+        let start: i64 = args[1].as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+        let stop: i64 = args[2].as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+
+        if !self.check_expiration(shards, key) {
+            return Ok(Message::array(vec![]));
+        }
+
+        let value = shards.shard(key).get(key);
+        let counter = if value.is_some() { &self.stats.keyspace_hits } else { &self.stats.keyspace_misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        match value {
+            Some(Value::List(list)) => {
+                let len = list.len() as i64;
+                let start = if start < 0 { (len + start).max(0) } else { start };
+                let stop = if stop < 0 { len + stop } else { stop.min(len - 1) };
+                if start > stop {
+                    return Ok(Message::array(vec![]));
+                }
+                let elements = list.iter().skip(start as usize).take((stop - start + 1) as usize);
+                Ok(Message::array(elements.map(|element| Message::BulkString(Some(element.to_vec()))).collect()))
+            },
+            Some(Value::Single(_)) => Err(ProcessingError::WrongType),
+            None => Ok(Message::array(vec![])),
+        }
+    }
+
+    // SHUTDOWN [NOSAVE|SAVE] [NOW] [FORCE] [ABORT], shutdown itself is performed by the server
+    fn command_shutdown(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let words = args.iter().map(|arg| arg.as_str()).collect::<Result<Vec<_>, _>>()?;
+        if let [word] = words.as_slice() {
+            if word.eq_ignore_ascii_case("abort") {
+                return match self.shutdown.abort() {
+                    true => Ok(Message::simple_string("OK")),
+                    false => Err("ERR No shutdown in progress.".into()),
+                };
+            }
+        }
+        let options = ShutdownOptions::parse(words, false).ok_or(ProcessingError::Syntax)?;
+        // connection is closed without reply when server exits
+        self.shutdown.request_and_wait(options)?;
+        Ok(Message::simple_string("OK"))
+    }
+
+    pub(crate) fn command_save(&self) -> Result<Message, ProcessingError> {
+        let changes = self.stats.changes_since_last_save.load(Ordering::Relaxed);
+        let saved = self.save();
+        self.stats.last_save_ok.store(saved.is_ok(), Ordering::Relaxed);
+        saved?;
+        // writes made while saving are counted for the next save
+        self.stats.changes_since_last_save.fetch_sub(changes, Ordering::Relaxed);
+        self.stats.last_save_time.store((now() / 1000) as u64, Ordering::Relaxed);
+
+        Ok(Message::SimpleString("OK".to_string()))
+    }
+
+    fn save(&self) -> Result<(), ProcessingError> {
+        let db_file_path = self.db_file_path.as_ref().ok_or(ProcessingError::from("ERR Persistence is not configured"))?;
+        // written next to the db file and renamed over it, so a failed save keeps the previous snapshot
+        let temp_path = format!("{}.tmp", db_file_path);
+        let mut file = File::create(&temp_path).map_err(|_| ProcessingError::Other("ERR Cannot open the file for write".to_string()))?;
+        let written = file.write_all(&self.snapshot()).and_then(|_| file.sync_all()).and_then(|_| fs::rename(&temp_path, db_file_path));
+        if written.is_err() {
+            let _ = fs::remove_file(&temp_path);
+            return Err(ProcessingError::Other("ERR Cannot write the file".to_string()));
+        }
+        Ok(())
+    }
+
+    // Dataset as RESP array of SET and RPUSH commands, used for db file and replica full sync
+    pub fn snapshot(&self) -> Vec<u8> {
+        snapshot_of(&self.memory.read_all())
+    }
+
+    // Replaces dataset with snapshot received from master
+    pub fn load_snapshot(&self, snapshot: &[u8]) -> Result<(), ProcessingError> {
+        let mut parser = MessageParser::new();
+        let mut parsed: Option<Message> = None;
+        for &byte in snapshot {
+            if let Some(message) = parser.add_byte(byte).map_err(|err| ProcessingError::from(format!("[sync] {}", err)))? {
+                parsed = Some(message);
+            }
+        }
+        let Some(Message::Array(Some(commands))) = parsed else {
+            return Err("[sync] snapshot is not an array of commands".into());
+        };
+
+        self.memory.clear();
+        for command in commands {
+            if let Message::Error(err) = self.process_resp_message(&command) {
+                return Err(err.into());
+            }
+        }
+        Ok(())
+    }
+
+    fn command_client(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (subcommand, args) = split_to_command_args(args)?;
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+
+        match subcommand.as_str()?.to_lowercase().as_str() {
+            "id" => Ok(Message::Integer(client.id as i64)),
+            "info" => Ok(Message::bulk_string(&format!("{}\n", client.info_line()))),
+            "list" => self.command_client_list(args),
+            "setname" => {
+                let name = args.first().ok_or(ProcessingError::WrongArity("client|setname".into()))?.as_str()?;
+                if name.chars().any(|c| !c.is_ascii_graphic()) {
+                    return Err("ERR Client names cannot contain spaces, newlines or special characters.".into());
+                }
+                client.set_name(if name.is_empty() { None } else { Some(name.to_string()) });
+                Ok(Message::simple_string("OK"))
+            },
+            "getname" => match client.name() {
+                Some(name) => Ok(Message::bulk_string(&name)),
+                None => Ok(Message::BulkString(None)),
+            },
+            "kill" => self.command_client_kill(client, args),
+            "pause" => {
+                let timeout: u128 = args.first().ok_or(ProcessingError::WrongArity("client|pause".into()))?
+                    .as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+                let mode = match args.get(1).map(|mode| mode.as_str()).transpose()?.map(|mode| mode.to_lowercase()).as_deref() {
+                    None | Some("all") => PauseMode::All,
+                    Some("write") => PauseMode::Write,
+                    Some(_) => return Err(ProcessingError::Syntax),
+                };
+                self.clients.pause(now() + timeout, mode);
+                Ok(Message::simple_string("OK"))
+            },
+            "unpause" => {
+                self.clients.unpause();
+                Ok(Message::simple_string("OK"))
+            },
+            "no-evict" => {
+                let enabled = match args.first().ok_or(ProcessingError::WrongArity("client|no-evict".into()))?.as_str()?.to_lowercase().as_str() {
+                    "on" => true,
+                    "off" => false,
+                    _ => return Err(ProcessingError::Syntax),
+                };
+                client.no_evict.store(enabled, std::sync::atomic::Ordering::Relaxed);
+                Ok(Message::simple_string("OK"))
+            },
+            subcommand => Err(ProcessingError::UnknownSubcommand { subcommand: subcommand.to_string(), command: "client".to_string() }),
+        }
+    }
+
+    // CLIENT LIST [ID client-id [client-id ...]]
+    fn command_client_list(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let mut ids: Option<Vec<u64>> = None;
+        if let Some((filter, values)) = args.split_first() {
+            if !filter.as_str()?.eq_ignore_ascii_case("id") || values.is_empty() {
+                return Err(ProcessingError::Syntax);
+            }
+            let parsed: Result<Vec<u64>, ProcessingError> = values.iter()
+                .map(|id| id.as_str()?.parse().map_err(|_| ProcessingError::NotInteger))
+                .collect();
+            ids = Some(parsed?);
+        }
+
+        let mut list = String::new();
+        for client in self.clients.all() {
+            if ids.as_ref().is_some_and(|ids| !ids.contains(&client.id)) {
+                continue;
+            }
+            list.push_str(&client.info_line());
+            list.push('\n');
+        }
+        Ok(Message::bulk_string(&list))
+    }
+
+    // CLIENT KILL addr
+    // CLIENT KILL [ID client-id] [ADDR ip:port] [LADDR ip:port] [USER username] [SKIPME yes/no]
+    fn command_client_kill(&self, current: &Client, args: &[Message]) -> Result<Message, ProcessingError> {
+        if args.len() == 1 {
+            let addr = args[0].as_str()?;
+            let client = self.clients.all().into_iter().find(|client| client.addr == addr).ok_or("ERR No such client")?;
+            client.kill();
+            return Ok(Message::simple_string("OK"));
+        }
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err(ProcessingError::Syntax);
+        }
+
+        let mut id: Option<u64> = None;
+        let mut addr: Option<&str> = None;
+        let mut laddr: Option<&str> = None;
+        let mut user: Option<&str> = None;
+        let mut skip_me = true;
+        for pair in args.chunks(2) {
+            let value = pair[1].as_str()?;
+            match pair[0].as_str()?.to_lowercase().as_str() {
+                "id" => id = Some(value.parse().map_err(|_| ProcessingError::NotInteger)?),
+                "addr" => addr = Some(value),
+                "laddr" => laddr = Some(value),
+                "user" => user = Some(value),
+                "skipme" => skip_me = match value.to_lowercase().as_str() {
+                    "yes" => true,
+                    "no" => false,
+                    _ => return Err(ProcessingError::Syntax),
+                },
+                _ => return Err(ProcessingError::Syntax),
+            }
+        }
+
+        let mut killed = 0;
+        for client in self.clients.all() {
+            if id.is_some_and(|id| id != client.id)
+                || addr.is_some_and(|addr| addr != client.addr)
+                || laddr.is_some_and(|laddr| laddr != client.laddr)
+                || user.is_some_and(|user| user != client.user())
+                || (skip_me && client.id == current.id) {
+                continue;
+            }
+            client.kill();
+            killed += 1;
+        }
+        Ok(Message::Integer(killed))
+    }
+
+    fn check_permissions(&self, client: &Client, spec: CommandSpec, command: &str, args: &[Message], keys: &[&[u8]]) -> Result<(), ProcessingError> {
+        if spec.has_flag("no_auth") {
+            return Ok(());
+        }
+        if !client.is_authenticated() {
+            return Err(ProcessingError::NoAuth);
+        }
+
+        let username = client.user();
+        let user = self.acl.get_user(&username);
+        let subcommand = args.first().and_then(|arg| arg.as_str().ok());
+        if !user.as_ref().is_some_and(|user| user.can_run(command, subcommand)) {
+            self.acl.add_log_entry("command", command, &username, &client.info_line());
+            return Err(format!("NOPERM User {} has no permissions to run the '{}' command", username, command).into());
+        }
+
+        for key in keys {
+            if !user.as_ref().is_some_and(|user| user.can_access_key(key)) {
+                self.acl.add_log_entry("key", &String::from_utf8_lossy(key), &username, &client.info_line());
+                return Err("NOPERM No permissions to access a key".into());
+            }
+        }
+
+        let channels = match command {
+            "subscribe" | "psubscribe" => args,
+            "publish" => &args[..args.len().min(1)],
+            _ => &[],
+        };
+        for channel in channels {
+            let channel = channel.extract_bulk_content()?;
+            if !user.as_ref().is_some_and(|user| user.can_access_channel(channel, command == "psubscribe")) {
+                self.acl.add_log_entry("channel", &String::from_utf8_lossy(channel), &username, &client.info_line());
+                return Err("NOPERM No permissions to access a channel".into());
+            }
+        }
+        Ok(())
+    }
+
+    // AUTH password
+    // AUTH username password
+    fn command_auth(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        let (username, password) = match args {
+            [password] => {
+                if !self.acl.default_user_requires_auth() {
+                    return Err("ERR AUTH <password> called without any password configured for the default user. Are you sure your configuration is correct?".into());
+                }
+                ("default", password.as_str()?)
+            },
+            [username, password] => (username.as_str()?, password.as_str()?),
+            _ => return Err(ProcessingError::Syntax),
+        };
+
+        if self.acl.authenticate(username, password) {
+            client.login(username);
+            Ok(Message::simple_string("OK"))
+        } else {
+            self.acl.add_log_entry("auth", "AUTH", username, &client.info_line());
+            Err("WRONGPASS invalid username-password pair or user is disabled.".into())
+        }
+    }
+
+    fn command_acl(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (subcommand, args) = split_to_command_args(args)?;
+
+        match subcommand.as_str()?.to_lowercase().as_str() {
+            "setuser" => {
+                let (name, rules) = split_to_command_args(args)?;
+                let rules: Result<Vec<String>, ProcessingError> = rules.iter().map(|rule| rule.as_str().map(String::from)).collect();
+                self.acl.set_user(name.as_str()?, &rules?).map_err(|err| ProcessingError::from(format!("ERR {}", err)))?;
+                Ok(Message::simple_string("OK"))
+            },
+            "getuser" => {
+                let name = args.first().ok_or(ProcessingError::WrongArity("acl|getuser".into()))?.as_str()?;
+                Ok(self.acl.get_user(name).map_or(Message::Array(None), |user| user.to_message()))
+            },
+            "deluser" => {
+                let mut deleted = 0;
+                for name in args {
+                    let name = name.as_str()?;
+                    if self.acl.delete_user(name).map_err(|err| ProcessingError::from(format!("ERR {}", err)))? {
+                        deleted += 1;
+                        // connections of removed user are closed like in Redis
+                        for client in self.clients.all().iter().filter(|client| client.user() == name) {
+                            client.kill();
+                        }
+                    }
+                }
+                Ok(Message::Integer(deleted))
+            },
+            "list" => Ok(Message::array(self.acl.users().iter().map(|user| Message::bulk_string(&user.describe())).collect())),
+            "users" => Ok(Message::array(self.acl.users().iter().map(|user| Message::bulk_string(&user.name)).collect())),
+            "whoami" => {
+                let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+                Ok(Message::bulk_string(&client.user()))
+            },
+            "cat" => match args.first() {
+                None => Ok(Message::array(acl::CATEGORIES.iter().map(|category| Message::bulk_string(category)).collect())),
+                Some(category) => {
+                    let category = category.as_str()?.to_lowercase();
+                    if !acl::CATEGORIES.contains(&category.as_str()) {
+                        return Err(format!("ERR Unknown category '{}'", category).into());
+                    }
+                    Ok(Message::array(commands::in_category(&category).iter().map(|command| Message::bulk_string(command)).collect()))
+                }
+            },
+            "log" => match args.first() {
+                None => Ok(Message::array(self.acl.log_messages(10))),
+                Some(arg) if arg.as_str()?.eq_ignore_ascii_case("reset") => {
+                    self.acl.reset_log();
+                    Ok(Message::simple_string("OK"))
+                },
+                Some(count) => {
+                    let count: i64 = count.as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+                    let count = usize::try_from(count).map_err(|_| ProcessingError::OutOfRange)?;
+                    Ok(Message::array(self.acl.log_messages(count)))
+                }
+            },
+            "load" => {
+                self.acl.load().map_err(|err| ProcessingError::from(format!("ERR {}", err)))?;
+                Ok(Message::simple_string("OK"))
+            },
+            "save" => {
+                self.acl.save().map_err(|err| ProcessingError::from(format!("ERR {}", err)))?;
+                Ok(Message::simple_string("OK"))
+            },
+            subcommand => Err(ProcessingError::UnknownSubcommand { subcommand: subcommand.to_string(), command: "acl".to_string() }),
+        }
+    }
+
+    fn command_slowlog(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (subcommand, args) = split_to_command_args(args)?;
+        match subcommand.as_str()?.to_lowercase().as_str() {
+            "get" => {
+                let count = args.first()
+                    .map(|count| count.as_str()?.parse::<i64>().map_err(|_| ProcessingError::NotInteger))
+                    .transpose()?;
+                Ok(self.slowlog.get(count))
+            },
+            "len" => Ok(Message::Integer(self.slowlog.len() as i64)),
+            "reset" => {
+                self.slowlog.reset();
+                Ok(Message::simple_string("OK"))
+            },
+            subcommand => Err(ProcessingError::UnknownSubcommand { subcommand: subcommand.to_string(), command: "slowlog".to_string() }),
+        }
+    }
+
+    fn command_latency(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (subcommand, args) = split_to_command_args(args)?;
+        let events: Vec<&str> = args.iter().map(|event| event.as_str()).collect::<Result<_, _>>()?;
+        match subcommand.as_str()?.to_lowercase().as_str() {
+            "latest" => Ok(self.latency.latest()),
+            "history" => {
+                let event = events.first().ok_or(ProcessingError::WrongArity("latency|history".into()))?;
+                Ok(self.latency.history(event))
+            },
+            "reset" => Ok(Message::Integer(self.latency.reset(&events) as i64)),
+            subcommand => Err(ProcessingError::UnknownSubcommand { subcommand: subcommand.to_string(), command: "latency".to_string() }),
+        }
+    }
+
+    // COMMAND [COUNT | INFO [name ...] | DOCS [name ...] | GETKEYS command [arg ...]]
+    fn command_command(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let Some((subcommand, args)) = args.split_first() else {
+            return Ok(commands::info(&[]));
+        };
+        let names = || args.iter().map(|name| name.as_str()).collect::<Result<Vec<&str>, _>>();
+        match subcommand.as_str()?.to_lowercase().as_str() {
+            "count" => Ok(Message::Integer(commands::count() as i64)),
+            "info" => Ok(commands::info(&names()?)),
+            "docs" => Ok(commands::docs(&names()?)),
+            "getkeys" => {
+                let spec = commands::lookup(args).map_err(|err| match err {
+                    ProcessingError::WrongArity(_) => ProcessingError::from("ERR Invalid number of arguments specified for command"),
+                    _ => ProcessingError::from("ERR Invalid command specified"),
+                })?;
+                let key_args: Vec<&[u8]> = args[1..].iter().map(|arg| arg.extract_bulk_content().map_or(&[][..], |content| content.as_slice())).collect();
+                let keys = spec.keys(&key_args);
+                if keys.is_empty() {
+                    return Err("ERR The command has no key arguments".into());
+                }
+                Ok(Message::array(keys.iter().map(|key| Message::BulkString(Some(key.to_vec()))).collect()))
+            },
+            subcommand => Err(ProcessingError::UnknownSubcommand { subcommand: subcommand.to_string(), command: "command".to_string() }),
+        }
+    }
+
+    // EVAL script numkeys [key ...] [arg ...]
+    fn command_eval(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (script, keys, argv) = script_arguments(args)?;
+        self.scripting.eval(script.as_str()?, keys, argv, &mut |parts| self.execute_from_script(shards, parts))
+    }
+
+    // EVALSHA sha1 numkeys [key ...] [arg ...]
+    fn command_evalsha(&self, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (sha, keys, argv) = script_arguments(args)?;
+        self.scripting.evalsha(sha.as_str()?, keys, argv, &mut |parts| self.execute_from_script(shards, parts))
+    }
+
+    // SCRIPT LOAD script | EXISTS sha1 [sha1 ...] | FLUSH [ASYNC | SYNC] | KILL
+    fn command_script(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (subcommand, args) = split_to_command_args(args)?;
+        match subcommand.as_str()?.to_lowercase().as_str() {
+            "load" => Ok(Message::bulk_string(&self.scripting.load(args[0].as_str()?)?)),
+            "exists" => {
+                let exists: Result<Vec<Message>, ProcessingError> = args.iter().map(|sha| Ok(Message::Integer(self.scripting.exists(sha.as_str()?) as i64))).collect();
+                Ok(Message::array(exists?))
+            },
+            "flush" => {
+                if let Some(mode) = args.first() {
+                    if !["async", "sync"].contains(&mode.as_str()?.to_lowercase().as_str()) || args.len() > 1 {
+                        return Err(ProcessingError::Syntax);
+                    }
+                }
+                self.scripting.flush();
+                Ok(Message::simple_string("OK"))
+            },
+            "kill" => {
+                self.scripting.kill()?;
+                Ok(Message::simple_string("OK"))
+            },
+            subcommand => Err(ProcessingError::UnknownSubcommand { subcommand: subcommand.to_string(), command: "script".to_string() }),
+        }
+    }
+
+    // INFO [section ...]
+    fn command_info(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let sections: Result<Vec<String>, ProcessingError> = args.iter().map(|arg| arg.as_str().map(|arg| arg.to_lowercase())).collect();
+        let sections = sections?;
+        let everything = sections.iter().any(|section| ["all", "everything"].contains(&section.as_str()));
+        let all = everything || sections.is_empty() || sections.iter().any(|section| section == "default");
+        let requested = |name: &str| all || sections.iter().any(|section| section == name);
+
+        let mut info: Vec<String> = Vec::new();
+        if requested("server") {
+            info.push(self.info_server());
+        }
+        if requested("clients") {
+            info.push(self.info_clients());
+        }
+        if requested("memory") {
+            info.push(self.info_memory());
+        }
+        if requested("persistence") {
+            info.push(self.info_persistence());
+        }
+        if requested("stats") {
+            info.push(self.info_stats());
+        }
+        if requested("replication") {
+            info.push(self.replication.info());
+        }
+        if requested("cluster") {
+            info.push(format!("# Cluster\r\ncluster_enabled:{}\r\n", self.cluster.is_some() as u8));
+        }
+        if requested("keyspace") {
+            info.push(self.info_keyspace());
+        }
+        // not a part of default sections, as in Redis
+        if everything || sections.iter().any(|section| section == "latencystats") {
+            info.push(self.latency.info());
+        }
+        Ok(Message::bulk_string(&info.join("\r\n")))
+    }
+
+    fn info_server(&self) -> String {
+        let uptime = self.stats.uptime_in_seconds();
+        let mut info = String::from("# Server\r\n");
+        info.push_str(&format!("ccredis_version:{}\r\n", env!("CARGO_PKG_VERSION")));
+        info.push_str(&format!("redis_mode:{}\r\n", if self.cluster.is_some() { "cluster" } else { "standalone" }));
+        info.push_str(&format!("os:{} {}\r\n", std::env::consts::OS, std::env::consts::ARCH));
+        info.push_str(&format!("arch_bits:{}\r\n", usize::BITS));
+        info.push_str(&format!("process_id:{}\r\n", std::process::id()));
+        info.push_str(&format!("tcp_port:{}\r\n", self.config.port));
+        info.push_str(&format!("server_time_usec:{}\r\n", now() * 1000));
+        info.push_str(&format!("uptime_in_seconds:{}\r\nuptime_in_days:{}\r\n", uptime, uptime / (24 * 60 * 60)));
+        info.push_str(&format!("hz:{}\r\n", self.config.hz));
+        info
+    }
+
+    fn info_clients(&self) -> String {
+        // replicas are reported in replication section
+        let connected = self.clients.all().iter().filter(|client| !client.is_replica()).count();
+        format!("# Clients\r\nconnected_clients:{}\r\nmaxclients:{}\r\n", connected, self.config.maxclients)
+    }
+
+    fn info_memory(&self) -> String {
+        let used_memory = self.memory.used_memory();
+        let mut info = String::from("# Memory\r\n");
+        info.push_str(&format!("used_memory:{}\r\nused_memory_human:{}\r\n", used_memory, stats::bytes_to_human(used_memory)));
+        info.push_str(&format!("maxmemory:{}\r\nmaxmemory_human:{}\r\n", self.memory.maxmemory, stats::bytes_to_human(self.memory.maxmemory)));
+        info.push_str(&format!("maxmemory_policy:{}\r\n", self.memory.policy.name()));
+        info
+    }
+
+    fn info_persistence(&self) -> String {
+        let mut info = String::from("# Persistence\r\nloading:0\r\n");
+        info.push_str(&format!("rdb_changes_since_last_save:{}\r\n", self.stats.changes_since_last_save.load(Ordering::Relaxed)));
+        info.push_str("rdb_bgsave_in_progress:0\r\n");
+        info.push_str(&format!("rdb_last_save_time:{}\r\n", self.stats.last_save_time.load(Ordering::Relaxed)));
+        // there is no background save, status of the last SAVE is reported
+        let status = if self.stats.last_save_ok.load(Ordering::Relaxed) { "ok" } else { "err" };
+        info.push_str(&format!("rdb_last_bgsave_status:{}\r\n", status));
+        info
+    }
+
+    fn info_stats(&self) -> String {
+        let counters = [
+            ("total_connections_received", &self.stats.total_connections_received),
+            ("total_commands_processed", &self.stats.total_commands_processed),
+            ("rejected_connections", &self.stats.rejected_connections),
+            ("expired_keys", &self.memory.expired_keys),
+            ("expired_time_cap_reached_count", &self.memory.expired_time_cap_reached_count),
+            ("evicted_keys", &self.memory.evicted_keys),
+            ("keyspace_hits", &self.stats.keyspace_hits),
+            ("keyspace_misses", &self.stats.keyspace_misses),
+        ];
+        let mut info = String::from("# Stats\r\n");
+        for (name, counter) in counters {
+            info.push_str(&format!("{}:{}\r\n", name, counter.load(Ordering::Relaxed)));
+        }
+        info.push_str(&self.replication.sync_stats());
+        info
+    }
+
+    fn info_keyspace(&self) -> String {
+        let mut info = String::from("# Keyspace\r\n");
+        if !self.memory.is_empty() {
+            info.push_str(&format!("db0:keys={},expires={}\r\n", self.memory.len(), self.memory.expires_len()));
+        }
+        info
+    }
+
+    // REPLICAOF host port
+    // REPLICAOF NO ONE
+    fn command_replicaof(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let [host, port] = args else {
+            return Err(ProcessingError::WrongArity("replicaof".into()));
+        };
+        let (host, port) = (host.as_str()?, port.as_str()?);
+        if host.eq_ignore_ascii_case("no") && port.eq_ignore_ascii_case("one") {
+            self.replication.become_master();
+            return Ok(Message::simple_string("OK"));
+        }
+
+        let port: u16 = port.parse().map_err(|_| ProcessingError::from("ERR Invalid master port"))?;
+        if self.replication.is_replicating_from(host, port) {
+            return Ok(Message::simple_string("OK Already connected to specified master"));
+        }
+        // commands from master are applied without client, so they are not checked by ACL and read-only mode
+        let processor = MessageProcessor { client: None, ..self.clone() };
+        self.replication.replicate_from(host, port, processor);
+        Ok(Message::simple_string("OK"))
+    }
+
+    // REPLCONF listening-port port | capa capability | ACK offset
+    fn command_replconf(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        if args.is_empty() || !args.len().is_multiple_of(2) {
+            return Err("ERR syntax error".into());
+        }
+        for pair in args.chunks(2) {
+            let value = pair[1].as_str()?;
+            match pair[0].as_str()?.to_lowercase().as_str() {
+                "listening-port" => self.replication.set_listening_port(client.id, value.parse().map_err(|_| ProcessingError::NotInteger)?),
+                "ack" => self.replication.acknowledge(client.id, value.parse().map_err(|_| ProcessingError::NotInteger)?),
+                "capa" => {},
+                option => return Err(format!("ERR Unrecognized REPLCONF option: {}", option).into()),
             }
         }
-        Ok(Message::Integer(removed))
+        Ok(Message::simple_string("OK"))
     }
 
-    fn command_incr(&self, args: &[Message]) -> Result<Message, ProcessingError> {
-        let key = args.get(0).ok_or("[incr] expected key")?.as_str()?;
-
-        if !self.check_expiration(key) {
-            return Ok(Message::BulkString(None));
+    // PSYNC replid offset, connection becomes replica and receives replication stream
+    fn command_psync(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        let [replid, offset] = args else {
+            return Err(ProcessingError::WrongArity("psync".into()));
+        };
+        if self.replication.is_replica() {
+            return Err("ERR Replica can't be synchronized with its replicas".into());
         }
+        let offset: i64 = offset.as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
 
-        let mut memory_write_lock = self.memory.write().expect("Memory lock poisoned");
-        let counter = memory_write_lock.entry(key.to_string()).or_insert("0".into());
-        if let Value::Single(counter) = counter {
-            let integer = std::str::from_utf8(counter).map_err(|_| ProcessingError::InvalidUtf8)?
-                                        .parse::<i64>().map_err(|_| ProcessingError::InvalidInteger)? + 1;
-            *counter = integer.to_string().into();
+        // writes are propagated under shard locks, so snapshot matches replication offset
+        let shards = self.memory.read_all();
+        self.replication.attach_replica(client, replid.as_str()?, offset, || snapshot_of(&shards));
+        // reply is not sent to replica connection
+        Ok(Message::simple_string("OK"))
+    }
 
-            Ok(Message::Integer(integer))
-        } else {
-            Err("Wrong type. Expected single element, got list.".into())
+    // WAIT numreplicas timeout
+    fn command_wait(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let [replicas, timeout] = args else {
+            return Err(ProcessingError::WrongArity("wait".into()));
+        };
+        if self.replication.is_replica() {
+            return Err("ERR WAIT cannot be used with replica instances. Please also note that since Redis 4.0 if a replica is configured to be writable (which is not the default) writes to replicas are just local and are not propagated.".into());
         }
+        let replicas: usize = replicas.as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+        let timeout: u64 = timeout.as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+
+        // keeps other connections of this runtime worker running while we wait
+        let acked = self.replication.wait(replicas, timeout);
+        Ok(Message::Integer(acked as i64))
     }
-    
-    fn command_decr(&self, args: &[Message]) -> Result<Message, ProcessingError> {
-        let key = args.get(0).ok_or("[decr] expected key")?.as_str()?;
 
-        if !self.check_expiration(key) {
-            return Ok(Message::BulkString(None));
+    // keys of command have to be in one slot served by this node, otherwise client is redirected
+    fn check_cluster_slot(&self, cluster: &Cluster, keys: &[&[u8]], asking: bool) -> Result<(), ProcessingError> {
+        let exists = |key: &[u8]| std::str::from_utf8(key).is_ok_and(|key| self.memory.read(key).contains_key(key));
+        cluster.check_keys(keys, asking, exists).map_err(ProcessingError::from)
+    }
+
+    fn command_cluster(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let (subcommand, args) = split_to_command_args(args)?;
+        let subcommand = subcommand.as_str()?.to_lowercase();
+        // KEYSLOT works without cluster mode as well
+        if subcommand == "keyslot" {
+            let key = args.first().ok_or(ProcessingError::WrongArity("cluster|keyslot".into()))?.extract_bulk_content()?;
+            return Ok(Message::Integer(cluster::key_hash_slot(key) as i64));
+        }
+        let cluster = self.cluster.as_ref().ok_or("ERR This instance has cluster support disabled")?;
+        let parse_slot = |slot: &Message| -> Result<usize, ProcessingError> {
+            slot.as_str()?.parse::<usize>().ok().filter(|slot| *slot < cluster::SLOTS).ok_or("ERR Invalid or out of range slot".into())
+        };
+
+        match subcommand.as_str() {
+            "myid" => Ok(Message::bulk_string(&cluster.myself)),
+            "info" => Ok(Message::bulk_string(&cluster.info())),
+            "nodes" => Ok(Message::bulk_string(&cluster.nodes())),
+            "slots" => Ok(cluster.slots()),
+            "shards" => Ok(cluster.shards()),
+            "addslots" => {
+                if args.is_empty() {
+                    return Err(ProcessingError::WrongArity("cluster|addslots".into()));
+                }
+                let slots: Result<Vec<usize>, ProcessingError> = args.iter().map(parse_slot).collect();
+                cluster.add_slots(&slots?)?;
+                Ok(Message::simple_string("OK"))
+            },
+            "addslotsrange" => {
+                if args.is_empty() || !args.len().is_multiple_of(2) {
+                    return Err(ProcessingError::WrongArity("cluster|addslotsrange".into()));
+                }
+                let mut slots: Vec<usize> = Vec::new();
+                for range in args.chunks(2) {
+                    let (start, end) = (parse_slot(&range[0])?, parse_slot(&range[1])?);
+                    if start > end {
+                        return Err(format!("ERR start slot number {} is greater than end slot number {}", start, end).into());
+                    }
+                    slots.extend(start..=end);
+                }
+                cluster.add_slots(&slots)?;
+                Ok(Message::simple_string("OK"))
+            },
+            "setslot" => {
+                let slot = parse_slot(args.first().ok_or(ProcessingError::WrongArity("cluster|setslot".into()))?)?;
+                let action = args.get(1).ok_or(ProcessingError::WrongArity("cluster|setslot".into()))?.as_str()?.to_lowercase();
+                let node_id = args.get(2).map(|id| id.as_str()).transpose()?;
+                cluster.set_slot(slot, &action, node_id)?;
+                Ok(Message::simple_string("OK"))
+            },
+            "meet" => {
+                let ip = args.first().ok_or(ProcessingError::WrongArity("cluster|meet".into()))?.as_str()?;
+                let port: u16 = args.get(1).ok_or(ProcessingError::WrongArity("cluster|meet".into()))?
+                    .as_str()?.parse().map_err(|_| ProcessingError::from("ERR Invalid base port specified"))?;
+                let cport: u16 = match args.get(2) {
+                    Some(cport) => cport.as_str()?.parse().map_err(|_| ProcessingError::from("ERR Invalid bus port specified"))?,
+                    None => port.checked_add(10000).ok_or("ERR Invalid base port specified")?,
+                };
+                cluster.meet(ip, port, cport);
+                Ok(Message::simple_string("OK"))
+            },
+            subcommand => Err(ProcessingError::UnknownSubcommand { subcommand: subcommand.to_string(), command: "cluster".to_string() }),
         }
+    }
 
-        let mut memory_write_lock = self.memory.write().expect("Memory lock poisoned");
-        let counter = memory_write_lock.entry(key.to_string()).or_insert("0".into());
-        if let Value::Single(counter) = counter {
-            let integer = std::str::from_utf8(counter).map_err(|_| ProcessingError::InvalidUtf8)?
-                                        .parse::<i64>().map_err(|_| ProcessingError::InvalidInteger)? - 1;
-            *counter = integer.to_string().into();
+    fn command_monitor(&self) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        self.clients.add_monitor(client.clone());
+        Ok(Message::simple_string("OK"))
+    }
 
-            Ok(Message::Integer(integer))
-        } else {
-            Err("Wrong type. Expected single element, got list.".into())
+    fn command_asking(&self) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        if self.cluster.is_none() {
+            return Err("ERR This instance has cluster support disabled".into());
         }
+        client.set_asking();
+        Ok(Message::simple_string("OK"))
     }
 
-    fn command_lpush(&self, args: &[Message]) -> Result<Message, ProcessingError> {
-        if args.len() <= 1 {
-            return Err("[lpush] Expected at least two arguments: key, and list element".into());
+    fn command_multi(&self) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        if !client.begin_transaction() {
+            return Err("ERR MULTI calls can not be nested".into());
+        }
+        Ok(Message::simple_string("OK"))
+    }
+
+    // Queued commands run with shards of their keys and of watched keys locked,
+    // so other clients can't change the keys in between
+    fn command_exec(&self) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        let transaction = client.take_transaction().ok_or("ERR EXEC without MULTI")?;
+        if transaction.refused {
+            self.memory.watches.unwatch(client.id);
+            return Err("EXECABORT Transaction discarded because of previous errors.".into());
+        }
+        let queued: Vec<(CommandSpec, &[Message])> = transaction.commands.iter()
+            .map(|parts| (commands::lookup(parts).expect("Queued command was looked up"), parts.as_slice()))
+            .collect();
+        // evicted watched key aborts the transaction
+        if queued.iter().any(|(spec, _)| spec.has_flag("denyoom")) && !self.replication.is_replica() {
+            self.free_memory().inspect_err(|_| {
+                self.memory.watches.unwatch(client.id);
+            })?;
         }
 
+        let watched = self.memory.watches.watched_keys(client.id);
+        let key_args: Vec<Vec<&[u8]>> = queued.iter().map(|(_, parts)| key_arguments(&parts[1..])).collect();
+        let mut keys: Vec<&[u8]> = queued.iter().zip(&key_args).flat_map(|((spec, _), args)| spec.keys(args)).collect();
+        keys.extend(watched.iter().map(Vec::as_slice));
+        let mut shards = self.memory.lock_keys(&keys);
+        if self.memory.watches.unwatch(client.id) {
+            return Ok(Message::Array(None));
+        }
 
-        let (key_message, element_messages) = split_to_command_args(args)?;
-        let key = key_message.as_str()?;
+        let replies = queued.into_iter().map(|(spec, parts)| {
+            let command = parts[0].as_str().map(str::to_lowercase).unwrap_or_default();
+            let reply = self.execute(spec, &command, &mut shards, parts).unwrap_or_else(|err| Message::Error(err.to_string()));
+            self.feed_monitors(spec, &command, parts);
+            reply
+        });
+        Ok(Message::array(replies.collect()))
+    }
 
-        self.check_expiration(key);
+    fn command_discard(&self) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        client.take_transaction().ok_or("ERR DISCARD without MULTI")?;
+        self.memory.watches.unwatch(client.id);
+        Ok(Message::simple_string("OK"))
+    }
 
-        let mut memory_write_lock = self.memory.write().expect("Memory lock poisoned");
-        let value = memory_write_lock.get_mut(key);
+    // shards of the keys are locked, so a key can't change before it is watched
+    fn command_watch(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        if client.in_transaction() {
+            return Err("ERR WATCH inside MULTI is not allowed".into());
+        }
+        self.memory.watches.watch(client.id, &key_arguments(args));
+        Ok(Message::simple_string("OK"))
+    }
 
-        match value {
-            Some(Value::Single(_)) => return Err("Wrong type. Expected list element, got single.".into()),
-            Some(Value::List(list)) => {
-                for element in element_messages {
-                    list.push_front(element.extract_bulk_content()?.clone());
-                }
-                return Ok(Message::Integer(list.len() as i64));
-            },
-            None => {
-                let mut list: VecDeque<Vec<u8>> = VecDeque::new();
-                for element in element_messages {
-                    list.push_front(element.extract_bulk_content()?.clone());
-                }
-                let length = list.len();
-                memory_write_lock.insert(key.to_string(), Value::List(list));
-                return Ok(Message::Integer(length as i64));
-            }
+    fn command_unwatch(&self) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        self.memory.watches.unwatch(client.id);
+        Ok(Message::simple_string("OK"))
+    }
+
+    // Every channel or pattern is confirmed by its own reply, all but the last one are queued here
+    fn confirm_subscriptions(client: &Client, confirmations: Vec<Message>) -> Message {
+        let (last, queued) = confirmations.split_last().expect("At least one confirmation");
+        for confirmation in queued {
+            client.send(confirmation);
         }
+        last.clone()
     }
 
+    fn command_subscribe(&self, args: &[Message], kind: Subscription) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        let reply = match kind {
+            Subscription::Channel => "subscribe",
+            Subscription::Pattern => "psubscribe",
+        };
+        let confirmations = args.iter().map(|arg| {
+            let name = arg.extract_bulk_content()?;
+            let count = self.pubsub.subscribe(client, kind, name);
+            Ok(Message::array(vec![Message::bulk_string(reply), arg.clone(), Message::Integer(count as i64)]))
+        }).collect::<Result<Vec<_>, ProcessingError>>()?;
+        Ok(Self::confirm_subscriptions(client, confirmations))
+    }
 
-    fn command_rpush(&self, args: &[Message]) -> Result<Message, ProcessingError> {
-        if args.len() <= 1 {
-            return Err("[lpush] Expected at least two arguments: key, and list element".into());
+    // without arguments unsubscribes from every channel or pattern of the client
+    fn command_unsubscribe(&self, args: &[Message], kind: Subscription) -> Result<Message, ProcessingError> {
+        let client = self.client.as_ref().ok_or("ERR command is available only for connected clients")?;
+        let reply = match kind {
+            Subscription::Channel => "unsubscribe",
+            Subscription::Pattern => "punsubscribe",
+        };
+        let names = match args.is_empty() {
+            true => self.pubsub.subscriptions(client.id, kind),
+            false => args.iter().map(|arg| arg.extract_bulk_content().cloned()).collect::<Result<_, _>>()?,
+        };
+        if names.is_empty() {
+            let count = self.pubsub.count(client.id) as i64;
+            return Ok(Message::array(vec![Message::bulk_string(reply), Message::BulkString(None), Message::Integer(count)]));
         }
+        let confirmations = names.into_iter().map(|name| {
+            let count = self.pubsub.unsubscribe(client.id, kind, &name);
+            Message::array(vec![Message::bulk_string(reply), Message::BulkString(Some(name)), Message::Integer(count as i64)])
+        }).collect();
+        Ok(Self::confirm_subscriptions(client, confirmations))
+    }
 
+    fn command_publish(&self, args: &[Message]) -> Result<Message, ProcessingError> {
+        let channel = args.first().ok_or(ProcessingError::WrongArity("publish".into()))?.extract_bulk_content()?;
+        let payload = args.get(1).ok_or(ProcessingError::WrongArity("publish".into()))?.extract_bulk_content()?;
+        Ok(Message::Integer(self.pubsub.publish(channel, payload) as i64))
+    }
 
-        let (key_message, element_messages) = split_to_command_args(args)?;
-        let key = key_message.as_str()?;
+    fn insert(&self, shards: &mut LockedShards, key: &str, value: &[u8], expire_at: Option<u128>) {
+        let shard = shards.shard(key);
+        shard.insert(key.to_string(), Value::Single(StringValue::new(value.to_vec())));
+        shard.set_expire(key, expire_at);
+    }
 
-        self.check_expiration(key);
+    fn remove(&self, shards: &mut LockedShards, key: &str) -> bool {
+        shards.shard(key).remove(key).is_some()
+    }
 
-        let mut memory_write_lock = self.memory.write().expect("Memory lock poisoned");
-        let value = memory_write_lock.get_mut(key);
+    fn check_expiration(&self, shards: &mut LockedShards, key: &str) -> bool {
+        let shard = shards.shard(key);
+        if shard.is_expired(key, now()) {
+            shard.remove(key);
+            self.memory.expired_keys.fetch_add(1, Ordering::Relaxed);
+            self.replication.propagate(&[Message::bulk_string("DEL"), Message::bulk_string(key)]);
+            return false;
+        }
+        true
+    }
+}
 
+// Dataset of locked shards as RESP array of SET and RPUSH commands
+fn snapshot_of(shards: &[RwLockReadGuard<Shard>]) -> Vec<u8> {
+    let mut messages: Vec<Message> = Vec::new();
+    for (key, value, expire_at) in shards.iter().flat_map(|shard| shard.iter()) {
+        let mut command: Vec<Message> = Vec::new();
         match value {
-            Some(Value::Single(_)) => return Err("Wrong type. Expected list element, got single.".into()),
-            Some(Value::List(list)) => {
-                for element in element_messages {
-                    list.push_back(element.extract_bulk_content()?.clone());
+            Value::Single(content) => {
+                command.push(Message::bulk_string("SET"));
+                command.push(Message::bulk_string(key));
+                command.push(Message::BulkString(Some(content.to_vec())));
+
+                if let Some(expire_at) = expire_at {
+                    command.push(Message::bulk_string("PXAT"));
+                    command.push(Message::bulk_string(&expire_at.to_string()));
                 }
-                return Ok(Message::Integer(list.len() as i64));
             },
-            None => {
-                let mut list: VecDeque<Vec<u8>> = VecDeque::new();
-                for element in element_messages {
-                    list.push_back(element.extract_bulk_content()?.clone());
+            Value::List(list) => {
+                command.push(Message::bulk_string("RPUSH"));
+                command.push(Message::bulk_string(key));
+                for element in list.iter() {
+                    command.push(Message::BulkString(Some(element.to_vec())));
                 }
-                let length = list.len();
-                memory_write_lock.insert(key.to_string(), Value::List(list));
-                return Ok(Message::Integer(length as i64));
             }
         }
+        messages.push(Message::Array(Some(command)));
     }
 
-    fn command_save(&self) -> Result<Message, ProcessingError> {
-        let file = File::create(self.db_file_path.clone()).map_err(|_| ProcessingError::Other("Cannot open the file for write".to_string()))?;
-        let lock = self.memory.write().expect("Memory lock poisoned");
-        let key_expiration_lock = self.key_expiration.write().expect("Memory lock poisoned");
-        
-        // format: 
-        // i64 - number of keys, [
-        //   i64 - key_length, [u8 * key_length] - key, 
-        //   i64 - number of elements (0 for single value, >0 for lists),
-        //   [i64 - value_length, [u8 * value_length] - value] * number of elements
-        // ] * number of keys
-        // file.write(&(lock.len() as i64).to_be_bytes());
-        // for (key, value) in lock.iter() {
-        //     file.write(&(key.len() as i64).to_be_bytes());
-        //     file.write(&key.as_bytes());
-        //     match value {
-        //         Value::Single(content) => {
-        //             file.write(&0_i64.to_be_bytes());
-        //             file.write(&(content.len() as i64).to_be_bytes());
-        //             file.write(&content);
-        //         },
-        //         Value::List(list) => {
-        //             file.write(&(list.len() as i64).to_be_bytes());
-        //             for element in list {
-        //                 file.write(&(element.len() as i64).to_be_bytes());
-        //                 file.write(&element);
-        //             }
-        //         }
-        //     }
-        // }
+    let mut buf: Vec<u8> = Vec::new();
+    Message::Array(Some(messages)).write_to(&mut buf).expect("Writing to Vec can't fail");
+    buf
+}
 
-        let mut messages: Vec<Message> = Vec::new();
-        for (key, value) in lock.iter() {
-            let mut command: Vec<Message> = Vec::new();
-            match value {
-                Value::Single(content) => {
-                    command.push(Message::bulk_string("SET"));
-                    command.push(Message::bulk_string(key));
-                    command.push(Message::BulkString(Some(content.clone())));
-
-                    if let Some(expire_at) = key_expiration_lock.get(key) {
-                        command.push(Message::bulk_string("PXAT"));
-                        command.push(Message::bulk_string(&expire_at.to_string()));
-                    }
-                },
-                Value::List(list) => {
-                    command.push(Message::bulk_string("RPUSH"));
-                    command.push(Message::bulk_string(key));
-                    for element in list {
-                        command.push(Message::BulkString(Some(element.clone())));
-                    }
-                }
-            }
-            messages.push(Message::Array(Some(command)));
+// double quoted string with escaped special and non-printable characters, as printed by Redis
+pub fn quote(bytes: &[u8]) -> String {
+    let mut quoted = String::from("\"");
+    for &byte in bytes {
+        match byte {
+            b'\\' => quoted.push_str("\\\\"),
+            b'"' => quoted.push_str("\\\""),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            0x07 => quoted.push_str("\\a"),
+            0x08 => quoted.push_str("\\b"),
+            byte if byte.is_ascii_graphic() || byte == b' ' => quoted.push(byte as char),
+            byte => quoted.push_str(&format!("\\x{:02x}", byte)),
         }
-
-        Message::Array(Some(messages)).write_to(&mut BufWriter::new(file)).map_err(|_| ProcessingError::Other("Cant write message to writer".to_string()))?;
-
-        Ok(Message::SimpleString("OK".to_string()))
     }
+    quoted.push('"');
+    quoted
+}
 
-    fn insert(&self, key: &str, value: &Vec<u8>, expire_at: Option<u128>) {
-        let mut memory_lock = self.memory.write().expect("Memory lock poisoned");
-        memory_lock.insert(key.to_string(), Value::Single(value.clone()));
-
-        let mut key_expiration_lock = self.key_expiration.write().expect("Memory lock poisoned");
-        if let Some(expire_timestamp) = expire_at {
-            key_expiration_lock.insert(key.to_string(), expire_timestamp);
-        } else {
-            key_expiration_lock.remove(key);
-        }
+// Sends commands to another server at once and reads reply for each of them, used by MIGRATE.
+// Connection to one of `own_ports` on an address of this host would restore keys into this server.
+fn send_to_target(host: &str, port: u16, timeout: Duration, own_ports: &[u16], commands: &[Message]) -> Result<Vec<Message>, ProcessingError> {
+    let connect_error = || ProcessingError::from("IOERR error or timeout connecting to the client");
+    let read_error = || ProcessingError::from("IOERR error or timeout reading to target instance");
+    let address = (host, port).to_socket_addrs().map_err(|_| connect_error())?.next().ok_or_else(connect_error)?;
+    let mut stream = TcpStream::connect_timeout(&address, timeout).map_err(|_| connect_error())?;
+    let local_ip = stream.local_addr().map_err(|_| connect_error())?.ip();
+    if own_ports.contains(&port) && (address.ip().is_loopback() || address.ip().is_unspecified() || address.ip() == local_ip) {
+        return Err("ERR Target instance is this server".into());
     }
+    stream.set_read_timeout(Some(timeout)).and_then(|_| stream.set_write_timeout(Some(timeout))).map_err(|_| connect_error())?;
 
-    // fn fetch(&self, key: &str) -> Option<String> {
-    //     if !self.check_expiration(key) {
-    //         return None;
-    //     }
-
-    //     let memory_read_lock = self.memory.read().expect("Memory lock poisoned");
-    //     memory_read_lock.get(key)
-    // }
-    
-    fn remove(&self, key: &str) -> bool {
-        let mut existed = false;
-        
-        if self.memory
-            .write()
-            .expect("Memory lock poisoned")
-            .remove(key)
-            .is_some() {
-                existed = true;
-            };
-        self.key_expiration
-            .write()
-            .expect("Memory lock poisoned")
-            .remove(key);
-
-        existed
+    let mut buf: Vec<u8> = Vec::new();
+    for command in commands {
+        command.write_to(&mut buf).expect("Writing to Vec can't fail");
     }
-
-    fn check_expiration(&self, key: &str) -> bool {
-        let key_expiration_read_lock = self.key_expiration.read().expect("Memory lock poisoned");
-        let key_timestamp = key_expiration_read_lock.get(key);
-        
-        if let Some(&key_timestamp) = key_timestamp {
-            drop(key_expiration_read_lock);
-            if now() > key_timestamp {
-                self.remove(key);
-                return false;
+    stream.write_all(&buf).map_err(|_| ProcessingError::from("IOERR error or timeout writing to target instance"))?;
+
+    let mut parser = MessageParser::new();
+    let mut replies: Vec<Message> = Vec::new();
+    let mut chunk = [0u8; 4096];
+    while replies.len() < commands.len() {
+        let read = stream.read(&mut chunk).map_err(|_| read_error())?;
+        if read == 0 {
+            return Err(read_error());
+        }
+        for &byte in &chunk[..read] {
+            if let Some(reply) = parser.add_byte(byte).map_err(|_| read_error())? {
+                replies.push(reply);
             }
         }
-        true
     }
+    Ok(replies)
+}
+
+// script or sha1, keys and arguments of EVAL and EVALSHA
+fn script_arguments(args: &[Message]) -> Result<(&Message, &[Message], &[Message]), ProcessingError> {
+    let numkeys: i64 = args[1].as_str()?.parse().map_err(|_| ProcessingError::NotInteger)?;
+    if numkeys < 0 {
+        return Err("ERR Number of keys can't be negative".into());
+    }
+    if numkeys as usize > args.len() - 2 {
+        return Err("ERR Number of keys can't be greater than number of args".into());
+    }
+    let (keys, argv) = args[2..].split_at(numkeys as usize);
+    Ok((&args[0], keys, argv))
+}
+
+// arguments as bytes for key lookup, arguments which are not bulk strings are empty
+fn key_arguments(args: &[Message]) -> Vec<&[u8]> {
+    args.iter().map(|arg| arg.extract_bulk_content().map_or(&[][..], |content| content.as_slice())).collect()
 }
 
 fn split_to_command_args<T>(vec: &[T]) -> Result<(&T, &[T]), ProcessingError> {
     match vec.split_first() {
         Some((head, tail)) => Ok((head, tail)),
-        None => Err(ProcessingError::from("Vector is empty"))
+        None => Err(ProcessingError::Syntax)
     }
 }
 
@@ -400,6 +1786,11 @@ pub fn now() -> u128 {
     TIMESTAMP.with(|timestamp| timestamp.get() )
 }
 
+#[cfg(test)]
+pub fn travel_to(timestamp: u128) {
+    TIMESTAMP.with(|ts| ts.set(timestamp));
+}
+
 thread_local! {
     static TIMESTAMP: Cell<u128> = const { Cell::new(0) };
 }
@@ -407,10 +1798,7 @@ thread_local! {
 #[cfg(test)]
 mod tests {
     use super::*;
-
-    pub fn travel_to(timestamp: u128) {
-        TIMESTAMP.with(|ts| ts.set(timestamp));
-    }
+    use crate::{connection::{Outbound, Outgoing}, keyspace::EvictionPolicy};
 
     fn from_cli(command: &str) -> Message {
         let mut messages: Vec<Message> = Vec::new();
@@ -421,10 +1809,31 @@ mod tests {
     }
 
     fn create_message_processor() -> MessageProcessor {
-        let memory: SharedMemory = Arc::new(RwLock::new(HashMap::new()));
-        let key_expiration: KeyExpiration = Arc::new(RwLock::new(HashMap::new()));
-        let db_file_path = "tmp/db.bin".to_string();
-        MessageProcessor { memory, key_expiration, db_file_path }
+        let memory: SharedMemory = Arc::new(Keyspace::default());
+        let db_file_path = None;
+        let clients = Arc::new(ClientRegistry::default());
+        let acl = Arc::new(Acl::default());
+        let replication = Arc::new(Replication::new(&Config::default()));
+        let config = Arc::new(Config::default());
+        let stats = Arc::new(Stats::default());
+        let slowlog = Arc::new(SlowLog::new(&config));
+        let latency = Arc::new(Latency::new(&config));
+        let scripting = Arc::new(Scripting::new(&config));
+        let shutdown = Arc::new(Shutdown::default());
+        let pubsub = Arc::new(PubSub::default());
+        MessageProcessor { memory, db_file_path, clients, acl, replication, cluster: None, config, stats, slowlog, latency, scripting, shutdown, pubsub, client: None }
+    }
+
+    // messages pushed to test clients are dropped
+    fn outbound() -> Outbound {
+        tokio::sync::mpsc::unbounded_channel().0
+    }
+
+    fn create_connected_message_processor() -> MessageProcessor {
+        let processor = create_message_processor();
+        let client = processor.clients.register("127.0.0.1:10001", "127.0.0.1:6379", outbound());
+        client.login("default");
+        processor.with_client(client)
     }
 
     #[test]
@@ -446,14 +1855,14 @@ mod tests {
         let response = processor.process_resp_message(&request);
 
         assert_eq!(response, Message::simple_string("OK"));
-        assert_eq!(*processor.memory.read().unwrap().get("test_key").unwrap(), "test_value".into());
+        assert_eq!(*processor.memory.read("test_key").get("test_key").unwrap(), "test_value".into());
     }
 
     
     #[test]
     fn message_exists() {
         let processor = create_message_processor();
-        processor.memory.write().unwrap().insert("foo".to_string(), "bar".into());
+        processor.memory.write("foo").insert("foo".to_string(), "bar".into());
 
         let request = from_cli("EXISTS foo");
         let response = processor.process_resp_message(&request);
@@ -546,14 +1955,14 @@ mod tests {
         assert_eq!(response, Message::BulkString(None));
 
         // memory is cleared after expire
-        assert_eq!(processor.memory.clone().read().unwrap().len(), 0);
-        assert_eq!(processor.key_expiration.clone().read().unwrap().len(), 0);
+        assert_eq!(processor.memory.len(), 0);
+        assert_eq!(processor.memory.expires_len(), 0);
     }
 
     #[test]
     fn test_incr() {
         let processor = create_message_processor();
-        processor.memory.write().unwrap().insert("foo".to_string(), "68".into());
+        processor.memory.write("foo").insert("foo".to_string(), "68".into());
 
         let request = from_cli("INCR foo");
         let response = processor.process_resp_message(&request);
@@ -563,13 +1972,37 @@ mod tests {
     #[test]
     fn test_decr() {
         let processor = create_message_processor();
-        processor.memory.write().unwrap().insert("foo".to_string(), "70".into());
+        processor.memory.write("foo").insert("foo".to_string(), "70".into());
 
         let request = from_cli("DECR foo");
         let response = processor.process_resp_message(&request);
         assert_eq!(response, Message::Integer(69));
     }
 
+    #[test]
+    fn test_redis_error_replies() {
+        let processor = create_connected_message_processor();
+        processor.memory.write("list").insert("list".to_string(), Value::List(ListValue::from_elements([b"a".to_vec()], -2)));
+        processor.memory.write("max").insert("max".to_string(), i64::MAX.to_string().into());
+        processor.memory.write("text").insert("text".to_string(), "abc".into());
+
+        let replies = [
+            ("GET list", "WRONGTYPE Operation against a key holding the wrong kind of value"),
+            ("INCR list", "WRONGTYPE Operation against a key holding the wrong kind of value"),
+            ("INCR text", "ERR value is not an integer or out of range"),
+            ("INCR max", "ERR increment or decrement would overflow"),
+            ("SET key value EX", "ERR syntax error"),
+            ("SET key value KEEP 10", "ERR syntax error"),
+            ("SET key value EX ten", "ERR value is not an integer or out of range"),
+            ("ACL LOG -1", "ERR value is out of range, must be positive"),
+            ("SLOWLOG FOO", "ERR unknown subcommand 'FOO'. Try SLOWLOG HELP."),
+            ("ECHO", "ERR wrong number of arguments for 'echo' command"),
+        ];
+        for (command, error) in replies {
+            assert_eq!(processor.process_resp_message(&from_cli(command)), Message::error(error), "{}", command);
+        }
+    }
+
     #[test]
     fn test_lpush() {
         let processor = create_message_processor();
@@ -578,9 +2011,9 @@ mod tests {
         let response = processor.process_resp_message(&request);
         assert_eq!(response, Message::Integer(3));
 
-        let lock = processor.memory.read().unwrap();
+        let lock = processor.memory.read("foo");
         if let Some(Value::List(list)) = lock.get("foo") {
-            assert_eq!(*list, VecDeque::from([Vec::from("3".as_bytes()), Vec::from("2".as_bytes()), Vec::from("1".as_bytes())]))
+            assert_eq!(list.iter().map(|element| element.to_vec()).collect::<Vec<_>>(), Vec::from([Vec::from("3".as_bytes()), Vec::from("2".as_bytes()), Vec::from("1".as_bytes())]))
         } else {
             unreachable!("Expected list");
         }
@@ -594,15 +2027,159 @@ mod tests {
         let response = processor.process_resp_message(&request);
         assert_eq!(response, Message::Integer(3));
 
-        let lock = processor.memory.read().unwrap();
+        let lock = processor.memory.read("foo");
         if let Some(Value::List(list)) = lock.get("foo") {
-            assert_eq!(*list, VecDeque::from([Vec::from("1".as_bytes()), Vec::from("2".as_bytes()), Vec::from("3".as_bytes())]))
+            assert_eq!(list.iter().map(|element| element.to_vec()).collect::<Vec<_>>(), Vec::from([Vec::from("1".as_bytes()), Vec::from("2".as_bytes()), Vec::from("3".as_bytes())]))
         } else {
             unreachable!("Expected list");
         }
     }
 
-        #[test]
+    #[test]
+    fn test_lrange() {
+        let processor = create_message_processor();
+        processor.process_resp_message(&from_cli("RPUSH foo 1 2 3 4"));
+
+        let elements = |elements: &[&str]| Message::array(elements.iter().map(|element| Message::bulk_string(element)).collect());
+        assert_eq!(processor.process_resp_message(&from_cli("LRANGE foo 0 -1")), elements(&["1", "2", "3", "4"]));
+        assert_eq!(processor.process_resp_message(&from_cli("LRANGE foo 1 2")), elements(&["2", "3"]));
+        assert_eq!(processor.process_resp_message(&from_cli("LRANGE foo -2 100")), elements(&["3", "4"]));
+        assert_eq!(processor.process_resp_message(&from_cli("LRANGE foo -100 0")), elements(&["1"]));
+        assert_eq!(processor.process_resp_message(&from_cli("LRANGE foo 3 1")), elements(&[]));
+        assert_eq!(processor.process_resp_message(&from_cli("LRANGE missing 0 -1")), elements(&[]));
+        assert_eq!(processor.process_resp_message(&from_cli("LRANGE foo a 1")), Message::error("ERR value is not an integer or out of range"));
+
+        processor.process_resp_message(&from_cli("SET bar 1"));
+        assert_eq!(processor.process_resp_message(&from_cli("LRANGE bar 0 -1")).type_as_str(), "Error");
+    }
+
+    #[test]
+    fn test_mset() {
+        let processor = create_message_processor();
+        processor.process_resp_message(&from_cli("SET a old EX 100"));
+
+        assert_eq!(processor.process_resp_message(&from_cli("MSET a 1 b 2")), Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli("GET a")), Message::bulk_string("1"));
+        assert_eq!(processor.process_resp_message(&from_cli("GET b")), Message::bulk_string("2"));
+        assert_eq!(processor.process_resp_message(&from_cli("TTL a")), Message::Integer(-1));
+        assert_eq!(processor.process_resp_message(&from_cli("MSET a 1 b")), Message::error("ERR wrong number of arguments for 'mset' command"));
+    }
+
+    #[test]
+    fn test_multi_exec() {
+        let processor = create_connected_message_processor();
+        assert_eq!(processor.process_resp_message(&from_cli("EXEC")), Message::error("ERR EXEC without MULTI"));
+        assert_eq!(processor.process_resp_message(&from_cli("MULTI")), Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli("MULTI")), Message::error("ERR MULTI calls can not be nested"));
+        for command in ["SET a 1", "INCR a", "LPUSH a x", "GET a"] {
+            assert_eq!(processor.process_resp_message(&from_cli(command)), Message::simple_string("QUEUED"));
+        }
+        assert_eq!(processor.process_resp_message(&from_cli("GET a")), Message::simple_string("QUEUED"));
+
+        // failed command doesn't stop the others
+        let Message::Array(Some(replies)) = processor.process_resp_message(&from_cli("EXEC")) else {
+            panic!("EXEC replies with array");
+        };
+        assert_eq!(replies[..2], [Message::simple_string("OK"), Message::Integer(2)]);
+        assert_eq!(replies[2].type_as_str(), "Error");
+        assert_eq!(replies[3..], [Message::bulk_string("2"), Message::bulk_string("2")]);
+
+        processor.process_resp_message(&from_cli("MULTI"));
+        processor.process_resp_message(&from_cli("SET a 3"));
+        assert_eq!(processor.process_resp_message(&from_cli("DISCARD")), Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli("DISCARD")), Message::error("ERR DISCARD without MULTI"));
+        assert_eq!(processor.process_resp_message(&from_cli("GET a")), Message::bulk_string("2"));
+    }
+
+    #[test]
+    fn test_exec_after_refused_command() {
+        let processor = create_connected_message_processor();
+        for refused in ["UNKNOWN a", "GET", "WAIT 0 0"] {
+            processor.process_resp_message(&from_cli("MULTI"));
+            assert_eq!(processor.process_resp_message(&from_cli("SET a 1")), Message::simple_string("QUEUED"));
+            assert_eq!(processor.process_resp_message(&from_cli(refused)).type_as_str(), "Error");
+            assert_eq!(processor.process_resp_message(&from_cli("EXEC")), Message::error("EXECABORT Transaction discarded because of previous errors."));
+        }
+        assert_eq!(processor.process_resp_message(&from_cli("EXISTS a")), Message::Integer(0));
+
+        processor.process_resp_message(&from_cli("MULTI"));
+        assert_eq!(processor.process_resp_message(&from_cli("WATCH a")), Message::error("ERR WATCH inside MULTI is not allowed"));
+        assert_eq!(processor.process_resp_message(&from_cli("EXEC")), Message::array(vec![]));
+    }
+
+    #[test]
+    fn test_watch() {
+        let processor = create_connected_message_processor();
+        let other = processor.clients.register("127.0.0.1:10002", "127.0.0.1:6379", outbound());
+        other.login("default");
+        let other = processor.with_client(other);
+
+        assert_eq!(processor.process_resp_message(&from_cli("WATCH a b")), Message::simple_string("OK"));
+        other.process_resp_message(&from_cli("SET b 1"));
+        processor.process_resp_message(&from_cli("MULTI"));
+        processor.process_resp_message(&from_cli("SET c 1"));
+        assert_eq!(processor.process_resp_message(&from_cli("EXEC")), Message::Array(None));
+        assert_eq!(processor.process_resp_message(&from_cli("EXISTS c")), Message::Integer(0));
+
+        // EXEC forgets watched keys
+        processor.process_resp_message(&from_cli("MULTI"));
+        processor.process_resp_message(&from_cli("SET c 1"));
+        assert_eq!(processor.process_resp_message(&from_cli("EXEC")), Message::array(vec![Message::simple_string("OK")]));
+
+        processor.process_resp_message(&from_cli("WATCH a"));
+        assert_eq!(processor.process_resp_message(&from_cli("UNWATCH")), Message::simple_string("OK"));
+        other.process_resp_message(&from_cli("SET a 1"));
+        processor.process_resp_message(&from_cli("MULTI"));
+        assert_eq!(processor.process_resp_message(&from_cli("EXEC")), Message::array(vec![]));
+
+        // expiration changes the key too
+        travel_to(1000);
+        processor.process_resp_message(&from_cli("SET a 1 PX 100"));
+        processor.process_resp_message(&from_cli("WATCH a"));
+        travel_to(1200);
+        assert_eq!(other.process_resp_message(&from_cli("GET a")), Message::BulkString(None));
+        processor.process_resp_message(&from_cli("MULTI"));
+        assert_eq!(processor.process_resp_message(&from_cli("EXEC")), Message::Array(None));
+    }
+
+    #[test]
+    fn test_pubsub() {
+        let processor = create_connected_message_processor();
+        let (sender, mut queue) = tokio::sync::mpsc::unbounded_channel();
+        let subscriber = processor.clients.register("127.0.0.1:10002", "127.0.0.1:6379", sender);
+        subscriber.login("default");
+        let subscriber = processor.with_client(subscriber);
+
+        // last confirmation is the reply, the ones before it are queued
+        let response = subscriber.process_resp_message(&from_cli("SUBSCRIBE news sports"));
+        assert_eq!(received(&mut queue), "*3\r\n$9\r\nsubscribe\r\n$4\r\nnews\r\n:1\r\n");
+        assert_eq!(response, Message::array(vec![Message::bulk_string("subscribe"), Message::bulk_string("sports"), Message::Integer(2)]));
+        subscriber.process_resp_message(&from_cli("PSUBSCRIBE n*"));
+
+        assert_eq!(subscriber.process_resp_message(&from_cli("GET a")), Message::error("ERR Can't execute 'get': only (P|S)SUBSCRIBE / (P|S)UNSUBSCRIBE / PING / QUIT / RESET are allowed in this context"));
+        assert_eq!(subscriber.process_resp_message(&from_cli("PING")), Message::array(vec![Message::bulk_string("pong"), Message::bulk_string("")]));
+
+        assert_eq!(processor.process_resp_message(&from_cli("PUBLISH news hello")), Message::Integer(2));
+        assert_eq!(processor.process_resp_message(&from_cli("PUBLISH weather hello")), Message::Integer(0));
+        assert_eq!(
+            received(&mut queue),
+            "*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n*4\r\n$8\r\npmessage\r\n$2\r\nn*\r\n$4\r\nnews\r\n$5\r\nhello\r\n"
+        );
+
+        let response = subscriber.process_resp_message(&from_cli("UNSUBSCRIBE"));
+        assert_eq!(received(&mut queue), "*3\r\n$11\r\nunsubscribe\r\n$4\r\nnews\r\n:2\r\n");
+        assert_eq!(response, Message::array(vec![Message::bulk_string("unsubscribe"), Message::bulk_string("sports"), Message::Integer(1)]));
+        let response = subscriber.process_resp_message(&from_cli("PUNSUBSCRIBE"));
+        assert_eq!(response, Message::array(vec![Message::bulk_string("punsubscribe"), Message::bulk_string("n*"), Message::Integer(0)]));
+        let response = subscriber.process_resp_message(&from_cli("UNSUBSCRIBE"));
+        assert_eq!(response, Message::array(vec![Message::bulk_string("unsubscribe"), Message::BulkString(None), Message::Integer(0)]));
+        assert_eq!(subscriber.process_resp_message(&from_cli("PING")), Message::simple_string("PONG"));
+
+        processor.process_resp_message(&from_cli("MULTI"));
+        assert_eq!(processor.process_resp_message(&from_cli("SUBSCRIBE news")), Message::error("ERR Command not allowed inside a transaction"));
+    }
+
+    #[test]
     fn test_rpush_with_append() {
         let processor = create_message_processor();
 
@@ -615,11 +2192,557 @@ mod tests {
         let response = processor.process_resp_message(&request);
         assert_eq!(response, Message::Integer(3));
 
-        let lock = processor.memory.read().unwrap();
+        let lock = processor.memory.read("foo");
         if let Some(Value::List(list)) = lock.get("foo") {
-            assert_eq!(*list, VecDeque::from([Vec::from("1".as_bytes()), Vec::from("2".as_bytes()), Vec::from("3".as_bytes())]))
+            assert_eq!(list.iter().map(|element| element.to_vec()).collect::<Vec<_>>(), Vec::from([Vec::from("1".as_bytes()), Vec::from("2".as_bytes()), Vec::from("3".as_bytes())]))
         } else {
             unreachable!("Expected list");
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_client_id_and_name() {
+        let processor = create_connected_message_processor();
+        let id = processor.client.as_ref().unwrap().id as i64;
+
+        assert_eq!(processor.process_resp_message(&from_cli("CLIENT ID")), Message::Integer(id));
+        assert_eq!(processor.process_resp_message(&from_cli("CLIENT GETNAME")), Message::BulkString(None));
+        assert_eq!(processor.process_resp_message(&from_cli("CLIENT SETNAME worker")), Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli("CLIENT GETNAME")), Message::bulk_string("worker"));
+    }
+
+    #[test]
+    fn test_client_list() {
+        let processor = create_connected_message_processor();
+        processor.clients.register("127.0.0.1:10002", "127.0.0.1:6379", outbound());
+        processor.process_resp_message(&from_cli("CLIENT SETNAME worker"));
+
+        let response = processor.process_resp_message(&from_cli("CLIENT LIST"));
+        let list = String::from_utf8(response.extract_bulk_content().unwrap().clone()).unwrap();
+        let lines: Vec<&str> = list.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("id=1 addr=127.0.0.1:10001 laddr=127.0.0.1:6379 name=worker "));
+        assert!(lines[0].ends_with(" cmd=client user=default"));
+        assert!(lines[1].starts_with("id=2 addr=127.0.0.1:10002 "));
+
+        let response = processor.process_resp_message(&from_cli("CLIENT LIST ID 2"));
+        let list = String::from_utf8(response.extract_bulk_content().unwrap().clone()).unwrap();
+        assert_eq!(list.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_client_kill() {
+        let processor = create_connected_message_processor();
+        let other = processor.clients.register("127.0.0.1:10002", "127.0.0.1:6379", outbound());
+
+        let response = processor.process_resp_message(&from_cli("CLIENT KILL ID 1"));
+        assert_eq!(response, Message::Integer(0), "current client is skipped by default");
+
+        let response = processor.process_resp_message(&from_cli("CLIENT KILL ADDR 127.0.0.1:10002"));
+        assert_eq!(response, Message::Integer(1));
+        assert!(other.is_killed());
+        assert!(!processor.client.as_ref().unwrap().is_killed());
+
+        let response = processor.process_resp_message(&from_cli("CLIENT KILL 127.0.0.1:10003"));
+        assert_eq!(response.type_as_str(), "Error");
+    }
+
+    #[test]
+    fn test_client_pause() {
+        travel_to(1000);
+        let processor = create_connected_message_processor();
+
+        let response = processor.process_resp_message(&from_cli("CLIENT PAUSE 500 WRITE"));
+        assert_eq!(response, Message::simple_string("OK"));
+        assert!(processor.is_write_command(&from_cli("SET foo bar")));
+        assert_eq!(processor.clients.pause_remaining(true), Some(500));
+        assert_eq!(processor.clients.pause_remaining(false), None);
+
+        travel_to(1600);
+        assert_eq!(processor.clients.pause_remaining(true), None);
+
+        processor.process_resp_message(&from_cli("CLIENT PAUSE 500"));
+        assert_eq!(processor.clients.pause_remaining(false), Some(500));
+        processor.process_resp_message(&from_cli("CLIENT UNPAUSE"));
+        assert_eq!(processor.clients.pause_remaining(true), None);
+    }
+
+    #[test]
+    fn test_auth_required() {
+        let processor = create_message_processor();
+        processor.acl.set_user("default", &["resetpass".to_string(), ">secret".to_string()]).unwrap();
+        let client = processor.clients.register("127.0.0.1:10001", "127.0.0.1:6379", outbound());
+        let processor = processor.with_client(client);
+
+        let response = processor.process_resp_message(&from_cli("GET foo"));
+        assert_eq!(response, Message::error("NOAUTH Authentication required."));
+
+        let response = processor.process_resp_message(&from_cli("AUTH wrong"));
+        assert_eq!(response, Message::error("WRONGPASS invalid username-password pair or user is disabled."));
+
+        let response = processor.process_resp_message(&from_cli("AUTH secret"));
+        assert_eq!(response, Message::simple_string("OK"));
+
+        let response = processor.process_resp_message(&from_cli("GET foo"));
+        assert_eq!(response, Message::BulkString(None));
+    }
+
+    #[test]
+    fn test_acl_permissions() {
+        let processor = create_connected_message_processor();
+        let response = processor.process_resp_message(&from_cli("ACL SETUSER alice on >pass ~cached:* +get +acl|whoami"));
+        assert_eq!(response, Message::simple_string("OK"));
+
+        let response = processor.process_resp_message(&from_cli("AUTH alice pass"));
+        assert_eq!(response, Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli("ACL WHOAMI")), Message::bulk_string("alice"));
+
+        let response = processor.process_resp_message(&from_cli("GET cached:1"));
+        assert_eq!(response, Message::BulkString(None));
+
+        let response = processor.process_resp_message(&from_cli("GET other"));
+        assert_eq!(response, Message::error("NOPERM No permissions to access a key"));
+
+        let response = processor.process_resp_message(&from_cli("SET cached:1 value"));
+        assert_eq!(response, Message::error("NOPERM User alice has no permissions to run the 'set' command"));
+
+        assert_eq!(processor.acl.log_messages(10).len(), 2);
+    }
+
+    #[test]
+    fn test_acl_channel_permissions() {
+        let processor = create_connected_message_processor();
+        processor.process_resp_message(&from_cli("ACL SETUSER alice on nopass +@all resetchannels &news:*"));
+        processor.process_resp_message(&from_cli("AUTH alice x"));
+        let Message::Array(Some(user)) = processor.process_resp_message(&from_cli("ACL GETUSER alice")) else {
+            panic!("ACL GETUSER replies with array");
+        };
+        assert_eq!(user[9], Message::bulk_string("&news:*"));
+
+        assert_eq!(processor.process_resp_message(&from_cli("PUBLISH news:eu hello")), Message::Integer(0));
+        assert_eq!(processor.process_resp_message(&from_cli("PUBLISH weather hello")), Message::error("NOPERM No permissions to access a channel"));
+        assert_eq!(processor.process_resp_message(&from_cli("SUBSCRIBE news:eu weather")), Message::error("NOPERM No permissions to access a channel"));
+        // pattern is allowed only when equal to a user pattern
+        assert_eq!(processor.process_resp_message(&from_cli("PSUBSCRIBE news:eu:*")), Message::error("NOPERM No permissions to access a channel"));
+        assert_eq!(processor.pubsub.count(processor.client.as_ref().unwrap().id), 0);
+        assert_eq!(processor.process_resp_message(&from_cli("PSUBSCRIBE news:*")).type_as_str(), "Array");
+        // both denials of weather are one log entry
+        assert_eq!(processor.acl.log_messages(10).len(), 2);
+    }
+
+    #[test]
+    fn test_acl_deluser() {
+        let processor = create_connected_message_processor();
+        processor.process_resp_message(&from_cli("ACL SETUSER alice on nopass +@all ~*"));
+        let other = processor.clients.register("127.0.0.1:10002", "127.0.0.1:6379", outbound());
+        other.login("alice");
+
+        assert_eq!(processor.process_resp_message(&from_cli("ACL DELUSER alice bob")), Message::Integer(1));
+        assert!(other.is_killed());
+        assert_eq!(processor.process_resp_message(&from_cli("ACL GETUSER alice")), Message::Array(None));
+        assert_eq!(processor.process_resp_message(&from_cli("ACL DELUSER default")).type_as_str(), "Error");
+    }
+
+    fn received(queue: &mut tokio::sync::mpsc::UnboundedReceiver<Outgoing>) -> String {
+        let mut bytes = Vec::new();
+        while let Ok(Outgoing::Data(data) | Outgoing::Snapshot(data)) = queue.try_recv() {
+            bytes.extend(data);
+        }
+        String::from_utf8(bytes).unwrap()
+    }
+
+    #[test]
+    fn test_psync_full_resync_and_stream() {
+        let processor = create_connected_message_processor();
+        processor.process_resp_message(&from_cli("SET foo bar"));
+
+        let (sender, mut queue) = tokio::sync::mpsc::unbounded_channel();
+        let replica = processor.clients.register("127.0.0.1:10002", "127.0.0.1:6379", sender);
+        replica.login("default");
+        let replica_processor = processor.with_client(replica.clone());
+        replica_processor.process_resp_message(&from_cli("PSYNC ? -1"));
+        assert!(replica.is_replica());
+
+        let snapshot = "*1\r\n*3\r\n$3\r\nSET\r\n$3\r\nfoo\r\n$3\r\nbar\r\n";
+        let full_resync = received(&mut queue);
+        assert!(full_resync.starts_with("+FULLRESYNC "));
+        assert!(full_resync.ends_with(&format!(" 0\r\n${}\r\n{}", snapshot.len(), snapshot)));
+
+        processor.process_resp_message(&from_cli("INCR counter"));
+        processor.process_resp_message(&from_cli("GET foo"));
+        assert_eq!(received(&mut queue), "*2\r\n$4\r\nINCR\r\n$7\r\ncounter\r\n");
+
+        // relative expiration is replicated as absolute timestamp
+        travel_to(1000);
+        processor.process_resp_message(&from_cli("SET foo bar EX 10"));
+        assert!(received(&mut queue).ends_with("$4\r\nPXAT\r\n$5\r\n11000\r\n"));
+    }
+
+    #[test]
+    fn test_psync_continue_from_backlog() {
+        let processor = create_connected_message_processor();
+        let (sender, _queue) = tokio::sync::mpsc::unbounded_channel();
+        let first = processor.clients.register("127.0.0.1:10002", "127.0.0.1:6379", sender);
+        first.login("default");
+        processor.with_client(first.clone()).process_resp_message(&from_cli("PSYNC ? -1"));
+        processor.process_resp_message(&from_cli("SET foo bar"));
+        processor.process_resp_message(&from_cli("DEL foo"));
+
+        let info = processor.replication.info();
+        let replid = info.lines().find_map(|line| line.strip_prefix("master_replid:")).unwrap().to_string();
+        assert!(info.contains("master_repl_offset:53\r\n"));
+
+        // replica reconnects after receiving SET, only DEL has to be sent
+        let (sender, mut queue) = tokio::sync::mpsc::unbounded_channel();
+        let second = processor.clients.register("127.0.0.1:10003", "127.0.0.1:6379", sender);
+        second.login("default");
+        processor.with_client(second).process_resp_message(&from_cli(&format!("PSYNC {} 32", replid)));
+        assert_eq!(received(&mut queue), format!("+CONTINUE {}\r\n*2\r\n$3\r\nDEL\r\n$3\r\nfoo\r\n", replid));
+
+        let (sender, mut queue) = tokio::sync::mpsc::unbounded_channel();
+        let third = processor.clients.register("127.0.0.1:10004", "127.0.0.1:6379", sender);
+        third.login("default");
+        processor.with_client(third).process_resp_message(&from_cli("PSYNC 0000000000000000000000000000000000000000 32"));
+        assert!(received(&mut queue).starts_with("+FULLRESYNC "));
+        assert_eq!(processor.replication.sync_stats(), "sync_full:2\r\nsync_partial_ok:1\r\nsync_partial_err:1\r\n");
+    }
+
+    #[test]
+    fn test_maxmemory_noeviction() {
+        let mut processor = create_connected_message_processor();
+        processor.memory = Arc::new(Keyspace::from_config(&Config { maxmemory: 150, ..Config::default() }));
+        for key in ["k1", "k2", "k3"] {
+            assert_eq!(processor.process_resp_message(&from_cli(&format!("SET {} value", key))), Message::simple_string("OK"));
+        }
+
+        let response = processor.process_resp_message(&from_cli("SET k4 value"));
+        assert_eq!(response, Message::error("OOM command not allowed when used memory > 'maxmemory'."));
+        assert_eq!(processor.process_resp_message(&from_cli("GET k1")), Message::bulk_string("value"));
+        assert_eq!(processor.process_resp_message(&from_cli("DEL k1")), Message::Integer(1));
+        assert_eq!(processor.process_resp_message(&from_cli("SET k4 value")), Message::simple_string("OK"));
+    }
+
+    #[test]
+    fn test_maxmemory_in_script() {
+        let mut processor = create_connected_message_processor();
+        let config = Config { maxmemory: 150, maxmemory_policy: EvictionPolicy::AllKeysLru, ..Config::default() };
+        processor.memory = Arc::new(Keyspace::from_config(&config));
+
+        let response = eval(&processor, &["EVAL", "for i = 1, 4 do redis.call('SET', 'k' .. i, 'value') end", "0"]);
+        assert!(format!("{:?}", response).contains("OOM command not allowed when used memory > 'maxmemory'."));
+        assert_eq!(processor.memory.len(), 3);
+        assert_eq!(processor.process_resp_message(&from_cli("GET k3")), Message::bulk_string("value"));
+        assert_eq!(processor.process_resp_message(&from_cli("GET k4")), Message::BulkString(None));
+    }
+
+    #[test]
+    fn test_maxmemory_eviction() {
+        let mut processor = create_connected_message_processor();
+        let config = Config { maxmemory: 150, maxmemory_policy: EvictionPolicy::AllKeysLru, ..Config::default() };
+        processor.memory = Arc::new(Keyspace::from_config(&config));
+        for key in ["k1", "k2", "k3", "k4"] {
+            assert_eq!(processor.process_resp_message(&from_cli(&format!("SET {} value", key))), Message::simple_string("OK"));
+        }
+
+        assert_eq!(processor.memory.len(), 3);
+        assert_eq!(processor.memory.evicted_keys.load(Ordering::Relaxed), 1);
+        let info = processor.process_resp_message(&from_cli("INFO memory"));
+        assert!(info.as_str().unwrap().contains("maxmemory:150\r\nmaxmemory_human:150B\r\nmaxmemory_policy:allkeys-lru\r\n"));
+    }
+
+    #[test]
+    fn test_info_sections() {
+        let processor = create_connected_message_processor();
+        for command in ["SET foo bar", "GET foo", "GET missing", "EXISTS foo missing"] {
+            processor.process_resp_message(&from_cli(command));
+        }
+
+        let stats = processor.process_resp_message(&from_cli("INFO stats"));
+        let stats = stats.as_str().unwrap();
+        assert!(stats.starts_with("# Stats\r\n"));
+        assert!(stats.contains("total_commands_processed:5\r\n"));
+        assert!(stats.contains("keyspace_hits:2\r\nkeyspace_misses:2\r\n"));
+        assert!(!stats.contains("# Server"));
+
+        let info = processor.process_resp_message(&from_cli("INFO"));
+        let headers: Vec<&str> = info.as_str().unwrap().lines().filter(|line| line.starts_with('#')).collect();
+        assert_eq!(headers, ["# Server", "# Clients", "# Memory", "# Persistence", "# Stats", "# Replication", "# Cluster", "# Keyspace"]);
+        assert!(info.as_str().unwrap().contains("rdb_changes_since_last_save:1\r\n"));
+        assert!(info.as_str().unwrap().contains("connected_clients:1\r\n"));
+        assert!(info.as_str().unwrap().ends_with("# Keyspace\r\ndb0:keys=1,expires=0\r\n"));
+
+        let info = processor.process_resp_message(&from_cli("INFO SERVER clients"));
+        let headers: Vec<&str> = info.as_str().unwrap().lines().filter(|line| line.starts_with('#')).collect();
+        assert_eq!(headers, ["# Server", "# Clients"]);
+    }
+
+    #[test]
+    fn test_slowlog_and_latency() {
+        let mut processor = create_connected_message_processor();
+        processor.slowlog = Arc::new(SlowLog::new(&Config { slowlog_log_slower_than: 0, slowlog_max_len: 2, ..Config::default() }));
+        for command in ["AUTH default secret", "SET foo bar", "GET foo", "GET foo"] {
+            processor.process_resp_message(&from_cli(command));
+        }
+
+        assert_eq!(processor.process_resp_message(&from_cli("SLOWLOG LEN")), Message::Integer(2));
+        let Message::Array(Some(entries)) = processor.process_resp_message(&from_cli("SLOWLOG GET 1")) else {
+            unreachable!("Expected array");
+        };
+        assert_eq!(entries.len(), 1);
+        let Message::Array(Some(entry)) = &entries[0] else {
+            unreachable!("Expected array");
+        };
+        // SLOWLOG LEN itself is logged too
+        assert_eq!(entry[0], Message::Integer(3));
+        assert_eq!(entry[3], from_cli("SLOWLOG LEN"));
+        assert_eq!(entry[4], Message::bulk_string("127.0.0.1:10001"));
+        assert_eq!(processor.process_resp_message(&from_cli("SLOWLOG RESET")), Message::simple_string("OK"));
+        // only SLOWLOG RESET is left
+        assert_eq!(processor.process_resp_message(&from_cli("SLOWLOG LEN")), Message::Integer(1));
+
+        assert_eq!(processor.process_resp_message(&from_cli("LATENCY LATEST")), Message::array(vec![]));
+        assert_eq!(processor.process_resp_message(&from_cli("LATENCY RESET")), Message::Integer(0));
+
+        let info = processor.process_resp_message(&from_cli("INFO latencystats"));
+        let info = info.as_str().unwrap();
+        assert!(info.starts_with("# Latencystats\r\n"), "{}", info);
+        assert!(info.contains("latency_percentiles_usec_get:p50="), "{}", info);
+        assert!(!processor.process_resp_message(&from_cli("INFO")).as_str().unwrap().contains("# Latencystats"));
+        assert!(processor.process_resp_message(&from_cli("INFO everything")).as_str().unwrap().contains("# Latencystats"));
+    }
+
+    #[test]
+    fn test_monitor() {
+        let processor = create_connected_message_processor();
+        let (sender, mut feed) = tokio::sync::mpsc::unbounded_channel();
+        let monitor = processor.clients.register("127.0.0.1:10002", "127.0.0.1:6379", sender);
+        monitor.login("default");
+        let monitor_processor = processor.with_client(monitor.clone());
+        assert_eq!(monitor_processor.process_resp_message(&from_cli("MONITOR")), Message::simple_string("OK"));
+        assert!(monitor.info_line().contains(" flags=O "));
+
+        travel_to(1_339_518_083_107);
+        processor.process_resp_message(&from_cli("SET key a\"b\\c"));
+        processor.process_resp_message(&Message::array(vec![Message::bulk_string("ECHO"), Message::BulkString(Some(b"\r\n\x01 ".to_vec()))]));
+        processor.process_resp_message(&from_cli("AUTH default secret"));
+        processor.process_resp_message(&from_cli("SLOWLOG LEN"));
+
+        let mut lines: Vec<Vec<u8>> = Vec::new();
+        while let Ok(Outgoing::Data(buf)) = feed.try_recv() {
+            lines.push(buf);
+        }
+        assert_eq!(lines, [
+            b"+1339518083.107000 [0 127.0.0.1:10001] \"SET\" \"key\" \"a\\\"b\\\\c\"\r\n".to_vec(),
+            b"+1339518083.107000 [0 127.0.0.1:10001] \"ECHO\" \"\\r\\n\\x01 \"\r\n".to_vec(),
+            b"+1339518083.107000 [0 127.0.0.1:10001] \"AUTH\" \"(redacted)\" \"(redacted)\"\r\n".to_vec(),
+        ]);
+
+        processor.clients.unregister(monitor.id);
+        processor.process_resp_message(&from_cli("GET key"));
+        assert!(feed.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_command_introspection() {
+        let processor = create_connected_message_processor();
+        assert_eq!(processor.process_resp_message(&from_cli("FOO bar")), Message::error("ERR unknown command 'FOO', with args beginning with: 'bar' "));
+        assert_eq!(processor.process_resp_message(&from_cli("GET")), Message::error("ERR wrong number of arguments for 'get' command"));
+        assert_eq!(processor.process_resp_message(&from_cli("COMMAND COUNT")), Message::Integer(commands::count() as i64));
+
+        assert_eq!(processor.process_resp_message(&from_cli("COMMAND INFO get missing")), Message::array(vec![
+            Message::array(vec![
+                Message::bulk_string("get"),
+                Message::Integer(2),
+                Message::array(vec![Message::simple_string("readonly"), Message::simple_string("fast")]),
+                Message::Integer(1),
+                Message::Integer(1),
+                Message::Integer(1),
+                Message::array(vec![Message::simple_string("@read"), Message::simple_string("@string"), Message::simple_string("@fast")]),
+                Message::array(vec![]),
+                Message::array(vec![]),
+                Message::array(vec![]),
+            ]),
+            Message::Array(None),
+        ]));
+        assert_eq!(processor.process_resp_message(&from_cli("COMMAND DOCS echo")), Message::array(vec![
+            Message::bulk_string("echo"),
+            Message::array(vec![
+                Message::bulk_string("summary"),
+                Message::bulk_string("Returns the given string."),
+                Message::bulk_string("group"),
+                Message::bulk_string("connection"),
+            ]),
+        ]));
+
+        assert_eq!(processor.process_resp_message(&from_cli("COMMAND GETKEYS DEL a b")), from_cli("a b"));
+        assert_eq!(processor.process_resp_message(&from_cli("COMMAND GETKEYS SET a")), Message::error("ERR Invalid number of arguments specified for command"));
+        assert_eq!(processor.process_resp_message(&from_cli("COMMAND GETKEYS FOO a")), Message::error("ERR Invalid command specified"));
+        assert_eq!(processor.process_resp_message(&from_cli("COMMAND GETKEYS PING")), Message::error("ERR The command has no key arguments"));
+    }
+
+    fn eval(processor: &MessageProcessor, parts: &[&str]) -> Message {
+        processor.process_resp_message(&Message::array(parts.iter().map(|part| Message::bulk_string(part)).collect()))
+    }
+
+    #[test]
+    fn test_eval() {
+        let processor = create_connected_message_processor();
+        let script = "redis.call('SET', KEYS[1], ARGV[1]); return {redis.call('GET', KEYS[1]), redis.call('INCR', KEYS[2])}";
+        let response = eval(&processor, &["EVAL", script, "2", "a", "b", "value"]);
+        assert_eq!(response, Message::array(vec![Message::bulk_string("value"), Message::Integer(1)]));
+        assert_eq!(processor.process_resp_message(&from_cli("GET b")), Message::bulk_string("1"));
+
+        let sha = crate::scripting::sha1_hex(script);
+        assert_eq!(processor.process_resp_message(&from_cli(&format!("SCRIPT EXISTS {} ffff", sha))), Message::array(vec![Message::Integer(1), Message::Integer(0)]));
+        let response = eval(&processor, &["EVALSHA", &sha, "2", "a", "b", "other"]);
+        assert_eq!(response, Message::array(vec![Message::bulk_string("other"), Message::Integer(2)]));
+
+        let response = processor.process_resp_message(&from_cli("SCRIPT LOAD return"));
+        assert_eq!(response, Message::bulk_string(&crate::scripting::sha1_hex("return")));
+        assert_eq!(processor.process_resp_message(&from_cli("SCRIPT FLUSH ASYNC")), Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli(&format!("EVALSHA {} 0", sha))), Message::error("NOSCRIPT No matching script. Please use EVAL."));
+
+        let replies = [
+            (vec!["EVAL", "return 1", "x"], "ERR value is not an integer or out of range"),
+            (vec!["EVAL", "return 1", "-1"], "ERR Number of keys can't be negative"),
+            (vec!["EVAL", "return 1", "2", "a"], "ERR Number of keys can't be greater than number of args"),
+            (vec!["EVAL", "return redis.call('INCR', KEYS[1])", "1", "a"], "ERR value is not an integer or out of range"),
+            (vec!["EVAL", "return redis.call('SAVE')", "0"], "ERR This Redis command is not allowed from script"),
+            (vec!["EVAL", "return redis.call('FOO')", "0"], "ERR unknown command 'FOO', with args beginning with: "),
+            (vec!["SCRIPT", "FLUSH", "LATER"], "ERR syntax error"),
+            (vec!["SCRIPT", "KILL"], "NOTBUSY No scripts in execution right now."),
+        ];
+        for (command, error) in replies {
+            assert_eq!(eval(&processor, &command), Message::error(error), "{:?}", command);
+        }
+        assert_eq!(eval(&processor, &["EVAL", "return redis.pcall('INCR', KEYS[1])", "1", "a"]), Message::error("ERR value is not an integer or out of range"));
+    }
+
+    // script must run longer than the time limit
+    fn wait_for_busy(processor: &MessageProcessor, running: &std::thread::JoinHandle<Message>) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        // other commands wait for the script until it becomes busy
+        while processor.scripting.check_busy().is_ok() {
+            assert!(!running.is_finished(), "script finished before it became busy");
+            assert!(Instant::now() < deadline, "script didn't become busy");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        assert!(processor.scripting.is_running());
+    }
+
+    #[test]
+    fn test_script_kill() {
+        let mut processor = create_connected_message_processor();
+        processor.scripting = Arc::new(Scripting::new(&Config { lua_time_limit: 10, ..Config::default() }));
+
+        let script = processor.clone();
+        let running = std::thread::spawn(move || eval(&script, &["EVAL", "while true do end", "0"]));
+        wait_for_busy(&processor, &running);
+        assert_eq!(processor.process_resp_message(&from_cli("SCRIPT KILL")), Message::simple_string("OK"));
+        assert_eq!(running.join().unwrap(), Message::error("ERR Script killed by user with SCRIPT KILL..."));
+        assert_eq!(processor.process_resp_message(&from_cli("GET a")), Message::BulkString(None));
+
+        // script which already wrote can't be killed
+        let script = processor.clone();
+        let running = std::thread::spawn(move || eval(&script, &["EVAL", "redis.call('SET', 'a', 1); local i = 0; while i < 20000000 do i = i + 1 end", "0"]));
+        wait_for_busy(&processor, &running);
+        let reply = processor.process_resp_message(&from_cli("SCRIPT KILL"));
+        assert!(matches!(reply, Message::Error(err) if err.starts_with("UNKILLABLE")));
+        assert_eq!(running.join().unwrap(), Message::BulkString(None));
+    }
+
+    #[test]
+    fn test_dump_restore() {
+        let processor = create_message_processor();
+        travel_to(1_000_000);
+        processor.process_resp_message(&from_cli("RPUSH list a b"));
+        processor.process_resp_message(&from_cli("SET key value PX 5000"));
+        assert_eq!(processor.process_resp_message(&from_cli("PTTL key")), Message::Integer(5000));
+        assert_eq!(processor.process_resp_message(&from_cli("TTL key")), Message::Integer(5));
+        assert_eq!(processor.process_resp_message(&from_cli("TTL list")), Message::Integer(-1));
+        assert_eq!(processor.process_resp_message(&from_cli("TTL missing")), Message::Integer(-2));
+        assert_eq!(processor.process_resp_message(&from_cli("DUMP missing")), Message::BulkString(None));
+
+        let restore = |args: &[&str], payload: &Message| {
+            let mut parts: Vec<Message> = args.iter().map(|arg| Message::bulk_string(arg)).collect();
+            parts.insert(3, payload.clone());
+            processor.process_resp_message(&Message::array(parts))
+        };
+        let list = processor.process_resp_message(&from_cli("DUMP list"));
+        assert_eq!(restore(&["RESTORE", "list", "0"], &list), Message::error("BUSYKEY Target key name already exists."));
+        assert_eq!(restore(&["RESTORE", "copy", "0"], &list), Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli("DUMP copy")), list);
+
+        let value = processor.process_resp_message(&from_cli("DUMP key"));
+        assert_eq!(restore(&["RESTORE", "list", "2000", "REPLACE", "IDLETIME", "10"], &value), Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli("GET list")), Message::bulk_string("value"));
+        assert_eq!(processor.process_resp_message(&from_cli("PTTL list")), Message::Integer(2000));
+        assert_eq!(restore(&["RESTORE", "abs", "1003000", "ABSTTL"], &value), Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli("PTTL abs")), Message::Integer(3000));
+        // expired TTL doesn't create key
+        assert_eq!(restore(&["RESTORE", "old", "1000", "ABSTTL"], &value), Message::simple_string("OK"));
+        assert_eq!(processor.process_resp_message(&from_cli("EXISTS old")), Message::Integer(0));
+
+        let replies = [
+            (vec!["RESTORE", "new", "-1"], "ERR Invalid TTL value, must be >= 0"),
+            (vec!["RESTORE", "new", "0", "IDLETIME", "-1"], "ERR Invalid IDLETIME value, must be >= 0"),
+            (vec!["RESTORE", "new", "0", "FOO"], "ERR syntax error"),
+        ];
+        for (args, error) in replies {
+            assert_eq!(restore(&args, &value), Message::error(error), "{:?}", args);
+        }
+        assert_eq!(restore(&["RESTORE", "new", "0"], &Message::bulk_string("garbage")), Message::error("ERR DUMP payload version or checksum are wrong"));
+        assert_eq!(processor.process_resp_message(&from_cli("MIGRATE host 6379 missing 0 1000")), Message::simple_string("NOKEY"));
+        assert_eq!(processor.process_resp_message(&from_cli("MIGRATE host 6379 key 1 1000")), Message::error("ERR DB index is out of range"));
+    }
+
+    #[test]
+    fn test_object_encoding() {
+        let processor = create_message_processor();
+        travel_to(1_000_000);
+        processor.process_resp_message(&from_cli("SET counter 10"));
+        processor.process_resp_message(&from_cli("SET short value"));
+        processor.process_resp_message(&from_cli(&format!("SET long {}", "x".repeat(30))));
+        processor.process_resp_message(&from_cli("RPUSH list a b"));
+        let encodings = [("counter", "int"), ("short", "embstr"), ("long", "raw"), ("list", "listpack")];
+        for (key, encoding) in encodings {
+            assert_eq!(processor.process_resp_message(&from_cli(&format!("OBJECT ENCODING {}", key))), Message::bulk_string(encoding), "{}", key);
+        }
+        assert_eq!(processor.process_resp_message(&from_cli("OBJECT ENCODING missing")), Message::BulkString(None));
+
+        // counters stay integers, strings which are not canonical integers are kept as they are
+        processor.process_resp_message(&from_cli("INCR counter"));
+        assert_eq!(processor.process_resp_message(&from_cli("OBJECT ENCODING counter")), Message::bulk_string("int"));
+        processor.process_resp_message(&from_cli("SET zeros 007"));
+        assert_eq!(processor.process_resp_message(&from_cli("INCR zeros")), Message::Integer(8));
+        processor.process_resp_message(&from_cli("SET zeros 007"));
+        assert_eq!(processor.process_resp_message(&from_cli("GET zeros")), Message::bulk_string("007"));
+
+        let mut processor = processor;
+        processor.config = Arc::new(Config { list_max_listpack_size: 2, ..Config::default() });
+        processor.process_resp_message(&from_cli("LPUSH list c"));
+        assert_eq!(processor.process_resp_message(&from_cli("OBJECT ENCODING list")), Message::bulk_string("quicklist"));
+
+        assert_eq!(processor.process_resp_message(&from_cli("MEMORY USAGE counter")), Message::Integer(64 + 7));
+        assert_eq!(processor.process_resp_message(&from_cli("MEMORY USAGE long SAMPLES 5")), Message::Integer(64 + 4 + 30));
+        assert_eq!(processor.process_resp_message(&from_cli("MEMORY USAGE missing")), Message::BulkString(None));
+        assert_eq!(processor.process_resp_message(&from_cli("MEMORY USAGE long SAMPLES")), Message::error("ERR syntax error"));
+
+        assert_eq!(processor.process_resp_message(&from_cli("OBJECT REFCOUNT short")), Message::Integer(1));
+        travel_to(1_005_000);
+        assert_eq!(processor.process_resp_message(&from_cli("OBJECT IDLETIME short")), Message::Integer(5));
+        assert_eq!(processor.process_resp_message(&from_cli("OBJECT FREQ short")), Message::error("ERR An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust."));
+        processor.memory = Arc::new(Keyspace::from_config(&Config { maxmemory_policy: EvictionPolicy::AllKeysLfu, ..Config::default() }));
+        processor.process_resp_message(&from_cli("SET short value"));
+        assert_eq!(processor.process_resp_message(&from_cli("OBJECT FREQ short")), Message::Integer(5));
+    }
+
+    #[test]
+    fn test_shutdown_arguments() {
+        let processor = create_connected_message_processor();
+        assert_eq!(processor.process_resp_message(&from_cli("SHUTDOWN ABORT")), Message::error("ERR No shutdown in progress."));
+        assert_eq!(processor.process_resp_message(&from_cli("SHUTDOWN SAVE NOSAVE")), Message::error("ERR syntax error"));
+        assert_eq!(processor.process_resp_message(&from_cli("SHUTDOWN NOW ABORT")), Message::error("ERR syntax error"));
+        assert!(!processor.shutdown.is_exiting());
+    }
+}
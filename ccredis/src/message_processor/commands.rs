@@ -0,0 +1,605 @@
+use crate::{keyspace::LockedShards, processing_error::ProcessingError, pubsub::Subscription, resp::message::Message};
+
+use super::MessageProcessor;
+
+type Handler = fn(&MessageProcessor, &mut LockedShards, &[Message]) -> Result<Message, ProcessingError>;
+
+// Which arguments of command are keys
+#[derive(Clone, Copy)]
+enum Keys {
+    None,
+    First,
+    // key after subcommand, e.g. OBJECT ENCODING key
+    Second,
+    All,
+    // MSET key value [key value ...]
+    Pairs,
+    // EVAL script numkeys key [key ...] arg [arg ...]
+    Script,
+    // MIGRATE host port key|"" db timeout [option ...] [KEYS key [key ...]]
+    Migrate,
+}
+
+pub struct Command {
+    // `command` or `command|subcommand`
+    pub name: &'static str,
+    group: &'static str,
+    summary: &'static str,
+    // positive is exact number of arguments including command name, negative is minimal number
+    arity: i32,
+    flags: &'static [&'static str],
+    categories: &'static [&'static str],
+    keys: Keys,
+    // subcommands are executed by handler of their container command
+    handler: Option<Handler>,
+    subcommands: &'static [Command],
+}
+
+impl Command {
+    const fn new(name: &'static str, group: &'static str, summary: &'static str, arity: i32) -> Command {
+        Command { name, group, summary, arity, flags: &[], categories: &[], keys: Keys::None, handler: None, subcommands: &[] }
+    }
+
+    const fn flags(self, flags: &'static [&'static str]) -> Command {
+        Command { flags, ..self }
+    }
+
+    const fn categories(self, categories: &'static [&'static str]) -> Command {
+        Command { categories, ..self }
+    }
+
+    const fn keys(self, keys: Keys) -> Command {
+        Command { keys, ..self }
+    }
+
+    const fn handler(self, handler: Handler) -> Command {
+        Command { handler: Some(handler), ..self }
+    }
+
+    const fn subcommands(self, subcommands: &'static [Command]) -> Command {
+        Command { subcommands, ..self }
+    }
+
+    fn key_positions(&self) -> (i64, i64, i64) {
+        match self.keys {
+            Keys::None => (0, 0, 0),
+            Keys::First => (1, 1, 1),
+            Keys::Second => (2, 2, 1),
+            Keys::All => (1, -1, 1),
+            Keys::Pairs => (1, -1, 2),
+            // keys are given by numkeys argument
+            Keys::Script => (0, 0, 0),
+            Keys::Migrate => (3, 3, 1),
+        }
+    }
+
+    fn find_subcommand(&self, subcommand: &str) -> Option<&'static Command> {
+        let name = format!("{}|{}", self.name, subcommand.to_lowercase());
+        self.subcommands.iter().find(|spec| spec.name == name)
+    }
+
+    // COMMAND INFO reply
+    fn info(&self) -> Message {
+        let (first, last, step) = self.key_positions();
+        Message::array(vec![
+            Message::bulk_string(self.name),
+            Message::Integer(self.arity as i64),
+            Message::array(self.flags.iter().map(|flag| Message::simple_string(flag)).collect()),
+            Message::Integer(first),
+            Message::Integer(last),
+            Message::Integer(step),
+            Message::array(self.categories.iter().map(|category| Message::simple_string(&format!("@{}", category))).collect()),
+            Message::array(vec![]),
+            Message::array(vec![]),
+            Message::array(self.subcommands.iter().map(|subcommand| subcommand.info()).collect()),
+        ])
+    }
+
+    // COMMAND DOCS reply, a map written as flat array
+    fn docs(&self) -> Message {
+        let mut docs = vec![
+            Message::bulk_string("summary"),
+            Message::bulk_string(self.summary),
+            Message::bulk_string("group"),
+            Message::bulk_string(self.group),
+        ];
+        if !self.subcommands.is_empty() {
+            docs.push(Message::bulk_string("subcommands"));
+            docs.push(Message::array(self.subcommands.iter().flat_map(|subcommand| [Message::bulk_string(subcommand.name), subcommand.docs()]).collect()));
+        }
+        Message::array(docs)
+    }
+}
+
+const CONNECTION_ADMIN: &[&str] = &["admin", "slow", "dangerous", "connection"];
+const ADMIN: &[&str] = &["admin", "slow", "dangerous"];
+
+// Every supported command, drives dispatch, arity checks, ACL categories and COMMAND introspection
+const COMMANDS: &[Command] = &[
+    Command::new("ping", "connection", "Returns the server's liveliness response.", -1)
+        .flags(&["fast"]).categories(&["fast", "connection"])
+        .handler(|processor, _, _| Ok(processor.command_ping())),
+    Command::new("echo", "connection", "Returns the given string.", 2)
+        .flags(&["fast"]).categories(&["fast", "connection"])
+        .handler(|processor, _, args| processor.command_echo(args)),
+    Command::new("auth", "connection", "Authenticates the connection.", -2)
+        .flags(&["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"]).categories(&["fast", "connection"])
+        .handler(|processor, _, args| processor.command_auth(args)),
+    Command::new("set", "string", "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.", -3)
+        .flags(&["write", "denyoom"]).categories(&["write", "string", "slow"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_set(shards, args)),
+    Command::new("get", "string", "Returns the string value of a key.", 2)
+        .flags(&["readonly", "fast"]).categories(&["read", "string", "fast"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_get(shards, args)),
+    Command::new("mset", "string", "Atomically creates or modifies the string values of one or more keys.", -3)
+        .flags(&["write", "denyoom"]).categories(&["write", "string", "slow"]).keys(Keys::Pairs)
+        .handler(|processor, shards, args| processor.command_mset(shards, args)),
+    Command::new("exists", "generic", "Determines whether one or more keys exist.", -2)
+        .flags(&["readonly", "fast"]).categories(&["keyspace", "read", "fast"]).keys(Keys::All)
+        .handler(|processor, shards, args| processor.command_exists(shards, args)),
+    Command::new("del", "generic", "Deletes one or more keys.", -2)
+        .flags(&["write"]).categories(&["keyspace", "write", "slow"]).keys(Keys::All)
+        .handler(|processor, shards, args| processor.command_del(shards, args)),
+    Command::new("ttl", "generic", "Returns the expiration time in seconds of a key.", 2)
+        .flags(&["readonly", "fast"]).categories(&["keyspace", "read", "fast"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_ttl(shards, args, 1000)),
+    Command::new("pttl", "generic", "Returns the expiration time in milliseconds of a key.", 2)
+        .flags(&["readonly", "fast"]).categories(&["keyspace", "read", "fast"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_ttl(shards, args, 1)),
+    Command::new("dump", "generic", "Returns a serialized representation of the value stored at a key.", 2)
+        .flags(&["readonly"]).categories(&["keyspace", "read", "slow"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_dump(shards, args)),
+    Command::new("restore", "generic", "Creates a key from the serialized representation of a value.", -4)
+        .flags(&["write", "denyoom"]).categories(&["keyspace", "write", "slow", "dangerous"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_restore(shards, args)),
+    Command::new("migrate", "generic", "Atomically transfers keys from one Redis instance to another.", -6)
+        .flags(&["write", "movablekeys", "no_multi"]).categories(&["keyspace", "write", "slow", "dangerous"]).keys(Keys::Migrate)
+        .handler(|processor, shards, args| processor.command_migrate(shards, args)),
+    Command::new("object", "generic", "A container for object introspection commands.", -2)
+        .categories(&["slow"])
+        .handler(|processor, shards, args| processor.command_object(shards, args))
+        .subcommands(&[
+            Command::new("object|encoding", "generic", "Returns the internal encoding of a Redis object.", 3)
+                .flags(&["readonly"]).categories(&["keyspace", "read", "slow"]).keys(Keys::Second),
+            Command::new("object|freq", "generic", "Returns the logarithmic access frequency counter of a Redis object.", 3)
+                .flags(&["readonly"]).categories(&["keyspace", "read", "slow"]).keys(Keys::Second),
+            Command::new("object|idletime", "generic", "Returns the time since the last access to a Redis object.", 3)
+                .flags(&["readonly"]).categories(&["keyspace", "read", "slow"]).keys(Keys::Second),
+            Command::new("object|refcount", "generic", "Returns the reference count of a value of a key.", 3)
+                .flags(&["readonly"]).categories(&["keyspace", "read", "slow"]).keys(Keys::Second),
+        ]),
+    Command::new("memory", "server", "A container for memory diagnostics commands.", -2)
+        .categories(&["slow"])
+        .handler(|processor, shards, args| processor.command_memory(shards, args))
+        .subcommands(&[
+            Command::new("memory|usage", "server", "Estimates the memory usage of a key.", -3)
+                .flags(&["readonly"]).categories(&["read", "slow"]).keys(Keys::Second),
+        ]),
+    Command::new("incr", "string", "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.", 2)
+        .flags(&["write", "denyoom", "fast"]).categories(&["write", "string", "fast"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_incr(shards, args)),
+    Command::new("decr", "string", "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.", 2)
+        .flags(&["write", "denyoom", "fast"]).categories(&["write", "string", "fast"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_decr(shards, args)),
+    Command::new("lpush", "list", "Prepends one or more elements to a list. Creates the key if it doesn't exist.", -3)
+        .flags(&["write", "denyoom", "fast"]).categories(&["write", "list", "fast"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_lpush(shards, args)),
+    Command::new("rpush", "list", "Appends one or more elements to a list. Creates the key if it doesn't exist.", -3)
+        .flags(&["write", "denyoom", "fast"]).categories(&["write", "list", "fast"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_rpush(shards, args)),
+    Command::new("lrange", "list", "Returns a range of elements from a list.", 4)
+        .flags(&["readonly"]).categories(&["read", "list", "slow"]).keys(Keys::First)
+        .handler(|processor, shards, args| processor.command_lrange(shards, args)),
+    Command::new("save", "server", "Synchronously saves the database to disk.", 1)
+        .flags(&["admin", "noscript"]).categories(ADMIN)
+        .handler(|processor, _, _| processor.command_save()),
+    Command::new("shutdown", "server", "Synchronously saves the database(s) to disk and shuts down the Redis server.", -1)
+        .flags(&["admin", "noscript", "loading", "stale", "allow_busy", "blocking", "no_multi"]).categories(ADMIN)
+        .handler(|processor, _, args| processor.command_shutdown(args)),
+    Command::new("client", "connection", "A container for client connection commands.", -2)
+        .categories(&["slow", "connection"])
+        .handler(|processor, _, args| processor.command_client(args))
+        .subcommands(&[
+            Command::new("client|id", "connection", "Returns the unique client ID of the connection.", 2)
+                .flags(&["noscript", "loading", "stale"]).categories(&["slow", "connection"]),
+            Command::new("client|info", "connection", "Returns information about the connection.", 2)
+                .flags(&["noscript", "loading", "stale"]).categories(&["slow", "connection"]),
+            Command::new("client|list", "connection", "Lists open connections.", -2)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(CONNECTION_ADMIN),
+            Command::new("client|setname", "connection", "Sets the connection name.", 3)
+                .flags(&["noscript", "loading", "stale"]).categories(&["slow", "connection"]),
+            Command::new("client|getname", "connection", "Returns the name of the connection.", 2)
+                .flags(&["noscript", "loading", "stale"]).categories(&["slow", "connection"]),
+            Command::new("client|kill", "connection", "Terminates open connections.", -3)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(CONNECTION_ADMIN),
+            Command::new("client|pause", "connection", "Suspends commands processing.", -3)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(CONNECTION_ADMIN),
+            Command::new("client|unpause", "connection", "Resumes processing commands from paused clients.", 2)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(CONNECTION_ADMIN),
+            Command::new("client|no-evict", "connection", "Sets the client eviction mode of the connection.", 3)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(CONNECTION_ADMIN),
+        ]),
+    Command::new("acl", "server", "A container for Access List Control commands.", -2)
+        .categories(ADMIN)
+        .handler(|processor, _, args| processor.command_acl(args))
+        .subcommands(&[
+            Command::new("acl|setuser", "server", "Creates and modifies an ACL user and its rules.", -3)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+            Command::new("acl|getuser", "server", "Lists the ACL rules of a user.", 3)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+            Command::new("acl|deluser", "server", "Deletes ACL users, and terminates their connections.", -3)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+            Command::new("acl|list", "server", "Dumps the effective rules in ACL file format.", 2)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+            Command::new("acl|users", "server", "Lists all ACL users.", 2)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+            Command::new("acl|whoami", "server", "Returns the authenticated username of the current connection.", 2)
+                .flags(&["noscript", "loading", "stale"]).categories(&["slow"]),
+            Command::new("acl|cat", "server", "Lists the ACL categories, or the commands inside a category.", -2)
+                .flags(&["noscript", "loading", "stale"]).categories(&["slow"]),
+            Command::new("acl|log", "server", "Lists recent security events generated due to ACL rules.", -2)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+            Command::new("acl|load", "server", "Reloads the rules from the configured ACL file.", 2)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+            Command::new("acl|save", "server", "Saves the effective ACL rules in the configured ACL file.", 2)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+        ]),
+    // reads every shard, so it would deadlock inside a script holding shard locks
+    Command::new("info", "server", "Returns information and statistics about the server.", -1)
+        .flags(&["noscript", "loading", "stale"]).categories(&["slow", "dangerous"])
+        .handler(|processor, _, args| processor.command_info(args)),
+    Command::new("replicaof", "server", "Configures a server as replica of another, or promotes it to a master.", 3)
+        .flags(&["admin", "noscript", "stale"]).categories(ADMIN)
+        .handler(|processor, _, args| processor.command_replicaof(args)),
+    Command::new("slaveof", "server", "Sets a Redis server as a replica of another, or promotes it to being a master.", 3)
+        .flags(&["admin", "noscript", "stale"]).categories(ADMIN)
+        .handler(|processor, _, args| processor.command_replicaof(args)),
+    Command::new("replconf", "server", "An internal command for configuring the replication stream.", -1)
+        .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN)
+        .handler(|processor, _, args| processor.command_replconf(args)),
+    Command::new("psync", "server", "An internal command used in replication.", -3)
+        .flags(&["admin", "noscript", "no_multi"]).categories(ADMIN)
+        .handler(|processor, _, args| processor.command_psync(args)),
+    Command::new("wait", "generic", "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.", 3)
+        .flags(&["noscript", "blocking", "no_multi"]).categories(&["slow", "connection"])
+        .handler(|processor, _, args| processor.command_wait(args)),
+    Command::new("cluster", "cluster", "A container for Redis Cluster commands.", -2)
+        .categories(&["slow"])
+        .handler(|processor, _, args| processor.command_cluster(args))
+        .subcommands(&[
+            Command::new("cluster|myid", "cluster", "Returns the ID of a node.", 2)
+                .flags(&["loading", "stale"]).categories(&["slow"]),
+            Command::new("cluster|info", "cluster", "Returns information about the state of a node.", 2)
+                .flags(&["loading", "stale"]).categories(&["slow"]),
+            Command::new("cluster|nodes", "cluster", "Returns the cluster configuration for a node.", 2)
+                .flags(&["loading", "stale"]).categories(&["slow"]),
+            Command::new("cluster|slots", "cluster", "Returns the mapping of cluster slots to nodes.", 2)
+                .flags(&["loading", "stale"]).categories(&["slow"]),
+            Command::new("cluster|shards", "cluster", "Returns the mapping of cluster slots to shards.", 2)
+                .flags(&["loading", "stale"]).categories(&["slow"]),
+            Command::new("cluster|keyslot", "cluster", "Returns the hash slot for a key.", 3)
+                .flags(&["loading", "stale"]).categories(&["slow"]),
+            Command::new("cluster|addslots", "cluster", "Assigns new hash slots to a node.", -3)
+                .flags(&["admin", "noscript", "stale"]).categories(ADMIN),
+            Command::new("cluster|addslotsrange", "cluster", "Assigns new hash slot ranges to a node.", -4)
+                .flags(&["admin", "noscript", "stale"]).categories(ADMIN),
+            Command::new("cluster|setslot", "cluster", "Binds a hash slot to a node.", -4)
+                .flags(&["admin", "noscript", "stale"]).categories(ADMIN),
+            Command::new("cluster|meet", "cluster", "Forces a node to handshake with another node.", -4)
+                .flags(&["admin", "noscript", "stale"]).categories(ADMIN),
+        ]),
+    Command::new("asking", "cluster", "Signals that a cluster client is following an -ASK redirect.", 1)
+        .flags(&["fast"]).categories(&["fast"])
+        .handler(|processor, _, _| processor.command_asking()),
+    Command::new("slowlog", "server", "A container for slow log commands.", -2)
+        .categories(ADMIN)
+        .handler(|processor, _, args| processor.command_slowlog(args))
+        .subcommands(&[
+            Command::new("slowlog|get", "server", "Returns the slow log's entries.", -2)
+                .flags(&["admin", "loading", "stale"]).categories(ADMIN),
+            Command::new("slowlog|len", "server", "Returns the number of entries in the slow log.", 2)
+                .flags(&["admin", "loading", "stale"]).categories(ADMIN),
+            Command::new("slowlog|reset", "server", "Clears all entries from the slow log.", 2)
+                .flags(&["admin", "loading", "stale"]).categories(ADMIN),
+        ]),
+    Command::new("latency", "server", "A container for latency diagnostics commands.", -2)
+        .categories(ADMIN)
+        .handler(|processor, _, args| processor.command_latency(args))
+        .subcommands(&[
+            Command::new("latency|latest", "server", "Returns the latest latency samples for all events.", 2)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+            Command::new("latency|history", "server", "Returns timestamp-latency samples for an event.", 3)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+            Command::new("latency|reset", "server", "Resets the latency data for one or more events.", -2)
+                .flags(&["admin", "noscript", "loading", "stale"]).categories(ADMIN),
+        ]),
+    Command::new("monitor", "server", "Listens for all requests received by the server in real-time.", 1)
+        .flags(&["admin", "noscript", "loading", "stale", "no_multi"]).categories(ADMIN)
+        .handler(|processor, _, _| processor.command_monitor()),
+    Command::new("command", "server", "Returns detailed information about all commands.", -1)
+        .flags(&["loading", "stale"]).categories(&["slow", "connection"])
+        .handler(|processor, _, args| processor.command_command(args))
+        .subcommands(&[
+            Command::new("command|count", "server", "Returns a count of commands.", 2)
+                .flags(&["loading", "stale"]).categories(&["slow", "connection"]),
+            Command::new("command|info", "server", "Returns information about one, multiple or all commands.", -2)
+                .flags(&["loading", "stale"]).categories(&["slow", "connection"]),
+            Command::new("command|docs", "server", "Returns documentary information about one, multiple or all commands.", -2)
+                .flags(&["loading", "stale"]).categories(&["slow", "connection"]),
+            Command::new("command|getkeys", "server", "Extracts the key names from an arbitrary command.", -3)
+                .flags(&["loading", "stale"]).categories(&["slow", "connection"]),
+        ]),
+    Command::new("eval", "scripting", "Executes a server-side Lua script.", -3)
+        .flags(&["noscript", "stale", "may_replicate", "denyoom", "no_multi"]).categories(&["slow", "scripting"]).keys(Keys::Script)
+        .handler(|processor, shards, args| processor.command_eval(shards, args)),
+    Command::new("evalsha", "scripting", "Executes a server-side Lua script by SHA1 digest.", -3)
+        .flags(&["noscript", "stale", "may_replicate", "denyoom", "no_multi"]).categories(&["slow", "scripting"]).keys(Keys::Script)
+        .handler(|processor, shards, args| processor.command_evalsha(shards, args)),
+    Command::new("script", "scripting", "A container for Lua scripts management commands.", -2)
+        .categories(&["slow", "scripting"])
+        .handler(|processor, _, args| processor.command_script(args))
+        .subcommands(&[
+            Command::new("script|load", "scripting", "Loads a server-side Lua script to the scripts cache.", 3)
+                .flags(&["noscript", "stale"]).categories(&["slow", "scripting"]),
+            Command::new("script|exists", "scripting", "Determines whether server-side Lua scripts exist in the script cache.", -3)
+                .flags(&["noscript"]).categories(&["slow", "scripting"]),
+            Command::new("script|flush", "scripting", "Removes all server-side Lua scripts from the script cache.", -2)
+                .flags(&["noscript"]).categories(&["slow", "scripting"]),
+            Command::new("script|kill", "scripting", "Terminates a server-side Lua script during execution.", 2)
+                .flags(&["noscript", "allow_busy"]).categories(&["slow", "scripting"]),
+        ]),
+    Command::new("multi", "transactions", "Starts a transaction.", 1)
+        .flags(&["noscript", "loading", "stale", "fast", "allow_busy"]).categories(&["fast", "transaction"])
+        .handler(|processor, _, _| processor.command_multi()),
+    Command::new("exec", "transactions", "Executes all commands in a transaction.", 1)
+        .flags(&["noscript", "loading", "stale"]).categories(&["slow", "transaction"])
+        .handler(|processor, _, _| processor.command_exec()),
+    Command::new("discard", "transactions", "Discards a transaction.", 1)
+        .flags(&["noscript", "loading", "stale", "fast", "allow_busy"]).categories(&["fast", "transaction"])
+        .handler(|processor, _, _| processor.command_discard()),
+    Command::new("watch", "transactions", "Monitors changes to keys to determine the execution of a transaction.", -2)
+        .flags(&["noscript", "loading", "stale", "fast"]).categories(&["fast", "transaction"]).keys(Keys::All)
+        .handler(|processor, _, args| processor.command_watch(args)),
+    Command::new("unwatch", "transactions", "Forgets about watched keys of a transaction.", 1)
+        .flags(&["noscript", "loading", "stale", "fast", "allow_busy"]).categories(&["fast", "transaction"])
+        .handler(|processor, _, _| processor.command_unwatch()),
+    Command::new("subscribe", "pubsub", "Listens for messages published to channels.", -2)
+        .flags(&["pubsub", "noscript", "loading", "stale", "no_multi"]).categories(&["pubsub", "slow"])
+        .handler(|processor, _, args| processor.command_subscribe(args, Subscription::Channel)),
+    Command::new("psubscribe", "pubsub", "Listens for messages published to channels that match one or more patterns.", -2)
+        .flags(&["pubsub", "noscript", "loading", "stale", "no_multi"]).categories(&["pubsub", "slow"])
+        .handler(|processor, _, args| processor.command_subscribe(args, Subscription::Pattern)),
+    Command::new("unsubscribe", "pubsub", "Stops listening to messages posted to channels.", -1)
+        .flags(&["pubsub", "noscript", "loading", "stale", "no_multi"]).categories(&["pubsub", "slow"])
+        .handler(|processor, _, args| processor.command_unsubscribe(args, Subscription::Channel)),
+    Command::new("punsubscribe", "pubsub", "Stops listening to messages published to channels that match one or more patterns.", -1)
+        .flags(&["pubsub", "noscript", "loading", "stale", "no_multi"]).categories(&["pubsub", "slow"])
+        .handler(|processor, _, args| processor.command_unsubscribe(args, Subscription::Pattern)),
+    Command::new("publish", "pubsub", "Posts a message to a channel.", 3)
+        .flags(&["pubsub", "loading", "stale", "fast"]).categories(&["pubsub", "fast"])
+        .handler(|processor, _, args| processor.command_publish(args)),
+];
+
+// Command with its subcommand, subcommand metadata takes precedence over container command
+#[derive(Clone, Copy)]
+pub struct CommandSpec {
+    command: &'static Command,
+    subcommand: Option<&'static Command>,
+}
+
+impl CommandSpec {
+    fn spec(&self) -> &'static Command {
+        self.subcommand.unwrap_or(self.command)
+    }
+
+    // `command` or `command|subcommand`
+    pub fn name(&self) -> &'static str {
+        self.spec().name
+    }
+
+    pub fn has_flag(&self, flag: &str) -> bool {
+        self.spec().flags.contains(&flag)
+    }
+
+    pub fn has_category(&self, category: &str) -> bool {
+        category == "all" || self.spec().categories.contains(&category)
+    }
+
+    // arguments which are keys, args don't include command name
+    pub fn keys<'a>(&self, args: &[&'a [u8]]) -> Vec<&'a [u8]> {
+        match self.spec().keys {
+            Keys::First => args[..args.len().min(1)].to_vec(),
+            Keys::Second => args[args.len().min(1)..args.len().min(2)].to_vec(),
+            Keys::All => args.to_vec(),
+            Keys::Pairs => args.iter().step_by(2).copied().collect(),
+            Keys::None => Vec::new(),
+            Keys::Script => {
+                // invalid numkeys is reported by command itself
+                let numkeys = args.get(1).and_then(|numkeys| std::str::from_utf8(numkeys).ok()?.parse::<usize>().ok()).unwrap_or(0);
+                args[args.len().min(2)..args.len().min(2 + numkeys)].to_vec()
+            },
+            Keys::Migrate => match args.get(2) {
+                Some(key) if !key.is_empty() => args[2..3].to_vec(),
+                _ => match args.iter().skip(5).position(|arg| arg.eq_ignore_ascii_case(b"keys")) {
+                    Some(position) => args[5 + position + 1..].to_vec(),
+                    None => Vec::new(),
+                },
+            },
+        }
+    }
+
+    // scripts may access any key, so the whole keyspace is locked while they run
+    pub fn locks_keyspace(&self) -> bool {
+        matches!(self.spec().keys, Keys::Script)
+    }
+
+    // scripts, network exchange of MIGRATE and blocking commands may take long, so they don't run on runtime workers
+    pub fn may_block(&self) -> bool {
+        matches!(self.spec().keys, Keys::Script | Keys::Migrate) || self.has_flag("blocking")
+    }
+
+    pub fn call(&self, processor: &MessageProcessor, shards: &mut LockedShards, args: &[Message]) -> Result<Message, ProcessingError> {
+        let handler = self.command.handler.expect("Top level commands have handler");
+        handler(processor, shards, args)
+    }
+}
+
+// Known command with its subcommand, None when container command doesn't have the given subcommand.
+// For commands without subcommands `subcommand` is just the first argument and is ignored.
+pub fn find(command: &str, subcommand: Option<&str>) -> Option<CommandSpec> {
+    let command = COMMANDS.iter().find(|spec| spec.name == command)?;
+    let subcommand = match subcommand {
+        Some(subcommand) if !command.subcommands.is_empty() => Some(command.find_subcommand(subcommand)?),
+        _ => None,
+    };
+    Some(CommandSpec { command, subcommand })
+}
+
+// Looks up command of request and checks number of its arguments
+pub fn lookup(parts: &[Message]) -> Result<CommandSpec, ProcessingError> {
+    let name = parts.first().ok_or("ERR empty command")?.as_str()?;
+    let Some(command) = COMMANDS.iter().find(|spec| spec.name.eq_ignore_ascii_case(name)) else {
+        let args = parts[1..].iter().map(|arg| String::from_utf8_lossy(arg.extract_bulk_content().map_or(&[][..], |arg| arg)).into_owned()).collect();
+        return Err(ProcessingError::UnknownCommand { name: name.to_string(), args });
+    };
+    check_arity(command, parts.len())?;
+    if command.subcommands.is_empty() {
+        return Ok(CommandSpec { command, subcommand: None });
+    }
+
+    let subcommand_name = parts[1].as_str()?;
+    let subcommand = command.find_subcommand(subcommand_name).ok_or_else(|| {
+        ProcessingError::UnknownSubcommand { subcommand: subcommand_name.to_string(), command: command.name.to_string() }
+    })?;
+    check_arity(subcommand, parts.len())?;
+    Ok(CommandSpec { command, subcommand: Some(subcommand) })
+}
+
+fn check_arity(command: &Command, parts: usize) -> Result<(), ProcessingError> {
+    let arity = command.arity;
+    if (arity > 0 && parts != arity as usize) || (arity < 0 && parts < arity.unsigned_abs() as usize) {
+        return Err(ProcessingError::WrongArity(command.name.to_string()));
+    }
+    Ok(())
+}
+
+// names of commands and subcommands in ACL category
+pub fn in_category(category: &str) -> Vec<&'static str> {
+    COMMANDS
+        .iter()
+        .flat_map(|command| std::iter::once(command).chain(command.subcommands))
+        .filter(|spec| category == "all" || spec.categories.contains(&category))
+        .map(|spec| spec.name)
+        .collect()
+}
+
+// names of commands with names of their subcommands, e.g. for completion in ccredis-cli
+pub fn names() -> Vec<(&'static str, Vec<&'static str>)> {
+    COMMANDS
+        .iter()
+        .map(|command| {
+            let subcommands = command.subcommands.iter().map(|subcommand| &subcommand.name[command.name.len() + 1..]).collect();
+            (command.name, subcommands)
+        })
+        .collect()
+}
+
+pub fn count() -> usize {
+    COMMANDS.len()
+}
+
+// COMMAND INFO for given names, nil for unknown ones, all commands when no names given
+pub fn info(names: &[&str]) -> Message {
+    if names.is_empty() {
+        return Message::array(COMMANDS.iter().map(|command| command.info()).collect());
+    }
+    Message::array(names.iter().map(|name| find_by_name(name).map_or(Message::Array(None), |command| command.info())).collect())
+}
+
+// COMMAND DOCS for given names, unknown ones are skipped
+pub fn docs(names: &[&str]) -> Message {
+    let commands: Vec<&Command> = if names.is_empty() {
+        COMMANDS.iter().collect()
+    } else {
+        names.iter().filter_map(|name| find_by_name(name)).collect()
+    };
+    Message::array(commands.iter().flat_map(|command| [Message::bulk_string(command.name), command.docs()]).collect())
+}
+
+// `command` or `command|subcommand`
+fn find_by_name(name: &str) -> Option<&'static Command> {
+    let name = name.to_lowercase();
+    match name.split_once('|') {
+        Some((command, subcommand)) => find(command, Some(subcommand)).and_then(|spec| spec.subcommand),
+        None => find(&name, None).map(|spec| spec.command),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parts(command: &str) -> Vec<Message> {
+        command.split(' ').map(Message::bulk_string).collect()
+    }
+
+    #[test]
+    fn command_keys_positions() {
+        let args: Vec<&[u8]> = vec![b"a", b"b", b"c"];
+        assert_eq!(find("get", None).unwrap().keys(&args), &args[..1]);
+        assert_eq!(find("del", None).unwrap().keys(&args), &args[..]);
+        assert!(find("ping", None).unwrap().keys(&args).is_empty());
+        assert_eq!(find("mset", None).unwrap().keys(&args), [args[0], args[2]]);
+
+        let args: Vec<&[u8]> = vec![b"return 1", b"2", b"a", b"b", b"c"];
+        assert_eq!(find("eval", None).unwrap().keys(&args), &args[2..4]);
+        let args: Vec<&[u8]> = vec![b"return 1", b"5", b"a"];
+        assert_eq!(find("eval", None).unwrap().keys(&args), &args[2..]);
+
+        assert_eq!(find("object", Some("encoding")).unwrap().keys(&args), &args[1..2]);
+
+        let args: Vec<&[u8]> = vec![b"host", b"6379", b"key", b"0", b"1000", b"COPY"];
+        assert_eq!(find("migrate", None).unwrap().keys(&args), &args[2..3]);
+        let args: Vec<&[u8]> = vec![b"host", b"6379", b"", b"0", b"1000", b"REPLACE", b"KEYS", b"a", b"b"];
+        assert_eq!(find("migrate", None).unwrap().keys(&args), &args[7..]);
+    }
+
+    #[test]
+    fn commands_which_may_block() {
+        for name in ["eval", "evalsha", "migrate", "wait", "shutdown"] {
+            assert!(find(name, None).unwrap().may_block(), "{}", name);
+            // EXEC runs queued commands on a runtime worker
+            assert!(find(name, None).unwrap().has_flag("no_multi"), "{}", name);
+        }
+        assert!(!find("get", None).unwrap().may_block());
+    }
+
+    #[test]
+    fn lookup_checks_arity() {
+        assert!(lookup(&parts("GET key")).is_ok());
+        assert!(lookup(&parts("DEL a b c")).is_ok());
+        assert_eq!(lookup(&parts("GET")).err().unwrap().to_string(), "ERR wrong number of arguments for 'get' command");
+        assert_eq!(lookup(&parts("GET a b")).err().unwrap().to_string(), "ERR wrong number of arguments for 'get' command");
+        assert_eq!(lookup(&parts("SET key")).err().unwrap().to_string(), "ERR wrong number of arguments for 'set' command");
+        assert_eq!(lookup(&parts("CLIENT")).err().unwrap().to_string(), "ERR wrong number of arguments for 'client' command");
+        assert_eq!(lookup(&parts("CLIENT SETNAME")).err().unwrap().to_string(), "ERR wrong number of arguments for 'client|setname' command");
+        assert_eq!(lookup(&parts("CLIENT FOO")).err().unwrap().to_string(), "ERR unknown subcommand 'FOO'. Try CLIENT HELP.");
+        assert_eq!(lookup(&parts("FOO a b")).err().unwrap().to_string(), "ERR unknown command 'FOO', with args beginning with: 'a' 'b' ");
+
+        let spec = lookup(&parts("CLIENT KILL ID 1")).unwrap();
+        assert!(spec.has_flag("admin"));
+        assert!(spec.has_category("dangerous"));
+        assert!(!find("client", Some("id")).unwrap().has_category("dangerous"));
+    }
+
+    #[test]
+    fn every_command_has_handler() {
+        for command in COMMANDS {
+            assert!(command.handler.is_some(), "{}", command.name);
+            for subcommand in command.subcommands {
+                assert!(subcommand.name.starts_with(&format!("{}|", command.name)), "{}", subcommand.name);
+                assert!(subcommand.handler.is_none() && subcommand.subcommands.is_empty(), "{}", subcommand.name);
+            }
+        }
+    }
+}
@@ -1,15 +1,51 @@
+// Errors are sent to client as `-<message>`, first word of message is error code clients branch on
 #[derive(Debug)]
 pub enum ProcessingError {
-    InvalidUtf8,     
-    InvalidInteger, 
-    Other(String),   
+    InvalidUtf8,
+    NotInteger,
+    WrongType,
+    Syntax,
+    OutOfRange,
+    // key must exist, e.g. RENAME or LSET
+    NoSuchKey,
+    // INCR/DECR result doesn't fit into i64
+    Overflow,
+    // name of command, e.g. `get` or `client|kill`
+    WrongArity(String),
+    UnknownCommand { name: String, args: Vec<String> },
+    UnknownSubcommand { subcommand: String, command: String },
+    NoAuth,
+    ReadOnly,
+    Oom,
+    NoScript,
+    // script runs longer than lua-time-limit
+    Busy,
+    Other(String),
 }
 
 impl std::fmt::Display for ProcessingError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ProcessingError::InvalidUtf8 => write!(f, "Invalid UTF-8 sequence encountered"),
-            ProcessingError::InvalidInteger => write!(f, "Invalid integer format encountered"),
+            ProcessingError::InvalidUtf8 => write!(f, "ERR invalid UTF-8 sequence"),
+            ProcessingError::NotInteger => write!(f, "ERR value is not an integer or out of range"),
+            ProcessingError::WrongType => write!(f, "WRONGTYPE Operation against a key holding the wrong kind of value"),
+            ProcessingError::Syntax => write!(f, "ERR syntax error"),
+            ProcessingError::OutOfRange => write!(f, "ERR value is out of range, must be positive"),
+            ProcessingError::NoSuchKey => write!(f, "ERR no such key"),
+            ProcessingError::Overflow => write!(f, "ERR increment or decrement would overflow"),
+            ProcessingError::WrongArity(command) => write!(f, "ERR wrong number of arguments for '{}' command", command),
+            ProcessingError::UnknownCommand { name, args } => {
+                write!(f, "ERR unknown command '{}', with args beginning with: ", name)?;
+                args.iter().try_for_each(|arg| write!(f, "'{}' ", arg))
+            },
+            ProcessingError::UnknownSubcommand { subcommand, command } => {
+                write!(f, "ERR unknown subcommand '{}'. Try {} HELP.", subcommand, command.to_uppercase())
+            },
+            ProcessingError::NoAuth => write!(f, "NOAUTH Authentication required."),
+            ProcessingError::ReadOnly => write!(f, "READONLY You can't write against a read only replica."),
+            ProcessingError::Oom => write!(f, "OOM command not allowed when used memory > 'maxmemory'."),
+            ProcessingError::NoScript => write!(f, "NOSCRIPT No matching script. Please use EVAL."),
+            ProcessingError::Busy => write!(f, "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE."),
             ProcessingError::Other(msg) => write!(f, "{}", msg),
         }
     }
@@ -27,4 +63,20 @@ impl From<String> for ProcessingError {
     fn from(error: String) -> Self {
         ProcessingError::Other(error)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redis_messages() {
+        assert_eq!(ProcessingError::WrongType.to_string(), "WRONGTYPE Operation against a key holding the wrong kind of value");
+        assert_eq!(ProcessingError::NoSuchKey.to_string(), "ERR no such key");
+        assert_eq!(ProcessingError::WrongArity("client|kill".to_string()).to_string(), "ERR wrong number of arguments for 'client|kill' command");
+        let unknown = ProcessingError::UnknownCommand { name: "foo".to_string(), args: vec!["a".to_string(), "b".to_string()] };
+        assert_eq!(unknown.to_string(), "ERR unknown command 'foo', with args beginning with: 'a' 'b' ");
+        let unknown = ProcessingError::UnknownSubcommand { subcommand: "foo".to_string(), command: "client".to_string() };
+        assert_eq!(unknown.to_string(), "ERR unknown subcommand 'foo'. Try CLIENT HELP.");
+    }
+}
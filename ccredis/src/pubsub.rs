@@ -0,0 +1,163 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use crate::{client_registry::Client, glob::glob_match, resp::message::Message};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subscription {
+    Channel,
+    Pattern,
+}
+
+#[derive(Default)]
+struct Subscriptions {
+    channels: BTreeSet<Vec<u8>>,
+    patterns: BTreeSet<Vec<u8>>,
+}
+
+impl Subscriptions {
+    fn of(&mut self, kind: Subscription) -> &mut BTreeSet<Vec<u8>> {
+        match kind {
+            Subscription::Channel => &mut self.channels,
+            Subscription::Pattern => &mut self.patterns,
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.channels.len() + self.patterns.len()
+    }
+}
+
+#[derive(Default)]
+struct PubSubState {
+    // subscribers of channel and of pattern
+    channels: HashMap<Vec<u8>, BTreeMap<u64, Arc<Client>>>,
+    patterns: HashMap<Vec<u8>, BTreeMap<u64, Arc<Client>>>,
+    clients: HashMap<u64, Subscriptions>,
+}
+
+impl PubSubState {
+    fn subscribers(&mut self, kind: Subscription) -> &mut HashMap<Vec<u8>, BTreeMap<u64, Arc<Client>>> {
+        match kind {
+            Subscription::Channel => &mut self.channels,
+            Subscription::Pattern => &mut self.patterns,
+        }
+    }
+
+    fn count(&self, client_id: u64) -> usize {
+        self.clients.get(&client_id).map_or(0, Subscriptions::count)
+    }
+}
+
+// Channel and pattern subscriptions of clients. PUBLISH queues the message to every subscriber.
+#[derive(Default)]
+pub struct PubSub {
+    state: Mutex<PubSubState>,
+}
+
+impl PubSub {
+    fn state(&self) -> MutexGuard<'_, PubSubState> {
+        self.state.lock().expect("Pub/sub lock poisoned")
+    }
+
+    // number of channels and patterns the client is subscribed to afterwards
+    pub fn subscribe(&self, client: &Arc<Client>, kind: Subscription, name: &[u8]) -> usize {
+        let mut state = self.state();
+        if state.clients.entry(client.id).or_default().of(kind).insert(name.to_vec()) {
+            state.subscribers(kind).entry(name.to_vec()).or_default().insert(client.id, client.clone());
+        }
+        state.count(client.id)
+    }
+
+    pub fn unsubscribe(&self, client_id: u64, kind: Subscription, name: &[u8]) -> usize {
+        let mut state = self.state();
+        let removed = state.clients.get_mut(&client_id).is_some_and(|subscriptions| subscriptions.of(kind).remove(name));
+        if removed {
+            let subscribers = state.subscribers(kind);
+            if let Some(clients) = subscribers.get_mut(name) {
+                clients.remove(&client_id);
+                if clients.is_empty() {
+                    subscribers.remove(name);
+                }
+            }
+        }
+        if state.count(client_id) == 0 {
+            state.clients.remove(&client_id);
+        }
+        state.count(client_id)
+    }
+
+    // channels or patterns of the client in order
+    pub fn subscriptions(&self, client_id: u64, kind: Subscription) -> Vec<Vec<u8>> {
+        self.state().clients.get_mut(&client_id).map_or_else(Vec::new, |subscriptions| subscriptions.of(kind).iter().cloned().collect())
+    }
+
+    // client with subscriptions may only run (un)subscribe commands and PING
+    pub fn count(&self, client_id: u64) -> usize {
+        self.state().count(client_id)
+    }
+
+    pub fn remove_client(&self, client_id: u64) {
+        for kind in [Subscription::Channel, Subscription::Pattern] {
+            for name in self.subscriptions(client_id, kind) {
+                self.unsubscribe(client_id, kind, &name);
+            }
+        }
+    }
+
+    // number of clients which received the message
+    pub fn publish(&self, channel: &[u8], payload: &[u8]) -> usize {
+        let state = self.state();
+        let mut receivers = 0;
+        if let Some(clients) = state.channels.get(channel) {
+            let message = Message::array(vec![Message::bulk_string("message"), bulk(channel), bulk(payload)]);
+            receivers += clients.values().filter(|client| client.send(&message)).count();
+        }
+        for (pattern, clients) in &state.patterns {
+            if glob_match(pattern, channel) {
+                let message = Message::array(vec![Message::bulk_string("pmessage"), bulk(pattern), bulk(channel), bulk(payload)]);
+                receivers += clients.values().filter(|client| client.send(&message)).count();
+            }
+        }
+        receivers
+    }
+}
+
+fn bulk(content: &[u8]) -> Message {
+    Message::BulkString(Some(content.to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{client_registry::ClientRegistry, connection::Outgoing};
+
+    #[test]
+    fn test_publish() {
+        let registry = ClientRegistry::default();
+        let pubsub = PubSub::default();
+        let (outbound, mut queue) = tokio::sync::mpsc::unbounded_channel();
+        let client = registry.register("127.0.0.1:10001", "127.0.0.1:6379", outbound);
+
+        assert_eq!(pubsub.subscribe(&client, Subscription::Channel, b"news"), 1);
+        assert_eq!(pubsub.subscribe(&client, Subscription::Channel, b"news"), 1);
+        assert_eq!(pubsub.subscribe(&client, Subscription::Pattern, b"n*"), 2);
+        assert_eq!(pubsub.publish(b"news", b"hello"), 2);
+        assert_eq!(pubsub.publish(b"sports", b"hello"), 0);
+
+        let mut received = Vec::new();
+        while let Ok(Outgoing::Data(data)) = queue.try_recv() {
+            received.extend(data);
+        }
+        assert_eq!(received, b"*3\r\n$7\r\nmessage\r\n$4\r\nnews\r\n$5\r\nhello\r\n*4\r\n$8\r\npmessage\r\n$2\r\nn*\r\n$4\r\nnews\r\n$5\r\nhello\r\n");
+
+        assert_eq!(pubsub.unsubscribe(client.id, Subscription::Channel, b"news"), 1);
+        assert_eq!(pubsub.unsubscribe(client.id, Subscription::Channel, b"news"), 1);
+        pubsub.remove_client(client.id);
+        assert_eq!(pubsub.count(client.id), 0);
+        assert_eq!(pubsub.publish(b"news", b"hello"), 0);
+        assert!(pubsub.state().patterns.is_empty());
+    }
+}
@@ -0,0 +1,485 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Condvar, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::{tcp::OwnedWriteHalf, TcpStream},
+    task::AbortHandle,
+};
+
+use crate::{
+    client_registry::Client,
+    config::Config,
+    message_processor::{now, MessageProcessor},
+    resp::{message::Message, message_parser::MessageParser},
+};
+
+const ACK_INTERVAL: Duration = Duration::from_secs(1);
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Clone, Copy, PartialEq)]
+enum LinkStatus {
+    Connecting,
+    Sync,
+    Up,
+}
+
+struct MasterLink {
+    host: String,
+    port: u16,
+    status: LinkStatus,
+    last_io: u128,
+    task: AbortHandle,
+}
+
+struct ReplicaLink {
+    client: Arc<Client>,
+    // port announced by REPLCONF listening-port
+    port: u16,
+    ack_offset: u64,
+    ack_time: u128,
+}
+
+struct State {
+    // Some when this server is a replica
+    master: Option<MasterLink>,
+    replid: String,
+    // previous replication id, replicas of the old master can continue from us after failover
+    replid2: String,
+    second_repl_offset: i64,
+    // number of bytes of replication stream produced (master) or processed (replica)
+    offset: u64,
+    // last bytes of replication stream, created when first replica is attached
+    backlog: Option<VecDeque<u8>>,
+    replicas: Vec<ReplicaLink>,
+    listening_ports: HashMap<u64, u16>,
+    sync_full: u64,
+    sync_partial_ok: u64,
+    sync_partial_err: u64,
+}
+
+// Master-replica replication.
+// Master sends snapshot of the dataset followed by stream of write commands in RESP format.
+// Replica which lost connection sends PSYNC with replication id and offset, and when
+// requested part of the stream is still in the backlog only missing bytes are sent.
+pub struct Replication {
+    state: Mutex<State>,
+    acked: Condvar,
+    // mirrors `state.master.is_some()`, checked by every command without taking state lock
+    replica: AtomicBool,
+    listening_port: u16,
+    masteruser: Option<String>,
+    masterauth: Option<String>,
+    backlog_size: usize,
+    pub read_only: bool,
+}
+
+impl Replication {
+    pub fn new(config: &Config) -> Replication {
+        Replication {
+            state: Mutex::new(State {
+                master: None,
+                replid: new_replid(),
+                replid2: "0".repeat(40),
+                second_repl_offset: -1,
+                offset: 0,
+                backlog: None,
+                replicas: Vec::new(),
+                listening_ports: HashMap::new(),
+                sync_full: 0,
+                sync_partial_ok: 0,
+                sync_partial_err: 0,
+            }),
+            acked: Condvar::new(),
+            replica: AtomicBool::new(false),
+            listening_port: config.port,
+            masteruser: config.masteruser.clone(),
+            masterauth: config.masterauth.clone(),
+            backlog_size: config.repl_backlog_size.max(1),
+            read_only: config.replica_read_only,
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().expect("Replication lock poisoned")
+    }
+
+    pub fn is_replica(&self) -> bool {
+        self.replica.load(Ordering::SeqCst)
+    }
+
+    // Sends write command to replicas. Replica forwards stream received from its master instead.
+    pub fn propagate(&self, command: &[Message]) {
+        if self.is_replica() {
+            return;
+        }
+        let mut buf: Vec<u8> = Vec::new();
+        Message::Array(Some(command.to_vec())).write_to(&mut buf).expect("Writing to Vec can't fail");
+        self.feed(&buf);
+    }
+
+    fn feed(&self, bytes: &[u8]) {
+        let mut state = self.state();
+        // master without replicas has no stream
+        if state.backlog.is_none() && state.master.is_none() {
+            return;
+        }
+        state.offset += bytes.len() as u64;
+        if let Some(backlog) = state.backlog.as_mut() {
+            backlog.extend(bytes);
+            let excess = backlog.len().saturating_sub(self.backlog_size);
+            backlog.drain(..excess);
+        }
+        state.replicas.retain(|replica| replica.client.send_raw(bytes.to_vec()));
+    }
+
+    pub fn set_listening_port(&self, client_id: u64, port: u16) {
+        self.state().listening_ports.insert(client_id, port);
+    }
+
+    // PSYNC replid offset
+    // Caller has to block writes until snapshot is taken, so snapshot matches the stream offset.
+    pub fn attach_replica(&self, client: &Arc<Client>, replid: &str, offset: i64, snapshot: impl FnOnce() -> Vec<u8>) {
+        let mut state = self.state();
+        let state = &mut *state;
+        let backlog = state.backlog.get_or_insert_with(VecDeque::new);
+        let first_byte_offset = state.offset + 1 - backlog.len() as u64;
+        let same_history = replid == state.replid || (replid == state.replid2 && offset <= state.second_repl_offset);
+
+        if same_history && offset >= first_byte_offset as i64 && offset <= state.offset as i64 + 1 {
+            client.send_raw(format!("+CONTINUE {}\r\n", state.replid).into_bytes());
+            let missing: Vec<u8> = backlog.iter().skip((offset as u64 - first_byte_offset) as usize).copied().collect();
+            client.send_raw(missing);
+            state.sync_partial_ok += 1;
+            println!("[Replication] Partial resynchronization of replica {} from offset {}", client.addr, offset);
+        } else {
+            if replid != "?" {
+                state.sync_partial_err += 1;
+            }
+            client.send_raw(format!("+FULLRESYNC {} {}\r\n", state.replid, state.offset).into_bytes());
+            let snapshot = snapshot();
+            let mut payload = format!("${}\r\n", snapshot.len()).into_bytes();
+            payload.extend(snapshot);
+            client.send_snapshot(payload);
+            state.sync_full += 1;
+            println!("[Replication] Full resynchronization of replica {}", client.addr);
+        }
+
+        client.set_replica();
+        let port = state.listening_ports.remove(&client.id).unwrap_or(0);
+        state.replicas.push(ReplicaLink { client: client.clone(), port, ack_offset: 0, ack_time: now() });
+    }
+
+    pub fn remove_replica(&self, client_id: u64) {
+        let mut state = self.state();
+        state.replicas.retain(|replica| replica.client.id != client_id);
+        state.listening_ports.remove(&client_id);
+    }
+
+    // REPLCONF ACK offset
+    pub fn acknowledge(&self, client_id: u64, offset: u64) {
+        let mut state = self.state();
+        if let Some(replica) = state.replicas.iter_mut().find(|replica| replica.client.id == client_id) {
+            replica.ack_offset = replica.ack_offset.max(offset);
+            replica.ack_time = now();
+        }
+        self.acked.notify_all();
+    }
+
+    pub fn replica_count(&self) -> usize {
+        self.state().replicas.len()
+    }
+
+    // Blocks until `replicas` replicas acknowledged all writes made before the call or timeout
+    // in milliseconds is reached (0 to wait forever). Returns number of replicas which acknowledged.
+    pub fn wait(&self, replicas: usize, timeout: u64) -> usize {
+        let target = self.state().offset;
+        let acked = |state: &State| state.replicas.iter().filter(|replica| replica.ack_offset >= target).count();
+        let count = acked(&self.state());
+        if count >= replicas {
+            return count;
+        }
+
+        let mut getack: Vec<u8> = Vec::new();
+        Message::array(vec![Message::bulk_string("REPLCONF"), Message::bulk_string("GETACK"), Message::bulk_string("*")])
+            .write_to(&mut getack)
+            .expect("Writing to Vec can't fail");
+        self.feed(&getack);
+
+        let deadline = Instant::now() + Duration::from_millis(timeout);
+        let mut state = self.state();
+        loop {
+            let count = acked(&state);
+            if count >= replicas {
+                return count;
+            }
+            if timeout == 0 {
+                state = self.acked.wait(state).expect("Replication lock poisoned");
+                continue;
+            }
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return count;
+            }
+            state = self.acked.wait_timeout(state, remaining).expect("Replication lock poisoned").0;
+        }
+    }
+
+    // REPLICAOF host port, must be called from tokio runtime.
+    // Dataset is kept until master sends full snapshot, so partial resync is possible.
+    pub fn replicate_from(self: &Arc<Self>, host: &str, port: u16, processor: MessageProcessor) {
+        let mut state = self.state();
+        if let Some(master) = state.master.take() {
+            master.task.abort();
+        }
+        // our replicas have to follow new history
+        for replica in state.replicas.drain(..) {
+            replica.client.kill();
+        }
+
+        let task = tokio::spawn(run_link(self.clone(), host.to_string(), port, processor));
+        state.master = Some(MasterLink { host: host.to_string(), port, status: LinkStatus::Connecting, last_io: now(), task: task.abort_handle() });
+        self.replica.store(true, Ordering::SeqCst);
+        println!("[Replication] Replicating from {}:{}", host, port);
+    }
+
+    // REPLICAOF NO ONE
+    pub fn become_master(&self) {
+        let mut state = self.state();
+        if let Some(master) = state.master.take() {
+            master.task.abort();
+            self.replica.store(false, Ordering::SeqCst);
+            // replicas of the same master can continue from current offset
+            state.replid2 = std::mem::replace(&mut state.replid, new_replid());
+            state.second_repl_offset = state.offset as i64 + 1;
+            println!("[Replication] Became master, new replication id {}", state.replid);
+        }
+    }
+
+    pub fn is_replicating_from(&self, host: &str, port: u16) -> bool {
+        self.state().master.as_ref().is_some_and(|master| master.host == host && master.port == port)
+    }
+
+    // `# Replication` section of INFO
+    pub fn info(&self) -> String {
+        let state = self.state();
+        let mut info = String::from("# Replication\r\n");
+        match &state.master {
+            None => info.push_str("role:master\r\n"),
+            Some(master) => {
+                info.push_str("role:slave\r\n");
+                info.push_str(&format!("master_host:{}\r\nmaster_port:{}\r\n", master.host, master.port));
+                let link_status = if master.status == LinkStatus::Up { "up" } else { "down" };
+                info.push_str(&format!("master_link_status:{}\r\n", link_status));
+                info.push_str(&format!("master_last_io_seconds_ago:{}\r\n", now().saturating_sub(master.last_io) / 1000));
+                info.push_str(&format!("master_sync_in_progress:{}\r\n", (master.status == LinkStatus::Sync) as u8));
+                info.push_str(&format!("slave_repl_offset:{}\r\n", state.offset));
+                info.push_str(&format!("slave_read_only:{}\r\n", self.read_only as u8));
+            }
+        }
+        info.push_str(&format!("connected_slaves:{}\r\n", state.replicas.len()));
+        for (index, replica) in state.replicas.iter().enumerate() {
+            let ip = replica.client.addr.rsplit_once(':').map_or(replica.client.addr.as_str(), |(ip, _)| ip);
+            let lag = now().saturating_sub(replica.ack_time) / 1000;
+            info.push_str(&format!("slave{}:ip={},port={},state=online,offset={},lag={}\r\n", index, ip, replica.port, replica.ack_offset, lag));
+        }
+        info.push_str(&format!("master_replid:{}\r\nmaster_replid2:{}\r\n", state.replid, state.replid2));
+        info.push_str(&format!("master_repl_offset:{}\r\nsecond_repl_offset:{}\r\n", state.offset, state.second_repl_offset));
+        let histlen = state.backlog.as_ref().map_or(0, |backlog| backlog.len() as u64);
+        info.push_str(&format!("repl_backlog_active:{}\r\n", state.backlog.is_some() as u8));
+        info.push_str(&format!("repl_backlog_size:{}\r\n", self.backlog_size));
+        info.push_str(&format!("repl_backlog_first_byte_offset:{}\r\n", state.offset + 1 - histlen));
+        info.push_str(&format!("repl_backlog_histlen:{}\r\n", histlen));
+        info
+    }
+
+    // full and partial resynchronizations served as master, reported in `# Stats` section of INFO
+    pub fn sync_stats(&self) -> String {
+        let state = self.state();
+        format!("sync_full:{}\r\nsync_partial_ok:{}\r\nsync_partial_err:{}\r\n", state.sync_full, state.sync_partial_ok, state.sync_partial_err)
+    }
+
+    fn set_link_status(&self, status: LinkStatus) {
+        if let Some(master) = self.state().master.as_mut() {
+            master.status = status;
+            master.last_io = now();
+        }
+    }
+
+    // replication id and offset of the next byte to ask master for in PSYNC
+    fn psync_position(&self) -> (String, u64) {
+        let state = self.state();
+        (state.replid.clone(), state.offset + 1)
+    }
+
+    // +FULLRESYNC replid offset
+    fn load_full_sync(&self, processor: &MessageProcessor, replid: &str, offset: u64, snapshot: &[u8]) -> Result<(), String> {
+        processor.load_snapshot(snapshot).map_err(|err| format!("Cannot load snapshot: {}", err))?;
+
+        let mut state = self.state();
+        state.replid = replid.to_string();
+        state.replid2 = "0".repeat(40);
+        state.second_repl_offset = -1;
+        state.offset = offset;
+        state.backlog = Some(VecDeque::new());
+        for replica in state.replicas.drain(..) {
+            replica.client.kill();
+        }
+        Ok(())
+    }
+
+    // +CONTINUE [replid], master may have new replication id after failover
+    fn continue_sync(&self, replid: Option<&str>) {
+        let mut state = self.state();
+        if let Some(replid) = replid.filter(|replid| **replid != state.replid) {
+            state.replid2 = std::mem::replace(&mut state.replid, replid.to_string());
+            state.second_repl_offset = state.offset as i64 + 1;
+        }
+        state.backlog.get_or_insert_with(VecDeque::new);
+    }
+
+    // applies command from master and forwards its bytes to own backlog and replicas
+    fn apply_from_master(&self, processor: &MessageProcessor, message: &Message, raw: &[u8]) {
+        if let Message::Error(err) = processor.process_resp_message(message) {
+            println!("[Replication] Command from master failed: {}", err);
+        }
+        self.feed(raw);
+        if let Some(master) = self.state().master.as_mut() {
+            master.last_io = now();
+        }
+    }
+
+    fn offset(&self) -> u64 {
+        self.state().offset
+    }
+}
+
+fn new_replid() -> String {
+    let mut rng = rand::thread_rng();
+    (0..40).map(|_| format!("{:x}", rng.gen_range(0..16))).collect()
+}
+
+async fn run_link(replication: Arc<Replication>, host: String, port: u16, processor: MessageProcessor) {
+    loop {
+        match sync_with_master(&replication, &host, port, &processor).await {
+            Ok(()) => println!("[Replication] Connection with master lost"),
+            Err(err) => println!("[Replication] {}", err),
+        }
+        replication.set_link_status(LinkStatus::Connecting);
+        tokio::time::sleep(RECONNECT_INTERVAL).await;
+    }
+}
+
+async fn sync_with_master(replication: &Replication, host: &str, port: u16, processor: &MessageProcessor) -> Result<(), String> {
+    let stream = TcpStream::connect((host, port)).await.map_err(|err| format!("Cannot connect to master {}:{}: {}", host, port, err))?;
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+
+    if let Some(password) = &replication.masterauth {
+        let mut auth = vec!["AUTH"];
+        auth.extend(replication.masteruser.as_deref());
+        auth.push(password);
+        handshake(&mut reader, &mut writer, &auth, "+OK").await?;
+    }
+    handshake(&mut reader, &mut writer, &["PING"], "+PONG").await?;
+    handshake(&mut reader, &mut writer, &["REPLCONF", "listening-port", &replication.listening_port.to_string()], "+OK").await?;
+    handshake(&mut reader, &mut writer, &["REPLCONF", "capa", "psync2"], "+OK").await?;
+
+    let (replid, offset) = replication.psync_position();
+    send_command(&mut writer, &["PSYNC", &replid, &offset.to_string()]).await?;
+    let reply = read_line(&mut reader).await?;
+    if let Some(position) = reply.strip_prefix("+FULLRESYNC ") {
+        replication.set_link_status(LinkStatus::Sync);
+        let (replid, offset) = position.split_once(' ').ok_or(format!("Unexpected PSYNC reply: {}", reply))?;
+        let offset: u64 = offset.parse().map_err(|_| format!("Unexpected PSYNC reply: {}", reply))?;
+
+        let length = read_line(&mut reader).await?;
+        let length: usize = length.strip_prefix('$').and_then(|length| length.parse().ok()).ok_or(format!("Unexpected snapshot header: {}", length))?;
+        let mut snapshot = vec![0u8; length];
+        reader.read_exact(&mut snapshot).await.map_err(|err| format!("Cannot read snapshot: {}", err))?;
+        replication.load_full_sync(processor, replid, offset, &snapshot)?;
+        println!("[Replication] Full synchronization with master {}:{} done", host, port);
+    } else if let Some(rest) = reply.strip_prefix("+CONTINUE") {
+        replication.continue_sync(Some(rest.trim()).filter(|replid| !replid.is_empty()));
+        println!("[Replication] Partial resynchronization with master {}:{} accepted", host, port);
+    } else {
+        return Err(format!("Unexpected PSYNC reply: {}", reply));
+    }
+    replication.set_link_status(LinkStatus::Up);
+
+    let mut parser = MessageParser::new();
+    let mut raw: Vec<u8> = Vec::new();
+    let mut buf = vec![0u8; 16 * 1024];
+    let mut ack_interval = tokio::time::interval(ACK_INTERVAL);
+    loop {
+        let read = tokio::select! {
+            read = reader.read(&mut buf) => read.map_err(|err| format!("Cannot read from master: {}", err))?,
+            _ = ack_interval.tick() => {
+                send_ack(&mut writer, replication.offset()).await?;
+                continue;
+            }
+        };
+        if read == 0 {
+            return Ok(());
+        }
+
+        for &byte in &buf[..read] {
+            raw.push(byte);
+            let Some(message) = parser.add_byte(byte).map_err(|err| format!("Invalid replication stream: {}", err))? else {
+                continue;
+            };
+            if is_getack(&message) {
+                // acknowledged offset does not include GETACK itself
+                send_ack(&mut writer, replication.offset()).await?;
+                replication.feed(&raw);
+            } else {
+                replication.apply_from_master(processor, &message, &raw);
+            }
+            raw.clear();
+        }
+    }
+}
+
+fn is_getack(message: &Message) -> bool {
+    let Message::Array(Some(items)) = message else {
+        return false;
+    };
+    matches!(
+        (items.first().and_then(|item| item.as_str().ok()), items.get(1).and_then(|item| item.as_str().ok())),
+        (Some(command), Some(subcommand)) if command.eq_ignore_ascii_case("replconf") && subcommand.eq_ignore_ascii_case("getack")
+    )
+}
+
+async fn send_ack(writer: &mut OwnedWriteHalf, offset: u64) -> Result<(), String> {
+    send_command(writer, &["REPLCONF", "ACK", &offset.to_string()]).await
+}
+
+async fn send_command(writer: &mut OwnedWriteHalf, args: &[&str]) -> Result<(), String> {
+    let mut buf: Vec<u8> = Vec::new();
+    Message::array(args.iter().map(|arg| Message::bulk_string(arg)).collect())
+        .write_to(&mut buf)
+        .expect("Writing to Vec can't fail");
+    writer.write_all(&buf).await.map_err(|err| format!("Cannot write to master: {}", err))
+}
+
+async fn read_line(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>) -> Result<String, String> {
+    let mut line = String::new();
+    match reader.read_line(&mut line).await {
+        Ok(0) => Err("Master closed connection".to_string()),
+        Ok(_) => Ok(line.trim_end_matches("\r\n").to_string()),
+        Err(err) => Err(format!("Cannot read from master: {}", err)),
+    }
+}
+
+async fn handshake(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>, writer: &mut OwnedWriteHalf, args: &[&str], expected: &str) -> Result<(), String> {
+    send_command(writer, args).await?;
+    let reply = read_line(reader).await?;
+    if reply != expected {
+        return Err(format!("Unexpected reply to {}: {}", args[0], reply));
+    }
+    Ok(())
+}
@@ -2,7 +2,7 @@ use std::io::Write;
 
 use crate::processing_error::ProcessingError;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Message {
     Array(Option<Vec<Message>>),
     BulkString(Option<Vec<u8>>),
@@ -44,7 +44,7 @@ impl Message {
                 Ok(content)
             },
             _ => {
-                Err(ProcessingError::Other(format!("Invalid message type {} expected Integer", self.type_as_str())))
+                Err(ProcessingError::Other(format!("ERR Protocol error: invalid message type {}, expected BulkString", self.type_as_str())))
             }
         }
     }
@@ -56,13 +56,13 @@ impl Message {
                 Ok(text)
             },
             Self::BulkString(None) => {  
-                Err(ProcessingError::Other("Expected bulk string to contain something".to_string()))
+                Err(ProcessingError::Other("ERR Protocol error: expected bulk string to contain something".to_string()))
             },
             Self::SimpleString(content) => {  
                 Ok(content)
             },
             _ => {
-                Err(ProcessingError::Other(format!("Invalid message type {} expected BulkString or SimpleString", self.type_as_str())))
+                Err(ProcessingError::Other(format!("ERR Protocol error: invalid message type {}, expected BulkString or SimpleString", self.type_as_str())))
             }
         }
     }
@@ -80,7 +80,7 @@ impl Message {
             }
             Message::BulkString(Some(data)) => {
                 write!(writer, "${}\r\n", data.len())?;
-                writer.write_all(&data)?;
+                writer.write_all(data)?;
                 write!(writer, "\r\n")?;
             }
             Message::BulkString(None) => {
@@ -3,6 +3,11 @@ use core::str;
 use super::message::*;
 use super::parse_error::*;
 
+pub const DEFAULT_MAX_BULK_LEN: usize = 512 * 1024 * 1024;
+pub const DEFAULT_MAX_MULTIBULK_LEN: usize = 1024 * 1024;
+// length of type line (e.g. `*3\r\n` or `+OK\r\n`)
+const MAX_LINE_LENGTH: usize = 64 * 1024;
+
 #[derive(Debug)]
 enum State {
     ParseType,
@@ -33,10 +38,22 @@ pub struct MessageParser {
     state: State,
     message_type: MessageType,
     bulk_string_size: usize,
+    max_bulk_len: usize,
+    max_multibulk_len: usize,
+}
+
+impl Default for MessageParser {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl MessageParser {
     pub fn new() -> Self {
+        Self::with_limits(DEFAULT_MAX_BULK_LEN, DEFAULT_MAX_MULTIBULK_LEN)
+    }
+
+    pub fn with_limits(max_bulk_len: usize, max_multibulk_len: usize) -> Self {
         Self {
             array_stack: Vec::new(),
             buf: Vec::new(),
@@ -44,9 +61,16 @@ impl MessageParser {
             prev_byte: 0,
             state: State::ParseType,
             message_type: MessageType::Unknown,
+            max_bulk_len,
+            max_multibulk_len,
         }
     }
 
+    // amount of bytes received for partially parsed element
+    pub fn buffered_len(&self) -> usize {
+        self.buf.len()
+    }
+
     fn reset_state(&mut self) {
         self.array_stack.clear();
         self.buf.clear();
@@ -58,8 +82,17 @@ impl MessageParser {
 
     // returns Result<Some> when message is fully parsed
     // returns Result<None> when message is partially parsed
-    // returns Err<MessageParseError> in case of some error
+    // returns Err<MessageParseError> in case of some error,
+    // parser is reset after error and next byte is expected to start a new message
     pub fn add_byte(&mut self, byte: u8) -> Result<Option<Message>, ParseError> {
+        let result = self.process_byte(byte);
+        if result.is_err() {
+            self.reset_state();
+        }
+        result
+    }
+
+    fn process_byte(&mut self, byte: u8) -> Result<Option<Message>, ParseError> {
         match self.state {
             State::ParseType => {
                 match byte {
@@ -75,6 +108,9 @@ impl MessageParser {
             }
 
             State::ReadBuf => {
+                if self.buf.len() >= MAX_LINE_LENGTH {
+                    return Err(ParseError::LineTooLong);
+                }
                 self.buf.push(byte);
                 if self.is_line_end(byte) {
                     self.state = State::ParseType;
@@ -89,6 +125,9 @@ impl MessageParser {
 
             State::ReadBulkStringContent => {
                 if self.buf.len() == self.bulk_string_size {
+                    if byte != b'\r' {
+                        return Err(ParseError::ExpectedCrlf);
+                    }
                     self.state = State::AwaitBulkStringEnd;
                 } else {
                     self.buf.push(byte);
@@ -96,13 +135,14 @@ impl MessageParser {
             }
 
             State::AwaitBulkStringEnd => {
-                if self.is_line_end(byte) {
-                    self.state = State::ParseType;
-                    let parsed_item = Some(Message::BulkString(Some(self.buf.clone())));
-                    if let Some(result) = self.try_result(parsed_item) {
-                        self.reset_state();
-                        return Ok(Some(result));
-                    }
+                if !self.is_line_end(byte) {
+                    return Err(ParseError::ExpectedCrlf);
+                }
+                self.state = State::ParseType;
+                let parsed_item = Some(Message::BulkString(Some(self.buf.clone())));
+                if let Some(result) = self.try_result(parsed_item) {
+                    self.reset_state();
+                    return Ok(Some(result));
                 }
             }
         }
@@ -134,6 +174,9 @@ impl MessageParser {
             }
             MessageType::BulkString => match self.parse_buffer_as_int()? {
                 -1 => return Ok(Some(Message::BulkString(None))),
+                size if size < 0 || size as u64 > self.max_bulk_len as u64 => {
+                    return Err(ParseError::InvalidBulkLength);
+                }
                 size => {
                     self.bulk_string_size = size as usize;
                     self.buf.clear();
@@ -143,6 +186,9 @@ impl MessageParser {
             MessageType::Array => match self.parse_buffer_as_int()? {
                 -1 => return Ok(Some(Message::Array(None))),
                 0 => return Ok(Some(Message::array(Vec::new()))),
+                size if size < 0 || size as u64 > self.max_multibulk_len as u64 => {
+                    return Err(ParseError::InvalidMultibulkLength);
+                }
                 size => {
                     self.array_stack.push(ArrayStackItem {
                         items: Vec::new(),
@@ -167,15 +213,15 @@ impl MessageParser {
                 let array_message = Message::array(self.array_stack.pop().unwrap().items);
                 let result = self.process_parsed_item(array_message);
                 if result.is_some() {
-                    return result;
+                    result
                 } else {
-                    return None;
+                    None
                 }
             } else {
-                return None;
+                None
             }
         } else {
-            return Some(message);
+            Some(message)
         }
     }
 
@@ -312,4 +358,59 @@ mod tests {
             ])
         );
     }
+
+    fn parse_error_with(parser: &mut MessageParser, string: &str) -> ParseError {
+        for byte in string.as_bytes() {
+            if let Err(err) = parser.add_byte(*byte) {
+                return err;
+            }
+        }
+        panic!("Expected parse error for {:?}", string);
+    }
+
+    #[test]
+    fn reject_too_big_bulk_length() {
+        let mut parser = MessageParser::with_limits(10, 10);
+        assert!(matches!(parse_error_with(&mut parser, "$11\r\n"), ParseError::InvalidBulkLength));
+        assert!(matches!(parse_error_with(&mut parser, "$9999999999\r\n"), ParseError::InvalidBulkLength));
+    }
+
+    #[test]
+    fn reject_too_big_multibulk_length() {
+        let mut parser = MessageParser::with_limits(10, 10);
+        assert!(matches!(parse_error_with(&mut parser, "*11\r\n"), ParseError::InvalidMultibulkLength));
+        assert!(matches!(parse_error_with(&mut parser, "*100000000\r\n"), ParseError::InvalidMultibulkLength));
+    }
+
+    #[test]
+    fn reject_negative_lengths() {
+        let mut parser = MessageParser::new();
+        assert!(matches!(parse_error_with(&mut parser, "$-2\r\n"), ParseError::InvalidBulkLength));
+        assert!(matches!(parse_error_with(&mut parser, "*-5\r\n"), ParseError::InvalidMultibulkLength));
+    }
+
+    #[test]
+    fn reject_bulk_string_without_crlf() {
+        let mut parser = MessageParser::new();
+        assert!(matches!(parse_error_with(&mut parser, "$2\r\nabc\r\n"), ParseError::ExpectedCrlf));
+    }
+
+    #[test]
+    fn reject_too_long_line() {
+        let mut parser = MessageParser::new();
+        let line = format!("+{}", "a".repeat(MAX_LINE_LENGTH + 1));
+        assert!(matches!(parse_error_with(&mut parser, &line), ParseError::LineTooLong));
+    }
+
+    #[test]
+    fn parser_is_reset_after_error() {
+        let mut parser = MessageParser::new();
+        parse_error_with(&mut parser, "*2\r\n$3\r\nfoo\r\n$x\r\n");
+
+        let mut message = None;
+        for byte in "+OK\r\n".as_bytes() {
+            message = parser.add_byte(*byte).unwrap();
+        }
+        assert_eq!(message, Some(Message::simple_string("OK")));
+    }
 }
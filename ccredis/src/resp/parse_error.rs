@@ -3,6 +3,10 @@ pub enum ParseError {
     InvalidByte(u8), // When an unexpected byte is encountered
     InvalidUtf8,     // When there's an error decoding UTF-8
     InvalidInteger,  // When parsing an integer fails
+    InvalidBulkLength,      // Negative (except -1) or exceeding proto-max-bulk-len
+    InvalidMultibulkLength, // Negative (except -1) or exceeding proto-max-multibulk-len
+    LineTooLong,     // Type line is not terminated in reasonable amount of bytes
+    ExpectedCrlf,    // Bulk string content is not followed by \r\n
     Other(String),   // Generic error case with a custom message
 }
 
@@ -12,6 +16,10 @@ impl std::fmt::Display for ParseError {
             ParseError::InvalidByte(byte) => write!(f, "Invalid byte encountered: 0x{:02x}", byte),
             ParseError::InvalidUtf8 => write!(f, "Invalid UTF-8 sequence encountered"),
             ParseError::InvalidInteger => write!(f, "Invalid integer format encountered"),
+            ParseError::InvalidBulkLength => write!(f, "invalid bulk length"),
+            ParseError::InvalidMultibulkLength => write!(f, "invalid multibulk length"),
+            ParseError::LineTooLong => write!(f, "too big line"),
+            ParseError::ExpectedCrlf => write!(f, "expected CRLF after bulk string"),
             ParseError::Other(msg) => write!(f, "{}", msg),
         }
     }
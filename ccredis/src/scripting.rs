@@ -0,0 +1,408 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{atomic::{AtomicBool, Ordering}, Arc, Condvar, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use mlua::{HookTriggers, Lua, LuaOptions, StdLib, Table, Value, Variadic};
+use sha1::{Digest, Sha1};
+use tokio::sync::{OwnedRwLockReadGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::{config::Config, message_processor::commands, processing_error::ProcessingError, resp::message::Message};
+
+// how often running script checks whether it was killed
+const HOOK_INSTRUCTIONS: u32 = 1000;
+const KILLED: &str = "ERR Script killed by user with SCRIPT KILL...";
+// how often command from master or file checks whether running script has ended
+const WAIT_INTERVAL: Duration = Duration::from_millis(1);
+
+// Executes command called by script with redis.call/redis.pcall
+pub type Executor<'a> = dyn FnMut(&[Message]) -> Result<Message, ProcessingError> + 'a;
+
+struct RunningScript {
+    started_at: Instant,
+    // script which changed dataset can't be killed, it would leave partial changes
+    wrote: bool,
+}
+
+// Lua scripts for EVAL/EVALSHA, scripts are executed one at a time in one Lua state
+pub struct Scripting {
+    lua: Mutex<Lua>,
+    // sha1 of script body -> body
+    scripts: Mutex<HashMap<String, String>>,
+    // after this time other clients get BUSY and script can be killed
+    time_limit: Duration,
+    // set from the moment script waits for the gate until it ends, one script at a time
+    running: Mutex<Option<RunningScript>>,
+    // notified when script ends
+    finished: Condvar,
+    kill: Arc<AtomicBool>,
+    // written by running script and read by other commands, so a command never waits
+    // for shards locked by the script and gets BUSY instead
+    gate: Arc<RwLock<()>>,
+}
+
+// Script started with `Scripting::start`, other commands wait until it is dropped
+pub struct ScriptSlot<'a> {
+    scripting: &'a Scripting,
+    _gate: RwLockWriteGuard<'a, ()>,
+}
+
+impl Drop for ScriptSlot<'_> {
+    fn drop(&mut self) {
+        let mut running = self.scripting.running();
+        *running = None;
+        // SCRIPT KILL which came after the script finished must not kill the next one
+        self.scripting.kill.store(false, Ordering::SeqCst);
+        self.scripting.finished.notify_all();
+    }
+}
+
+impl Scripting {
+    pub fn new(config: &Config) -> Scripting {
+        // io and os libraries are not available to scripts, as in Redis
+        let lua = Lua::new_with(StdLib::TABLE | StdLib::STRING | StdLib::MATH, LuaOptions::default())
+            .expect("Lua state can be created");
+        lua.load(
+            "redis = {}
+            function redis.status_reply(status) return {ok = status} end
+            function redis.error_reply(err) return {err = err} end",
+        )
+        .exec()
+        .expect("redis library is valid Lua");
+
+        let kill = Arc::new(AtomicBool::new(false));
+        let killed = kill.clone();
+        lua.set_hook(HookTriggers::new().every_nth_instruction(HOOK_INSTRUCTIONS), move |_, _| {
+            if killed.load(Ordering::SeqCst) {
+                return Err(mlua::Error::RuntimeError(KILLED.to_string()));
+            }
+            Ok(())
+        });
+
+        Scripting {
+            lua: Mutex::new(lua),
+            scripts: Mutex::new(HashMap::new()),
+            time_limit: Duration::from_millis(config.lua_time_limit),
+            running: Mutex::new(None),
+            finished: Condvar::new(),
+            kill,
+            gate: Arc::new(RwLock::new(())),
+        }
+    }
+
+    fn scripts(&self) -> std::sync::MutexGuard<'_, HashMap<String, String>> {
+        self.scripts.lock().expect("Scripts lock poisoned")
+    }
+
+    fn running(&self) -> std::sync::MutexGuard<'_, Option<RunningScript>> {
+        self.running.lock().expect("Running script lock poisoned")
+    }
+
+    // SCRIPT LOAD, returns sha1 of the script
+    pub fn load(&self, body: &str) -> Result<String, ProcessingError> {
+        let lua = self.lua.lock().expect("Lua lock poisoned");
+        lua.load(body).set_name("@user_script").into_function().map_err(|err| compile_error(&err))?;
+        let sha = sha1_hex(body);
+        self.scripts().insert(sha.clone(), body.to_string());
+        Ok(sha)
+    }
+
+    pub fn exists(&self, sha: &str) -> bool {
+        self.scripts().contains_key(&sha.to_lowercase())
+    }
+
+    pub fn flush(&self) {
+        self.scripts().clear();
+    }
+
+    // SCRIPT KILL
+    pub fn kill(&self) -> Result<(), ProcessingError> {
+        match self.running().as_ref() {
+            None => Err("NOTBUSY No scripts in execution right now.".into()),
+            Some(script) if script.wrote => Err("UNKILLABLE Sorry the script already executed write commands against the dataset. You can either wait the script termination or kill the server in a hard way using the SHUTDOWN NOSAVE command.".into()),
+            Some(_) => {
+                self.kill.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+    }
+
+    // script running longer than time limit blocks other clients
+    pub fn check_busy(&self) -> Result<(), ProcessingError> {
+        match self.running().as_ref() {
+            Some(script) if script.started_at.elapsed() > self.time_limit => Err(ProcessingError::Busy),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running().is_some()
+    }
+
+    // Must be held while script runs, waits for the running script until it becomes busy.
+    // Scripts don't run on runtime workers, so they may block here.
+    pub fn start(&self) -> Result<ScriptSlot<'_>, ProcessingError> {
+        let mut running = self.running();
+        while let Some(script) = running.as_ref() {
+            let remaining = self.time_limit.saturating_sub(script.started_at.elapsed());
+            if remaining.is_zero() {
+                return Err(ProcessingError::Busy);
+            }
+            running = self.finished.wait_timeout(running, remaining).expect("Running script lock poisoned").0;
+        }
+        *running = Some(RunningScript { started_at: Instant::now(), wrote: false });
+        drop(running);
+        // readers of the gate never wait for anything while holding it
+        Ok(ScriptSlot { scripting: self, _gate: self.gate.blocking_write() })
+    }
+
+    // Command of a client waits for the running script in the connection task until it ends
+    // or becomes busy, the gate is held while the command runs
+    pub async fn enter(&self) -> Result<OwnedRwLockReadGuard<()>, ProcessingError> {
+        loop {
+            let busy_at = match self.running().as_ref() {
+                Some(script) => script.started_at + self.time_limit,
+                // script may start meanwhile, it is checked again later
+                None => Instant::now() + self.time_limit.max(WAIT_INTERVAL),
+            };
+            tokio::select! {
+                biased;
+                guard = self.gate.clone().read_owned() => return Ok(guard),
+                _ = tokio::time::sleep_until(busy_at.into()) => self.check_busy()?,
+            }
+        }
+    }
+
+    // Commands from master or file are never refused, they wait for the script to end
+    pub fn enter_blocking(&self) -> RwLockReadGuard<'_, ()> {
+        loop {
+            if let Ok(guard) = self.gate.try_read() {
+                return guard;
+            }
+            thread::sleep(WAIT_INTERVAL);
+        }
+    }
+
+    pub fn eval(&self, body: &str, keys: &[Message], argv: &[Message], execute: &mut Executor) -> Result<Message, ProcessingError> {
+        let sha = sha1_hex(body);
+        self.scripts().insert(sha.clone(), body.to_string());
+        self.run(&sha, body, keys, argv, execute)
+    }
+
+    pub fn evalsha(&self, sha: &str, keys: &[Message], argv: &[Message], execute: &mut Executor) -> Result<Message, ProcessingError> {
+        let sha = sha.to_lowercase();
+        let body = self.scripts().get(&sha).cloned().ok_or(ProcessingError::NoScript)?;
+        self.run(&sha, &body, keys, argv, execute)
+    }
+
+    fn run(&self, sha: &str, body: &str, keys: &[Message], argv: &[Message], execute: &mut Executor) -> Result<Message, ProcessingError> {
+        let lua = self.lua.lock().expect("Lua lock poisoned");
+        let function = lua.load(body).set_name("@user_script").into_function().map_err(|err| compile_error(&err))?;
+
+        let execute = RefCell::new(execute);
+        let result = lua.scope(|scope| {
+            let globals = lua.globals();
+            globals.set("KEYS", arguments_table(&lua, keys)?)?;
+            globals.set("ARGV", arguments_table(&lua, argv)?)?;
+            let redis: Table = globals.get("redis")?;
+            redis.set("call", scope.create_function(|lua, args: Variadic<Value>| {
+                match self.call(&mut **execute.borrow_mut(), &args)? {
+                    Message::Error(err) => Err(mlua::Error::external(ProcessingError::from(err))),
+                    reply => to_lua(lua, reply),
+                }
+            })?)?;
+            redis.set("pcall", scope.create_function(|lua, args: Variadic<Value>| {
+                to_lua(lua, self.call(&mut **execute.borrow_mut(), &args)?)
+            })?)?;
+            function.call::<_, Value>(())
+        });
+        let killed = self.kill.swap(false, Ordering::SeqCst);
+
+        match result {
+            Ok(value) => Ok(from_lua(value)),
+            Err(_) if killed => Err(KILLED.into()),
+            Err(err) => Err(runtime_error(sha, &err)),
+        }
+    }
+
+    // errors of commands are returned as error replies, arguments errors are raised
+    fn call(&self, execute: &mut Executor, args: &[Value]) -> mlua::Result<Message> {
+        let parts: Option<Vec<Message>> = args.iter().map(argument_to_message).collect();
+        let parts = parts
+            .filter(|parts| !parts.is_empty())
+            .ok_or_else(|| mlua::Error::external(ProcessingError::from("ERR Lua redis lib command arguments must be strings or integers")))?;
+
+        let reply = execute(&parts).unwrap_or_else(|err| Message::Error(err.to_string()));
+        let name = parts[0].as_str().map(|name| name.to_lowercase()).unwrap_or_default();
+        let subcommand = parts.get(1).and_then(|arg| arg.as_str().ok());
+        if !matches!(reply, Message::Error(_)) && commands::find(&name, subcommand).is_some_and(|spec| spec.has_flag("write")) {
+            if let Some(script) = self.running().as_mut() {
+                script.wrote = true;
+            }
+        }
+        Ok(reply)
+    }
+}
+
+pub fn sha1_hex(body: &str) -> String {
+    Sha1::digest(body.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn arguments_table<'lua>(lua: &'lua Lua, arguments: &[Message]) -> mlua::Result<Table<'lua>> {
+    let contents: Vec<&[u8]> = arguments.iter().map(|arg| arg.extract_bulk_content().map_or(&[][..], |arg| arg)).collect();
+    lua.create_sequence_from(contents.into_iter().map(|arg| lua.create_string(arg)).collect::<mlua::Result<Vec<_>>>()?)
+}
+
+fn argument_to_message(value: &Value) -> Option<Message> {
+    match value {
+        Value::String(string) => Some(Message::BulkString(Some(string.as_bytes().to_vec()))),
+        Value::Integer(integer) => Some(Message::bulk_string(&integer.to_string())),
+        Value::Number(number) if number.fract() == 0.0 && number.abs() < 1e17 => Some(Message::bulk_string(&(*number as i64).to_string())),
+        Value::Number(number) => Some(Message::bulk_string(&number.to_string())),
+        _ => None,
+    }
+}
+
+// RESP -> Lua conversion rules of Redis
+fn to_lua(lua: &Lua, message: Message) -> mlua::Result<Value<'_>> {
+    Ok(match message {
+        Message::Integer(integer) => Value::Integer(integer),
+        Message::BulkString(Some(content)) => Value::String(lua.create_string(&content)?),
+        Message::BulkString(None) | Message::Array(None) => Value::Boolean(false),
+        Message::Array(Some(items)) => {
+            let values: mlua::Result<Vec<Value>> = items.into_iter().map(|item| to_lua(lua, item)).collect();
+            Value::Table(lua.create_sequence_from(values?)?)
+        },
+        Message::SimpleString(status) => {
+            let table = lua.create_table()?;
+            table.set("ok", status)?;
+            Value::Table(table)
+        },
+        Message::Error(err) => {
+            let table = lua.create_table()?;
+            table.set("err", err)?;
+            Value::Table(table)
+        },
+    })
+}
+
+// Lua -> RESP conversion rules of Redis
+fn from_lua(value: Value) -> Message {
+    match value {
+        Value::Boolean(true) => Message::Integer(1),
+        Value::Integer(integer) => Message::Integer(integer),
+        Value::Number(number) => Message::Integer(number as i64),
+        Value::String(string) => Message::BulkString(Some(string.as_bytes().to_vec())),
+        Value::Table(table) => {
+            if let Ok(err) = table.raw_get::<_, mlua::String>("err") {
+                return Message::Error(err.to_string_lossy().into_owned());
+            }
+            if let Ok(status) = table.raw_get::<_, mlua::String>("ok") {
+                return Message::SimpleString(status.to_string_lossy().into_owned());
+            }
+            // array ends at the first nil, as in Redis
+            Message::array(table.sequence_values::<Value>().map_while(Result::ok).map(from_lua).collect())
+        },
+        _ => Message::BulkString(None),
+    }
+}
+
+fn compile_error(err: &mlua::Error) -> ProcessingError {
+    format!("ERR Error compiling script (new function): {}", first_line(err)).into()
+}
+
+// errors of redis.call are replied as they are, other errors get script context
+fn runtime_error(sha: &str, err: &mlua::Error) -> ProcessingError {
+    match err {
+        mlua::Error::CallbackError { cause, .. } => runtime_error(sha, cause),
+        mlua::Error::ExternalError(external) => match external.downcast_ref::<ProcessingError>() {
+            Some(err) => err.to_string().into(),
+            None => format!("ERR Error running script (call to f_{}): {}", sha, external).into(),
+        },
+        err => format!("ERR Error running script (call to f_{}): {}", sha, first_line(err)).into(),
+    }
+}
+
+// Lua errors carry stack traceback on the next lines
+fn first_line(err: &mlua::Error) -> String {
+    let message = match err {
+        mlua::Error::SyntaxError { message, .. } => message.clone(),
+        mlua::Error::RuntimeError(message) => message.clone(),
+        err => err.to_string(),
+    };
+    message.lines().next().unwrap_or_default().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(scripting: &Scripting, body: &str, execute: &mut Executor) -> Result<Message, ProcessingError> {
+        scripting.eval(body, &[Message::bulk_string("key")], &[Message::bulk_string("arg")], execute)
+    }
+
+    fn echo(parts: &[Message]) -> Result<Message, ProcessingError> {
+        match parts[0].as_str()? {
+            "FAIL" => Err(ProcessingError::WrongType),
+            "STATUS" => Ok(Message::simple_string("OK")),
+            _ => Ok(Message::array(parts.to_vec())),
+        }
+    }
+
+    #[test]
+    fn test_type_conversions() {
+        let scripting = Scripting::new(&Config::default());
+        let replies = [
+            ("return 1.9", Message::Integer(1)),
+            ("return true", Message::Integer(1)),
+            ("return false", Message::BulkString(None)),
+            ("return nil", Message::BulkString(None)),
+            ("return 'text'", Message::bulk_string("text")),
+            ("return {1, 'a', {2}, nil, 3}", Message::array(vec![Message::Integer(1), Message::bulk_string("a"), Message::array(vec![Message::Integer(2)])])),
+            ("return redis.status_reply('FINE')", Message::simple_string("FINE")),
+            ("return redis.error_reply('ERR my error')", Message::error("ERR my error")),
+            ("return {KEYS[1], ARGV[1]}", Message::array(vec![Message::bulk_string("key"), Message::bulk_string("arg")])),
+            ("return redis.call('ECHO', 5, 'a')", Message::array(vec![Message::bulk_string("ECHO"), Message::bulk_string("5"), Message::bulk_string("a")])),
+            ("return redis.call('STATUS').ok", Message::bulk_string("OK")),
+            ("return redis.pcall('FAIL').err", Message::bulk_string("WRONGTYPE Operation against a key holding the wrong kind of value")),
+        ];
+        for (body, expected) in replies {
+            assert_eq!(eval(&scripting, body, &mut echo).unwrap(), expected, "{}", body);
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let scripting = Scripting::new(&Config::default());
+        let err = eval(&scripting, "return redis.call('FAIL')", &mut echo).unwrap_err();
+        assert_eq!(err.to_string(), "WRONGTYPE Operation against a key holding the wrong kind of value");
+
+        let err = eval(&scripting, "error('boom')", &mut echo).unwrap_err().to_string();
+        assert!(err.starts_with(&format!("ERR Error running script (call to f_{}): user_script:1: boom", sha1_hex("error('boom')"))), "{}", err);
+
+        let err = eval(&scripting, "return (", &mut echo).unwrap_err().to_string();
+        assert!(err.starts_with("ERR Error compiling script (new function): user_script:1:"), "{}", err);
+
+        let err = eval(&scripting, "return redis.call({})", &mut echo).unwrap_err().to_string();
+        assert_eq!(err, "ERR Lua redis lib command arguments must be strings or integers");
+
+        assert!(matches!(scripting.evalsha("ffffffffffffffffffffffffffffffffffffffff", &[], &[], &mut echo), Err(ProcessingError::NoScript)));
+        assert!(scripting.kill().is_err());
+    }
+
+    #[test]
+    fn test_script_cache() {
+        let scripting = Scripting::new(&Config::default());
+        let sha = scripting.load("return ARGV[1]").unwrap();
+        assert_eq!(sha, "098e0f0d1448c0a81dafe820f66d460eb09263da");
+        assert!(scripting.exists(&sha.to_uppercase()));
+        assert_eq!(scripting.evalsha(&sha, &[], &[Message::bulk_string("a")], &mut echo).unwrap(), Message::bulk_string("a"));
+
+        scripting.flush();
+        assert!(!scripting.exists(&sha));
+        assert!(scripting.load("return (").is_err());
+    }
+}
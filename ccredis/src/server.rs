@@ -0,0 +1,426 @@
+use std::{
+    fmt,
+    fs,
+    io,
+    net::SocketAddr,
+    sync::{atomic::{AtomicBool, Ordering}, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::{net::TcpListener, runtime::Runtime, task::JoinSet};
+
+use crate::{
+    acl::Acl,
+    client_registry::{ClientRegistry, PauseMode},
+    cluster::{self, Cluster},
+    config::Config,
+    connection::ConnectedClients,
+    db_file::{self, Corruption, DbFile},
+    expire,
+    keyspace::Keyspace,
+    latency::Latency,
+    listener,
+    message_processor::{now, MessageProcessor, SharedMemory},
+    pubsub::PubSub,
+    replication::Replication,
+    resp::message::Message,
+    scripting::Scripting,
+    shutdown::{Shutdown, ShutdownOptions},
+    slowlog::SlowLog,
+    stats::Stats,
+    tls,
+};
+
+// time given to running tasks to finish on shutdown
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+// how often SHUTDOWN ABORT is checked while waiting for replicas
+const REPLICA_WAIT_INTERVAL_MS: u64 = 100;
+// writes stay paused after replicas are waited for, until snapshot is saved
+const SAVE_PAUSE_MARGIN_MS: u128 = 60 * 1000;
+
+// Server running in its own tokio runtime, e.g.
+// `Server::builder().bind("127.0.0.1:0").persistence("db.txt").start()`
+pub struct Server;
+
+impl Server {
+    pub fn builder() -> ServerBuilder {
+        ServerBuilder { config: Config::default(), bind: None, db_file_path: None }
+    }
+}
+
+pub struct ServerBuilder {
+    config: Config,
+    bind: Option<String>,
+    db_file_path: Option<String>,
+}
+
+impl ServerBuilder {
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    // TCP address instead of `bind` and `port` from config, port 0 picks a free one
+    pub fn bind(mut self, addr: impl Into<String>) -> Self {
+        self.bind = Some(addr.into());
+        self
+    }
+
+    // file loaded on start and written by SAVE and on shutdown,
+    // without it nothing is loaded and SAVE writes to db.txt
+    pub fn persistence(mut self, db_file_path: impl Into<String>) -> Self {
+        self.db_file_path = Some(db_file_path.into());
+        self
+    }
+
+    pub fn start(self) -> Result<ServerHandle, StartError> {
+        let mut config = self.config;
+        let acl = Arc::new(Acl::new(config.requirepass.as_deref(), config.aclfile.as_deref()).map_err(StartError::Acl)?);
+        let tls_acceptor = match config.tls_port {
+            0 => None,
+            _ => Some(tls::create_acceptor(&config).map_err(StartError::Tls)?),
+        };
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(config.io_threads).enable_all().build()?;
+
+        // bound first, so the port picked by OS is known to INFO, replication and cluster
+        let tcp_listener = runtime.block_on(async {
+            match &self.bind {
+                Some(addr) => TcpListener::bind(addr.as_str()).await.map(Some),
+                None if config.port != 0 => TcpListener::bind((config.bind.as_str(), config.port)).await.map(Some),
+                None => Ok(None),
+            }
+        })?;
+        let local_addr = tcp_listener.as_ref().map(|listener| listener.local_addr()).transpose()?;
+        if let Some(addr) = local_addr {
+            config.bind = addr.ip().to_string();
+            config.port = addr.port();
+        }
+        let config = Arc::new(config);
+
+        let memory: SharedMemory = Arc::new(Keyspace::from_config(&config));
+        if let Some(db_file_path) = &self.db_file_path {
+            load(memory.clone(), db_file_path, config.clone())?;
+        }
+        let replication = Arc::new(Replication::new(&config));
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        {
+            let memory = memory.clone();
+            let replication = replication.clone();
+            let stopped = stopped.clone();
+            let hz = config.hz;
+            thread::spawn(move || {
+                key_expirer_worker(memory, replication, hz, stopped);
+            });
+        }
+
+        let message_processor = MessageProcessor {
+            memory,
+            db_file_path: self.db_file_path.clone(),
+            clients: Arc::new(ClientRegistry::default()),
+            acl,
+            replication: replication.clone(),
+            cluster: config.cluster_enabled.then(|| Arc::new(Cluster::new(&config))),
+            slowlog: Arc::new(SlowLog::new(&config)),
+            latency: Arc::new(Latency::new(&config)),
+            scripting: Arc::new(Scripting::new(&config)),
+            config: config.clone(),
+            stats: Arc::new(Stats::default()),
+            shutdown: Arc::new(Shutdown::default()),
+            pubsub: Arc::new(PubSub::default()),
+            client: None,
+        };
+
+        let mut handle = ServerHandle {
+            runtime: None,
+            local_addr,
+            listeners: JoinSet::new(),
+            stopped,
+            message_processor: message_processor.clone(),
+            pid_file: config.pidfile.as_deref().and_then(PidFile::create),
+            #[cfg(unix)]
+            unix_socket_file: None,
+        };
+        let started = runtime.block_on(async {
+            if let Some((host, port)) = &config.replicaof {
+                replication.replicate_from(host, *port, message_processor.clone());
+            }
+
+            let connected_clients = ConnectedClients::default();
+            if let Some(listener) = tcp_listener {
+                println!("[TCP] Listening on {}", listener.local_addr()?);
+                handle.listeners.spawn(listener::serve_tcp(listener, None, message_processor.clone(), config.clone(), connected_clients.clone()));
+            }
+
+            if let Some(acceptor) = tls_acceptor {
+                let listener = TcpListener::bind((config.bind.as_str(), config.tls_port)).await?;
+                println!("[TLS] Listening on {}", listener.local_addr()?);
+                handle.listeners.spawn(listener::serve_tcp(listener, Some(acceptor), message_processor.clone(), config.clone(), connected_clients.clone()));
+            }
+
+            #[cfg(unix)]
+            if let Some(path) = &config.unixsocket {
+                let (listener, socket_file) = listener::bind_unix(path, config.unixsocketperm)?;
+                println!("[Unix] Listening on {}", path);
+                handle.listeners.spawn(listener::serve_unix(listener, path.clone(), message_processor.clone(), config.clone(), connected_clients.clone()));
+                handle.unix_socket_file = Some(socket_file);
+            }
+
+            if let Some(cluster) = &message_processor.cluster {
+                let listener = TcpListener::bind((config.bind.as_str(), config.cluster_bus_port())).await?;
+                println!("[Cluster] Node {} listening for cluster bus on {}", cluster.myself, listener.local_addr()?);
+                handle.listeners.spawn(cluster::serve_bus(cluster.clone(), listener));
+                tokio::spawn(cluster::run_cron(cluster.clone()));
+            }
+            Ok::<(), io::Error>(())
+        });
+        handle.runtime = Some(runtime);
+        started?;
+
+        if handle.listeners.is_empty() {
+            return Err(StartError::NothingToListen);
+        }
+        Ok(handle)
+    }
+}
+
+#[derive(Debug)]
+pub enum StartError {
+    Acl(String),
+    Tls(String),
+    NothingToListen,
+    // db file path and where it is damaged
+    Load(String, Corruption),
+    Io(io::Error),
+}
+
+impl fmt::Display for StartError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StartError::Acl(err) => write!(f, "[ACL] {}", err),
+            StartError::Tls(err) => write!(f, "[TLS] {}", err),
+            StartError::NothingToListen => write!(f, "[Config] Neither port, tls-port nor unixsocket is set, nothing to listen on"),
+            StartError::Load(path, corruption) => write!(
+                f,
+                "[Load] Bad file format reading {} {}. Start with --load-truncated yes to keep commands before it or repair the file with ccredis-check-db --fix",
+                path, corruption
+            ),
+            StartError::Io(err) => write!(f, "[Server] {}", err),
+        }
+    }
+}
+
+impl std::error::Error for StartError {}
+
+impl From<io::Error> for StartError {
+    fn from(err: io::Error) -> Self {
+        StartError::Io(err)
+    }
+}
+
+// Running server, stopped by `shutdown()` or when dropped
+pub struct ServerHandle {
+    runtime: Option<Runtime>,
+    local_addr: Option<SocketAddr>,
+    listeners: JoinSet<()>,
+    // stops key expirer thread
+    stopped: Arc<AtomicBool>,
+    message_processor: MessageProcessor,
+    pid_file: Option<PidFile>,
+    #[cfg(unix)]
+    unix_socket_file: Option<listener::UnixSocketFile>,
+}
+
+impl ServerHandle {
+    // address of plain TCP listener, None when only TLS or unix socket is used
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    // Blocks until shutdown requested by SHUTDOWN, SIGINT or SIGTERM succeeds or all listeners stop.
+    // Returns exit status: 0, or 1 when snapshot wasn't saved but shutdown was forced.
+    pub fn wait(&mut self) -> i32 {
+        let Some(runtime) = &self.runtime else {
+            return 0;
+        };
+        let listeners = &mut self.listeners;
+        let processor = &self.message_processor;
+        let config = &processor.config;
+        runtime.block_on(async {
+            let mut terminate = terminate_signal();
+            loop {
+                tokio::select! {
+                    _ = async { while listeners.join_next().await.is_some() {} } => return 0,
+                    options = processor.shutdown.requested() => {
+                        if let Some(code) = perform_shutdown(processor, options) {
+                            return code;
+                        }
+                    },
+                    _ = tokio::signal::ctrl_c() => {
+                        println!("[Server] Received SIGINT scheduling shutdown...");
+                        processor.shutdown.request(config.shutdown_on_sigint);
+                    },
+                    _ = terminated(&mut terminate) => {
+                        println!("[Server] Received SIGTERM scheduling shutdown...");
+                        processor.shutdown.request(config.shutdown_on_sigterm);
+                    },
+                }
+            }
+        })
+    }
+
+    // Saves snapshot when persistence is configured, stops accepting connections and closes connected ones
+    pub fn shutdown(mut self) {
+        if self.runtime.is_some() && !self.message_processor.shutdown.is_exiting() {
+            perform_shutdown(&self.message_processor, ShutdownOptions { force: true, ..ShutdownOptions::default() });
+        }
+        self.stop();
+    }
+
+    fn stop(&mut self) {
+        let Some(runtime) = self.runtime.take() else {
+            return;
+        };
+        self.listeners.abort_all();
+        self.stopped.store(true, Ordering::Relaxed);
+        // connection tasks are dropped at their next await, between commands
+        runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+        #[cfg(unix)]
+        self.unix_socket_file.take();
+        self.pid_file.take();
+        println!("[Server] Stopped");
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Holds writes, waits for replicas to catch up and saves the final snapshot.
+// None when it failed or was aborted, then server keeps running.
+fn perform_shutdown(processor: &MessageProcessor, options: ShutdownOptions) -> Option<i32> {
+    let shutdown = &processor.shutdown;
+    let timeout = Duration::from_secs(processor.config.shutdown_timeout);
+    println!("[Server] User requested shutdown...");
+    processor.clients.pause(now() + timeout.as_millis() + SAVE_PAUSE_MARGIN_MS, PauseMode::Write);
+
+    let deadline = Instant::now() + timeout;
+    while !options.now && !shutdown.is_aborted() && Instant::now() < deadline {
+        // replicas may disconnect while we wait
+        let replicas = processor.replication.replica_count();
+        if replicas == 0 || processor.replication.wait(replicas, REPLICA_WAIT_INTERVAL_MS) >= replicas {
+            break;
+        }
+    }
+    if shutdown.is_aborted() {
+        println!("[Server] Shutdown aborted");
+        return cancel_shutdown(processor);
+    }
+
+    let mut code = 0;
+    // final snapshot is skipped without persistence, unless SHUTDOWN SAVE asks for it and fails
+    if options.save.unwrap_or(processor.db_file_path.is_some()) {
+        println!("[Server] Saving the final snapshot before exiting");
+        if let Err(err) = processor.command_save() {
+            println!("[Server] Error trying to save the DB: {}", err);
+            if !options.force {
+                println!("[Server] Errors trying to shut down the server. Check the logs for more information.");
+                return cancel_shutdown(processor);
+            }
+            code = 1;
+        }
+    }
+    shutdown.finish(true);
+    println!("[Server] Ready to exit, bye bye...");
+    Some(code)
+}
+
+fn cancel_shutdown(processor: &MessageProcessor) -> Option<i32> {
+    processor.clients.unpause();
+    processor.shutdown.finish(false);
+    None
+}
+
+#[cfg(unix)]
+type TerminateSignal = Option<tokio::signal::unix::Signal>;
+#[cfg(not(unix))]
+type TerminateSignal = ();
+
+fn terminate_signal() -> TerminateSignal {
+    #[cfg(unix)]
+    return tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).ok();
+}
+
+// resolves on SIGTERM, never on other platforms
+async fn terminated(signal: &mut TerminateSignal) {
+    #[cfg(unix)]
+    if let Some(signal) = signal {
+        signal.recv().await;
+        return;
+    }
+    let _ = signal;
+    std::future::pending::<()>().await
+}
+
+// File with process id, removed when server stops
+struct PidFile(String);
+
+impl PidFile {
+    fn create(path: &str) -> Option<PidFile> {
+        match fs::write(path, format!("{}\n", std::process::id())) {
+            Ok(()) => Some(PidFile(path.to_string())),
+            Err(err) => {
+                println!("[Server] Failed to write PID file {}: {}", path, err);
+                None
+            },
+        }
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+// Commands before the first damaged one are kept only with `load-truncated`, otherwise start fails.
+// Loaded values are encoded according to config.
+fn load(memory: SharedMemory, db_file_path: &str, config: Arc<Config>) -> Result<(), StartError> {
+    let bytes = match fs::read(db_file_path) {
+        Ok(bytes) => bytes,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err.into()),
+    };
+    let mut file = DbFile::parse(bytes);
+    if file.corruption.is_none() || config.load_truncated {
+        db_file::replay(&mut file, memory, config.clone());
+    }
+    match file.corruption {
+        None => println!("[Load] Memory loaded from file"),
+        Some(corruption) if config.load_truncated => {
+            println!("[Load] Bad file format reading {} {}, loaded {} commands before it", db_file_path, corruption, file.records.len());
+        },
+        Some(corruption) => return Err(StartError::Load(db_file_path.to_string(), corruption)),
+    }
+    Ok(())
+}
+
+fn key_expirer_worker(memory: SharedMemory, replication: Arc<Replication>, hz: u64, stopped: Arc<AtomicBool>) {
+    let interval = Duration::from_millis(1000 / hz);
+    let time_limit = interval * expire::CYCLE_TIME_PERCENT / 100;
+    while !stopped.load(Ordering::Relaxed) {
+        thread::sleep(interval);
+        // replica removes expired keys when master sends DEL
+        if replication.is_replica() {
+            continue;
+        }
+
+        // DEL is propagated while shard is locked, so it can't overtake a new write to the key
+        expire::active_expire_cycle(&memory, time_limit, |key| {
+            replication.propagate(&[Message::bulk_string("DEL"), Message::bulk_string(key)]);
+        });
+    }
+}
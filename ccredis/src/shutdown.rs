@@ -0,0 +1,173 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Condvar, Mutex, MutexGuard,
+};
+
+use tokio::sync::Notify;
+
+use crate::processing_error::ProcessingError;
+
+// Flags of SHUTDOWN command, also used by shutdown-on-sigint and shutdown-on-sigterm
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ShutdownOptions {
+    // None saves snapshot when persistence is configured
+    pub save: Option<bool>,
+    // don't wait for lagging replicas
+    pub now: bool,
+    // exit even when snapshot can't be saved
+    pub force: bool,
+}
+
+impl ShutdownOptions {
+    // e.g. `NOSAVE NOW`, `default` is accepted only in config
+    pub fn parse<'a>(words: impl IntoIterator<Item = &'a str>, allow_default: bool) -> Option<ShutdownOptions> {
+        let mut options = ShutdownOptions::default();
+        for word in words {
+            match word.to_lowercase().as_str() {
+                "save" if options.save.is_none() => options.save = Some(true),
+                "nosave" if options.save.is_none() => options.save = Some(false),
+                "now" => options.now = true,
+                "force" => options.force = true,
+                "default" if allow_default => {},
+                _ => return None,
+            }
+        }
+        Some(options)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Running,
+    Requested(ShutdownOptions),
+    InProgress,
+    // snapshot is saved, connections are being closed
+    Exiting,
+}
+
+// Coordinates shutdown between SHUTDOWN command, signals and the server which performs it.
+// Clients which requested shutdown are blocked until it fails or server exits.
+pub struct Shutdown {
+    phase: Mutex<Phase>,
+    changed: Condvar,
+    requested: Notify,
+    aborted: AtomicBool,
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Shutdown { phase: Mutex::new(Phase::Running), changed: Condvar::new(), requested: Notify::new(), aborted: AtomicBool::new(false) }
+    }
+}
+
+impl Shutdown {
+    fn phase(&self) -> MutexGuard<'_, Phase> {
+        self.phase.lock().expect("Shutdown lock poisoned")
+    }
+
+    // joins shutdown already in progress
+    pub fn request(&self, options: ShutdownOptions) {
+        let mut phase = self.phase();
+        if *phase == Phase::Running {
+            *phase = Phase::Requested(options);
+            self.aborted.store(false, Ordering::SeqCst);
+            self.requested.notify_one();
+        }
+    }
+
+    // SHUTDOWN blocks until server exits (Ok) or shutdown fails or is aborted
+    pub fn request_and_wait(&self, options: ShutdownOptions) -> Result<(), ProcessingError> {
+        self.request(options);
+        let mut phase = self.phase();
+        loop {
+            match *phase {
+                Phase::Running => return Err("ERR Errors trying to SHUTDOWN. Check logs.".into()),
+                Phase::Exiting => return Ok(()),
+                _ => phase = self.changed.wait(phase).expect("Shutdown lock poisoned"),
+            }
+        }
+    }
+
+    // resolves with options of the next request, shutdown is in progress after that
+    pub async fn requested(&self) -> ShutdownOptions {
+        loop {
+            let notified = self.requested.notified();
+            {
+                let mut phase = self.phase();
+                if let Phase::Requested(options) = *phase {
+                    *phase = Phase::InProgress;
+                    return options;
+                }
+            }
+            notified.await;
+        }
+    }
+
+    // SHUTDOWN ABORT, false when there is no shutdown to abort
+    pub fn abort(&self) -> bool {
+        let phase = self.phase();
+        if matches!(*phase, Phase::Requested(_) | Phase::InProgress) {
+            self.aborted.store(true, Ordering::SeqCst);
+            return true;
+        }
+        false
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.aborted.load(Ordering::SeqCst)
+    }
+
+    pub fn is_exiting(&self) -> bool {
+        *self.phase() == Phase::Exiting
+    }
+
+    // called by the server when shutdown is done or failed
+    pub fn finish(&self, exiting: bool) {
+        *self.phase() = if exiting { Phase::Exiting } else { Phase::Running };
+        self.changed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(ShutdownOptions::parse([], false), Some(ShutdownOptions::default()));
+        assert_eq!(ShutdownOptions::parse(["NOSAVE", "now"], false), Some(ShutdownOptions { save: Some(false), now: true, force: false }));
+        assert_eq!(ShutdownOptions::parse(["save", "force"], false), Some(ShutdownOptions { save: Some(true), now: false, force: true }));
+        assert_eq!(ShutdownOptions::parse(["save", "nosave"], false), None);
+        assert_eq!(ShutdownOptions::parse(["default"], false), None);
+        assert_eq!(ShutdownOptions::parse(["default"], true), Some(ShutdownOptions::default()));
+    }
+
+    #[test]
+    fn test_abort() {
+        let shutdown = Shutdown::default();
+        assert!(!shutdown.abort());
+        shutdown.request(ShutdownOptions::default());
+        assert!(shutdown.abort());
+        assert!(shutdown.is_aborted());
+        shutdown.finish(false);
+        assert!(!shutdown.abort());
+        assert_eq!(shutdown.request_and_wait_after(|shutdown| shutdown.finish(false)), Err("ERR Errors trying to SHUTDOWN. Check logs.".to_string()));
+        assert_eq!(shutdown.request_and_wait_after(|shutdown| shutdown.finish(true)), Ok(()));
+        assert!(shutdown.is_exiting());
+    }
+
+    impl Shutdown {
+        // finishes shutdown from another thread while this one waits
+        fn request_and_wait_after(&self, finish: fn(&Shutdown)) -> Result<(), String> {
+            std::thread::scope(|scope| {
+                scope.spawn(|| {
+                    while !matches!(*self.phase(), Phase::Requested(_)) {
+                        std::thread::yield_now();
+                    }
+                    finish(self);
+                });
+                self.request_and_wait(ShutdownOptions::default()).map_err(|err| err.to_string())
+            })
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    collections::VecDeque,
+    sync::{atomic::{AtomicU64, Ordering}, Mutex},
+    time::Duration,
+};
+
+use crate::{client_registry::Client, config::Config, message_processor::now, resp::message::Message};
+
+// the same limits as in Redis, so long commands don't blow up the log
+const MAX_ARGS: usize = 32;
+const MAX_ARG_LEN: usize = 128;
+
+struct Entry {
+    id: u64,
+    // unix time in seconds
+    timestamp: u64,
+    duration_usec: u64,
+    args: Vec<Vec<u8>>,
+    client_addr: String,
+    client_name: String,
+}
+
+// Commands which took longer than `slowlog-log-slower-than`, newest first,
+// at most `slowlog-max-len` of them.
+pub struct SlowLog {
+    entries: Mutex<VecDeque<Entry>>,
+    next_id: AtomicU64,
+    // microseconds, negative disables the log
+    log_slower_than: i64,
+    max_len: usize,
+}
+
+impl SlowLog {
+    pub fn new(config: &Config) -> SlowLog {
+        SlowLog {
+            entries: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(0),
+            log_slower_than: config.slowlog_log_slower_than,
+            max_len: config.slowlog_max_len,
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, VecDeque<Entry>> {
+        self.entries.lock().expect("Slowlog lock poisoned")
+    }
+
+    pub fn record(&self, duration: Duration, args: &[Message], client: Option<&Client>) {
+        let duration_usec = duration.as_micros() as u64;
+        if self.log_slower_than < 0 || duration_usec < self.log_slower_than as u64 || self.max_len == 0 {
+            return;
+        }
+
+        let mut logged_args: Vec<Vec<u8>> = args.iter().take(MAX_ARGS).map(|arg| truncate(arg_bytes(arg))).collect();
+        if args.len() > MAX_ARGS {
+            // last slot tells how many arguments are missing
+            logged_args[MAX_ARGS - 1] = format!("... ({} more arguments)", args.len() - MAX_ARGS + 1).into_bytes();
+        }
+        let entry = Entry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: (now() / 1000) as u64,
+            duration_usec,
+            args: logged_args,
+            client_addr: client.map(|client| client.addr.clone()).unwrap_or_default(),
+            client_name: client.and_then(|client| client.name()).unwrap_or_default(),
+        };
+
+        let mut entries = self.entries();
+        entries.push_front(entry);
+        entries.truncate(self.max_len);
+    }
+
+    // SLOWLOG GET [count], -1 returns all entries
+    pub fn get(&self, count: Option<i64>) -> Message {
+        let count = match count {
+            Some(count) if count >= 0 => count as usize,
+            Some(_) => usize::MAX,
+            None => 10,
+        };
+        let entries = self.entries();
+        let replies = entries
+            .iter()
+            .take(count)
+            .map(|entry| {
+                Message::array(vec![
+                    Message::Integer(entry.id as i64),
+                    Message::Integer(entry.timestamp as i64),
+                    Message::Integer(entry.duration_usec as i64),
+                    Message::array(entry.args.iter().map(|arg| Message::BulkString(Some(arg.clone()))).collect()),
+                    Message::bulk_string(&entry.client_addr),
+                    Message::bulk_string(&entry.client_name),
+                ])
+            })
+            .collect();
+        Message::array(replies)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    pub fn reset(&self) {
+        self.entries().clear();
+    }
+}
+
+fn arg_bytes(arg: &Message) -> Vec<u8> {
+    match arg {
+        Message::BulkString(Some(content)) => content.clone(),
+        Message::SimpleString(content) => content.clone().into_bytes(),
+        Message::Integer(integer) => integer.to_string().into_bytes(),
+        _ => Vec::new(),
+    }
+}
+
+fn truncate(mut arg: Vec<u8>) -> Vec<u8> {
+    if arg.len() > MAX_ARG_LEN {
+        let more = arg.len() - MAX_ARG_LEN;
+        arg.truncate(MAX_ARG_LEN);
+        arg.extend(format!("... ({} more bytes)", more).into_bytes());
+    }
+    arg
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_slowlog(log_slower_than: i64, max_len: usize) -> SlowLog {
+        SlowLog::new(&Config { slowlog_log_slower_than: log_slower_than, slowlog_max_len: max_len, ..Config::default() })
+    }
+
+    fn args(command: &str) -> Vec<Message> {
+        command.split(' ').map(Message::bulk_string).collect()
+    }
+
+    #[test]
+    fn test_threshold_and_max_len() {
+        let slowlog = create_slowlog(1000, 2);
+        slowlog.record(Duration::from_micros(999), &args("GET fast"), None);
+        assert_eq!(slowlog.len(), 0);
+
+        for key in ["a", "b", "c"] {
+            slowlog.record(Duration::from_micros(1000), &args(&format!("GET {}", key)), None);
+        }
+        assert_eq!(slowlog.len(), 2);
+        let Message::Array(Some(entries)) = slowlog.get(None) else {
+            unreachable!("Expected array");
+        };
+        assert_eq!(entries[0], Message::array(vec![
+            Message::Integer(2),
+            Message::Integer(0),
+            Message::Integer(1000),
+            Message::array(args("GET c")),
+            Message::bulk_string(""),
+            Message::bulk_string(""),
+        ]));
+        assert_eq!(slowlog.get(Some(1)), Message::array(vec![entries[0].clone()]));
+
+        slowlog.reset();
+        assert_eq!(slowlog.len(), 0);
+
+        let disabled = create_slowlog(-1, 128);
+        disabled.record(Duration::from_secs(1), &args("GET key"), None);
+        assert_eq!(disabled.len(), 0);
+    }
+
+    #[test]
+    fn test_long_commands_are_truncated() {
+        let slowlog = create_slowlog(0, 128);
+        let mut long: Vec<Message> = vec![Message::bulk_string("RPUSH"), Message::bulk_string(&"k".repeat(130))];
+        long.extend((0..40).map(|index| Message::bulk_string(&index.to_string())));
+        slowlog.record(Duration::ZERO, &long, None);
+
+        let Message::Array(Some(entries)) = slowlog.get(None) else {
+            unreachable!("Expected array");
+        };
+        let Message::Array(Some(entry)) = &entries[0] else {
+            unreachable!("Expected array");
+        };
+        let Message::Array(Some(logged)) = &entry[3] else {
+            unreachable!("Expected array");
+        };
+        assert_eq!(logged.len(), MAX_ARGS);
+        assert_eq!(logged[1], Message::bulk_string(&format!("{}... (2 more bytes)", "k".repeat(128))));
+        assert_eq!(logged[MAX_ARGS - 1], Message::bulk_string("... (11 more arguments)"));
+    }
+}
@@ -0,0 +1,68 @@
+use std::sync::atomic::{AtomicBool, AtomicU64};
+
+use crate::message_processor::now;
+
+// Server-wide counters reported by INFO.
+// Keyspace keeps its own expiration and eviction counters, replication keeps sync counters.
+pub struct Stats {
+    // unix time in milliseconds
+    pub started_at: u128,
+    pub total_connections_received: AtomicU64,
+    pub rejected_connections: AtomicU64,
+    pub total_commands_processed: AtomicU64,
+    pub keyspace_hits: AtomicU64,
+    pub keyspace_misses: AtomicU64,
+    // successful write commands since last save
+    pub changes_since_last_save: AtomicU64,
+    // unix time in seconds of last successful save, or server start
+    pub last_save_time: AtomicU64,
+    pub last_save_ok: AtomicBool,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        let started_at = now();
+        Stats {
+            started_at,
+            total_connections_received: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
+            total_commands_processed: AtomicU64::new(0),
+            keyspace_hits: AtomicU64::new(0),
+            keyspace_misses: AtomicU64::new(0),
+            changes_since_last_save: AtomicU64::new(0),
+            last_save_time: AtomicU64::new((started_at / 1000) as u64),
+            last_save_ok: AtomicBool::new(true),
+        }
+    }
+}
+
+impl Stats {
+    pub fn uptime_in_seconds(&self) -> u64 {
+        (now().saturating_sub(self.started_at) / 1000) as u64
+    }
+}
+
+// the same format as Redis uses for `used_memory_human`, e.g. 1.50M
+pub fn bytes_to_human(bytes: usize) -> String {
+    let units = [("G", 1024 * 1024 * 1024), ("M", 1024 * 1024), ("K", 1024)];
+    for (unit, size) in units {
+        if bytes >= size {
+            return format!("{:.2}{}", bytes as f64 / size as f64, unit);
+        }
+    }
+    format!("{}B", bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bytes_to_human() {
+        assert_eq!(bytes_to_human(0), "0B");
+        assert_eq!(bytes_to_human(1023), "1023B");
+        assert_eq!(bytes_to_human(1536), "1.50K");
+        assert_eq!(bytes_to_human(3 * 1024 * 1024), "3.00M");
+        assert_eq!(bytes_to_human(5 * 1024 * 1024 * 1024), "5.00G");
+    }
+}
@@ -0,0 +1,47 @@
+use std::sync::Arc;
+
+use tokio_rustls::{
+    rustls::{
+        crypto::ring,
+        pki_types::{pem::PemObject, CertificateDer, PrivateKeyDer},
+        server::WebPkiClientVerifier,
+        RootCertStore, ServerConfig,
+    },
+    TlsAcceptor,
+};
+
+use crate::config::{Config, TlsAuthClients};
+
+pub fn create_acceptor(config: &Config) -> Result<TlsAcceptor, String> {
+    let cert_file = config.tls_cert_file.as_ref().ok_or("tls-cert-file is required when tls-port is set")?;
+    let key_file = config.tls_key_file.as_ref().ok_or("tls-key-file is required when tls-port is set")?;
+
+    let certs: Vec<CertificateDer> = CertificateDer::pem_file_iter(cert_file)
+        .and_then(|certs| certs.collect())
+        .map_err(|err| format!("Cannot load certificate {}: {}", cert_file, err))?;
+    let key = PrivateKeyDer::from_pem_file(key_file).map_err(|err| format!("Cannot load private key {}: {}", key_file, err))?;
+
+    let provider = Arc::new(ring::default_provider());
+    let builder = ServerConfig::builder_with_provider(provider.clone())
+        .with_safe_default_protocol_versions()
+        .map_err(|err| err.to_string())?;
+
+    let builder = match config.tls_auth_clients {
+        TlsAuthClients::No => builder.with_no_client_auth(),
+        auth_clients => {
+            let ca_file = config.tls_ca_cert_file.as_ref().ok_or("tls-ca-cert-file is required to verify client certificates")?;
+            let mut roots = RootCertStore::empty();
+            for cert in CertificateDer::pem_file_iter(ca_file).map_err(|err| format!("Cannot load CA certificate {}: {}", ca_file, err))? {
+                let cert = cert.map_err(|err| format!("Cannot load CA certificate {}: {}", ca_file, err))?;
+                roots.add(cert).map_err(|err| format!("Invalid CA certificate {}: {}", ca_file, err))?;
+            }
+
+            let verifier = WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+            let verifier = if auth_clients == TlsAuthClients::Optional { verifier.allow_unauthenticated() } else { verifier };
+            builder.with_client_cert_verifier(verifier.build().map_err(|err| err.to_string())?)
+        }
+    };
+
+    let server_config = builder.with_single_cert(certs, key).map_err(|err| format!("Invalid certificate or key: {}", err))?;
+    Ok(TlsAcceptor::from(Arc::new(server_config)))
+}
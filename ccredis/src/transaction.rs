@@ -0,0 +1,126 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{atomic::{AtomicUsize, Ordering}, Mutex, MutexGuard},
+};
+
+use crate::resp::message::Message;
+
+// Commands queued by a client after MULTI, they run on EXEC
+#[derive(Default)]
+pub struct Transaction {
+    pub commands: Vec<Vec<Message>>,
+    pub writes: bool,
+    // a command was refused while queued, EXEC discards the transaction
+    pub refused: bool,
+}
+
+#[derive(Default)]
+struct WatchingClient {
+    keys: BTreeSet<Vec<u8>>,
+    // one of the keys was changed since WATCH
+    touched: bool,
+}
+
+#[derive(Default)]
+struct WatchState {
+    // clients watching the key
+    keys: HashMap<Vec<u8>, BTreeSet<u64>>,
+    clients: HashMap<u64, WatchingClient>,
+}
+
+// Keys watched by WATCH. Every change of a watched key makes EXEC of the watching clients fail,
+// it is reported by shards, so writes, expiration and eviction are all noticed.
+#[derive(Default)]
+pub struct Watches {
+    state: Mutex<WatchState>,
+    // changes don't take the lock while nothing is watched
+    watched_keys: AtomicUsize,
+}
+
+impl Watches {
+    fn state(&self) -> MutexGuard<'_, WatchState> {
+        self.state.lock().expect("Watches lock poisoned")
+    }
+
+    pub fn watch(&self, client_id: u64, keys: &[&[u8]]) {
+        let mut state = self.state();
+        for key in keys {
+            if state.clients.entry(client_id).or_default().keys.insert(key.to_vec()) {
+                state.keys.entry(key.to_vec()).or_default().insert(client_id);
+                self.watched_keys.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+    }
+
+    pub fn watched_keys(&self, client_id: u64) -> Vec<Vec<u8>> {
+        self.state().clients.get(&client_id).map_or_else(Vec::new, |client| client.keys.iter().cloned().collect())
+    }
+
+    // Forgets keys watched by the client, true when one of them was changed
+    pub fn unwatch(&self, client_id: u64) -> bool {
+        let mut state = self.state();
+        let Some(client) = state.clients.remove(&client_id) else {
+            return false;
+        };
+        for key in &client.keys {
+            if let Some(watchers) = state.keys.get_mut(key) {
+                watchers.remove(&client_id);
+                if watchers.is_empty() {
+                    state.keys.remove(key);
+                }
+            }
+        }
+        self.watched_keys.fetch_sub(client.keys.len(), Ordering::SeqCst);
+        client.touched
+    }
+
+    // called by shard while the key is locked
+    pub fn touch(&self, key: &str) {
+        if self.watched_keys.load(Ordering::SeqCst) == 0 {
+            return;
+        }
+        let mut state = self.state();
+        let Some(watchers) = state.keys.get(key.as_bytes()).cloned() else {
+            return;
+        };
+        for client_id in watchers {
+            if let Some(client) = state.clients.get_mut(&client_id) {
+                client.touched = true;
+            }
+        }
+    }
+
+    // every watched key is changed, e.g. when replica loads snapshot of master
+    pub fn touch_all(&self) {
+        for client in self.state().clients.values_mut() {
+            client.touched = true;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watches() {
+        let watches = Watches::default();
+        watches.watch(1, &[b"a", b"b"]);
+        watches.watch(2, &[b"b"]);
+        watches.watch(2, &[b"b"]);
+        assert_eq!(watches.watched_keys(2), [b"b".to_vec()]);
+
+        watches.touch("a");
+        assert!(watches.unwatch(1));
+        assert!(!watches.unwatch(2));
+        assert!(!watches.unwatch(3));
+        assert_eq!(watches.watched_keys.load(Ordering::SeqCst), 0);
+
+        // keys changed after UNWATCH don't count
+        watches.watch(1, &[b"a"]);
+        assert!(!watches.unwatch(1));
+        watches.touch("a");
+        watches.watch(1, &[b"a"]);
+        assert!(!watches.unwatch(1));
+    }
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use std::process::Command;
+
+use common::{command, ServerProcess};
+
+#[test]
+fn report_workloads_in_csv() {
+    let server = ServerProcess::start(&[]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ccredis-benchmark"))
+        .args(["-p", &server.port.to_string(), "-c", "4", "-n", "400", "-P", "8", "-r", "10", "-t", "ping,set,get,incr,lpush,lrange,mset", "--csv"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], "\"test\",\"rps\",\"avg_latency_ms\",\"min_latency_ms\",\"p50_latency_ms\",\"p99_latency_ms\",\"p999_latency_ms\",\"max_latency_ms\"");
+    let tests: Vec<&str> = lines[1..].iter().map(|line| line.split(',').next().unwrap()).collect();
+    assert_eq!(tests, ["\"PING_MBULK\"", "\"SET\"", "\"GET\"", "\"INCR\"", "\"LPUSH\"", "\"LRANGE_100 (first 100 elements)\"", "\"MSET (10 keys)\""]);
+    for line in &lines[1..] {
+        let values: Vec<f64> = line.split(',').skip(1).map(|value| value.trim_matches('"').parse().unwrap()).collect();
+        assert_eq!(values.len(), 7);
+        assert!(values[0] > 0.0, "{}", line);
+        // percentiles grow from min to max
+        assert!(values[2..].windows(2).all(|pair| pair[0] <= pair[1]), "{}", line);
+    }
+
+    let mut stream = server.connect();
+    assert_eq!(command(&mut stream, &["EXISTS", "key:000000000000", "counter:000000000009", "key:000000000010"]), "(integer) 2");
+}
+
+#[test]
+fn default_workloads_succeed() {
+    let server = ServerProcess::start(&[]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ccredis-benchmark"))
+        .args(["-p", &server.port.to_string(), "-c", "2", "-n", "100", "-q"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    // the first error reply of a test would be shown
+    assert_eq!(String::from_utf8(output.stderr).unwrap(), "");
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let tests: Vec<&str> = stdout.lines().map(|line| line.split(':').next().unwrap()).collect();
+    assert_eq!(tests, ["PING_MBULK", "SET", "GET", "INCR", "LPUSH", "LRANGE_100 (first 100 elements)", "MSET (10 keys)"]);
+}
+
+#[test]
+fn error_replies_are_not_counted_as_requests() {
+    // only the first SET fits into memory
+    let server = ServerProcess::start(&["--maxmemory", "1", "--maxmemory-policy", "noeviction"]);
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ccredis-benchmark"))
+        .args(["-p", &server.port.to_string(), "-c", "1", "-n", "50", "-t", "set"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.starts_with("Error from server: OOM"), "{}", stderr);
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("  1 requests completed in"), "{}", stdout);
+    assert!(stdout.contains("  49 errors"), "{}", stdout);
+}
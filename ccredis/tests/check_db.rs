@@ -0,0 +1,33 @@
+mod common;
+
+use std::process::{Command, Output};
+
+fn check_db(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_ccredis-check-db")).args(args).output().unwrap()
+}
+
+#[test]
+fn validate_and_repair() {
+    let dir = common::temp_dir("check_db");
+    let path = dir.join("db.txt");
+    let path_arg = path.to_str().unwrap();
+    let snapshot = "*3\r\n*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n*3\r\n$5\r\nRPUSH\r\n$1\r\na\r\n$1\r\nx\r\n*3\r\n$3\r\nSET\r\n$1\r\nb\r\n$1\r\n2\r\n";
+    std::fs::write(&path, snapshot).unwrap();
+
+    // RPUSH to string key can't be loaded
+    let output = check_db(&[path_arg]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains("Bad file format at byte 31: command failed: WRONGTYPE"), "{}", stdout);
+    assert!(stdout.contains("1 commands (31 of 87 bytes) before it are valid"), "{}", stdout);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), snapshot);
+
+    assert!(check_db(&["--fix", path_arg]).status.success());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "*1\r\n*3\r\n$3\r\nSET\r\n$1\r\na\r\n$1\r\n1\r\n");
+    let output = check_db(&[path_arg]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("{}: OK, 1 commands\n", path_arg));
+
+    assert_eq!(check_db(&[]).status.code(), Some(1));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+use common::{command, ServerProcess};
+
+fn cli(server: &ServerProcess, args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ccredis-cli"))
+        .arg("-p")
+        .arg(server.port.to_string())
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start ccredis-cli");
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+#[test]
+fn run_one_shot_commands() {
+    let server = ServerProcess::start(&[]);
+
+    assert_eq!(stdout(&cli(&server, &["--no-raw", "RPUSH", "list", "a", "b c"], "")), "(integer) 2\n");
+    let script = "return {'a', 'b c', {1}}";
+    assert_eq!(stdout(&cli(&server, &["--no-raw", "EVAL", script, "0"], "")), "1) \"a\"\n2) \"b c\"\n3) 1) (integer) 1\n");
+    assert_eq!(stdout(&cli(&server, &["--no-raw", "GET", "missing"], "")), "(nil)\n");
+    assert_eq!(stdout(&cli(&server, &["EVAL", script, "0"], "")), "a\nb c\n1\n");
+
+    let output = cli(&server, &["--no-raw", "UNKNOWN"], "");
+    assert!(stdout(&output).starts_with("(error) ERR unknown command"));
+    assert_eq!(output.status.code(), Some(1));
+
+    assert_eq!(stdout(&cli(&server, &["-r", "3", "INCR", "counter"], "")), "1\n2\n3\n");
+    assert_eq!(stdout(&cli(&server, &["-r", "2", "-i", "0.01", "GET", "counter"], "")), "3\n3\n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_ccredis-cli")).args(["-p", &common::free_port().to_string(), "PING"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("Could not connect to ccredis"));
+}
+
+#[test]
+fn read_commands_from_stdin() {
+    let server = ServerProcess::start(&[]);
+
+    let output = cli(&server, &["--no-raw"], "SET key \"two words\"\nGET key\n2 INCR n\nSET 'unbalanced\nquit\nPING\n");
+    assert_eq!(stdout(&output), "OK\n\"two words\"\n(integer) 1\n(integer) 2\nInvalid argument(s)\n");
+}
+
+#[test]
+fn pipe_mass_insertion() {
+    let server = ServerProcess::start(&[]);
+
+    let mut input = String::new();
+    for index in 0..1000 {
+        input.push_str(&format!("*3\r\n$3\r\nSET\r\n${}\r\nkey{}\r\n$1\r\nv\r\n", format!("key{}", index).len(), index));
+    }
+    input.push_str("*1\r\n$7\r\nUNKNOWN\r\n");
+
+    let output = cli(&server, &["--pipe"], &input);
+    let stdout = stdout(&output);
+    assert!(stdout.contains("All data transferred. Waiting for the last reply..."));
+    assert!(stdout.contains("Last reply received from server."));
+    assert!(stdout.ends_with("errors: 1, replies: 1001\n"), "{}", stdout);
+    assert_eq!(output.status.code(), Some(1));
+
+    let mut stream = server.connect();
+    assert_eq!(command(&mut stream, &["EXISTS", "key0", "key999", "key1000"]), "(integer) 2");
+}
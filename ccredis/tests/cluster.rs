@@ -0,0 +1,93 @@
+mod common;
+
+use std::{
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{command, free_port, wait_for_port, ServerProcess};
+
+struct Node {
+    server: ServerProcess,
+    cport: u16,
+    stream: TcpStream,
+    id: String,
+}
+
+fn start_node() -> Node {
+    let cport = free_port();
+    let server = ServerProcess::start(&["--cluster-enabled", "yes", "--cluster-port", &cport.to_string()]);
+    wait_for_port(cport);
+    let mut stream = server.connect();
+    let id = command(&mut stream, &["CLUSTER", "MYID"]);
+    Node { server, cport, stream, id }
+}
+
+fn wait_until(description: &str, mut condition: impl FnMut() -> bool) {
+    let started_at = Instant::now();
+    while !condition() {
+        assert!(started_at.elapsed() < Duration::from_secs(10), "Timed out waiting for {}", description);
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn address(node: &Node) -> String {
+    format!("127.0.0.1:{}", node.server.port)
+}
+
+#[test]
+fn cluster_nodes_gossip_slots_and_redirect_clients() {
+    let mut nodes = [start_node(), start_node(), start_node()];
+    let ranges = [("0", "5460"), ("5461", "10922"), ("10923", "16383")];
+    for (node, (start, end)) in nodes.iter_mut().zip(ranges) {
+        assert_eq!(command(&mut node.stream, &["CLUSTER", "ADDSLOTSRANGE", start, end]), "OK");
+    }
+
+    // the second and the third node learn about each other through gossip of the first one
+    let (first, others) = nodes.split_first_mut().unwrap();
+    for other in others.iter() {
+        let port = other.server.port.to_string();
+        let cport = other.cport.to_string();
+        assert_eq!(command(&mut first.stream, &["CLUSTER", "MEET", "127.0.0.1", &port, &cport]), "OK");
+    }
+    for (node, (start, end)) in nodes.iter_mut().zip(ranges) {
+        wait_until("cluster state ok", || command(&mut node.stream, &["CLUSTER", "INFO"]).contains("cluster_state:ok"));
+        let cluster_nodes = command(&mut node.stream, &["CLUSTER", "NODES"]);
+        assert_eq!(cluster_nodes.lines().count(), 3, "{}", cluster_nodes);
+        let myself = cluster_nodes.lines().find(|line| line.contains("myself,master")).unwrap();
+        assert!(myself.starts_with(&format!("{} {}@{} ", node.id, address(node), node.cport)), "{}", myself);
+        assert!(myself.ends_with(&format!(" connected {}-{}", start, end)), "{}", myself);
+    }
+
+    let [first, second, third] = &mut nodes;
+    assert_eq!(command(&mut first.stream, &["CLUSTER", "KEYSLOT", "foo"]), "(integer) 12182");
+    assert_eq!(
+        command(&mut first.stream, &["CLUSTER", "KEYSLOT", "{user1000}.following"]),
+        command(&mut first.stream, &["CLUSTER", "KEYSLOT", "{user1000}.followers"])
+    );
+
+    assert_eq!(command(&mut first.stream, &["SET", "foo", "bar"]), format!("(error) MOVED 12182 {}", address(third)));
+    assert_eq!(command(&mut third.stream, &["SET", "foo", "bar"]), "OK");
+    assert_eq!(command(&mut third.stream, &["GET", "foo"]), "bar");
+    assert_eq!(command(&mut first.stream, &["DEL", "a", "b"]), "(error) CROSSSLOT Keys in request don't hash to the same slot");
+    assert_eq!(command(&mut first.stream, &["EXISTS", "{b}1", "{b}2"]), "(integer) 0");
+
+    let slots = command(&mut second.stream, &["CLUSTER", "SLOTS"]);
+    assert!(slots.starts_with(&format!("[[(integer) 0, (integer) 5460, [127.0.0.1, (integer) {}, {}]]", first.server.port, first.id)), "{}", slots);
+    assert!(command(&mut second.stream, &["CLUSTER", "SHARDS"]).contains(&third.id));
+
+    // slot 12182 is migrated from the third node to the second one
+    assert_eq!(command(&mut second.stream, &["CLUSTER", "SETSLOT", "12182", "IMPORTING", &third.id]), "OK");
+    assert_eq!(command(&mut third.stream, &["CLUSTER", "SETSLOT", "12182", "MIGRATING", &second.id]), "OK");
+    assert_eq!(command(&mut third.stream, &["GET", "foo"]), "bar");
+    assert_eq!(command(&mut third.stream, &["GET", "{foo}missing"]), format!("(error) ASK 12182 {}", address(second)));
+    assert_eq!(command(&mut second.stream, &["GET", "{foo}missing"]), format!("(error) MOVED 12182 {}", address(third)));
+    assert_eq!(command(&mut second.stream, &["ASKING"]), "OK");
+    assert_eq!(command(&mut second.stream, &["GET", "{foo}missing"]), "(nil)");
+
+    // new owner is spread by gossip
+    assert_eq!(command(&mut second.stream, &["CLUSTER", "SETSLOT", "12182", "NODE", &second.id]), "OK");
+    let second_address = address(second);
+    wait_until("slot owner update", || command(&mut first.stream, &["GET", "foo"]) == format!("(error) MOVED 12182 {}", second_address));
+}
@@ -0,0 +1,116 @@
+#![allow(dead_code)]
+
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    path::PathBuf,
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+// ccredis binary running in its own temporary directory, killed on drop
+pub struct ServerProcess {
+    child: Child,
+    pub port: u16,
+    pub dir: PathBuf,
+}
+
+impl ServerProcess {
+    pub fn start(args: &[&str]) -> ServerProcess {
+        let port = free_port();
+        Self::start_on_port(port, args)
+    }
+
+    pub fn start_on_port(port: u16, args: &[&str]) -> ServerProcess {
+        let dir = temp_dir(&format!("server_{}", port));
+        let child = Command::new(env!("CARGO_BIN_EXE_ccredis"))
+            .arg("--port")
+            .arg(port.to_string())
+            .args(args)
+            .current_dir(&dir)
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("Failed to start ccredis");
+        let server = ServerProcess { child, port, dir };
+        wait_for_port(port);
+        server
+    }
+
+    // sends signal (e.g. INT or TERM) and waits for process to exit
+    #[cfg(unix)]
+    pub fn signal(&mut self, signal: &str) -> std::process::ExitStatus {
+        Command::new("kill").arg(format!("-{}", signal)).arg(self.child.id().to_string()).status().unwrap();
+        self.child.wait().unwrap()
+    }
+
+    // waits for process to exit by itself, e.g. after SHUTDOWN
+    pub fn wait(&mut self) -> std::process::ExitStatus {
+        self.child.wait().unwrap()
+    }
+
+    pub fn connect(&self) -> TcpStream {
+        TcpStream::connect(("127.0.0.1", self.port)).unwrap()
+    }
+}
+
+impl Drop for ServerProcess {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("ccredis_test_{}_{}", std::process::id(), name));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+pub fn wait_for_port(port: u16) {
+    let started_at = Instant::now();
+    while TcpStream::connect(("127.0.0.1", port)).is_err() {
+        assert!(started_at.elapsed() < Duration::from_secs(10), "Server did not start on port {}", port);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+// sends command and returns reply in redis-cli like form, e.g. `OK`, `(integer) 1`, `(nil)`, `[a, b]`
+pub fn command<S: Read + Write>(stream: &mut S, args: &[&str]) -> String {
+    let mut request = format!("*{}\r\n", args.len());
+    for arg in args {
+        request.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    stream.write_all(request.as_bytes()).unwrap();
+    stream.flush().unwrap();
+    read_reply(&mut BufReader::new(stream))
+}
+
+pub fn read_reply<R: BufRead>(reader: &mut R) -> String {
+    let mut line = String::new();
+    reader.read_line(&mut line).unwrap();
+    let line = line.trim_end_matches("\r\n");
+    let (kind, rest) = line.split_at(1.min(line.len()));
+    match kind {
+        "+" => rest.to_string(),
+        "-" => format!("(error) {}", rest),
+        ":" => format!("(integer) {}", rest),
+        "$" if rest == "-1" => "(nil)".to_string(),
+        "$" => {
+            let mut content = vec![0u8; rest.parse::<usize>().unwrap() + 2];
+            reader.read_exact(&mut content).unwrap();
+            String::from_utf8_lossy(&content[..content.len() - 2]).to_string()
+        }
+        "*" if rest == "-1" => "(nil)".to_string(),
+        "*" => {
+            let items: Vec<String> = (0..rest.parse::<usize>().unwrap()).map(|_| read_reply(reader)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        _ => panic!("Unexpected reply line {:?}", line),
+    }
+}
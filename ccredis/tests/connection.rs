@@ -0,0 +1,75 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{command, read_reply, ServerProcess};
+
+#[test]
+fn maxclients_rejects_new_connections() {
+    let server = ServerProcess::start(&["--maxclients", "2"]);
+    let mut first = server.connect();
+    let mut second = server.connect();
+    assert_eq!(command(&mut first, &["PING"]), "PONG");
+    assert_eq!(command(&mut second, &["PING"]), "PONG");
+
+    let mut rejected = server.connect();
+    assert_eq!(read_reply(&mut std::io::BufReader::new(&mut rejected)), "(error) ERR max number of clients reached");
+    assert_eq!(rejected.read(&mut [0; 16]).unwrap_or(0), 0);
+
+    // slot is released when connection is closed
+    drop(second);
+    let started_at = Instant::now();
+    loop {
+        let mut stream = server.connect();
+        stream.write_all(b"*1\r\n$4\r\nPING\r\n").unwrap();
+        if read_reply(&mut std::io::BufReader::new(&mut stream)) == "PONG" {
+            break;
+        }
+        assert!(started_at.elapsed() < Duration::from_secs(5), "Slot was not released");
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+#[test]
+fn client_over_output_buffer_limit_is_closed() {
+    let server = ServerProcess::start(&["--client-output-buffer-limit", "normal 1mb 0 0"]);
+    let mut admin = server.connect();
+    let value = "x".repeat(100 * 1024);
+    assert_eq!(command(&mut admin, &["SET", "big", &value]), "OK");
+
+    // pipelines replies of 100 mb without reading them
+    let mut greedy = server.connect();
+    let request = b"*2\r\n$3\r\nGET\r\n$3\r\nbig\r\n".repeat(1000);
+    let _ = greedy.write_all(&request);
+
+    let started_at = Instant::now();
+    while command(&mut admin, &["CLIENT", "LIST"]).lines().count() != 1 {
+        assert!(started_at.elapsed() < Duration::from_secs(5), "Client was not closed");
+        thread::sleep(Duration::from_millis(20));
+    }
+    // other clients are served as usual
+    assert_eq!(command(&mut admin, &["EXISTS", "big"]), "(integer) 1");
+}
+
+#[test]
+fn idle_timeout_spares_monitors() {
+    let server = ServerProcess::start(&["--timeout", "1"]);
+    let mut idle = server.connect();
+    assert_eq!(command(&mut idle, &["PING"]), "PONG");
+    let mut monitor = server.connect();
+    assert_eq!(command(&mut monitor, &["MONITOR"]), "OK");
+
+    thread::sleep(Duration::from_millis(1500));
+    idle.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+    assert_eq!(idle.read(&mut [0; 16]).unwrap_or(0), 0);
+
+    let mut client = server.connect();
+    assert_eq!(command(&mut client, &["PING"]), "PONG");
+    let line = read_reply(&mut std::io::BufReader::new(&mut monitor));
+    assert!(line.ends_with("\"PING\""), "{}", line);
+    assert!(line.contains("[0 127.0.0.1:"), "{}", line);
+}
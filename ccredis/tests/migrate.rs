@@ -0,0 +1,88 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpListener,
+    thread,
+};
+
+use ccredis::resp::message_parser::MessageParser;
+use common::{command, ServerProcess};
+
+#[test]
+fn migrate_moves_keys_with_ttl() {
+    let source = ServerProcess::start(&[]);
+    let target = ServerProcess::start(&[]);
+    let mut source_stream = source.connect();
+    let mut target_stream = target.connect();
+    let port = target.port.to_string();
+
+    assert_eq!(command(&mut source_stream, &["SET", "key", "value", "EX", "100"]), "OK");
+    assert_eq!(command(&mut source_stream, &["RPUSH", "list", "a", "b", "c"]), "(integer) 3");
+    assert_eq!(command(&mut source_stream, &["SET", "copied", "value"]), "OK");
+
+    assert_eq!(command(&mut source_stream, &["MIGRATE", "127.0.0.1", &port, "key", "0", "1000"]), "OK");
+    assert_eq!(command(&mut source_stream, &["EXISTS", "key"]), "(integer) 0");
+    assert_eq!(command(&mut target_stream, &["GET", "key"]), "value");
+    let ttl = command(&mut target_stream, &["TTL", "key"]);
+    assert!(ttl == "(integer) 100" || ttl == "(integer) 99", "{}", ttl);
+
+    assert_eq!(command(&mut source_stream, &["MIGRATE", "127.0.0.1", &port, "", "0", "1000", "COPY", "KEYS", "list", "copied", "missing"]), "OK");
+    assert_eq!(command(&mut source_stream, &["EXISTS", "list", "copied"]), "(integer) 2");
+    assert_eq!(command(&mut target_stream, &["RPUSH", "list", "d"]), "(integer) 4");
+    assert_eq!(command(&mut target_stream, &["TTL", "list"]), "(integer) -1");
+
+    // existing keys of target are kept unless REPLACE is given
+    let reply = command(&mut source_stream, &["MIGRATE", "127.0.0.1", &port, "list", "0", "1000"]);
+    assert_eq!(reply, "(error) ERR Target instance replied with error: BUSYKEY Target key name already exists.");
+    assert_eq!(command(&mut source_stream, &["EXISTS", "list"]), "(integer) 1");
+    assert_eq!(command(&mut source_stream, &["MIGRATE", "127.0.0.1", &port, "list", "0", "1000", "REPLACE"]), "OK");
+    assert_eq!(command(&mut target_stream, &["RPUSH", "list", "d"]), "(integer) 4");
+    assert_eq!(command(&mut source_stream, &["MIGRATE", "127.0.0.1", &port, "list", "0", "1000"]), "NOKEY");
+
+    drop(target);
+    let reply = command(&mut source_stream, &["MIGRATE", "127.0.0.1", &port, "copied", "0", "100"]);
+    assert_eq!(reply, "(error) IOERR error or timeout connecting to the client");
+    assert_eq!(command(&mut source_stream, &["EXISTS", "copied"]), "(integer) 1");
+}
+
+#[test]
+fn migrate_releases_keys_during_transfer() {
+    let source = ServerProcess::start(&[]);
+    let mut source_stream = source.connect();
+    assert_eq!(command(&mut source_stream, &["SET", "moved", "value"]), "OK");
+    assert_eq!(command(&mut source_stream, &["SET", "changed", "value"]), "OK");
+
+    // target which replies only after a key was changed on the source
+    let target = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = target.local_addr().unwrap().port().to_string();
+    let mut other = source.connect();
+    let target = thread::spawn(move || {
+        let (mut stream, _) = target.accept().unwrap();
+        let mut parser = MessageParser::new();
+        let mut received = 0;
+        let mut buf = [0; 1024];
+        while received < 2 {
+            let read = stream.read(&mut buf).unwrap();
+            received += buf[..read].iter().filter(|&&byte| parser.add_byte(byte).unwrap().is_some()).count();
+        }
+        assert_eq!(command(&mut other, &["SET", "changed", "new value"]), "OK");
+        stream.write_all(b"+OK\r\n+OK\r\n").unwrap();
+    });
+
+    assert_eq!(command(&mut source_stream, &["MIGRATE", "127.0.0.1", &port, "", "0", "5000", "KEYS", "moved", "changed"]), "OK");
+    target.join().unwrap();
+    assert_eq!(command(&mut source_stream, &["EXISTS", "moved"]), "(integer) 0");
+    assert_eq!(command(&mut source_stream, &["GET", "changed"]), "new value");
+}
+
+#[test]
+fn migrate_to_itself_is_rejected() {
+    let server = ServerProcess::start(&[]);
+    let mut stream = server.connect();
+    let port = server.port.to_string();
+    assert_eq!(command(&mut stream, &["SET", "key", "value"]), "OK");
+    let reply = command(&mut stream, &["MIGRATE", "127.0.0.1", &port, "key", "0", "1000", "REPLACE"]);
+    assert_eq!(reply, "(error) ERR Target instance is this server");
+    assert_eq!(command(&mut stream, &["GET", "key"]), "value");
+}
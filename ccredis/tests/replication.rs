@@ -0,0 +1,100 @@
+mod common;
+
+use std::{
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+use common::{command, ServerProcess};
+
+fn start_replica(master: &ServerProcess) -> ServerProcess {
+    ServerProcess::start(&["--replicaof", &format!("127.0.0.1 {}", master.port)])
+}
+
+// retries command until it returns expected reply
+fn wait_for_reply(stream: &mut TcpStream, args: &[&str], expected: &str) {
+    let started_at = Instant::now();
+    loop {
+        let reply = command(stream, args);
+        if reply == expected {
+            return;
+        }
+        assert!(started_at.elapsed() < Duration::from_secs(10), "Expected {:?} for {:?}, got {:?}", expected, args, reply);
+        thread::sleep(Duration::from_millis(20));
+    }
+}
+
+fn info_field(stream: &mut TcpStream, field: &str) -> String {
+    let info = command(stream, &["INFO"]);
+    info.lines()
+        .find_map(|line| line.strip_prefix(&format!("{}:", field)))
+        .unwrap_or_else(|| panic!("No {} in INFO: {}", field, info))
+        .to_string()
+}
+
+#[test]
+fn replica_receives_snapshot_and_write_stream() {
+    let master = ServerProcess::start(&[]);
+    let mut master_stream = master.connect();
+    assert_eq!(command(&mut master_stream, &["SET", "before", "sync"]), "OK");
+    assert_eq!(command(&mut master_stream, &["RPUSH", "list", "a", "b"]), "(integer) 2");
+
+    let replica = start_replica(&master);
+    let mut replica_stream = replica.connect();
+    wait_for_reply(&mut replica_stream, &["GET", "before"], "sync");
+    assert_eq!(info_field(&mut replica_stream, "role"), "slave");
+    assert_eq!(info_field(&mut replica_stream, "master_link_status"), "up");
+
+    assert_eq!(command(&mut master_stream, &["INCR", "counter"]), "(integer) 1");
+    assert_eq!(command(&mut master_stream, &["SET", "expiring", "value", "EX", "100"]), "OK");
+    assert_eq!(command(&mut master_stream, &["WAIT", "1", "5000"]), "(integer) 1");
+    assert_eq!(command(&mut replica_stream, &["GET", "counter"]), "1");
+    assert_eq!(command(&mut replica_stream, &["EXISTS", "list", "expiring"]), "(integer) 2");
+
+    assert_eq!(info_field(&mut master_stream, "role"), "master");
+    assert_eq!(info_field(&mut master_stream, "connected_slaves"), "1");
+    let master_offset = info_field(&mut master_stream, "master_repl_offset");
+    let started_at = Instant::now();
+    while info_field(&mut replica_stream, "slave_repl_offset") != master_offset {
+        assert!(started_at.elapsed() < Duration::from_secs(10), "Replica offset does not match master offset {}", master_offset);
+        thread::sleep(Duration::from_millis(20));
+    }
+
+    assert_eq!(
+        command(&mut replica_stream, &["SET", "key", "value"]),
+        "(error) READONLY You can't write against a read only replica."
+    );
+}
+
+#[test]
+fn replica_resumes_with_partial_resync() {
+    let master = ServerProcess::start(&[]);
+    let mut master_stream = master.connect();
+    let replica = start_replica(&master);
+    let mut replica_stream = replica.connect();
+
+    assert_eq!(command(&mut master_stream, &["SET", "first", "1"]), "OK");
+    wait_for_reply(&mut replica_stream, &["GET", "first"], "1");
+
+    // drop replication link, replica reconnects and continues from its offset
+    let clients = command(&mut master_stream, &["CLIENT", "LIST"]);
+    let replica_id = clients
+        .lines()
+        .find(|line| line.contains("flags=S"))
+        .and_then(|line| line.strip_prefix("id="))
+        .and_then(|line| line.split(' ').next())
+        .expect("Replica is not connected")
+        .to_string();
+    assert_eq!(command(&mut master_stream, &["CLIENT", "KILL", "ID", &replica_id]), "(integer) 1");
+    assert_eq!(command(&mut master_stream, &["SET", "second", "2"]), "OK");
+
+    wait_for_reply(&mut replica_stream, &["GET", "second"], "2");
+    assert_eq!(info_field(&mut master_stream, "sync_full"), "1");
+    assert_eq!(info_field(&mut master_stream, "sync_partial_ok"), "1");
+
+    // promoted replica accepts writes
+    assert_eq!(command(&mut replica_stream, &["REPLICAOF", "NO", "ONE"]), "OK");
+    assert_eq!(command(&mut replica_stream, &["SET", "third", "3"]), "OK");
+    assert_eq!(info_field(&mut replica_stream, "role"), "master");
+}
@@ -0,0 +1,43 @@
+mod common;
+
+use std::{
+    io::{BufReader, Write},
+    net::TcpStream,
+    thread,
+    time::Duration,
+};
+
+use common::{command, read_reply, ServerProcess};
+
+const BUSY: &str = "(error) BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSAVE.";
+
+fn connect(server: &ServerProcess) -> TcpStream {
+    let stream = server.connect();
+    // a hung server fails the test instead of blocking it
+    stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+    stream
+}
+
+#[test]
+fn script_kill_stops_endless_script() {
+    let server = ServerProcess::start(&["--io-threads", "2", "--lua-time-limit", "300"]);
+    let mut script = connect(&server);
+    script.write_all(b"*3\r\n$4\r\nEVAL\r\n$17\r\nwhile true do end\r\n$1\r\n0\r\n").unwrap();
+    thread::sleep(Duration::from_millis(100));
+
+    // more clients with keyed commands than runtime workers, they wait without blocking workers
+    let clients: Vec<_> = (0..4).map(|_| {
+        let mut client = connect(&server);
+        thread::spawn(move || command(&mut client, &["GET", "a"]))
+    }).collect();
+    for client in clients {
+        assert_eq!(client.join().unwrap(), BUSY);
+    }
+
+    let mut admin = connect(&server);
+    assert_eq!(command(&mut admin, &["SET", "a", "1"]), BUSY);
+    assert_eq!(command(&mut admin, &["SCRIPT", "KILL"]), "OK");
+    assert_eq!(read_reply(&mut BufReader::new(&mut script)), "(error) ERR Script killed by user with SCRIPT KILL...");
+    assert_eq!(command(&mut admin, &["GET", "a"]), "(nil)");
+    assert_eq!(command(&mut admin, &["SCRIPT", "KILL"]), "(error) NOTBUSY No scripts in execution right now.");
+}
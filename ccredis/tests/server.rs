@@ -0,0 +1,92 @@
+mod common;
+
+use std::{io::Read, net::TcpStream};
+
+use ccredis::{config::Config, server::Server};
+use common::command;
+
+#[test]
+fn serve_commands_in_process() {
+    let dir = common::temp_dir("in_process");
+    let db_file_path = dir.join("db.txt").to_str().unwrap().to_string();
+
+    let server = Server::builder().bind("127.0.0.1:0").persistence(db_file_path.clone()).start().unwrap();
+    let addr = server.local_addr().unwrap();
+    assert_ne!(addr.port(), 0);
+
+    let mut stream = TcpStream::connect(addr).unwrap();
+    assert_eq!(command(&mut stream, &["SET", "foo", "bar"]), "OK");
+    assert_eq!(command(&mut stream, &["RPUSH", "list", "a", "b"]), "(integer) 2");
+    assert_eq!(command(&mut stream, &["GET", "foo"]), "bar");
+    // the port picked by OS is reported, not 0
+    assert!(command(&mut stream, &["INFO", "server"]).contains(&format!("tcp_port:{}", addr.port())));
+    assert_eq!(command(&mut stream, &["SAVE"]), "OK");
+
+    server.shutdown();
+    // connected client is closed and no new connections are accepted
+    assert_eq!(stream.read(&mut [0; 16]).unwrap_or(0), 0);
+    assert!(TcpStream::connect(addr).is_err());
+
+    // dataset is loaded from the same file on start
+    let server = Server::builder().bind("127.0.0.1:0").persistence(db_file_path).start().unwrap();
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    assert_eq!(command(&mut stream, &["GET", "foo"]), "bar");
+    assert_eq!(command(&mut stream, &["EXISTS", "list"]), "(integer) 1");
+    drop(server);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn serve_without_persistence() {
+    let server = Server::builder().bind("127.0.0.1:0").start().unwrap();
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    assert_eq!(command(&mut stream, &["SET", "foo", "bar"]), "OK");
+    assert_eq!(command(&mut stream, &["SAVE"]), "(error) ERR Persistence is not configured");
+
+    // final snapshot is skipped, nothing is written to the working directory
+    server.shutdown();
+    assert!(!std::path::Path::new("db.txt").exists());
+}
+
+#[test]
+fn start_with_config() {
+    let dir = common::temp_dir("in_process_config");
+    let config = Config::from_args(["--requirepass", "secret"].into_iter().map(String::from)).unwrap();
+    let server = Server::builder().config(config).bind("127.0.0.1:0").persistence(dir.join("db.txt").to_str().unwrap()).start().unwrap();
+
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    assert!(command(&mut stream, &["GET", "foo"]).starts_with("(error) NOAUTH"));
+    assert_eq!(command(&mut stream, &["AUTH", "secret"]), "OK");
+    assert_eq!(command(&mut stream, &["GET", "foo"]), "(nil)");
+    server.shutdown();
+
+    let config = Config::from_args(["--port", "0"].into_iter().map(String::from)).unwrap();
+    let err = Server::builder().config(config).start().err().unwrap();
+    assert_eq!(err.to_string(), "[Config] Neither port, tls-port nor unixsocket is set, nothing to listen on");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn damaged_db_file() {
+    let dir = common::temp_dir("in_process_damaged");
+    let db_file_path = dir.join("db.txt").to_str().unwrap().to_string();
+    let server = Server::builder().bind("127.0.0.1:0").persistence(db_file_path.clone()).start().unwrap();
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    assert_eq!(command(&mut stream, &["SET", "foo", "bar"]), "OK");
+    assert_eq!(command(&mut stream, &["RPUSH", "list", "a", "b"]), "(integer) 2");
+    assert_eq!(command(&mut stream, &["SAVE"]), "OK");
+    drop(server);
+
+    // the last command is cut in the middle
+    let bytes = std::fs::read(&db_file_path).unwrap();
+    std::fs::write(&db_file_path, &bytes[..bytes.len() - 3]).unwrap();
+    let err = Server::builder().bind("127.0.0.1:0").persistence(db_file_path.clone()).start().err().unwrap();
+    assert!(err.to_string().starts_with(&format!("[Load] Bad file format reading {} at byte {}: unexpected end of file", db_file_path, bytes.len() - 3)), "{}", err);
+
+    let config = Config::from_args(["--load-truncated", "yes"].into_iter().map(String::from)).unwrap();
+    let server = Server::builder().config(config).bind("127.0.0.1:0").persistence(db_file_path).start().unwrap();
+    let mut stream = TcpStream::connect(server.local_addr().unwrap()).unwrap();
+    assert_eq!(command(&mut stream, &["EXISTS", "foo", "list"]), "(integer) 1");
+    drop(server);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
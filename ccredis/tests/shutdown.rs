@@ -0,0 +1,101 @@
+mod common;
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    thread,
+    time::Duration,
+};
+
+use common::{command, ServerProcess};
+
+// SHUTDOWN has no reply when it succeeds, connection is closed by exiting server
+fn shutdown(stream: &mut TcpStream, args: &str) {
+    let request = std::iter::once("SHUTDOWN").chain(args.split_whitespace()).collect::<Vec<_>>();
+    let mut encoded = format!("*{}\r\n", request.len());
+    for arg in request {
+        encoded.push_str(&format!("${}\r\n{}\r\n", arg.len(), arg));
+    }
+    stream.write_all(encoded.as_bytes()).unwrap();
+    assert_eq!(stream.read(&mut [0; 64]).unwrap_or(0), 0);
+}
+
+#[test]
+fn shutdown_saves_snapshot_and_removes_pidfile() {
+    let mut server = ServerProcess::start(&["--pidfile", "ccredis.pid"]);
+    let pid = std::fs::read_to_string(server.dir.join("ccredis.pid")).unwrap();
+    assert!(pid.trim().parse::<u32>().is_ok());
+
+    let mut stream = server.connect();
+    assert_eq!(command(&mut stream, &["SET", "foo", "bar"]), "OK");
+    shutdown(&mut stream, "");
+    assert!(server.wait().success());
+    assert!(std::fs::read_to_string(server.dir.join("db.txt")).unwrap().contains("foo"));
+    assert!(!server.dir.join("db.txt.tmp").exists());
+    assert!(!server.dir.join("ccredis.pid").exists());
+}
+
+#[test]
+fn shutdown_nosave() {
+    let mut server = ServerProcess::start(&[]);
+    let mut stream = server.connect();
+    assert_eq!(command(&mut stream, &["SET", "foo", "bar"]), "OK");
+    shutdown(&mut stream, "NOSAVE NOW");
+    assert!(server.wait().success());
+    assert!(!server.dir.join("db.txt").exists());
+}
+
+#[test]
+fn scripts_are_paused_during_shutdown() {
+    let mut server = ServerProcess::start(&["--shutdown-timeout", "1"]);
+    // replica which never acknowledges, so shutdown waits for it
+    let mut replica = server.connect();
+    replica.write_all(b"*3\r\n$5\r\nPSYNC\r\n$1\r\n?\r\n$2\r\n-1\r\n").unwrap();
+    assert!(replica.read(&mut [0; 64]).unwrap() > 0);
+
+    let mut stream = server.connect();
+    assert_eq!(command(&mut stream, &["SET", "foo", "bar"]), "OK");
+    stream.write_all(b"*1\r\n$8\r\nSHUTDOWN\r\n").unwrap();
+    thread::sleep(Duration::from_millis(200));
+    let mut script = server.connect();
+    script.write_all(b"*3\r\n$4\r\nEVAL\r\n$30\r\nredis.call('SET', 'late', '1')\r\n$1\r\n0\r\n").unwrap();
+
+    assert!(server.wait().success());
+    // script was held until the server exited, so it's not in the final snapshot
+    assert_eq!(script.read(&mut [0; 64]).unwrap_or(0), 0);
+    assert!(!std::fs::read_to_string(server.dir.join("db.txt")).unwrap().contains("late"));
+}
+
+#[test]
+fn failed_save_keeps_server_running() {
+    let mut server = ServerProcess::start(&[]);
+    // snapshot can't replace a directory
+    std::fs::create_dir(server.dir.join("db.txt")).unwrap();
+
+    let mut stream = server.connect();
+    assert_eq!(command(&mut stream, &["SHUTDOWN"]), "(error) ERR Errors trying to SHUTDOWN. Check logs.");
+    assert!(!server.dir.join("db.txt.tmp").exists());
+    // writes paused during shutdown are allowed again
+    assert_eq!(command(&mut stream, &["SET", "foo", "bar"]), "OK");
+    assert_eq!(command(&mut stream, &["SHUTDOWN", "ABORT"]), "(error) ERR No shutdown in progress.");
+
+    shutdown(&mut stream, "FORCE");
+    assert_eq!(server.wait().code(), Some(1));
+}
+
+#[cfg(unix)]
+#[test]
+fn sigterm_saves_snapshot() {
+    let mut server = ServerProcess::start(&[]);
+    let mut stream = server.connect();
+    assert_eq!(command(&mut stream, &["SET", "foo", "bar"]), "OK");
+    assert!(server.signal("TERM").success());
+    assert!(std::fs::read_to_string(server.dir.join("db.txt")).unwrap().contains("foo"));
+    assert!(!server.dir.join("db.txt.tmp").exists());
+
+    let mut server = ServerProcess::start(&["--shutdown-on-sigint", "nosave"]);
+    let mut stream = server.connect();
+    assert_eq!(command(&mut stream, &["SET", "foo", "bar"]), "OK");
+    assert!(server.signal("INT").success());
+    assert!(!server.dir.join("db.txt").exists());
+}
@@ -0,0 +1,111 @@
+mod common;
+
+use std::{io::{Read, Write}, net::TcpStream, path::Path, sync::Arc};
+
+use common::{command, free_port, wait_for_port, ServerProcess};
+use rcgen::{BasicConstraints, CertificateParams, ExtendedKeyUsagePurpose, IsCa, KeyPair};
+use rustls::{
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, ServerName},
+    ClientConfig, ClientConnection, RootCertStore, StreamOwned,
+};
+
+struct Certificates {
+    ca_cert: CertificateDer<'static>,
+    client_cert: CertificateDer<'static>,
+    client_key: PrivateKeyDer<'static>,
+}
+
+// self-signed CA with server certificate for localhost and client certificate, written as PEM files to dir
+fn generate_certificates(dir: &Path) -> Certificates {
+    let ca_key = KeyPair::generate().unwrap();
+    let mut ca_params = CertificateParams::new(Vec::<String>::new()).unwrap();
+    ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+    let ca_cert = ca_params.self_signed(&ca_key).unwrap();
+
+    let server_key = KeyPair::generate().unwrap();
+    let mut server_params = CertificateParams::new(vec!["localhost".to_string()]).unwrap();
+    server_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+    let server_cert = server_params.signed_by(&server_key, &ca_cert, &ca_key).unwrap();
+
+    let client_key = KeyPair::generate().unwrap();
+    let mut client_params = CertificateParams::new(vec!["client".to_string()]).unwrap();
+    client_params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ClientAuth];
+    let client_cert = client_params.signed_by(&client_key, &ca_cert, &ca_key).unwrap();
+
+    std::fs::write(dir.join("ca.crt"), ca_cert.pem()).unwrap();
+    std::fs::write(dir.join("server.crt"), server_cert.pem()).unwrap();
+    std::fs::write(dir.join("server.key"), server_key.serialize_pem()).unwrap();
+
+    Certificates {
+        ca_cert: ca_cert.der().clone(),
+        client_cert: client_cert.der().clone(),
+        client_key: PrivateKeyDer::Pkcs8(client_key.serialize_der().into()),
+    }
+}
+
+fn start_tls_server(extra_args: &[&str]) -> (ServerProcess, Certificates, u16) {
+    let dir = common::temp_dir(&format!("tls_{}", free_port()));
+    let certificates = generate_certificates(&dir);
+    let tls_port = free_port();
+    let tls_port_arg = tls_port.to_string();
+    let cert_file = dir.join("server.crt");
+    let key_file = dir.join("server.key");
+    let ca_file = dir.join("ca.crt");
+
+    let mut args = vec![
+        "--tls-port", &tls_port_arg,
+        "--tls-cert-file", cert_file.to_str().unwrap(),
+        "--tls-key-file", key_file.to_str().unwrap(),
+        "--tls-ca-cert-file", ca_file.to_str().unwrap(),
+    ];
+    args.extend_from_slice(extra_args);
+    let server = ServerProcess::start(&args);
+    wait_for_port(tls_port);
+    // certificates are loaded on startup
+    std::fs::remove_dir_all(&dir).unwrap();
+    (server, certificates, tls_port)
+}
+
+fn connect_tls(port: u16, certificates: &Certificates, with_client_cert: bool) -> StreamOwned<ClientConnection, TcpStream> {
+    let mut roots = RootCertStore::empty();
+    roots.add(certificates.ca_cert.clone()).unwrap();
+    let builder = ClientConfig::builder_with_provider(Arc::new(ring::default_provider()))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots);
+    let config = if with_client_cert {
+        builder.with_client_auth_cert(vec![certificates.client_cert.clone()], certificates.client_key.clone_key()).unwrap()
+    } else {
+        builder.with_no_client_auth()
+    };
+
+    let connection = ClientConnection::new(Arc::new(config), ServerName::try_from("localhost").unwrap()).unwrap();
+    StreamOwned::new(connection, TcpStream::connect(("127.0.0.1", port)).unwrap())
+}
+
+#[test]
+fn tls_and_plain_listeners_share_keyspace() {
+    let (server, certificates, tls_port) = start_tls_server(&[]);
+
+    let mut tls_stream = connect_tls(tls_port, &certificates, false);
+    assert_eq!(command(&mut tls_stream, &["PING"]), "PONG");
+    assert_eq!(command(&mut tls_stream, &["SET", "shared", "value"]), "OK");
+
+    let mut plain_stream = server.connect();
+    assert_eq!(command(&mut plain_stream, &["GET", "shared"]), "value");
+}
+
+#[test]
+fn tls_client_certificate_is_verified() {
+    let (_server, certificates, tls_port) = start_tls_server(&["--tls-auth-clients", "yes"]);
+
+    let mut with_cert = connect_tls(tls_port, &certificates, true);
+    assert_eq!(command(&mut with_cert, &["PING"]), "PONG");
+
+    let mut without_cert = connect_tls(tls_port, &certificates, false);
+    let _ = without_cert.write_all(b"*1\r\n$4\r\nPING\r\n");
+    let mut buf = [0u8; 16];
+    let read = without_cert.read(&mut buf);
+    assert!(matches!(read, Err(_) | Ok(0)), "Expected handshake failure, got {:?}", read);
+}
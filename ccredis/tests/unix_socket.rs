@@ -0,0 +1,29 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::{os::unix::{fs::PermissionsExt, net::UnixStream}, path::Path};
+
+use common::{command, ServerProcess};
+
+#[test]
+fn serve_commands_on_unix_socket() {
+    let dir = common::temp_dir("unix_socket");
+    let path = dir.join("ccredis.sock");
+    // stale socket file left by previous run
+    drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+
+    let mut server = ServerProcess::start(&["--unixsocket", path.to_str().unwrap(), "--unixsocketperm", "700"]);
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o700);
+
+    let mut unix_stream = UnixStream::connect(&path).unwrap();
+    assert_eq!(command(&mut unix_stream, &["SET", "foo", "bar"]), "OK");
+    assert!(command(&mut unix_stream, &["CLIENT", "INFO"]).contains(&format!("addr={}:0", path.display())));
+
+    let mut tcp_stream = server.connect();
+    assert_eq!(command(&mut tcp_stream, &["GET", "foo"]), "bar");
+
+    server.signal("INT");
+    assert!(!Path::new(&path).exists(), "Socket file is removed on shutdown");
+    std::fs::remove_dir_all(&dir).unwrap();
+}